@@ -0,0 +1,16 @@
+#![no_main]
+// Feeds arbitrary bytes to the archive parser and reads whatever entries it finds. Run it with
+// `cargo fuzz run parse_bytes` from this directory. Errors are fine, panics and hangs are bugs.
+use libfuzzer_sys::fuzz_target;
+use rip::security::SecurityPolicy;
+use rip::ziparchive::ZipArchive;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(archive) = ZipArchive::parse_bytes(data) {
+        for entry in archive.entries() {
+            let _ = entry.name();
+            let _ = entry.read();
+        }
+        let _ = archive.analyze_security(&SecurityPolicy::default());
+    }
+});
@@ -0,0 +1,161 @@
+#![allow(dead_code)]
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use crate::crc32;
+use crate::datetime::DateTime;
+use crate::walk::{self, SkippedLink, WalkOptions};
+use crate::zipwriter::{ZipWriter, FileOptions};
+use crate::ziperror::{ZipError, ZipResult};
+
+/// Page sized, so every asset can be mapped straight out of the archive
+pub const ASSET_PACK_ALIGNMENT: u16 = 4096;
+/// Name of the index entry written after the assets
+pub const ASSET_INDEX_NAME: &str = ".asset-index";
+const ASSET_INDEX_HEADER: &str = "# rip asset index v1";
+
+#[derive(Debug, Clone)]
+pub struct AssetPackOptions {
+    pub alignment: u16,
+    /// Append an index of where every asset's data is, so a loader doesn't need to parse the central directory
+    pub write_index: bool,
+    /// Stamped on every entry, so packs built from the same files are byte for byte identical
+    pub modified: DateTime
+}
+
+impl Default for AssetPackOptions {
+    fn default() -> AssetPackOptions {
+        AssetPackOptions { alignment: ASSET_PACK_ALIGNMENT, write_index: true, modified: DateTime::default() }
+    }
+}
+
+/// One line of the asset index
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AssetIndexEntry {
+    pub name: String,
+    /// Offset of the asset's data from the start of the archive
+    pub offset: u64,
+    pub size: u64,
+    pub crc32: u32
+}
+
+/// Builds a zip meant to be mmapped by a game or data loader: stored entries, aligned data,
+/// sorted by name, fixed timestamps, and an optional index entry at the end
+#[derive(Debug, Default)]
+pub struct AssetPack {
+    assets: BTreeMap<String, Vec<u8>>,
+    options: AssetPackOptions
+}
+
+impl AssetPack {
+    pub fn new(options: AssetPackOptions) -> AssetPack {
+        AssetPack { assets: BTreeMap::new(), options }
+    }
+
+    /// Adds an asset, replacing any earlier one with the same name
+    pub fn add(&mut self, name: &str, data: Vec<u8>) {
+        self.assets.insert(name.to_string(), data);
+    }
+
+    pub fn add_file<P: AsRef<Path>>(&mut self, name: &str, path: P) -> ZipResult<()> {
+        self.add(name, fs::read(path)?);
+        Ok(())
+    }
+
+    /// Adds every file under root, named relative to it. Directories don't get entries of their own. Links that
+    /// couldn't be followed are returned.
+    pub fn add_dir_all<P: AsRef<Path>>(&mut self, root: P, walk_options: &WalkOptions) -> ZipResult<Vec<SkippedLink>> {
+        let walked = walk::walk_dir(root.as_ref(), walk_options)?;
+        for found in walked.entries {
+            if !found.is_dir && found.symlink_target.is_none() {
+                self.add_file(&found.name, &found.path)?;
+            }
+        }
+        Ok(walked.skipped)
+    }
+
+    /// Writes the pack out, handing back the writer
+    pub fn write<W: Write>(self, output: W) -> ZipResult<W> {
+        if self.assets.contains_key(ASSET_INDEX_NAME) {
+            return Err(ZipError::InvalidArchive(format!("{} is reserved for the asset index", ASSET_INDEX_NAME)));
+        }
+
+        let options = FileOptions::default()
+            .last_modified_time(self.options.modified)
+            .with_alignment(self.options.alignment);
+        let mut writer = ZipWriter::new(output);
+        let mut index = Vec::new();
+
+        for (name, data) in &self.assets {
+            writer.start_file(name, options.clone())?;
+            writer.write_all(data)?;
+            if let Some(range) = writer.finish_entry()? {
+                index.push(AssetIndexEntry { name: name.clone(), offset: range.start, size: data.len() as u64, crc32: crc32::checksum(data) });
+            }
+        }
+
+        if self.options.write_index {
+            writer.start_file(ASSET_INDEX_NAME, options.clone())?;
+            writer.write_all(format_asset_index(&index).as_bytes())?;
+        }
+        writer.finish()
+    }
+}
+
+/// The index is text: a header line, then "offset size crc32 name" per asset, with the name last so it can hold spaces
+pub fn format_asset_index(index: &[AssetIndexEntry]) -> String {
+    let mut text = format!("{}\n", ASSET_INDEX_HEADER);
+    for entry in index {
+        text.push_str(&format!("{} {} {:08x} {}\n", entry.offset, entry.size, entry.crc32, entry.name));
+    }
+    text
+}
+
+pub fn parse_asset_index(text: &str) -> ZipResult<Vec<AssetIndexEntry>> {
+    let mut lines = text.lines();
+    if lines.next() != Some(ASSET_INDEX_HEADER) {
+        return Err(ZipError::InvalidArchive("Not an asset index".to_string()));
+    }
+
+    lines.filter(|line| !line.is_empty()).map(|line| {
+        let bad_line = || ZipError::InvalidArchive(format!("Bad asset index line: {}", line));
+        let mut fields = line.splitn(4, ' ');
+        let mut next = || fields.next().ok_or_else(bad_line);
+        let offset = next()?.parse().map_err(|_| bad_line())?;
+        let size = next()?.parse().map_err(|_| bad_line())?;
+        let crc32 = u32::from_str_radix(next()?, 16).map_err(|_| bad_line())?;
+        let name = next()?.to_string();
+        Ok(AssetIndexEntry { name, offset, size, crc32 })
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ziparchive::ZipArchive;
+
+    #[test]
+    fn assets_are_aligned_and_indexed() {
+        let mut pack = AssetPack::new(AssetPackOptions::default());
+        pack.add("textures/b.png", vec![0xAB; 5000]);
+        pack.add("a name with spaces", b"short".to_vec());
+        let written = pack.write(Vec::new()).unwrap();
+
+        let path = std::env::temp_dir().join(format!("rip-assetpack-{}.zip", std::process::id()));
+        fs::write(&path, &written).unwrap();
+        let archive = ZipArchive::new(path.to_str().unwrap());
+        let names: Vec<String> = archive.entries().iter().map(|entry| entry.name()).collect();
+        let index = parse_asset_index(&String::from_utf8(archive.entries()[2].read().unwrap()).unwrap()).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(names, ["a name with spaces", "textures/b.png", ASSET_INDEX_NAME]);
+        assert_eq!(index.len(), 2);
+        for (asset, expected) in index.iter().zip([&b"short"[..], &[0xAB; 5000]]) {
+            assert_eq!(asset.offset % ASSET_PACK_ALIGNMENT as u64, 0);
+            assert_eq!(&written[asset.offset as usize..(asset.offset + asset.size) as usize], expected);
+            assert_eq!(asset.crc32, crc32::checksum(expected));
+        }
+        assert_eq!(parse_asset_index(&format_asset_index(&index)).unwrap(), index);
+    }
+}
@@ -0,0 +1,139 @@
+#![allow(dead_code)]
+// Host systems from the upper byte of version_made_by, APPNOTE 4.4.2
+// https://pkware.cachefly.net/webdocs/casestudies/APPNOTE.TXT
+
+/// DOS attribute bits, in the low byte of external_file_attributes
+pub const DOS_READ_ONLY: u8 = 0x01;
+pub const DOS_HIDDEN: u8 = 0x02;
+pub const DOS_SYSTEM: u8 = 0x04;
+pub const DOS_DIRECTORY: u8 = 0x10;
+pub const DOS_ARCHIVE: u8 = 0x20;
+
+/// Bit 0 of internal_file_attributes: the entry is (apparently) text rather than binary
+pub const INTERNAL_TEXT: u16 = 0x0001;
+
+/// Unix file type bits, in the st_mode stored in the high 16 bits of external_file_attributes
+pub const UNIX_FILE_TYPE_MASK: u32 = 0o170000;
+pub const UNIX_DIRECTORY: u32 = 0o040000;
+pub const UNIX_REGULAR_FILE: u32 = 0o100000;
+pub const UNIX_SYMLINK: u32 = 0o120000;
+
+/// The DOS/Windows attribute bits an entry can carry
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct DosAttributes {
+    pub read_only: bool,
+    pub hidden: bool,
+    pub system: bool,
+    pub archive: bool
+}
+
+impl DosAttributes {
+    pub fn from_bits(bits: u8) -> DosAttributes {
+        DosAttributes {
+            read_only: bits & DOS_READ_ONLY != 0,
+            hidden: bits & DOS_HIDDEN != 0,
+            system: bits & DOS_SYSTEM != 0,
+            archive: bits & DOS_ARCHIVE != 0
+        }
+    }
+
+    pub fn to_bits(self) -> u8 {
+        let mut bits = 0;
+        if self.read_only { bits |= DOS_READ_ONLY; }
+        if self.hidden { bits |= DOS_HIDDEN; }
+        if self.system { bits |= DOS_SYSTEM; }
+        if self.archive { bits |= DOS_ARCHIVE; }
+        bits
+    }
+}
+
+/// The system an entry was made on, which decides how its external attributes are laid out
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum HostSystem {
+    MsDos,
+    Amiga,
+    OpenVms,
+    Unix,
+    VmCms,
+    AtariSt,
+    Os2Hpfs,
+    Macintosh,
+    ZSystem,
+    CpM,
+    WindowsNtfs,
+    Mvs,
+    Vse,
+    AcornRisc,
+    Vfat,
+    AlternateMvs,
+    BeOs,
+    Tandem,
+    Os400,
+    OsxDarwin,
+    Unknown(u8)
+}
+
+impl HostSystem {
+    pub fn from_u8(host: u8) -> HostSystem {
+        match host {
+            0 => HostSystem::MsDos,
+            1 => HostSystem::Amiga,
+            2 => HostSystem::OpenVms,
+            3 => HostSystem::Unix,
+            4 => HostSystem::VmCms,
+            5 => HostSystem::AtariSt,
+            6 => HostSystem::Os2Hpfs,
+            7 => HostSystem::Macintosh,
+            8 => HostSystem::ZSystem,
+            9 => HostSystem::CpM,
+            10 => HostSystem::WindowsNtfs,
+            11 => HostSystem::Mvs,
+            12 => HostSystem::Vse,
+            13 => HostSystem::AcornRisc,
+            14 => HostSystem::Vfat,
+            15 => HostSystem::AlternateMvs,
+            16 => HostSystem::BeOs,
+            17 => HostSystem::Tandem,
+            18 => HostSystem::Os400,
+            19 => HostSystem::OsxDarwin,
+            other => HostSystem::Unknown(other)
+        }
+    }
+
+    pub fn to_u8(self) -> u8 {
+        match self {
+            HostSystem::MsDos => 0,
+            HostSystem::Amiga => 1,
+            HostSystem::OpenVms => 2,
+            HostSystem::Unix => 3,
+            HostSystem::VmCms => 4,
+            HostSystem::AtariSt => 5,
+            HostSystem::Os2Hpfs => 6,
+            HostSystem::Macintosh => 7,
+            HostSystem::ZSystem => 8,
+            HostSystem::CpM => 9,
+            HostSystem::WindowsNtfs => 10,
+            HostSystem::Mvs => 11,
+            HostSystem::Vse => 12,
+            HostSystem::AcornRisc => 13,
+            HostSystem::Vfat => 14,
+            HostSystem::AlternateMvs => 15,
+            HostSystem::BeOs => 16,
+            HostSystem::Tandem => 17,
+            HostSystem::Os400 => 18,
+            HostSystem::OsxDarwin => 19,
+            HostSystem::Unknown(other) => other
+        }
+    }
+
+    /// Whether the high 16 bits of the external attributes hold a Unix st_mode
+    pub fn has_unix_mode(self) -> bool {
+        matches!(self, HostSystem::Unix | HostSystem::OsxDarwin | HostSystem::BeOs | HostSystem::AcornRisc)
+    }
+
+    /// Whether the low byte of the external attributes holds DOS attribute bits.
+    /// Info-ZIP on Unix fills these in too, so Unix counts.
+    pub fn has_dos_attributes(self) -> bool {
+        matches!(self, HostSystem::MsDos | HostSystem::Os2Hpfs | HostSystem::WindowsNtfs | HostSystem::Vfat | HostSystem::Unix)
+    }
+}
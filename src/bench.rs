@@ -0,0 +1,240 @@
+#![allow(dead_code)]
+// `rip bench` and `rip selftest`: throughput on the local machine for synthetic archives, and quick round trip checks
+// that the pieces a deployment depends on work there. Everything happens under a scratch directory in the system temp
+// directory, removed afterwards.
+use std::fs::{self, File};
+use std::io::{self, Cursor, Read, Write};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use rip::crc32;
+use rip::extract::ExtractOptions;
+use rip::lz4::{Lz4Decoder, Lz4Encoder};
+use rip::options::{ReaderOptions, WriterOptions, DEFAULT_IO_BUFFER};
+use rip::search::{self, SearchOptions};
+use rip::synth;
+use rip::ziparchive::ZipArchive;
+use rip::ziperror::{ZipError, ZipResult};
+use rip::zipwriter::{FileOptions, ZipWriter};
+
+#[derive(Debug, Clone)]
+pub struct BenchOptions {
+    pub entries: usize,
+    /// Bytes in each entry
+    pub entry_size: usize,
+    /// Rough percentage of each entry that's repeated filler rather than noise, 0 to 100
+    pub compressibility: u8,
+    /// For the search phase, the only one that runs in parallel
+    pub threads: usize,
+    /// Reader and writer buffer size
+    pub io_buffer: usize
+}
+
+impl Default for BenchOptions {
+    fn default() -> BenchOptions {
+        BenchOptions { entries: 1000, entry_size: 64 * 1024, compressibility: 50, threads: 1, io_buffer: DEFAULT_IO_BUFFER }
+    }
+}
+
+/// How one phase of the benchmark went
+#[derive(Debug, Clone)]
+pub struct PhaseResult {
+    pub phase: &'static str,
+    pub entries: usize,
+    /// Uncompressed bytes the phase handled
+    pub bytes: u64,
+    pub elapsed: Duration
+}
+
+impl PhaseResult {
+    pub fn bytes_per_second(&self) -> f64 {
+        self.bytes as f64 / self.elapsed.as_secs_f64().max(1e-9)
+    }
+
+    pub fn entries_per_second(&self) -> f64 {
+        self.entries as f64 / self.elapsed.as_secs_f64().max(1e-9)
+    }
+}
+
+/// A directory in the system temp directory that's removed when dropped
+struct Scratch {
+    path: PathBuf
+}
+
+impl Scratch {
+    fn new(purpose: &str) -> io::Result<Scratch> {
+        let path = std::env::temp_dir().join(format!("rip-{}-{}", purpose, std::process::id()));
+        fs::create_dir_all(&path)?;
+        Ok(Scratch { path })
+    }
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+/// Creates, lists, extracts and searches a synthetic archive, timing each
+pub fn run(options: &BenchOptions) -> ZipResult<Vec<PhaseResult>> {
+    let scratch = Scratch::new("bench")?;
+    let archive_path = scratch.path.join("bench.zip");
+    let total_bytes = (options.entries * options.entry_size) as u64;
+    let mut results = Vec::new();
+
+    let contents: Vec<Vec<u8>> = (0..options.entries).map(|index| synth::data(index as u64 + 1, options.entry_size, options.compressibility)).collect();
+    let started = Instant::now();
+    let writer_options = WriterOptions { io_buffer: options.io_buffer, ..WriterOptions::default() };
+    let mut writer = ZipWriter::with_options(File::create(&archive_path)?, &writer_options);
+    for (index, data) in contents.iter().enumerate() {
+        writer.start_file(&format!("bench/{:06}.txt", index), FileOptions::default().large_file(data.len() as u64 >= u32::MAX as u64))?;
+        writer.write_all(data)?;
+    }
+    writer.finish()?.sync_all()?;
+    results.push(PhaseResult { phase: "create", entries: options.entries, bytes: total_bytes, elapsed: started.elapsed() });
+    drop(contents);
+
+    let started = Instant::now();
+    let path = archive_path.to_string_lossy().into_owned();
+    let archive = ZipArchive::with_options(&path, &ReaderOptions { io_buffer: options.io_buffer, ..ReaderOptions::default() })?;
+    let listed = archive.entries().iter().filter(|entry| !entry.name().is_empty()).count();
+    results.push(PhaseResult { phase: "list", entries: listed, bytes: total_bytes, elapsed: started.elapsed() });
+
+    let started = Instant::now();
+    let report = archive.extract_all(scratch.path.join("out"), &ExtractOptions::default())?;
+    results.push(PhaseResult { phase: "extract", entries: report.extracted.len(), bytes: total_bytes, elapsed: started.elapsed() });
+
+    let started = Instant::now();
+    let search_options = SearchOptions { name_filter: None, threads: options.threads };
+    archive.search(&search_options, &search::literal("needle", false));
+    results.push(PhaseResult { phase: "search", entries: options.entries, bytes: total_bytes, elapsed: started.elapsed() });
+    Ok(results)
+}
+
+/// One selftest check, Err saying what went wrong
+pub type CheckResult = Result<(), String>;
+
+fn check_entries(archive: &ZipArchive, expected: &[(&str, &[u8])]) -> CheckResult {
+    if archive.entries().len() != expected.len() {
+        return Err(format!("{} entries read back, {} written", archive.entries().len(), expected.len()));
+    }
+    for (entry, (name, data)) in archive.entries().iter().zip(expected) {
+        if entry.name() != *name {
+            return Err(format!("{} read back as {}", name, entry.name()));
+        }
+        if entry.read().map_err(|why| format!("{}: {}", name, why))? != *data {
+            return Err(format!("{} came back with different contents", name));
+        }
+    }
+    Ok(())
+}
+
+fn sample_archive(files: &[(&str, &[u8])]) -> ZipResult<Vec<u8>> {
+    let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+    for (name, data) in files {
+        if name.ends_with('/') {
+            writer.add_directory(name, FileOptions::default())?;
+        } else {
+            writer.start_file(name, FileOptions::default().unix_permissions(0o644))?;
+            writer.write_all(data)?;
+        }
+    }
+    Ok(writer.finish()?.into_inner())
+}
+
+fn check_crc32() -> CheckResult {
+    match crc32::checksum(b"123456789") {
+        0xCBF43926 => Ok(()),
+        other => Err(format!("crc32 of the check string is {:08x}, not cbf43926", other))
+    }
+}
+
+fn check_write_read(files: &[(&str, &[u8])]) -> CheckResult {
+    let bytes = sample_archive(files).map_err(|why| why.to_string())?;
+    let archive = ZipArchive::parse_bytes(&bytes).map_err(|why| why.to_string())?;
+    check_entries(&archive, files)
+}
+
+fn check_extract(files: &[(&str, &[u8])]) -> CheckResult {
+    let scratch = Scratch::new("selftest").map_err(|why| why.to_string())?;
+    let archive_path = scratch.path.join("selftest.zip");
+    fs::write(&archive_path, sample_archive(files).map_err(|why| why.to_string())?).map_err(|why| why.to_string())?;
+    let path = archive_path.to_string_lossy().into_owned();
+    let archive = ZipArchive::with_options(&path, &ReaderOptions::default()).map_err(|why| why.to_string())?;
+    let dest = scratch.path.join("out");
+    archive.extract_all(&dest, &ExtractOptions::default()).map_err(|why| why.to_string())?;
+    for (name, data) in files.iter().filter(|(name, _)| !name.ends_with('/')) {
+        let written = fs::read(dest.join(name)).map_err(|why| format!("{}: {}", name, why))?;
+        if written != *data {
+            return Err(format!("{} was extracted with different contents", name));
+        }
+    }
+    Ok(())
+}
+
+fn check_rename(files: &[(&str, &[u8])]) -> CheckResult {
+    let bytes = sample_archive(files).map_err(|why| why.to_string())?;
+    let archive = ZipArchive::parse_bytes(&bytes).map_err(|why| why.to_string())?;
+    let prefixed = archive.prepend_prefix(Vec::new(), "top").map_err(|why| why.to_string())?;
+    let prefixed = ZipArchive::parse_bytes(&prefixed).map_err(|why| why.to_string())?;
+    let stripped = prefixed.rewrite_renamed(Vec::new(), |name| name.strip_prefix("top/").filter(|rest| !rest.is_empty()).map(str::to_string))
+        .map_err(|why| why.to_string())?;
+    let stripped = ZipArchive::parse_bytes(&stripped).map_err(|why| why.to_string())?;
+    // The top/ directory entry is left behind as "top/", everything after it should be as written
+    let names: Vec<String> = stripped.entries().iter().skip(1).map(|entry| entry.name()).collect();
+    let expected: Vec<String> = files.iter().map(|(name, _)| name.to_string()).collect();
+    if names != expected {
+        return Err(format!("renamed there and back the names are {:?}", names));
+    }
+    for (entry, (name, data)) in stripped.entries().iter().skip(1).zip(files) {
+        if entry.read().map_err(|why| format!("{}: {}", name, why))? != *data {
+            return Err(format!("{} came back with different contents", name));
+        }
+    }
+    Ok(())
+}
+
+fn check_lz4(data: &[u8]) -> CheckResult {
+    let mut encoder = Lz4Encoder::new(Vec::new());
+    encoder.write_all(data).map_err(|why| why.to_string())?;
+    let compressed = encoder.finish().map_err(|why| why.to_string())?;
+    let mut decompressed = Vec::new();
+    Lz4Decoder::new(compressed.as_slice()).read_to_end(&mut decompressed).map_err(|why| why.to_string())?;
+    if decompressed != data {
+        return Err("decompressed data differs from the original".to_string());
+    }
+    Ok(())
+}
+
+fn check_corrupt_rejected(files: &[(&str, &[u8])]) -> CheckResult {
+    let mut bytes = sample_archive(files).map_err(|why| why.to_string())?;
+    // Flip a byte of the first entry's data so its crc no longer matches
+    let data_start = 30 + files[0].0.len();
+    bytes[data_start] ^= 0xFF;
+    let archive = ZipArchive::parse_bytes(&bytes).map_err(|why| why.to_string())?;
+    match archive.entries()[0].read() {
+        Err(ZipError::CrcMismatch { .. }) => Ok(()),
+        Err(other) => Err(format!("a damaged entry failed with {} instead of a crc mismatch", other)),
+        Ok(_) => Err("a damaged entry was read without an error".to_string())
+    }
+}
+
+/// Runs every check, returning each one's name and outcome
+pub fn selftest() -> Vec<(&'static str, CheckResult)> {
+    let big = synth::data(7, 3 * 1024 * 1024 + 17, 50);
+    let files: Vec<(&str, &[u8])> = vec![
+        ("hello.txt", b"hello, world\n"),
+        ("empty", b""),
+        ("dir/", b""),
+        ("dir/ünïcode 日本.txt", "non-ASCII name".as_bytes()),
+        ("dir/big.bin", &big)
+    ];
+    vec![
+        ("crc32", check_crc32()),
+        ("write and read back", check_write_read(&files)),
+        ("extract to disk", check_extract(&files)),
+        ("raw rename there and back", check_rename(&files)),
+        ("lz4 round trip", check_lz4(&big)),
+        ("damaged data detected", check_corrupt_rejected(&files))
+    ]
+}
+
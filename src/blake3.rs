@@ -0,0 +1,254 @@
+#![allow(dead_code)]
+// BLAKE3, hashing only (no keyed or key derivation modes, 32 byte output), for naming content by its hash
+// https://github.com/BLAKE3-team/BLAKE3-specs/blob/master/blake3.pdf
+use std::convert::TryInto;
+
+const IV: [u32; 8] = [0x6A09E667, 0xBB67AE85, 0x3C6EF372, 0xA54FF53A, 0x510E527F, 0x9B05688C, 0x1F83D9AB, 0x5BE0CD19];
+const MESSAGE_PERMUTATION: [usize; 16] = [2, 6, 3, 10, 7, 0, 4, 13, 1, 11, 12, 5, 9, 14, 15, 8];
+
+const BLOCK_LEN: usize = 64;
+const CHUNK_LEN: usize = 1024;
+
+const CHUNK_START: u32 = 1;
+const CHUNK_END: u32 = 1 << 1;
+const PARENT: u32 = 1 << 2;
+const ROOT: u32 = 1 << 3;
+
+fn g(state: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize, x: u32, y: u32) {
+    state[a] = state[a].wrapping_add(state[b]).wrapping_add(x);
+    state[d] = (state[d] ^ state[a]).rotate_right(16);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_right(12);
+    state[a] = state[a].wrapping_add(state[b]).wrapping_add(y);
+    state[d] = (state[d] ^ state[a]).rotate_right(8);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_right(7);
+}
+
+fn round(state: &mut [u32; 16], m: &[u32; 16]) {
+    // Columns, then diagonals
+    g(state, 0, 4, 8, 12, m[0], m[1]);
+    g(state, 1, 5, 9, 13, m[2], m[3]);
+    g(state, 2, 6, 10, 14, m[4], m[5]);
+    g(state, 3, 7, 11, 15, m[6], m[7]);
+    g(state, 0, 5, 10, 15, m[8], m[9]);
+    g(state, 1, 6, 11, 12, m[10], m[11]);
+    g(state, 2, 7, 8, 13, m[12], m[13]);
+    g(state, 3, 4, 9, 14, m[14], m[15]);
+}
+
+/// The compression function, returning the new chaining value (the first 8 words of its output)
+fn compress(chaining_value: &[u32; 8], block: &[u32; 16], counter: u64, block_len: u32, flags: u32) -> [u32; 8] {
+    let mut state = [
+        chaining_value[0], chaining_value[1], chaining_value[2], chaining_value[3],
+        chaining_value[4], chaining_value[5], chaining_value[6], chaining_value[7],
+        IV[0], IV[1], IV[2], IV[3],
+        counter as u32, (counter >> 32) as u32, block_len, flags
+    ];
+    let mut block = *block;
+    for i in 0..7 {
+        round(&mut state, &block);
+        if i < 6 {
+            block = MESSAGE_PERMUTATION.map(|from| block[from]);
+        }
+    }
+    let mut output = [0; 8];
+    for (i, word) in output.iter_mut().enumerate() {
+        *word = state[i] ^ state[i + 8];
+    }
+    output
+}
+
+fn block_words(block: &[u8; BLOCK_LEN]) -> [u32; 16] {
+    let mut words = [0; 16];
+    for (word, bytes) in words.iter_mut().zip(block.chunks_exact(4)) {
+        *word = u32::from_le_bytes(bytes.try_into().unwrap());
+    }
+    words
+}
+
+/// The last compression of a chunk or parent, held back until it's known whether it's the root
+#[derive(Debug, Clone)]
+struct Output {
+    chaining_value: [u32; 8],
+    block: [u32; 16],
+    counter: u64,
+    block_len: u32,
+    flags: u32
+}
+
+impl Output {
+    fn chaining_value(&self) -> [u32; 8] {
+        compress(&self.chaining_value, &self.block, self.counter, self.block_len, self.flags)
+    }
+
+    fn root_hash(&self) -> [u8; 32] {
+        let words = compress(&self.chaining_value, &self.block, 0, self.block_len, self.flags | ROOT);
+        let mut hash = [0; 32];
+        for (bytes, word) in hash.chunks_exact_mut(4).zip(words) {
+            bytes.copy_from_slice(&word.to_le_bytes());
+        }
+        hash
+    }
+}
+
+fn parent_output(left: &[u32; 8], right: &[u32; 8]) -> Output {
+    let mut block = [0; 16];
+    block[..8].copy_from_slice(left);
+    block[8..].copy_from_slice(right);
+    Output { chaining_value: IV, block, counter: 0, block_len: BLOCK_LEN as u32, flags: PARENT }
+}
+
+/// One 1 KiB chunk being hashed
+#[derive(Debug, Clone)]
+struct ChunkState {
+    chaining_value: [u32; 8],
+    counter: u64,
+    block: [u8; BLOCK_LEN],
+    block_len: usize,
+    blocks_compressed: u32
+}
+
+impl ChunkState {
+    fn new(counter: u64) -> ChunkState {
+        ChunkState { chaining_value: IV, counter, block: [0; BLOCK_LEN], block_len: 0, blocks_compressed: 0 }
+    }
+
+    fn len(&self) -> usize {
+        BLOCK_LEN * self.blocks_compressed as usize + self.block_len
+    }
+
+    fn start_flag(&self) -> u32 {
+        if self.blocks_compressed == 0 { CHUNK_START } else { 0 }
+    }
+
+    fn update(&mut self, mut data: &[u8]) {
+        while !data.is_empty() {
+            // The last block is only compressed once there's more after it, so it can be flagged as the end
+            if self.block_len == BLOCK_LEN {
+                let words = block_words(&self.block);
+                self.chaining_value = compress(&self.chaining_value, &words, self.counter, BLOCK_LEN as u32, self.start_flag());
+                self.blocks_compressed += 1;
+                self.block = [0; BLOCK_LEN];
+                self.block_len = 0;
+            }
+            let take = (BLOCK_LEN - self.block_len).min(data.len());
+            self.block[self.block_len..self.block_len + take].copy_from_slice(&data[..take]);
+            self.block_len += take;
+            data = &data[take..];
+        }
+    }
+
+    fn output(&self) -> Output {
+        Output {
+            chaining_value: self.chaining_value,
+            block: block_words(&self.block),
+            counter: self.counter,
+            block_len: self.block_len as u32,
+            flags: self.start_flag() | CHUNK_END
+        }
+    }
+}
+
+/// Incremental hasher, for when the data arrives in pieces
+#[derive(Debug, Clone)]
+pub struct Blake3 {
+    chunk: ChunkState,
+    /// Chaining values of finished subtrees, one per set bit of the number of chunks done
+    stack: Vec<[u32; 8]>
+}
+
+impl Blake3 {
+    pub fn new() -> Blake3 {
+        Blake3 { chunk: ChunkState::new(0), stack: Vec::new() }
+    }
+
+    /// Adds a finished chunk's chaining value, merging subtrees for as long as the chunk count's low bits are 0
+    fn add_chunk_chaining_value(&mut self, mut chaining_value: [u32; 8], mut total_chunks: u64) {
+        while total_chunks & 1 == 0 {
+            let left = self.stack.pop().unwrap();
+            chaining_value = parent_output(&left, &chaining_value).chaining_value();
+            total_chunks >>= 1;
+        }
+        self.stack.push(chaining_value);
+    }
+
+    pub fn update(&mut self, mut data: &[u8]) {
+        while !data.is_empty() {
+            // Like blocks, a full chunk waits for more data before it's finished off
+            if self.chunk.len() == CHUNK_LEN {
+                let chaining_value = self.chunk.output().chaining_value();
+                let total_chunks = self.chunk.counter + 1;
+                self.add_chunk_chaining_value(chaining_value, total_chunks);
+                self.chunk = ChunkState::new(total_chunks);
+            }
+            let take = (CHUNK_LEN - self.chunk.len()).min(data.len());
+            self.chunk.update(&data[..take]);
+            data = &data[take..];
+        }
+    }
+
+    pub fn finish(&self) -> [u8; 32] {
+        let mut output = self.chunk.output();
+        for left in self.stack.iter().rev() {
+            output = parent_output(left, &output.chaining_value());
+        }
+        output.root_hash()
+    }
+}
+
+impl Default for Blake3 {
+    fn default() -> Blake3 {
+        Blake3::new()
+    }
+}
+
+pub fn hash(data: &[u8]) -> [u8; 32] {
+    let mut hasher = Blake3::new();
+    hasher.update(data);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    /// The official vectors' input: bytes counting up mod 251
+    fn input(length: usize) -> Vec<u8> {
+        (0..length).map(|i| (i % 251) as u8).collect()
+    }
+
+    // From test_vectors.json in the BLAKE3 repository, default hash mode, first 32 bytes
+    const VECTORS: &[(usize, &str)] = &[
+        (0, "af1349b9f5f9a1a6a0404dea36dcc9499bcb25c9adc112b7cc9a93cae41f3262"),
+        (1, "2d3adedff11b61f14c886e35afa036736dcd87a74d27b5c1510225d0f592e213"),
+        (1023, "10108970eeda3eb932baac1428c7a2163b0e924c9a9e25b35bba72b28f70bd11"),
+        (1024, "42214739f095a406f3fc83deb889744ac00df831c10daa55189b5d121c855af7"),
+        (1025, "d00278ae47eb27b34faecf67b4fe263f82d5412916c1ffd97c8cb7fb814b8444"),
+        (2049, "5f4d72f40d7a5f82b15ca2b2e44b1de3c2ef86c426c95c1af0b6879522563030"),
+        (8193, "bab6c09cb8ce8cf459261398d2e7aef35700bf488116ceb94a36d0f5f1b7bc3b"),
+        (31744, "62b6960e1a44bcc1eb1a611a8d6235b6b4b78f32e7abc4fb4c6cdcce94895c47"),
+        (102400, "bc3e3d41a1146b069abffad3c0d44860cf664390afce4d9661f7902e7943e085")
+    ];
+
+    #[test]
+    fn known_answers() {
+        for &(length, expected) in VECTORS {
+            assert_eq!(hex(&hash(&input(length))), expected, "{} bytes", length);
+        }
+    }
+
+    #[test]
+    fn updates_in_pieces_match() {
+        let data = input(102400);
+        let mut hasher = Blake3::new();
+        for piece in data.chunks(1000) {
+            hasher.update(piece);
+        }
+        assert_eq!(hasher.finish(), hash(&data));
+    }
+}
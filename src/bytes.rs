@@ -0,0 +1,33 @@
+#![allow(dead_code)]
+// Bounds-checked little-endian reads, for containers that are parsed from a buffer rather than a reader
+use std::convert::TryInto;
+use crate::ziperror::{ZipError, ZipResult};
+
+pub(crate) fn slice_at(data: &[u8], offset: usize, length: usize) -> ZipResult<&[u8]> {
+    offset.checked_add(length).and_then(|end| data.get(offset..end))
+        .ok_or_else(|| ZipError::InvalidArchive(format!("Unexpected end of data reading {} bytes at offset {:#X}", length, offset)))
+}
+
+pub(crate) fn u8_at(data: &[u8], offset: usize) -> ZipResult<u8> {
+    Ok(slice_at(data, offset, 1)?[0])
+}
+
+pub(crate) fn u16_at(data: &[u8], offset: usize) -> ZipResult<u16> {
+    Ok(u16::from_le_bytes(slice_at(data, offset, 2)?.try_into().unwrap()))
+}
+
+pub(crate) fn u32_at(data: &[u8], offset: usize) -> ZipResult<u32> {
+    Ok(u32::from_le_bytes(slice_at(data, offset, 4)?.try_into().unwrap()))
+}
+
+pub(crate) fn u64_at(data: &[u8], offset: usize) -> ZipResult<u64> {
+    Ok(u64::from_le_bytes(slice_at(data, offset, 8)?.try_into().unwrap()))
+}
+
+/// A NUL terminated string's bytes starting at offset, without the NUL
+pub(crate) fn c_string_at(data: &[u8], offset: usize) -> ZipResult<&[u8]> {
+    let rest = data.get(offset..).unwrap_or_default();
+    let length = rest.iter().position(|&byte| byte == 0)
+        .ok_or_else(|| ZipError::InvalidArchive(format!("Unterminated string at offset {:#X}", offset)))?;
+    Ok(&rest[..length])
+}
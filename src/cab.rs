@@ -0,0 +1,286 @@
+#![allow(dead_code)]
+// Microsoft Cabinet files, as described in [MS-CAB]
+// https://learn.microsoft.com/en-us/openspecs/windows_protocols/ms-cab/
+use std::fs;
+use std::path::Path;
+use crate::bytes::{c_string_at, slice_at, u8_at, u16_at, u32_at};
+use crate::cp437;
+use crate::datetime::DateTime;
+use crate::ziperror::{ZipError, ZipResult};
+
+const SIGNATURE: &[u8; 4] = b"MSCF";
+const HEADER_LENGTH: usize = 36;
+
+const FLAG_PREV_CABINET: u16 = 0x0001;
+const FLAG_NEXT_CABINET: u16 = 0x0002;
+const FLAG_RESERVE_PRESENT: u16 = 0x0004;
+
+/// Set on a file whose name is UTF-8 rather than the system code page
+const ATTRIBUTE_NAME_IS_UTF: u16 = 0x80;
+/// Folder indexes at or above this mean the file continues from or into another cabinet
+const FOLDER_CONTINUED: u16 = 0xFFFD;
+
+/// How a folder's data blocks are compressed (the low four bits of typeCompress)
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CabCompression {
+    None,
+    MsZip,
+    Quantum,
+    Lzx,
+    Unknown(u16)
+}
+
+impl CabCompression {
+    fn from_u16(type_compress: u16) -> CabCompression {
+        match type_compress & 0x000F {
+            0 => CabCompression::None,
+            1 => CabCompression::MsZip,
+            2 => CabCompression::Quantum,
+            3 => CabCompression::Lzx,
+            other => CabCompression::Unknown(other)
+        }
+    }
+}
+
+/// A run of data blocks that files are carved out of. Files in one folder are compressed as a single stream.
+#[derive(Debug, Clone)]
+pub struct CabFolder {
+    pub compression: CabCompression,
+    /// Offset of the first data block
+    data_offset: usize,
+    block_count: u16
+}
+
+#[derive(Debug, Clone)]
+pub struct CabFile {
+    pub name: String,
+    pub size: u64,
+    /// Index into folders()
+    pub folder: u16,
+    /// Where the file starts in its folder's uncompressed data
+    pub folder_offset: u64,
+    pub modified: Option<DateTime>,
+    /// FAT attribute bits (read only, hidden, system, archive, ...)
+    pub attributes: u16
+}
+
+impl CabFile {
+    /// True when part of the file lives in the previous or next cabinet of a set
+    pub fn is_continued(&self) -> bool {
+        self.folder >= FOLDER_CONTINUED
+    }
+}
+
+/// A cabinet held in memory. Only uncompressed folders can be read; MSZIP, Quantum and LZX are listed but not decoded.
+#[derive(Debug, Clone)]
+pub struct CabArchive {
+    data: Vec<u8>,
+    folders: Vec<CabFolder>,
+    files: Vec<CabFile>,
+    /// Bytes reserved at the end of each data block header
+    data_reserve: usize,
+    /// Part of a set spanning several cabinets
+    spanned: bool
+}
+
+/// The data block checksum from [MS-CAB] 2.5, XOR of little-endian words with the odd bytes at the end packed big-endian
+fn checksum(data: &[u8], seed: u32) -> u32 {
+    let mut words = data.chunks_exact(4);
+    let mut sum = seed;
+    for word in words.by_ref() {
+        sum ^= u32::from_le_bytes([word[0], word[1], word[2], word[3]]);
+    }
+    let tail = words.remainder().iter().fold(0u32, |tail, &byte| (tail << 8) | byte as u32);
+    sum ^ tail
+}
+
+impl CabArchive {
+    pub fn open<P: AsRef<Path>>(path: P) -> ZipResult<CabArchive> {
+        CabArchive::parse_bytes(fs::read(path)?)
+    }
+
+    pub fn parse_bytes(data: Vec<u8>) -> ZipResult<CabArchive> {
+        if slice_at(&data, 0, 4)? != SIGNATURE {
+            return Err(ZipError::InvalidArchive("Not a cabinet file (no MSCF signature)".to_string()));
+        }
+        let files_offset = u32_at(&data, 16)? as usize;
+        let folder_count = u16_at(&data, 26)?;
+        let file_count = u16_at(&data, 28)?;
+        let flags = u16_at(&data, 30)?;
+
+        let mut offset = HEADER_LENGTH;
+        let (mut folder_reserve, mut data_reserve) = (0, 0);
+        if flags & FLAG_RESERVE_PRESENT != 0 {
+            let header_reserve = u16_at(&data, offset)? as usize;
+            folder_reserve = u8_at(&data, offset + 2)? as usize;
+            data_reserve = u8_at(&data, offset + 3)? as usize;
+            offset += 4 + header_reserve;
+        }
+        // Names of the neighbouring cabinets and their disks
+        for flag in [FLAG_PREV_CABINET, FLAG_NEXT_CABINET] {
+            if flags & flag != 0 {
+                for _ in 0..2 {
+                    offset += c_string_at(&data, offset)?.len() + 1;
+                }
+            }
+        }
+
+        let mut folders = Vec::with_capacity(folder_count as usize);
+        for _ in 0..folder_count {
+            folders.push(CabFolder {
+                data_offset: u32_at(&data, offset)? as usize,
+                block_count: u16_at(&data, offset + 4)?,
+                compression: CabCompression::from_u16(u16_at(&data, offset + 6)?)
+            });
+            offset += 8 + folder_reserve;
+        }
+
+        let mut files = Vec::with_capacity(file_count as usize);
+        let mut offset = files_offset;
+        for _ in 0..file_count {
+            let attributes = u16_at(&data, offset + 14)?;
+            let raw_name = c_string_at(&data, offset + 16)?;
+            let name = if attributes & ATTRIBUTE_NAME_IS_UTF != 0 {
+                String::from_utf8_lossy(raw_name).into_owned()
+            } else {
+                cp437::decode(raw_name)
+            };
+            files.push(CabFile {
+                size: u32_at(&data, offset)? as u64,
+                folder_offset: u32_at(&data, offset + 4)? as u64,
+                folder: u16_at(&data, offset + 8)?,
+                modified: DateTime::from_dos(u16_at(&data, offset + 10)?, u16_at(&data, offset + 12)?),
+                attributes,
+                name
+            });
+            offset += 16 + raw_name.len() + 1;
+        }
+
+        let spanned = flags & (FLAG_PREV_CABINET | FLAG_NEXT_CABINET) != 0;
+        Ok(CabArchive { data, folders, files, data_reserve, spanned })
+    }
+
+    pub fn files(&self) -> &[CabFile] {
+        &self.files
+    }
+
+    pub fn folders(&self) -> &[CabFolder] {
+        &self.folders
+    }
+
+    pub fn by_name(&self, name: &str) -> Option<&CabFile> {
+        self.files.iter().find(|file| file.name == name)
+    }
+
+    /// A folder's data blocks joined together, checking each block's checksum when it has one
+    fn folder_data(&self, index: u16) -> ZipResult<Vec<u8>> {
+        let folder = self.folders.get(index as usize)
+            .ok_or_else(|| ZipError::InvalidArchive(format!("No folder {} in the cabinet", index)))?;
+        if folder.compression != CabCompression::None {
+            return Err(ZipError::UnsupportedFeature(format!("Cabinet folder {} uses {:?} compression, which is not supported", index, folder.compression)));
+        }
+
+        let mut output = Vec::new();
+        let mut offset = folder.data_offset;
+        for _ in 0..folder.block_count {
+            let stored_sum = u32_at(&self.data, offset)?;
+            let length = u16_at(&self.data, offset + 4)? as usize;
+            let block_start = offset + 8 + self.data_reserve;
+            let block = slice_at(&self.data, block_start, length)?;
+            if stored_sum != 0 {
+                let sum = checksum(slice_at(&self.data, offset + 4, 4)?, checksum(block, 0));
+                if sum != stored_sum {
+                    return Err(ZipError::InvalidArchive(format!("Bad checksum on the cabinet data block at offset {:#X}", offset)));
+                }
+            }
+            output.extend_from_slice(block);
+            offset = block_start + length;
+        }
+        Ok(output)
+    }
+
+    /// A file's contents. Files in compressed folders, or split across cabinets, can't be read.
+    pub fn read(&self, name: &str) -> ZipResult<Vec<u8>> {
+        let file = self.by_name(name).ok_or_else(|| ZipError::FileNotFound(name.to_string()))?;
+        if file.is_continued() || self.spanned {
+            return Err(ZipError::UnsupportedFeature(format!("{} is part of a multi-cabinet set, which is not supported", name)));
+        }
+        let data = self.folder_data(file.folder)?;
+        let start = file.folder_offset as usize;
+        start.checked_add(file.size as usize).and_then(|end| data.get(start..end))
+            .map(<[u8]>::to_vec)
+            .ok_or_else(|| ZipError::InvalidArchive(format!("{} runs past the end of its folder", name)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A cabinet with one data block per folder, each folder given as its typeCompress and contents, and files as
+    /// (name, size, offset in folder, folder)
+    fn cabinet(folders: &[(u16, &[u8])], files: &[(&str, u32, u32, u16)]) -> Vec<u8> {
+        let files_offset = HEADER_LENGTH + folders.len() * 8;
+        let files_length: usize = files.iter().map(|(name, ..)| 16 + name.len() + 1).sum();
+        let mut data_offset = files_offset + files_length;
+
+        let mut header = Vec::new();
+        header.extend_from_slice(SIGNATURE);
+        header.extend_from_slice(&[0; 12]);
+        header.extend_from_slice(&(files_offset as u32).to_le_bytes());
+        header.extend_from_slice(&[0, 0, 0, 0, 3, 1]);
+        header.extend_from_slice(&(folders.len() as u16).to_le_bytes());
+        header.extend_from_slice(&(files.len() as u16).to_le_bytes());
+        header.extend_from_slice(&[0; 6]);
+
+        let mut blocks = Vec::new();
+        for (type_compress, contents) in folders {
+            header.extend_from_slice(&(data_offset as u32).to_le_bytes());
+            header.extend_from_slice(&1u16.to_le_bytes());
+            header.extend_from_slice(&type_compress.to_le_bytes());
+            let lengths = [(contents.len() as u16).to_le_bytes(), (contents.len() as u16).to_le_bytes()].concat();
+            blocks.extend_from_slice(&checksum(&lengths, checksum(contents, 0)).to_le_bytes());
+            blocks.extend_from_slice(&lengths);
+            blocks.extend_from_slice(contents);
+            data_offset += 8 + contents.len();
+        }
+        for (name, size, folder_offset, folder) in files {
+            header.extend_from_slice(&size.to_le_bytes());
+            header.extend_from_slice(&folder_offset.to_le_bytes());
+            header.extend_from_slice(&folder.to_le_bytes());
+            // 2024-03-01 12:00:00, and the archive attribute
+            header.extend_from_slice(&[0x61, 0x58, 0x00, 0x60, 0x20, 0x00]);
+            header.extend_from_slice(name.as_bytes());
+            header.push(0);
+        }
+        header.extend_from_slice(&blocks);
+        header
+    }
+
+    #[test]
+    fn reads_uncompressed_folders() {
+        let data = cabinet(&[(0, b"helloworld"), (1, b"deflated")], &[
+            ("hello.txt", 5, 0, 0),
+            ("world.txt", 5, 5, 0),
+            ("packed.txt", 8, 0, 1)
+        ]);
+        let cab = CabArchive::parse_bytes(data.clone()).unwrap();
+
+        let names: Vec<&str> = cab.files().iter().map(|file| file.name.as_str()).collect();
+        assert_eq!(names, ["hello.txt", "world.txt", "packed.txt"]);
+        assert_eq!(cab.folders()[1].compression, CabCompression::MsZip);
+        assert_eq!(cab.by_name("world.txt").unwrap().attributes, 0x20);
+        assert_eq!(cab.read("hello.txt").unwrap(), b"hello");
+        assert_eq!(cab.read("world.txt").unwrap(), b"world");
+        assert!(matches!(cab.read("packed.txt"), Err(ZipError::UnsupportedFeature(_))));
+        assert!(matches!(cab.read("missing.txt"), Err(ZipError::FileNotFound(_))));
+
+        // Flip a byte of the first folder's data under its checksum
+        let mut corrupt = data;
+        let at = corrupt.windows(10).position(|window| window == b"helloworld").unwrap();
+        corrupt[at] = b'j';
+        let cab = CabArchive::parse_bytes(corrupt).unwrap();
+        assert!(matches!(cab.read("hello.txt"), Err(ZipError::InvalidArchive(_))));
+        assert!(CabArchive::parse_bytes(b"PK\x03\x04".to_vec()).is_err());
+    }
+}
@@ -0,0 +1,195 @@
+#![allow(dead_code)]
+// A cache of catalogs on disk, so opening the same big archive again skips its central directory. Each archive's
+// catalog is kept in a file named after a hash of its canonical path, headed by the archive's size and modification
+// time when the catalog was made: an archive whose size or time has changed since gets its catalog made afresh.
+//   "RIPCACHE", archive size (u64), modification time (u64 nanoseconds since the epoch), path (u32 length, bytes),
+//   then the catalog (see catalog.rs)
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use crate::catalog::Catalog;
+use crate::options::ReaderOptions;
+use crate::xxhash;
+use crate::ziparchive::ZipArchive;
+use crate::ziperror::{ZipError, ZipResult};
+
+const MAGIC: &[u8; 8] = b"RIPCACHE";
+
+/// $RIP_CACHE_DIR if it's set, otherwise catalogs in rip's directory under the user's cache directory
+pub fn default_dir() -> Option<PathBuf> {
+    if let Some(dir) = std::env::var_os("RIP_CACHE_DIR") {
+        return Some(PathBuf::from(dir));
+    }
+    let cache_dir = if cfg!(windows) {
+        std::env::var_os("LOCALAPPDATA").map(PathBuf::from)
+    } else {
+        std::env::var_os("XDG_CACHE_HOME").map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".cache")))
+    };
+    cache_dir.map(|dir| dir.join("rip").join("catalogs"))
+}
+
+/// What identifies an archive's state: its canonical path, size and modification time
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Key {
+    path: String,
+    size: u64,
+    modified: u64
+}
+
+impl Key {
+    fn of(path: &Path) -> io::Result<Key> {
+        let path = fs::canonicalize(path)?;
+        let metadata = fs::metadata(&path)?;
+        let modified = metadata.modified()?.duration_since(UNIX_EPOCH).map_or(0, |since| since.as_nanos() as u64);
+        Ok(Key { path: path.to_string_lossy().into_owned(), size: metadata.len(), modified })
+    }
+
+    fn header(&self) -> Vec<u8> {
+        let mut header = Vec::with_capacity(MAGIC.len() + 20 + self.path.len());
+        header.extend_from_slice(MAGIC);
+        header.extend_from_slice(&self.size.to_le_bytes());
+        header.extend_from_slice(&self.modified.to_le_bytes());
+        header.extend_from_slice(&(self.path.len() as u32).to_le_bytes());
+        header.extend_from_slice(self.path.as_bytes());
+        header
+    }
+}
+
+/// How CatalogCache::open got on with the cache, beside the archive it opened
+#[derive(Debug, Default)]
+pub struct CacheStatus {
+    /// The archive was opened from its cached catalog
+    pub hit: bool,
+    /// Why a cached catalog for the archive as it is now couldn't be used
+    pub rejected: Option<ZipError>,
+    /// Why the archive's catalog couldn't be cached for next time
+    pub not_stored: Option<io::Error>
+}
+
+#[derive(Debug, Clone)]
+pub struct CatalogCache {
+    dir: PathBuf
+}
+
+impl CatalogCache {
+    pub fn new<P: AsRef<Path>>(dir: P) -> CatalogCache {
+        CatalogCache { dir: dir.as_ref().to_path_buf() }
+    }
+
+    /// The cache in default_dir, None when there's nowhere to put one
+    pub fn open_default() -> Option<CatalogCache> {
+        default_dir().map(CatalogCache::new)
+    }
+
+    fn file_for(&self, key: &Key) -> PathBuf {
+        let path = key.path.as_bytes();
+        self.dir.join(format!("{:08x}{:08x}.catalog", xxhash::xxh32(path, 0), xxhash::xxh32(path, 0x9E3779B1)))
+    }
+
+    /// The cached catalog for key, None if there isn't one or it's for the archive as it was before a change
+    fn lookup(&self, key: &Key) -> Option<Catalog> {
+        let cached = fs::read(self.file_for(key)).ok()?;
+        let catalog = cached.strip_prefix(key.header().as_slice())?;
+        Catalog::from_bytes(catalog).ok()
+    }
+
+    fn store(&self, key: &Key, catalog: &Catalog) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let target = self.file_for(key);
+        // Written beside the target and renamed over it, so a reader never sees half a catalog
+        let partial = target.with_extension(format!("{}.partial", std::process::id()));
+        let mut file = File::create(&partial)?;
+        file.write_all(&key.header())?;
+        file.write_all(&catalog.to_bytes())?;
+        drop(file);
+        fs::rename(&partial, &target).inspect_err(|_| {
+            let _ = fs::remove_file(&partial);
+        })
+    }
+
+    /// Opens the archive at filename, from its cached catalog when there's one for it as it is now, otherwise by
+    /// reading its central directory and caching the catalog for next time. A cached catalog that can't be used or
+    /// a cache that can't be written doesn't fail the open, the status says what happened.
+    pub fn open<'a>(&self, filename: &'a str, options: &ReaderOptions) -> ZipResult<(ZipArchive<'a>, CacheStatus)> {
+        let mut status = CacheStatus::default();
+        let key = match Key::of(Path::new(filename)) {
+            Ok(key) => key,
+            Err(_) => return Ok((ZipArchive::with_options(filename, options)?, status))
+        };
+        if let Some(catalog) = self.lookup(&key) {
+            match ZipArchive::with_catalog(filename, &catalog, options) {
+                Ok(archive) => return Ok((archive, CacheStatus { hit: true, ..status })),
+                Err(why) => status.rejected = Some(why)
+            }
+        }
+
+        let archive = ZipArchive::with_options(filename, options)?;
+        status.not_stored = self.store(&key, &archive.export_catalog()).err();
+        Ok((archive, status))
+    }
+
+    /// Removes every cached catalog
+    pub fn clear(&self) -> io::Result<()> {
+        match fs::remove_dir_all(&self.dir) {
+            Err(why) if why.kind() == io::ErrorKind::NotFound => Ok(()),
+            result => result
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use super::*;
+    use crate::zipwriter::{FileOptions, ZipWriter};
+
+    fn write_archive(path: &Path, contents: &str) {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        writer.start_file("a.txt", FileOptions::default()).unwrap();
+        writer.write_all(contents.as_bytes()).unwrap();
+        fs::write(path, writer.finish().unwrap().into_inner()).unwrap();
+    }
+
+    #[test]
+    fn hits_until_the_archive_changes() {
+        let root = std::env::temp_dir().join(format!("rip-cache-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        let path = root.join("archive.zip");
+        let filename = path.to_str().unwrap();
+        let cache = CatalogCache::new(root.join("cache"));
+        let options = ReaderOptions::default();
+        write_archive(&path, "first");
+
+        let (_, first) = cache.open(filename, &options).unwrap();
+        let (archive, second) = cache.open(filename, &options).unwrap();
+        let hit_contents = archive.entries()[0].read().unwrap();
+
+        write_archive(&path, "changed");
+        let (archive, after_change) = cache.open(filename, &options).unwrap();
+        let changed_contents = archive.entries()[0].read().unwrap();
+
+        // A cached catalog under the right key that doesn't fit the archive
+        let key = Key::of(&path).unwrap();
+        let mut catalog = cache.lookup(&key).unwrap();
+        catalog.archive_length += 1;
+        cache.store(&key, &catalog).unwrap();
+        let (_, rejected) = cache.open(filename, &options).unwrap();
+        let (_, repaired) = cache.open(filename, &options).unwrap();
+
+        cache.clear().unwrap();
+        let cleared = !root.join("cache").exists();
+        fs::remove_dir_all(&root).unwrap();
+
+        assert!(!first.hit && first.rejected.is_none() && first.not_stored.is_none());
+        assert!(second.hit);
+        assert_eq!(hit_contents, b"first");
+        assert!(!after_change.hit && after_change.rejected.is_none());
+        assert_eq!(changed_contents, b"changed");
+        assert!(!rejected.hit && matches!(rejected.rejected, Some(ZipError::InvalidArchive(_))));
+        assert!(repaired.hit);
+        assert!(cleared);
+    }
+}
@@ -0,0 +1,289 @@
+#![allow(dead_code)]
+// A catalog is an archive's index kept outside it: what the central directory says about each entry, in a compact
+// form of its own. Opening an archive with one goes straight to the local headers it lists, without searching for the
+// end of central directory record or reading the central directory, which matters for huge archives in cold storage
+// where every seek costs. The layout, all little endian:
+//   "RIPCATLG", format version (u16), archive length (u64), end of central directory record offset (u64, u64::MAX
+//   when there was none), entry count (u64), then for each entry:
+//   header offset, compressed size, uncompressed size (u64 each), crc32 (u32), method, flags, time, date, version made
+//   by, version needed, internal attributes (u16 each), external attributes (u32), then name, extra field and comment
+//   (u16 length and bytes each). Sizes and offsets are always 64 bits, so the extra field leaves out any ZIP64 record.
+use std::convert::TryInto;
+use crate::extrafield;
+use crate::ziparchive::{Cdfhr, ZipArchive};
+use crate::zipentry::ZipEntry;
+use crate::ziperror::{ZipError, ZipResult};
+use crate::zipwriter;
+
+const MAGIC: &[u8; 8] = b"RIPCATLG";
+const VERSION: u16 = 1;
+
+/// What a catalog keeps of one entry
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CatalogEntry {
+    pub header_offset: u64,
+    pub compressed_size: u64,
+    pub uncompressed_size: u64,
+    pub crc32: u32,
+    pub method: u16,
+    pub flags: u16,
+    pub last_modify_time: u16,
+    pub last_modify_date: u16,
+    pub version_made_by: u16,
+    pub version_needed: u16,
+    pub internal_attributes: u16,
+    pub external_attributes: u32,
+    pub name: Vec<u8>,
+    pub extra_field: Vec<u8>,
+    pub comment: Vec<u8>
+}
+
+impl CatalogEntry {
+    fn from_entry(entry: &ZipEntry) -> CatalogEntry {
+        let header = entry.record.static_data;
+        CatalogEntry {
+            header_offset: entry.header_offset(),
+            compressed_size: entry.compressed_size(),
+            uncompressed_size: entry.uncompressed_size(),
+            crc32: header.crc32_uncompressed,
+            method: header.compression_method,
+            flags: header.general_purpose_flag,
+            last_modify_time: header.last_modify_time,
+            last_modify_date: header.last_modify_date,
+            version_made_by: header.version_made_by,
+            version_needed: header.version_needed,
+            internal_attributes: header.internal_file_attributes,
+            external_attributes: header.external_file_attributes,
+            name: entry.record.file_name_data.clone(),
+            extra_field: extrafield::without(&entry.record.extra_field_data, extrafield::ZIP64_EXTENDED_INFO),
+            comment: entry.record.file_comment_data.clone()
+        }
+    }
+
+    /// The central directory record this stands in for, with a ZIP64 record for whatever overflows 32 bits
+    pub(crate) fn to_record(&self) -> Cdfhr {
+        let sizes_overflow = self.compressed_size >= u32::MAX as u64 || self.uncompressed_size >= u32::MAX as u64;
+        let offset_overflows = self.header_offset >= u32::MAX as u64;
+        let mut extra_field = zipwriter::central_zip64_record(
+            Some((self.uncompressed_size, self.compressed_size)).filter(|_| sizes_overflow),
+            Some(self.header_offset).filter(|_| offset_overflows)
+        );
+        extra_field.extend_from_slice(&self.extra_field);
+
+        let mut record = Cdfhr::new();
+        let header = &mut record.static_data;
+        header.magic_number = 0x02014b50;
+        header.version_made_by = self.version_made_by;
+        header.version_needed = self.version_needed;
+        header.general_purpose_flag = self.flags;
+        header.compression_method = self.method;
+        header.last_modify_time = self.last_modify_time;
+        header.last_modify_date = self.last_modify_date;
+        header.crc32_uncompressed = self.crc32;
+        header.compressed_size = if sizes_overflow { u32::MAX } else { self.compressed_size as u32 };
+        header.uncompressed_size = if sizes_overflow { u32::MAX } else { self.uncompressed_size as u32 };
+        header.file_name_length = self.name.len() as u16;
+        header.extra_field_length = extra_field.len() as u16;
+        header.file_comment_length = self.comment.len() as u16;
+        header.internal_file_attributes = self.internal_attributes;
+        header.external_file_attributes = self.external_attributes;
+        header.relative_offset_localheader = self.header_offset.min(u32::MAX as u64) as u32;
+        record.file_name_data = self.name.clone();
+        record.extra_field_data = extra_field;
+        record.file_comment_data = self.comment.clone();
+        record
+    }
+}
+
+/// An archive's index, from ZipArchive::export_catalog, for ZipArchive::open_with_catalog
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Catalog {
+    /// The catalog only fits an archive of exactly this length, anything else has been changed since
+    pub archive_length: u64,
+    /// Where the end of central directory record is, None for an archive recovered from its local headers
+    pub eocd_offset: Option<u64>,
+    pub entries: Vec<CatalogEntry>
+}
+
+/// Reads the fields of a catalog in order, failing on any that run past the end
+struct Fields<'a> {
+    data: &'a [u8]
+}
+
+impl<'a> Fields<'a> {
+    fn take(&mut self, length: usize) -> ZipResult<&'a [u8]> {
+        if self.data.len() < length {
+            return Err(ZipError::InvalidArchive("Catalog ends part way through".to_string()));
+        }
+        let (field, rest) = self.data.split_at(length);
+        self.data = rest;
+        Ok(field)
+    }
+
+    fn u16(&mut self) -> ZipResult<u16> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> ZipResult<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> ZipResult<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn bytes(&mut self) -> ZipResult<Vec<u8>> {
+        let length = self.u16()? as usize;
+        Ok(self.take(length)?.to_vec())
+    }
+}
+
+fn push_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    out.extend_from_slice(&(bytes.len() as u16).to_le_bytes());
+    out.extend_from_slice(bytes);
+}
+
+impl Catalog {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(MAGIC);
+        out.extend_from_slice(&VERSION.to_le_bytes());
+        out.extend_from_slice(&self.archive_length.to_le_bytes());
+        out.extend_from_slice(&self.eocd_offset.unwrap_or(u64::MAX).to_le_bytes());
+        out.extend_from_slice(&(self.entries.len() as u64).to_le_bytes());
+        for entry in &self.entries {
+            for value in [entry.header_offset, entry.compressed_size, entry.uncompressed_size] {
+                out.extend_from_slice(&value.to_le_bytes());
+            }
+            out.extend_from_slice(&entry.crc32.to_le_bytes());
+            for value in [entry.method, entry.flags, entry.last_modify_time, entry.last_modify_date, entry.version_made_by,
+                entry.version_needed, entry.internal_attributes] {
+                out.extend_from_slice(&value.to_le_bytes());
+            }
+            out.extend_from_slice(&entry.external_attributes.to_le_bytes());
+            push_bytes(&mut out, &entry.name);
+            push_bytes(&mut out, &entry.extra_field);
+            push_bytes(&mut out, &entry.comment);
+        }
+        out
+    }
+
+    pub fn from_bytes(data: &[u8]) -> ZipResult<Catalog> {
+        let mut fields = Fields { data };
+        if fields.take(MAGIC.len())? != MAGIC {
+            return Err(ZipError::InvalidArchive("Not a catalog, the signature is wrong".to_string()));
+        }
+        let version = fields.u16()?;
+        if version != VERSION {
+            return Err(ZipError::InvalidArchive(format!("Catalog format version {} isn't supported", version)));
+        }
+        let archive_length = fields.u64()?;
+        let eocd_offset = Some(fields.u64()?).filter(|&offset| offset != u64::MAX);
+        let count = fields.u64()?;
+
+        // Every entry takes at least 52 bytes, which bounds how many a short catalog can claim before reading them
+        let mut entries = Vec::with_capacity(count.min(data.len() as u64 / 52) as usize);
+        for _ in 0..count {
+            entries.push(CatalogEntry {
+                header_offset: fields.u64()?,
+                compressed_size: fields.u64()?,
+                uncompressed_size: fields.u64()?,
+                crc32: fields.u32()?,
+                method: fields.u16()?,
+                flags: fields.u16()?,
+                last_modify_time: fields.u16()?,
+                last_modify_date: fields.u16()?,
+                version_made_by: fields.u16()?,
+                version_needed: fields.u16()?,
+                internal_attributes: fields.u16()?,
+                external_attributes: fields.u32()?,
+                name: fields.bytes()?,
+                extra_field: fields.bytes()?,
+                comment: fields.bytes()?
+            });
+        }
+        if !fields.data.is_empty() {
+            return Err(ZipError::InvalidArchive(format!("{} bytes of junk after the end of the catalog", fields.data.len())));
+        }
+        Ok(Catalog { archive_length, eocd_offset, entries })
+    }
+}
+
+impl ZipArchive<'_> {
+    /// The archive's index, to store somewhere and open it with later through open_with_catalog.
+    /// Broken entries aren't in it.
+    pub fn export_catalog(&self) -> Catalog {
+        Catalog {
+            archive_length: self.archive_length(),
+            eocd_offset: self.eocd_offset(),
+            entries: self.entries().iter().map(CatalogEntry::from_entry).collect()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{self, File};
+    use std::io::{Cursor, Write};
+    use super::*;
+    use crate::options::ReaderOptions;
+    use crate::zipwriter::{FileOptions, ZipWriter};
+
+    #[test]
+    fn round_trips_and_opens_the_archive() {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        writer.set_comment("comment");
+        for (name, contents) in [("a.txt", "first"), ("dir/b.txt", "second")] {
+            writer.start_file(name, FileOptions::default().comment("entry comment")).unwrap();
+            writer.write_all(contents.as_bytes()).unwrap();
+        }
+        let data = writer.finish().unwrap().into_inner();
+        let path = std::env::temp_dir().join(format!("rip-catalog-{}.zip", std::process::id()));
+        fs::write(&path, &data).unwrap();
+
+        let archive = ZipArchive::parse_bytes(&data).unwrap();
+        let catalog = archive.export_catalog();
+        let bytes = catalog.to_bytes();
+        assert_eq!(Catalog::from_bytes(&bytes).unwrap(), catalog);
+        assert_eq!(catalog.archive_length, data.len() as u64);
+        assert_eq!(catalog.eocd_offset, archive.eocd_offset());
+
+        let opened = ZipArchive::open_with_catalog(File::open(&path).unwrap(), &catalog, &ReaderOptions::default());
+        // A catalog whose first entry points at the second's header
+        let mut moved = catalog.clone();
+        moved.entries[0].header_offset = catalog.entries[1].header_offset;
+        let misled = ZipArchive::open_with_catalog(File::open(&path).unwrap(), &moved, &ReaderOptions::default());
+        let shorter = Catalog { archive_length: catalog.archive_length - 1, ..catalog.clone() };
+        let wrong_length = ZipArchive::open_with_catalog(File::open(&path).unwrap(), &shorter, &ReaderOptions::default());
+        fs::remove_file(&path).unwrap();
+
+        let opened = opened.unwrap();
+        let read: Vec<(String, Vec<u8>)> = opened.entries().iter().map(|entry| (entry.name(), entry.read().unwrap())).collect();
+        assert_eq!(read, [("a.txt".to_string(), b"first".to_vec()), ("dir/b.txt".to_string(), b"second".to_vec())]);
+        assert_eq!(opened.comment(), b"comment");
+        assert_eq!(opened.entries()[1].record.file_comment_data, b"entry comment");
+        assert_eq!(opened.export_catalog(), catalog);
+
+        let misled = misled.unwrap();
+        assert_eq!(misled.broken_entries().len(), 1);
+        assert!(matches!(wrong_length, Err(ZipError::InvalidArchive(_))));
+    }
+
+    #[test]
+    fn damaged_catalogs_are_refused() {
+        let catalog = Catalog { archive_length: 100, eocd_offset: None, entries: Vec::new() };
+        let bytes = catalog.to_bytes();
+        assert_eq!(Catalog::from_bytes(&bytes).unwrap(), catalog);
+
+        let mut wrong_magic = bytes.clone();
+        wrong_magic[0] = b'X';
+        let mut wrong_version = bytes.clone();
+        wrong_version[8] = 9;
+        let mut claims_an_entry = bytes.clone();
+        claims_an_entry[26] = 1;
+        let junk = [&bytes[..], b"junk"].concat();
+        for damaged in [wrong_magic, wrong_version, claims_an_entry, junk, bytes[..10].to_vec()] {
+            assert!(matches!(Catalog::from_bytes(&damaged), Err(ZipError::InvalidArchive(_))));
+        }
+    }
+}
@@ -0,0 +1,364 @@
+#![allow(dead_code)]
+// OLE compound files ([MS-CFB]), the container behind MSI packages and old Office documents.
+// Only enough to list the streams and read them back.
+// https://learn.microsoft.com/en-us/openspecs/windows_protocols/ms-cfb/
+use std::fs;
+use std::path::Path;
+use crate::bytes::{slice_at, u16_at, u32_at, u64_at};
+use crate::ziperror::{ZipError, ZipResult};
+
+const SIGNATURE: [u8; 8] = [0xD0, 0xCF, 0x11, 0xE0, 0xA1, 0xB1, 0x1A, 0xE1];
+const HEADER_LENGTH: usize = 512;
+const DIRECTORY_ENTRY_LENGTH: usize = 128;
+/// DIFAT entries held in the header itself
+const HEADER_DIFAT_ENTRIES: usize = 109;
+
+/// Sector numbers at or above this aren't sectors (end of chain, free, FAT, DIFAT)
+const MAX_SECTOR: u32 = 0xFFFFFFFA;
+const NO_STREAM: u32 = 0xFFFFFFFF;
+
+const OBJECT_STORAGE: u8 = 1;
+const OBJECT_STREAM: u8 = 2;
+const OBJECT_ROOT: u8 = 5;
+
+/// A stream in the compound file
+#[derive(Debug, Clone)]
+pub struct CfbStream {
+    /// Storage names and the stream name joined with '/'
+    pub path: String,
+    pub size: u64,
+    start_sector: u32
+}
+
+impl CfbStream {
+    /// The path with MSI's packed stream names decoded, so tables read as "!_Tables", "!File" and so on
+    pub fn msi_path(&self) -> String {
+        self.path.split('/').map(decode_msi_name).collect::<Vec<_>>().join("/")
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CompoundFile {
+    data: Vec<u8>,
+    sector_size: usize,
+    mini_sector_size: usize,
+    /// Streams smaller than this live in the mini stream
+    mini_cutoff: u64,
+    fat: Vec<u32>,
+    mini_fat: Vec<u32>,
+    mini_stream: Vec<u8>,
+    streams: Vec<CfbStream>
+}
+
+/// One of the 64 characters MSI packs two to a UTF-16 code unit
+fn msi_char(value: u32) -> char {
+    match value {
+        0..=9 => (b'0' + value as u8) as char,
+        10..=35 => (b'A' + (value - 10) as u8) as char,
+        36..=61 => (b'a' + (value - 36) as u8) as char,
+        62 => '.',
+        _ => '_'
+    }
+}
+
+/// Decodes an MSI stream name. MSI squeezes names into the 31 characters a compound file allows by packing them
+/// into U+3800 to U+483F, and marks its database tables with U+4840.
+pub fn decode_msi_name(name: &str) -> String {
+    let mut decoded = String::new();
+    for c in name.chars() {
+        match c as u32 {
+            0x3800..=0x47FF => {
+                let packed = c as u32 - 0x3800;
+                decoded.push(msi_char(packed & 0x3F));
+                decoded.push(msi_char((packed >> 6) & 0x3F));
+            },
+            0x4800..=0x483F => decoded.push(msi_char(c as u32 - 0x4800)),
+            0x4840 => decoded.push('!'),
+            _ => decoded.push(c)
+        }
+    }
+    decoded
+}
+
+impl CompoundFile {
+    pub fn open<P: AsRef<Path>>(path: P) -> ZipResult<CompoundFile> {
+        CompoundFile::parse_bytes(fs::read(path)?)
+    }
+
+    pub fn parse_bytes(data: Vec<u8>) -> ZipResult<CompoundFile> {
+        if slice_at(&data, 0, 8)? != SIGNATURE {
+            return Err(ZipError::InvalidArchive("Not a compound file (bad signature)".to_string()));
+        }
+        let sector_shift = u16_at(&data, 0x1E)?;
+        let mini_sector_shift = u16_at(&data, 0x20)?;
+        if !(sector_shift == 9 || sector_shift == 12) || mini_sector_shift != 6 {
+            return Err(ZipError::InvalidArchive(format!("Unexpected compound file sector shifts {} and {}", sector_shift, mini_sector_shift)));
+        }
+
+        let mut file = CompoundFile {
+            sector_size: 1 << sector_shift,
+            mini_sector_size: 1 << mini_sector_shift,
+            mini_cutoff: u32_at(&data, 0x38)? as u64,
+            data,
+            fat: Vec::new(),
+            mini_fat: Vec::new(),
+            mini_stream: Vec::new(),
+            streams: Vec::new()
+        };
+        file.fat = file.load_fat()?;
+        let mini_fat_start = u32_at(&file.data, 0x3C)?;
+        file.mini_fat = words(&file.read_chain(mini_fat_start, None)?);
+
+        let directory = file.read_chain(u32_at(&file.data, 0x30)?, None)?;
+        let size_mask = if file.sector_size == 512 { u32::MAX as u64 } else { u64::MAX };
+        let root = directory_entry(&directory, 0, size_mask)?;
+        if root.object_type != OBJECT_ROOT {
+            return Err(ZipError::InvalidArchive("The compound file directory doesn't start with a root entry".to_string()));
+        }
+        file.mini_stream = file.read_chain(root.start_sector, Some(root.size))?;
+
+        let mut streams = Vec::new();
+        let mut visited = vec![false; directory.len() / DIRECTORY_ENTRY_LENGTH];
+        collect_streams(&directory, root.child, "", size_mask, &mut visited, &mut streams)?;
+        streams.sort_by(|a, b| a.path.cmp(&b.path));
+        file.streams = streams;
+        Ok(file)
+    }
+
+    fn sector(&self, sector: u32) -> ZipResult<&[u8]> {
+        let offset = (sector as usize + 1).checked_mul(self.sector_size)
+            .ok_or_else(|| ZipError::InvalidArchive(format!("Sector {} is out of range", sector)))?;
+        slice_at(&self.data, offset, self.sector_size)
+    }
+
+    /// The FAT's own sectors are listed in the header's DIFAT, then in a chain of DIFAT sectors
+    fn load_fat(&self) -> ZipResult<Vec<u32>> {
+        let mut fat_sectors: Vec<u32> = (0..HEADER_DIFAT_ENTRIES).map(|i| u32_at(&self.data, 0x4C + i * 4))
+            .collect::<ZipResult<_>>()?;
+        let mut difat_sector = u32_at(&self.data, 0x44)?;
+        let mut remaining = u32_at(&self.data, 0x48)?;
+        while difat_sector <= MAX_SECTOR && remaining > 0 {
+            if fat_sectors.len() > self.data.len() / self.sector_size + HEADER_DIFAT_ENTRIES {
+                return Err(ZipError::InvalidArchive("Compound file DIFAT chain loops".to_string()));
+            }
+            let entries = words(self.sector(difat_sector)?);
+            let (next, listed) = entries.split_last().unwrap();
+            fat_sectors.extend_from_slice(listed);
+            difat_sector = *next;
+            remaining -= 1;
+        }
+
+        let mut fat = Vec::new();
+        for sector in fat_sectors.into_iter().filter(|&sector| sector <= MAX_SECTOR) {
+            fat.extend(words(self.sector(sector)?));
+        }
+        Ok(fat)
+    }
+
+    /// Follows a chain of regular sectors, cut to size when it's known. Chains that loop are refused.
+    fn read_chain(&self, start: u32, size: Option<u64>) -> ZipResult<Vec<u8>> {
+        let mut output = Vec::new();
+        let mut sector = start;
+        while sector <= MAX_SECTOR {
+            if output.len() > self.data.len() {
+                return Err(ZipError::InvalidArchive("Compound file sector chain loops".to_string()));
+            }
+            output.extend_from_slice(self.sector(sector)?);
+            sector = *self.fat.get(sector as usize)
+                .ok_or_else(|| ZipError::InvalidArchive(format!("Sector {} isn't in the FAT", sector)))?;
+        }
+        truncate(output, size)
+    }
+
+    fn read_mini_chain(&self, start: u32, size: u64) -> ZipResult<Vec<u8>> {
+        let mut output = Vec::new();
+        let mut sector = start;
+        while sector <= MAX_SECTOR {
+            if output.len() > self.mini_stream.len() {
+                return Err(ZipError::InvalidArchive("Compound file mini sector chain loops".to_string()));
+            }
+            output.extend_from_slice(slice_at(&self.mini_stream, sector as usize * self.mini_sector_size, self.mini_sector_size)?);
+            sector = *self.mini_fat.get(sector as usize)
+                .ok_or_else(|| ZipError::InvalidArchive(format!("Mini sector {} isn't in the mini FAT", sector)))?;
+        }
+        truncate(output, Some(size))
+    }
+
+    /// Every stream, sorted by path. Storages only show up as path components.
+    pub fn streams(&self) -> &[CfbStream] {
+        &self.streams
+    }
+
+    pub fn by_path(&self, path: &str) -> Option<&CfbStream> {
+        self.streams.iter().find(|stream| stream.path == path)
+    }
+
+    pub fn read(&self, path: &str) -> ZipResult<Vec<u8>> {
+        let stream = self.by_path(path).ok_or_else(|| ZipError::FileNotFound(path.to_string()))?;
+        if stream.size < self.mini_cutoff {
+            self.read_mini_chain(stream.start_sector, stream.size)
+        } else {
+            self.read_chain(stream.start_sector, Some(stream.size))
+        }
+    }
+}
+
+fn words(data: &[u8]) -> Vec<u32> {
+    data.chunks_exact(4).map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]])).collect()
+}
+
+fn truncate(mut data: Vec<u8>, size: Option<u64>) -> ZipResult<Vec<u8>> {
+    if let Some(size) = size {
+        if size > data.len() as u64 {
+            return Err(ZipError::InvalidArchive(format!("Compound file stream is {} bytes but its sectors only hold {}", size, data.len())));
+        }
+        data.truncate(size as usize);
+    }
+    Ok(data)
+}
+
+struct DirectoryEntry {
+    name: String,
+    object_type: u8,
+    left: u32,
+    right: u32,
+    child: u32,
+    start_sector: u32,
+    size: u64
+}
+
+/// size_mask is applied to the stream size, as version 3 files may leave junk in its high half
+fn directory_entry(directory: &[u8], index: u32, size_mask: u64) -> ZipResult<DirectoryEntry> {
+    let offset = (index as usize).saturating_mul(DIRECTORY_ENTRY_LENGTH);
+    let entry = slice_at(directory, offset, DIRECTORY_ENTRY_LENGTH)?;
+    // The length counts bytes including the terminating NUL
+    let name_length = (u16_at(entry, 64)? as usize).clamp(2, 64) - 2;
+    let units: Vec<u16> = slice_at(entry, 0, name_length)?.chunks_exact(2).map(|unit| u16::from_le_bytes([unit[0], unit[1]])).collect();
+    Ok(DirectoryEntry {
+        name: String::from_utf16_lossy(&units),
+        object_type: entry[66],
+        left: u32_at(entry, 68)?,
+        right: u32_at(entry, 72)?,
+        child: u32_at(entry, 76)?,
+        start_sector: u32_at(entry, 116)?,
+        size: u64_at(entry, 120)? & size_mask
+    })
+}
+
+/// Walks the red-black tree of siblings under a storage, descending into child storages
+fn collect_streams(directory: &[u8], index: u32, prefix: &str, size_mask: u64, visited: &mut [bool], streams: &mut Vec<CfbStream>) -> ZipResult<()> {
+    if index == NO_STREAM {
+        return Ok(());
+    }
+    match visited.get_mut(index as usize) {
+        Some(seen) if !*seen => *seen = true,
+        _ => return Err(ZipError::InvalidArchive(format!("Compound file directory entry {} is missing or used twice", index)))
+    }
+
+    let entry = directory_entry(directory, index, size_mask)?;
+    collect_streams(directory, entry.left, prefix, size_mask, visited, streams)?;
+    let path = format!("{}{}", prefix, entry.name);
+    match entry.object_type {
+        OBJECT_STREAM => streams.push(CfbStream { path, size: entry.size, start_sector: entry.start_sector }),
+        OBJECT_STORAGE => collect_streams(directory, entry.child, &format!("{}/", path), size_mask, visited, streams)?,
+        _ => {}
+    }
+    collect_streams(directory, entry.right, prefix, size_mask, visited, streams)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const END_OF_CHAIN: u32 = 0xFFFFFFFE;
+    const FREE: u32 = 0xFFFFFFFF;
+
+    fn directory_record(name: &str, object_type: u8, siblings: (u32, u32), child: u32, start: u32, size: u64) -> Vec<u8> {
+        let mut entry = vec![0; DIRECTORY_ENTRY_LENGTH];
+        let units: Vec<u16> = name.encode_utf16().collect();
+        for (i, unit) in units.iter().enumerate() {
+            entry[i * 2..i * 2 + 2].copy_from_slice(&unit.to_le_bytes());
+        }
+        entry[64..66].copy_from_slice(&((units.len() as u16 + 1) * 2).to_le_bytes());
+        entry[66] = object_type;
+        for (at, value) in [(68, siblings.0), (72, siblings.1), (76, child), (116, start)] {
+            entry[at..at + 4].copy_from_slice(&value.to_le_bytes());
+        }
+        entry[120..128].copy_from_slice(&size.to_le_bytes());
+        entry
+    }
+
+    /// A version 3 file: FAT in sector 0, directory in 1, mini FAT in 2, mini stream in 3, then big's sectors.
+    /// Sub/small lives in the mini stream, big in regular sectors.
+    fn compound_file(small: &[u8], big: &[u8]) -> Vec<u8> {
+        let mut header = vec![0; HEADER_LENGTH];
+        header[..8].copy_from_slice(&SIGNATURE);
+        for (at, value) in [(0x18, 0x3Eu16), (0x1A, 3), (0x1C, 0xFFFE), (0x1E, 9), (0x20, 6)] {
+            header[at..at + 2].copy_from_slice(&value.to_le_bytes());
+        }
+        for (at, value) in [(0x2C, 1), (0x30, 1), (0x38, 4096), (0x3C, 2), (0x40, 1), (0x44, END_OF_CHAIN), (0x48, 0)] {
+            header[at..at + 4].copy_from_slice(&value.to_le_bytes());
+        }
+        for i in 0..HEADER_DIFAT_ENTRIES {
+            let sector = if i == 0 { 0 } else { FREE };
+            header[0x4C + i * 4..0x50 + i * 4].copy_from_slice(&sector.to_le_bytes());
+        }
+
+        let big_sectors = big.len().div_ceil(512) as u32;
+        let mut fat = vec![0xFFFFFFFD, END_OF_CHAIN, END_OF_CHAIN, END_OF_CHAIN];
+        fat.extend((5..4 + big_sectors).chain([END_OF_CHAIN]));
+        fat.resize(128, FREE);
+
+        let mini_sectors = small.len().div_ceil(64) as u32;
+        let mut mini_fat: Vec<u32> = (1..mini_sectors).chain([END_OF_CHAIN]).collect();
+        mini_fat.resize(128, FREE);
+
+        let directory = [
+            directory_record("Root Entry", OBJECT_ROOT, (NO_STREAM, NO_STREAM), 1, 3, mini_sectors as u64 * 64),
+            directory_record("big", OBJECT_STREAM, (NO_STREAM, 2), NO_STREAM, 4, big.len() as u64),
+            directory_record("Sub", OBJECT_STORAGE, (NO_STREAM, NO_STREAM), 3, 0, 0),
+            directory_record("small", OBJECT_STREAM, (NO_STREAM, NO_STREAM), NO_STREAM, 0, small.len() as u64)
+        ].concat();
+
+        let padded = |data: &[u8]| {
+            let mut sectors = data.to_vec();
+            sectors.resize(data.len().div_ceil(512).max(1) * 512, 0);
+            sectors
+        };
+        let words = |words: &[u32]| words.iter().flat_map(|word| word.to_le_bytes()).collect::<Vec<u8>>();
+        [header, words(&fat), directory, words(&mini_fat), padded(small), padded(big)].concat()
+    }
+
+    #[test]
+    fn reads_mini_and_regular_streams() {
+        let small = b"kept in the mini stream".repeat(4);
+        let big: Vec<u8> = (0..5000u32).map(|i| i as u8).collect();
+        let file = CompoundFile::parse_bytes(compound_file(&small, &big)).unwrap();
+
+        let paths: Vec<&str> = file.streams().iter().map(|stream| stream.path.as_str()).collect();
+        assert_eq!(paths, ["Sub/small", "big"]);
+        assert_eq!(file.by_path("big").unwrap().size, 5000);
+        assert_eq!(file.read("Sub/small").unwrap(), small);
+        assert_eq!(file.read("big").unwrap(), big);
+        assert!(matches!(file.read("Sub"), Err(ZipError::FileNotFound(_))));
+    }
+
+    #[test]
+    fn directory_loops_are_refused() {
+        let mut data = compound_file(b"small", b"big");
+        // Point big's right sibling back at itself
+        let big_entry = HEADER_LENGTH + 512 + DIRECTORY_ENTRY_LENGTH;
+        data[big_entry + 72..big_entry + 76].copy_from_slice(&1u32.to_le_bytes());
+        assert!(matches!(CompoundFile::parse_bytes(data), Err(ZipError::InvalidArchive(_))));
+        assert!(CompoundFile::parse_bytes(b"PK\x03\x04".to_vec()).is_err());
+    }
+
+    #[test]
+    fn msi_names_are_unpacked() {
+        let pack = |low: u32, high: u32| char::from_u32(0x3800 + low + (high << 6)).unwrap();
+        // "!_Tables": the table mark, then "_T", "ab", "le" two to a character and "s" on its own
+        let packed: String = ['\u{4840}', pack(63, 29), pack(36, 37), pack(47, 40), char::from_u32(0x4800 + 54).unwrap()].iter().collect();
+        assert_eq!(decode_msi_name(&packed), "!_Tables");
+        assert_eq!(decode_msi_name("\u{5}SummaryInformation"), "\u{5}SummaryInformation");
+    }
+}
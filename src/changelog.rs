@@ -0,0 +1,85 @@
+#![allow(dead_code)]
+// A log of who changed an archive, when and how, kept at the end of the archive comment so it travels with the
+// file and any zip tool still shows it. The block starts at a marker line and has one tab separated line per change:
+//   -- rip change log --
+//   <unix seconds>	<who>	<what>
+// Anything before the marker is the comment proper and is left alone.
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const MARKER: &[u8] = b"-- rip change log --\n";
+
+/// One modification made to an archive
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChangeRecord {
+    /// Seconds since the Unix epoch
+    pub when: u64,
+    pub who: String,
+    pub what: String
+}
+
+impl ChangeRecord {
+    /// A change made now by the current user ($USER, or %USERNAME% on Windows)
+    pub fn new(what: &str) -> ChangeRecord {
+        let who = std::env::var("USER").or_else(|_| std::env::var("USERNAME")).unwrap_or_else(|_| "unknown".to_string());
+        let when = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |since| since.as_secs());
+        ChangeRecord { when, who, what: what.to_string() }
+    }
+
+    fn to_line(&self) -> String {
+        // Tabs and newlines would split the record, so they become spaces
+        let clean = |text: &str| text.replace(['\t', '\n', '\r'], " ");
+        format!("{}\t{}\t{}\n", self.when, clean(&self.who), clean(&self.what))
+    }
+
+    fn from_line(line: &str) -> Option<ChangeRecord> {
+        let mut fields = line.splitn(3, '\t');
+        let when = fields.next()?.parse().ok()?;
+        let who = fields.next()?.to_string();
+        let what = fields.next()?.to_string();
+        Some(ChangeRecord { when, who, what })
+    }
+}
+
+/// Where the log block starts in comment, if it has one
+fn log_start(comment: &[u8]) -> Option<usize> {
+    if comment.starts_with(MARKER) {
+        return Some(0);
+    }
+    comment.windows(MARKER.len() + 1).rposition(|window| window[0] == b'\n' && &window[1..] == MARKER).map(|at| at + 1)
+}
+
+/// The comment without any log block, or the newline append put before it
+pub fn strip(comment: &[u8]) -> &[u8] {
+    match log_start(comment) {
+        Some(start) => &comment[..start.saturating_sub(1)],
+        None => comment
+    }
+}
+
+/// The changes logged in comment, oldest first. Lines that don't parse are skipped.
+pub fn parse(comment: &[u8]) -> Vec<ChangeRecord> {
+    let Some(start) = log_start(comment) else {
+        return Vec::new();
+    };
+    String::from_utf8_lossy(&comment[start + MARKER.len()..]).lines().filter_map(ChangeRecord::from_line).collect()
+}
+
+/// comment with changes added to the end of its log, starting one if there isn't one yet
+pub fn append(comment: &[u8], changes: &[ChangeRecord]) -> Vec<u8> {
+    let mut appended = comment.to_vec();
+    if changes.is_empty() {
+        return appended;
+    }
+    if log_start(comment).is_none() {
+        if !appended.is_empty() && !appended.ends_with(b"\n") {
+            appended.push(b'\n');
+        }
+        appended.extend_from_slice(MARKER);
+    } else if !appended.ends_with(b"\n") {
+        appended.push(b'\n');
+    }
+    for change in changes {
+        appended.extend_from_slice(change.to_line().as_bytes());
+    }
+    appended
+}
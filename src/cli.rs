@@ -0,0 +1,633 @@
+#![allow(dead_code)]
+// The rip command line. Each subcommand takes the arguments after its name and returns the exit code.
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, IsTerminal, Read, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use rip::cache::CatalogCache;
+use rip::extract::{ConflictAction, ExtractOptions};
+use rip::lz4::{Lz4Decoder, Lz4Encoder};
+use rip::options::{ReaderOptions, WriterOptions};
+use rip::search::{self, SearchOptions};
+use rip::walk;
+use rip::ziparchive::ZipArchive;
+use rip::ziperror::{ZipError, ZipResult};
+use rip::zipwriter::ZipWriter;
+use crate::bench::{self, BenchOptions};
+use crate::completions;
+use crate::config::{Config, Settings};
+use crate::progress::{self, Progress, Verbosity};
+
+const USAGE: &str = "Usage:
+    rip list [--types | --names] [--escaped] [--cache] ARCHIVE
+    rip extract [-d DEST] [-o | -n] ARCHIVE [ENTRY...]
+    rip create [--profile NAME] ARCHIVE [PATH...]
+    rip grep [-i] [-j THREADS] [--profile NAME] PATTERN ARCHIVE [NAME_GLOB]
+    rip compress INPUT [OUTPUT]
+    rip decompress INPUT [OUTPUT]
+    rip completions bash|zsh|fish
+    rip bench [--entries N] [--size BYTES] [--compressibility PERCENT] [-j THREADS] [--buffer BYTES]
+    rip selftest
+
+An ARCHIVE of - reads the archive from stdin, or for create writes it to stdout. create with no PATHs
+reads them from stdin, one per line.
+-q, -v and -vv anywhere on the line print less or more than usual.
+Defaults for create and grep come from ~/.config/rip/config.toml ($RIP_CONFIG), --profile picks a [profile.NAME] in it.
+list --cache (or cache = true in the config) keeps archives' catalogs in ~/.cache/rip/catalogs ($RIP_CACHE_DIR),
+so listing a big archive again doesn't read its central directory. list --escaped writes bytes in names that aren't
+printable UTF-8 as \\xNN and backslashes as \\\\, so every name is one line that scripts can read back exactly.";
+
+pub fn run(args: &[String]) -> i32 {
+    let (verbosity, args) = take_verbosity(args);
+    match args.first().map(String::as_str) {
+        Some("list") => list(&args[1..]),
+        Some("extract") => extract(&args[1..], verbosity),
+        Some("create") => create(&args[1..], verbosity),
+        Some("grep") => grep(&args[1..]),
+        Some("compress") => compress(&args[1..]),
+        Some("decompress") => decompress(&args[1..]),
+        Some("completions") => print_completions(&args[1..]),
+        Some("bench") => run_bench(&args[1..]),
+        Some("selftest") => run_selftest(&args[1..]),
+        _ => usage_error()
+    }
+}
+
+fn usage_error() -> i32 {
+    eprintln!("{}", USAGE);
+    2
+}
+
+/// Pulls "-x" style flags off the front of args, passing each flag and the remaining args to handle.
+/// handle returns how many values the flag used, or None if it doesn't know the flag.
+fn take_flags<'a>(mut args: &'a [String], mut handle: impl FnMut(&str, &'a [String]) -> Option<usize>) -> Option<&'a [String]> {
+    while let Some(flag) = args.first().filter(|arg| arg.starts_with('-') && arg.len() > 1) {
+        let used = handle(flag, &args[1..])?;
+        if args.len() < 1 + used {
+            return None;
+        }
+        args = &args[1 + used..];
+    }
+    Some(args)
+}
+
+/// Pulls -q, -v and -vv out from wherever they are in args. The last one given wins.
+fn take_verbosity(args: &[String]) -> (Verbosity, Vec<String>) {
+    let mut verbosity = Verbosity::Normal;
+    let mut rest = Vec::with_capacity(args.len());
+    for arg in args {
+        match arg.as_str() {
+            "-q" | "--quiet" => verbosity = Verbosity::Quiet,
+            "-v" | "--verbose" => verbosity = Verbosity::Verbose,
+            "-vv" => verbosity = Verbosity::Debug,
+            _ => rest.push(arg.clone())
+        }
+    }
+    (verbosity, rest)
+}
+
+/// The config file's settings with profile on top, or None after saying what's wrong with them
+fn load_settings(profile: Option<&str>) -> Option<Settings> {
+    let settings = Config::load_default().map_err(|why| format!("couldn't read the config: {}", why))
+        .and_then(|config| config.settings(profile));
+    match settings {
+        Ok(settings) => Some(settings),
+        Err(why) => {
+            eprintln!("rip: {}", why);
+            None
+        }
+    }
+}
+
+fn open(path: &str) -> Option<ZipArchive<'_>> {
+    open_with(path, &ReaderOptions::default())
+}
+
+/// Opens path, or with "-" reads the whole archive from stdin (a pipe can't be seeked, and archives are held in
+/// memory once open anyway)
+fn open_with<'a>(path: &'a str, options: &ReaderOptions) -> Option<ZipArchive<'a>> {
+    let opened = if path == "-" {
+        let mut data = Vec::new();
+        io::stdin().lock().read_to_end(&mut data).map_err(ZipError::from).and_then(|_| ZipArchive::parse_bytes_with_options(data, options))
+    } else {
+        ZipArchive::with_options(path, options)
+    };
+    match opened {
+        Ok(archive) => Some(archive),
+        Err(why) => {
+            eprintln!("rip: couldn't open {}: {}", path, why);
+            None
+        }
+    }
+}
+
+/// Prints each entry's size and name. With --types the sniffed type goes in between, and entries whose extension
+/// doesn't fit their contents are flagged. --names prints just the names, for shell completion to read. --escaped
+/// prints names escaped rather than with broken bytes replaced.
+fn list(args: &[String]) -> i32 {
+    let mut types = false;
+    let mut names_only = false;
+    let mut escaped = false;
+    let mut cache = false;
+    let rest = take_flags(args, |flag, _| match flag {
+        "--types" => {
+            types = true;
+            Some(0)
+        },
+        "--names" => {
+            names_only = true;
+            Some(0)
+        },
+        "--escaped" => {
+            escaped = true;
+            Some(0)
+        },
+        "--cache" => {
+            cache = true;
+            Some(0)
+        },
+        _ => None
+    });
+    let path = match rest {
+        Some([path]) => path,
+        _ => return usage_error()
+    };
+    let settings = match load_settings(None) {
+        Some(settings) => settings,
+        None => return 2
+    };
+
+    let cache = (cache || settings.cache == Some(true)).then(CatalogCache::open_default).flatten().filter(|_| path != "-");
+    let archive = match cache {
+        Some(cache) => match cache.open(path, &ReaderOptions::default()) {
+            Ok((archive, status)) => {
+                if let Some(why) = status.rejected {
+                    eprintln!("rip: ignoring the cached catalog for {}: {}", path, why);
+                }
+                if let Some(why) = status.not_stored {
+                    eprintln!("rip: couldn't cache the catalog for {}: {}", path, why);
+                }
+                Some(archive)
+            },
+            Err(why) => {
+                eprintln!("rip: couldn't open {}: {}", path, why);
+                None
+            }
+        },
+        None => open(path)
+    };
+    let archive = match archive {
+        Some(archive) => archive,
+        None => return 2
+    };
+    for entry in archive.entries() {
+        let name = entry.name();
+        let shown = if escaped { entry.name_escaped() } else { name.clone() };
+        if names_only {
+            println!("{}", shown);
+            continue;
+        }
+        if !types || entry.is_dir() {
+            println!("{:>10}  {}", entry.uncompressed_size(), shown);
+            continue;
+        }
+        match entry.sniff_type() {
+            Ok(file_type) => {
+                let mislabeled = if file_type.matches_name(&name) == Some(false) { "  (extension doesn't match)" } else { "" };
+                println!("{:>10}  {:<28}  {}{}", entry.uncompressed_size(), file_type.mime, shown, mislabeled);
+            },
+            Err(why) => println!("{:>10}  {:<28}  {}  ({})", entry.uncompressed_size(), "?", shown, why)
+        }
+    }
+    0
+}
+
+/// Whether entry_name is one of the ENTRY arguments, or inside a directory that is. No arguments selects everything.
+fn selected(selection: &[String], entry_name: &str) -> bool {
+    selection.is_empty() || selection.iter().any(|wanted| {
+        let directory = wanted.trim_end_matches('/');
+        entry_name == wanted || entry_name.strip_prefix(directory).is_some_and(|rest| rest.starts_with('/'))
+    })
+}
+
+/// Asks on the terminal what to do about output that's already there, like unzip does. All and None answer for
+/// every conflict after this one too.
+fn ask_about_conflict(output: &Path, progress: &Progress, remembered: &Mutex<Option<ConflictAction>>) -> ConflictAction {
+    let mut remembered = remembered.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    if let Some(action) = remembered.as_ref() {
+        return action.clone();
+    }
+    progress.finish();
+    let mut stdin = io::stdin().lock();
+    loop {
+        eprint!("replace {}? [y]es, [n]o, [A]ll, [N]one, [r]ename: ", output.display());
+        let mut answer = String::new();
+        if stdin.read_line(&mut answer).unwrap_or(0) == 0 {
+            // Nobody left to ask, so leave everything that's there alone
+            *remembered = Some(ConflictAction::Skip);
+            return ConflictAction::Skip;
+        }
+        match answer.trim() {
+            "y" | "yes" => return ConflictAction::Overwrite,
+            "n" | "no" => return ConflictAction::Skip,
+            "A" | "All" => {
+                *remembered = Some(ConflictAction::Overwrite);
+                return ConflictAction::Overwrite;
+            },
+            "N" | "None" => {
+                *remembered = Some(ConflictAction::Skip);
+                return ConflictAction::Skip;
+            },
+            "r" | "rename" => {
+                eprint!("new name: ");
+                let mut new_name = String::new();
+                if stdin.read_line(&mut new_name).is_ok() && !new_name.trim().is_empty() {
+                    return ConflictAction::Rename(new_name.trim().to_string());
+                }
+            },
+            _ => {}
+        }
+    }
+}
+
+/// Extracts the ENTRYs given (directories with everything in them), or the whole archive, into DEST or the current
+/// directory, with a progress bar on the way. Files already there are overwritten with -o and kept with -n; without
+/// either the user is asked, or if there's no terminal to ask on they're kept. Carries on past entries that fail, or
+/// ENTRYs that aren't in the archive, exiting 1 if there were any.
+fn extract(args: &[String], verbosity: Verbosity) -> i32 {
+    let mut dest = ".".to_string();
+    let mut conflicts = None;
+    let rest = take_flags(args, |flag, values| match flag {
+        "-d" => {
+            dest = values.first()?.clone();
+            Some(1)
+        },
+        "-o" => {
+            conflicts = Some(ConflictAction::Overwrite);
+            Some(0)
+        },
+        "-n" => {
+            conflicts = Some(ConflictAction::Skip);
+            Some(0)
+        },
+        _ => None
+    });
+    let (path, selection) = match rest {
+        Some([path, selection @ ..]) => (path.as_str(), selection.to_vec()),
+        _ => return usage_error()
+    };
+    let dest = dest.as_str();
+
+    let progress = Arc::new(Progress::new(verbosity));
+    let reader_options = ReaderOptions { metrics: Some(progress.clone()), ..ReaderOptions::default() };
+    let archive = match open_with(path, &reader_options) {
+        Some(archive) => archive,
+        None => return 2
+    };
+    if verbosity >= Verbosity::Verbose {
+        for warning in archive.warnings() {
+            eprintln!("rip: {}: {}", path, warning);
+        }
+    }
+    let mut unmatched = 0;
+    for wanted in &selection {
+        if !archive.entries().iter().any(|entry| selected(std::slice::from_ref(wanted), &entry.name())) {
+            eprintln!("rip: no entry named {} in {}", wanted, path);
+            unmatched += 1;
+        }
+    }
+    let selected_count = archive.entries().iter().filter(|entry| selected(&selection, &entry.name())).count();
+    progress.set_total(selected_count, archive.extracted_size(|entry| selected(&selection, &entry.name())).data_bytes);
+
+    let started = Instant::now();
+    let mut options = ExtractOptions { continue_on_error: true, ..ExtractOptions::default() };
+    options = match conflicts {
+        Some(ConflictAction::Overwrite) => options,
+        Some(_) => options.on_conflict(|_, _| ConflictAction::Skip),
+        None if io::stdin().is_terminal() => {
+            let progress = progress.clone();
+            let remembered = Mutex::new(None);
+            options.on_conflict(move |_, output| ask_about_conflict(output, &progress, &remembered))
+        },
+        None => {
+            let progress = progress.clone();
+            options.on_conflict(move |_, output| {
+                if verbosity > Verbosity::Quiet {
+                    progress.println(&format!("rip: {} is already there, keeping it (-o overwrites)", output.display()));
+                }
+                ConflictAction::Skip
+            })
+        }
+    };
+    let everything = selection.is_empty();
+    if !everything {
+        options = options.rename(move |name| Some(name.to_string()).filter(|name| selected(&selection, name)));
+    }
+    let result = archive.extract_all(dest, &options);
+    progress.finish();
+    let report = match result {
+        Ok(report) => report,
+        Err(why) => {
+            eprintln!("rip: couldn't extract {}: {}", path, why);
+            return 2;
+        }
+    };
+
+    for failure in &report.failures {
+        eprintln!("rip: {}: {}", failure.name, failure.error);
+    }
+    // Everything left out of a selection counts as skipped, which isn't news
+    if verbosity >= Verbosity::Verbose && everything {
+        for skipped in &report.skipped {
+            eprintln!("skipped {}", skipped);
+        }
+    }
+    if verbosity > Verbosity::Quiet {
+        let extracted: HashSet<&str> = report.extracted.iter().map(String::as_str).collect();
+        let bytes = archive.entries().iter().filter(|entry| extracted.contains(entry.name().as_str())).map(|entry| entry.uncompressed_size()).sum();
+        eprintln!("Extracted {} of {} entries ({}) to {} in {:.1?}", report.extracted.len(), selected_count,
+            progress::human_bytes(bytes), dest, started.elapsed());
+    }
+    if report.failures.is_empty() && unmatched == 0 { 0 } else { 1 }
+}
+
+/// Prints the completion script for a shell, to be sourced from its startup file
+fn print_completions(args: &[String]) -> i32 {
+    let shell = match args {
+        [shell] => shell,
+        _ => return usage_error()
+    };
+    match completions::script(shell) {
+        Some(script) => {
+            print!("{}", script);
+            0
+        },
+        None => {
+            eprintln!("rip: no completions for {}, only bash, zsh and fish", shell);
+            2
+        }
+    }
+}
+
+/// The archive name for a path given on the command line: '/' separated, without a leading / or ./
+fn entry_name(path: &str) -> String {
+    let name = path.replace('\\', "/");
+    let mut name = name.as_str();
+    loop {
+        match name.strip_prefix("./").or_else(|| name.strip_prefix('/')) {
+            Some(rest) => name = rest,
+            None => return name.to_string()
+        }
+    }
+}
+
+/// Adds path to the archive, and everything under it if it's a directory
+fn add_path<W: Write>(writer: &mut ZipWriter<W>, path: &str, settings: &Settings) -> ZipResult<()> {
+    let options = settings.file_options();
+    let name = entry_name(path);
+    if name.is_empty() || name.split('/').any(|part| part == "..") {
+        return Err(ZipError::UnsafePath(path.to_string()));
+    }
+    if !Path::new(path).is_dir() {
+        writer.start_file(&name, options)?;
+        io::copy(&mut File::open(path)?, writer)?;
+        return Ok(());
+    }
+
+    let name = name.trim_end_matches('/');
+    writer.add_directory(name, options.clone())?;
+    let walked = walk::walk_dir(Path::new(path), &settings.walk_options())?;
+    for skipped in &walked.skipped {
+        eprintln!("rip: {}", skipped);
+    }
+    for found in walked.entries {
+        let found_name = format!("{}/{}", name, found.name);
+        if found.is_dir {
+            writer.add_directory(&found_name, options.clone())?;
+        } else {
+            writer.start_file(&found_name, options.clone())?;
+            io::copy(&mut File::open(&found.path)?, writer)?;
+        }
+    }
+    Ok(())
+}
+
+fn write_archive<W: Write>(output: W, paths: &[String], settings: &Settings, progress: Arc<Progress>) -> ZipResult<W> {
+    let mut writer = ZipWriter::with_options(output, &WriterOptions { metrics: Some(progress.clone()), ..WriterOptions::default() });
+    for path in paths {
+        add_path(&mut writer, path, settings).map_err(|why| match why {
+            ZipError::Io(why) => ZipError::Io(io::Error::new(why.kind(), format!("{}: {}", path, why))),
+            why => why
+        })?;
+    }
+    writer.finish_entry()?;
+    if progress.verbosity() >= Verbosity::Verbose {
+        for (added, earlier) in writer.duplicates() {
+            progress.println(&format!("rip: {} has the same contents as {}", added, earlier));
+        }
+    }
+    writer.finish()
+}
+
+/// Stores the PATHs (directories with everything in them) in a new archive. ARCHIVE - writes to stdout, and
+/// without PATHs they're read from stdin, so `find . -name '*.txt' | rip create - > texts.zip` works without
+/// temp files. Stdout is written straight through, it never needs seeking.
+fn create(args: &[String], verbosity: Verbosity) -> i32 {
+    let mut profile = None;
+    let rest = take_flags(args, |flag, values| match flag {
+        "--profile" => {
+            profile = Some(values.first()?.clone());
+            Some(1)
+        },
+        _ => None
+    });
+    let (path, paths) = match rest {
+        Some([path, paths @ ..]) => (path.as_str(), paths.to_vec()),
+        _ => return usage_error()
+    };
+    let settings = match load_settings(profile.as_deref()) {
+        Some(settings) => settings,
+        None => return 2
+    };
+    let paths = if paths.is_empty() {
+        match io::stdin().lock().lines().collect::<io::Result<Vec<String>>>() {
+            Ok(lines) => lines.into_iter().filter(|line| !line.trim().is_empty()).collect(),
+            Err(why) => {
+                eprintln!("rip: couldn't read the paths to add from stdin: {}", why);
+                return 2;
+            }
+        }
+    } else {
+        paths
+    };
+
+    let progress = Arc::new(Progress::new(verbosity));
+    let started = Instant::now();
+    let result = if path == "-" {
+        write_archive(BufWriter::new(io::stdout().lock()), &paths, &settings, progress.clone()).and_then(|mut out| Ok(out.flush()?))
+    } else {
+        File::create(path).map_err(ZipError::from).and_then(|file| write_archive(BufWriter::new(file), &paths, &settings, progress.clone()))
+            .and_then(|mut out| Ok(out.flush()?))
+    };
+    progress.finish();
+    match result {
+        Ok(()) => {
+            if verbosity > Verbosity::Quiet {
+                let shown = if path == "-" { "the archive on stdout" } else { path };
+                eprintln!("Created {} from {} paths in {:.1?}", shown, paths.len(), started.elapsed());
+            }
+            0
+        },
+        Err(why) => {
+            eprintln!("rip: couldn't create {}: {}", path, why);
+            2
+        }
+    }
+}
+
+/// Prints matching lines as name:line:text. Exits 0 if anything matched, 1 if nothing did, like grep.
+fn grep(args: &[String]) -> i32 {
+    let mut ignore_case = false;
+    let mut threads = None;
+    let mut profile = None;
+    let rest = take_flags(args, |flag, values| match flag {
+        "-i" => {
+            ignore_case = true;
+            Some(0)
+        },
+        "-j" => {
+            threads = Some(values.first()?.parse().ok()?);
+            Some(1)
+        },
+        "--profile" => {
+            profile = Some(values.first()?.clone());
+            Some(1)
+        },
+        _ => None
+    });
+    let (pattern, path, name_filter) = match rest {
+        Some([pattern, path]) => (pattern, path, None),
+        Some([pattern, path, name_filter]) => (pattern, path, Some(name_filter.clone())),
+        _ => return usage_error()
+    };
+    let threads = match threads {
+        Some(threads) => threads,
+        None => match load_settings(profile.as_deref()) {
+            Some(settings) => settings.threads.unwrap_or(1),
+            None => return 2
+        }
+    };
+
+    let archive = match open(path) {
+        Some(archive) => archive,
+        None => return 2
+    };
+    let options = SearchOptions { name_filter, threads };
+    let matches = archive.search(&options, &search::literal(pattern, ignore_case));
+    for found in &matches {
+        println!("{}:{}:{}", found.entry, found.line_number, found.line);
+    }
+    if matches.is_empty() { 1 } else { 0 }
+}
+
+/// Copies input to output through the given wrappers, reporting failures the same way for both directions
+fn convert(input: &str, output: &str, run: impl FnOnce(File, File) -> io::Result<u64>) -> i32 {
+    let files = File::open(input).and_then(|reader| Ok((reader, File::create(output)?)));
+    match files.and_then(|(reader, writer)| run(reader, writer)) {
+        Ok(_) => 0,
+        Err(why) => {
+            eprintln!("rip: {} -> {}: {}", input, output, why);
+            2
+        }
+    }
+}
+
+/// Compresses a file to an LZ4 frame, written to INPUT.lz4 unless OUTPUT is given
+fn compress(args: &[String]) -> i32 {
+    let (input, output) = match args {
+        [input] => (input, format!("{}.lz4", input)),
+        [input, output] => (input, output.clone()),
+        _ => return usage_error()
+    };
+    convert(input, &output, |reader, writer| {
+        let mut encoder = Lz4Encoder::new(BufWriter::new(writer));
+        let copied = io::copy(&mut BufReader::new(reader), &mut encoder)?;
+        encoder.finish()?;
+        Ok(copied)
+    })
+}
+
+/// Decompresses an LZ4 file, written to INPUT without its .lz4 unless OUTPUT is given
+fn decompress(args: &[String]) -> i32 {
+    let (input, output) = match args {
+        [input] => match input.strip_suffix(".lz4") {
+            Some(stem) if !stem.is_empty() => (input, stem.to_string()),
+            _ => {
+                eprintln!("rip: {} doesn't end in .lz4, give an output name", input);
+                return 2;
+            }
+        },
+        [input, output] => (input, output.clone()),
+        _ => return usage_error()
+    };
+    convert(input, &output, |reader, writer| {
+        io::copy(&mut Lz4Decoder::new(BufReader::new(reader)), &mut BufWriter::new(writer))
+    })
+}
+
+/// Times creating, listing, extracting and searching a synthetic archive, for tuning thread and buffer sizes.
+fn run_bench(args: &[String]) -> i32 {
+    let mut options = BenchOptions::default();
+    let rest = take_flags(args, |flag, values| {
+        let value = values.first()?;
+        match flag {
+            "--entries" => options.entries = value.parse().ok()?,
+            "--size" => options.entry_size = value.parse().ok()?,
+            "--compressibility" => options.compressibility = value.parse().ok().filter(|&percent| percent <= 100)?,
+            "-j" => options.threads = value.parse().ok()?,
+            "--buffer" => options.io_buffer = value.parse().ok().filter(|&size| size > 0)?,
+            _ => return None
+        }
+        Some(1)
+    });
+    if rest != Some(&[]) {
+        return usage_error();
+    }
+
+    println!("{} entries of {} ({}% compressible), {} search threads, {} buffers",
+        options.entries, progress::human_bytes(options.entry_size as u64), options.compressibility, options.threads,
+        progress::human_bytes(options.io_buffer as u64));
+    match bench::run(&options) {
+        Ok(results) => {
+            for result in results {
+                println!("{:<8} {:>10.1?}  {:>12}/s  {:>10.0} entries/s", result.phase, result.elapsed,
+                    progress::human_bytes(result.bytes_per_second() as u64), result.entries_per_second());
+            }
+            0
+        },
+        Err(why) => {
+            eprintln!("rip: benchmark failed: {}", why);
+            2
+        }
+    }
+}
+
+/// Runs the round trip checks, printing each one's result. Exits 1 if any failed.
+fn run_selftest(args: &[String]) -> i32 {
+    if !args.is_empty() {
+        return usage_error();
+    }
+    let mut failed = 0;
+    for (check, result) in bench::selftest() {
+        match result {
+            Ok(()) => println!("ok    {}", check),
+            Err(why) => {
+                println!("FAIL  {}: {}", check, why);
+                failed += 1;
+            }
+        }
+    }
+    if failed == 0 { 0 } else { 1 }
+}
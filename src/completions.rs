@@ -0,0 +1,115 @@
+#![allow(dead_code)]
+// Shell completion scripts for the rip command line, printed by `rip completions SHELL`.
+// Besides subcommands and files, they complete the names of entries inside the archive given to extract, which
+// they get from `rip list --names`.
+
+const BASH: &str = r#"_rip() {
+    local cur=${COMP_WORDS[COMP_CWORD]}
+    if [[ $COMP_CWORD -eq 1 ]]; then
+        COMPREPLY=($(compgen -W "list extract create grep compress decompress completions" -- "$cur"))
+        return
+    fi
+    case ${COMP_WORDS[1]} in
+        extract)
+            local i archive=
+            for ((i = 2; i < COMP_CWORD; i++)); do
+                case ${COMP_WORDS[i]} in
+                    -d) ((i++)) ;;
+                    -*) ;;
+                    *) archive=${COMP_WORDS[i]}; break ;;
+                esac
+            done
+            if [[ ${COMP_WORDS[COMP_CWORD-1]} == -d ]]; then
+                compopt -o filenames 2>/dev/null
+                COMPREPLY=($(compgen -d -- "$cur"))
+            elif [[ -n $archive && -f $archive ]]; then
+                local IFS=$'\n'
+                COMPREPLY=($(compgen -W "$("${COMP_WORDS[0]}" list --names "$archive" 2>/dev/null)" -- "$cur"))
+            else
+                compopt -o filenames 2>/dev/null
+                COMPREPLY=($(compgen -f -- "$cur"))
+            fi ;;
+        completions)
+            COMPREPLY=($(compgen -W "bash zsh fish" -- "$cur")) ;;
+        *)
+            compopt -o filenames 2>/dev/null
+            COMPREPLY=($(compgen -f -- "$cur")) ;;
+    esac
+}
+complete -F _rip rip
+"#;
+
+const ZSH: &str = r#"#compdef rip
+_rip() {
+    local -a subcommands
+    subcommands=(
+        'list:list the entries in an archive'
+        'extract:extract an archive, or some of its entries'
+        'create:create an archive'
+        'grep:search the entries in an archive'
+        'compress:compress a file to LZ4'
+        'decompress:decompress an LZ4 file'
+        'completions:print a shell completion script'
+    )
+    if (( CURRENT == 2 )); then
+        _describe 'subcommand' subcommands
+        return
+    fi
+    case $words[2] in
+        extract)
+            local i archive
+            for (( i = 3; i < CURRENT; i++ )); do
+                case $words[i] in
+                    -d) (( i++ )) ;;
+                    -*) ;;
+                    *) archive=$words[i]; break ;;
+                esac
+            done
+            if [[ $words[CURRENT-1] == -d ]]; then
+                _directories
+            elif [[ -n $archive && -f $archive ]]; then
+                local -a entries
+                entries=(${(f)"$($words[1] list --names $archive 2>/dev/null)"})
+                compadd -a entries
+            else
+                _files
+            fi ;;
+        completions) compadd bash zsh fish ;;
+        *) _files ;;
+    esac
+}
+compdef _rip rip
+"#;
+
+const FISH: &str = r#"function __rip_archive_entries
+    set -l tokens (commandline -opc)
+    set -l skip 0
+    for token in $tokens[3..-1]
+        if test $skip = 1
+            set skip 0
+            continue
+        end
+        switch $token
+            case -d
+                set skip 1
+            case '-*'
+            case '*'
+                test -f $token; and $tokens[1] list --names $token 2>/dev/null
+                return
+        end
+    end
+end
+complete -c rip -f -n __fish_use_subcommand -a 'list extract create grep compress decompress completions'
+complete -c rip -n '__fish_seen_subcommand_from extract' -a '(__rip_archive_entries)'
+complete -c rip -f -n '__fish_seen_subcommand_from completions' -a 'bash zsh fish'
+"#;
+
+/// The completion script for shell, None for one we don't have a script for
+pub fn script(shell: &str) -> Option<&'static str> {
+    match shell {
+        "bash" => Some(BASH),
+        "zsh" => Some(ZSH),
+        "fish" => Some(FISH),
+        _ => None
+    }
+}
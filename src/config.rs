@@ -0,0 +1,230 @@
+#![allow(dead_code)]
+// The command line's config file, ~/.config/rip/config.toml (or $RIP_CONFIG). Defaults go at the top level and
+// named profiles, picked with --profile, in [profile.NAME] tables on top of them:
+//   compression = "stored"
+//   threads = 4
+//   exclude = ["*.tmp", "target/"]
+//   cache = true
+//
+//   [profile.backups]
+//   threads = 1
+//   exclude = ["*.cache"]
+// Only the part of TOML these need is read: comments, tables, and strings, integers, booleans and single line
+// arrays of strings as values.
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use rip::walk::WalkOptions;
+use rip::zipentry::CompressionMethod;
+use rip::zipwriter::FileOptions;
+
+/// One set of settings, from the top of the file or a profile. Anything not given is None, so profiles only
+/// override what they mention.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Settings {
+    pub compression: Option<CompressionMethod>,
+    pub level: Option<i32>,
+    pub threads: Option<usize>,
+    /// .gitignore-style patterns left out when create walks a directory
+    pub exclude: Option<Vec<String>>,
+    /// Keep archives' catalogs in the cache directory, so list opens them again without their central directory
+    pub cache: Option<bool>
+}
+
+impl Settings {
+    /// self with everything over sets taking its place
+    fn overlay(&self, over: &Settings) -> Settings {
+        Settings {
+            compression: over.compression.or(self.compression),
+            level: over.level.or(self.level),
+            threads: over.threads.or(self.threads),
+            exclude: over.exclude.clone().or_else(|| self.exclude.clone()),
+            cache: over.cache.or(self.cache)
+        }
+    }
+
+    pub fn file_options(&self) -> FileOptions {
+        let options = FileOptions::default().compression_level(self.level);
+        match self.compression {
+            Some(method) => options.compression_method(method),
+            None => options
+        }
+    }
+
+    pub fn walk_options(&self) -> WalkOptions {
+        WalkOptions { excludes: self.exclude.clone().unwrap_or_default(), ..WalkOptions::default() }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Config {
+    pub defaults: Settings,
+    pub profiles: HashMap<String, Settings>
+}
+
+/// $RIP_CONFIG if it's set, otherwise config.toml in rip's directory under the user's config directory
+pub fn default_path() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os("RIP_CONFIG") {
+        return Some(PathBuf::from(path));
+    }
+    let config_dir = if cfg!(windows) {
+        std::env::var_os("APPDATA").map(PathBuf::from)
+    } else {
+        std::env::var_os("XDG_CONFIG_HOME").map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+    };
+    config_dir.map(|dir| dir.join("rip").join("config.toml"))
+}
+
+fn invalid(line_number: usize, why: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("line {}: {}", line_number, why))
+}
+
+fn parse_method(name: &str) -> Option<CompressionMethod> {
+    let method = match name.to_ascii_lowercase().as_str() {
+        "stored" | "store" => CompressionMethod::Stored,
+        "deflated" | "deflate" => CompressionMethod::Deflated,
+        "deflate64" => CompressionMethod::Deflate64,
+        "bzip2" => CompressionMethod::Bzip2,
+        "lzma" => CompressionMethod::Lzma,
+        "zstd" => CompressionMethod::Zstd,
+        "xz" => CompressionMethod::Xz,
+        "ppmd" => CompressionMethod::Ppmd,
+        _ => return None
+    };
+    Some(method)
+}
+
+/// A quoted string at the start of text, returning it and whatever follows the closing quote
+fn parse_string(text: &str) -> Option<(String, &str)> {
+    let mut chars = text.strip_prefix('"')?.char_indices();
+    let mut value = String::new();
+    while let Some((at, c)) = chars.next() {
+        match c {
+            '"' => return Some((value, &text[at + 2..])),
+            '\\' => value.push(match chars.next()?.1 {
+                'n' => '\n',
+                't' => '\t',
+                other => other
+            }),
+            c => value.push(c)
+        }
+    }
+    None
+}
+
+/// The string array at the start of text, with whatever follows it
+fn parse_array(text: &str) -> Option<(Vec<String>, &str)> {
+    let mut rest = text.strip_prefix('[')?.trim_start();
+    let mut values = Vec::new();
+    loop {
+        if let Some(after) = rest.strip_prefix(']') {
+            return Some((values, after));
+        }
+        let (value, after) = parse_string(rest)?;
+        values.push(value);
+        rest = after.trim_start();
+        rest = rest.strip_prefix(',').unwrap_or(rest).trim_start();
+    }
+}
+
+/// Drops a trailing comment, leaving anything inside quotes alone
+fn without_comment(value: &str) -> &str {
+    let mut quoted = false;
+    let mut escaped = false;
+    for (at, c) in value.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            '#' if !quoted => return value[..at].trim_end(),
+            _ => {}
+        }
+    }
+    value.trim_end()
+}
+
+fn set(settings: &mut Settings, key: &str, value: &str, line_number: usize) -> io::Result<()> {
+    let value = without_comment(value);
+    let string = || parse_string(value).filter(|(_, rest)| rest.trim().is_empty()).map(|(string, _)| string)
+        .ok_or_else(|| invalid(line_number, &format!("{} should be a string", key)));
+    let number = || value.parse::<i64>().map_err(|_| invalid(line_number, &format!("{} should be a number", key)));
+    match key {
+        "compression" => {
+            let name = string()?;
+            settings.compression = Some(parse_method(&name).ok_or_else(|| invalid(line_number, &format!("unknown compression method {}", name)))?);
+        },
+        "level" => settings.level = Some(number()? as i32),
+        "threads" => settings.threads = Some(number()?.max(1) as usize),
+        "cache" => settings.cache = Some(match value {
+            "true" => true,
+            "false" => false,
+            _ => return Err(invalid(line_number, "cache should be true or false"))
+        }),
+        "exclude" => {
+            let (patterns, rest) = parse_array(value).ok_or_else(|| invalid(line_number, "exclude should be an array of strings"))?;
+            if !rest.trim().is_empty() {
+                return Err(invalid(line_number, "unexpected text after the exclude array"));
+            }
+            settings.exclude = Some(patterns);
+        },
+        _ => return Err(invalid(line_number, &format!("unknown setting {}", key)))
+    }
+    Ok(())
+}
+
+impl Config {
+    pub fn parse(text: &str) -> io::Result<Config> {
+        let mut config = Config::default();
+        let mut profile: Option<String> = None;
+        for (index, line) in text.lines().enumerate() {
+            let line_number = index + 1;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some(table) = line.strip_prefix('[') {
+                let table = without_comment(table).strip_suffix(']').ok_or_else(|| invalid(line_number, "unclosed table header"))?;
+                let name = table.trim().strip_prefix("profile.").map(|name| name.trim_matches('"'))
+                    .filter(|name| !name.is_empty())
+                    .ok_or_else(|| invalid(line_number, &format!("unknown table [{}], expected [profile.NAME]", table)))?;
+                config.profiles.entry(name.to_string()).or_default();
+                profile = Some(name.to_string());
+                continue;
+            }
+            let (key, value) = line.split_once('=').ok_or_else(|| invalid(line_number, "expected key = value"))?;
+            let settings = match &profile {
+                Some(name) => config.profiles.entry(name.clone()).or_default(),
+                None => &mut config.defaults
+            };
+            set(settings, key.trim(), value.trim(), line_number)?;
+        }
+        Ok(config)
+    }
+
+    pub fn load(path: &Path) -> io::Result<Config> {
+        let text = fs::read_to_string(path)?;
+        Config::parse(&text).map_err(|why| io::Error::new(why.kind(), format!("{}, {}", path.display(), why)))
+    }
+
+    /// The config at default_path, or an empty one if there's no file there
+    pub fn load_default() -> io::Result<Config> {
+        match default_path() {
+            Some(path) => match Config::load(&path) {
+                Err(why) if why.kind() == io::ErrorKind::NotFound => Ok(Config::default()),
+                result => result
+            },
+            None => Ok(Config::default())
+        }
+    }
+
+    /// The defaults with the named profile on top, or an error naming the profile if there's no such profile
+    pub fn settings(&self, profile: Option<&str>) -> Result<Settings, String> {
+        match profile {
+            Some(name) => self.profiles.get(name).map(|profile| self.defaults.overlay(profile))
+                .ok_or_else(|| format!("no profile named {} in the config", name)),
+            None => Ok(self.defaults.clone())
+        }
+    }
+}
@@ -0,0 +1,106 @@
+#![allow(dead_code)]
+// Content-addressed extraction: every file goes to <dest>/<blake3 hash of its contents>, so identical files share one
+// copy however many entries (or archives) hold them, and a manifest says which entry has which hash. For artifact
+// caches and build systems that keep their own names for things.
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use crate::blake3;
+use crate::extract::{ExtractOptions, ExtractReport};
+use crate::sink::{EntryMetadata, ExtractSink};
+use crate::ziparchive::ZipArchive;
+use crate::ziperror::ZipResult;
+
+/// Lower case hex, the name a file is stored under
+pub fn hex(hash: &[u8; 32]) -> String {
+    hash.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// What a content-addressed extraction stored
+#[derive(Debug, Default)]
+pub struct ContentManifest {
+    /// Each file's output name (its entry name, after ExtractOptions renaming) and the hex hash it's stored under
+    pub entries: BTreeMap<String, String>,
+    /// Files written to the store
+    pub stored: usize,
+    /// Files whose contents were there already, from this extraction or an earlier one
+    pub deduplicated: usize,
+    pub report: ExtractReport
+}
+
+/// A sink that stores files by their BLAKE3 hash. Directories aren't made and metadata isn't kept: a stored file
+/// can stand for any number of entries, which needn't agree on either.
+#[derive(Debug)]
+pub struct ContentStore {
+    dest: PathBuf,
+    /// The file being written, by name, until set_metadata says it's done
+    pending: Option<(String, Vec<u8>)>,
+    manifest: ContentManifest
+}
+
+impl ContentStore {
+    pub fn new<P: AsRef<Path>>(dest: P) -> ContentStore {
+        ContentStore { dest: dest.as_ref().to_path_buf(), pending: None, manifest: ContentManifest::default() }
+    }
+
+    pub fn into_manifest(self) -> ContentManifest {
+        self.manifest
+    }
+
+    /// Puts data in the store unless it's already there, written beside its final name and renamed so nothing
+    /// ever sees half a file under a hash
+    fn store(&mut self, data: &[u8]) -> io::Result<String> {
+        let hash = hex(&blake3::hash(data));
+        let target = self.dest.join(&hash);
+        if target.is_file() {
+            self.manifest.deduplicated += 1;
+            return Ok(hash);
+        }
+        fs::create_dir_all(&self.dest)?;
+        let partial = self.dest.join(format!("{}.{}.partial", hash, std::process::id()));
+        fs::write(&partial, data)?;
+        fs::rename(&partial, &target).inspect_err(|_| {
+            let _ = fs::remove_file(&partial);
+        })?;
+        self.manifest.stored += 1;
+        Ok(hash)
+    }
+}
+
+impl ExtractSink for ContentStore {
+    fn create_dir(&mut self, _path: &str) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn create_file(&mut self, path: &str) -> io::Result<Box<dyn Write + '_>> {
+        let (_, data) = self.pending.insert((path.to_string(), Vec::new()));
+        Ok(Box::new(data))
+    }
+
+    fn set_metadata(&mut self, path: &str, _metadata: &EntryMetadata) -> io::Result<()> {
+        if let Some((name, data)) = self.pending.take_if(|(name, _)| name == path) {
+            let hash = self.store(&data)?;
+            self.manifest.entries.insert(name, hash);
+        }
+        Ok(())
+    }
+
+    fn abandon_file(&mut self, path: &str) -> io::Result<()> {
+        self.pending.take_if(|(name, _)| name == path);
+        Ok(())
+    }
+}
+
+impl ZipArchive<'_> {
+    /// Extracts every file to dest/<BLAKE3 hash of its contents> through a ContentStore, returning which entry went
+    /// where. Files already in dest aren't written again. Links are only copied (with LinkPolicy::CreateOrCopy or
+    /// Copy), since there's nowhere in the store for a link to go.
+    pub fn extract_content_addressed<P: AsRef<Path>>(&self, dest: P, options: &ExtractOptions) -> ZipResult<ContentManifest> {
+        let mut store = ContentStore::new(dest);
+        let report = self.extract_all_to(&mut store, options)?;
+        let mut manifest = store.into_manifest();
+        manifest.report = report;
+        Ok(manifest)
+    }
+}
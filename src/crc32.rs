@@ -0,0 +1,84 @@
+#![allow(dead_code)]
+// CRC-32 as used by zip (IEEE 802.3, reflected polynomial 0xEDB88320)
+// https://en.wikipedia.org/wiki/Cyclic_redundancy_check
+
+const POLYNOMIAL: u32 = 0xEDB88320;
+
+const fn make_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ POLYNOMIAL } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+static TABLE: [u32; 256] = make_table();
+
+/// Feeds a single byte into a running (non-inverted) crc. ZipCrypto's key schedule uses this directly.
+pub fn update_byte(crc: u32, byte: u8) -> u32 {
+    TABLE[((crc ^ byte as u32) & 0xFF) as usize] ^ (crc >> 8)
+}
+
+/// Incremental hasher, for when the data arrives in chunks
+#[derive(Debug, Clone, Copy)]
+pub struct Crc32 {
+    state: u32
+}
+
+impl Crc32 {
+    pub fn new() -> Crc32 {
+        Crc32 { state: 0xFFFFFFFF }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        for byte in data {
+            self.state = update_byte(self.state, *byte);
+        }
+    }
+
+    pub fn finish(&self) -> u32 {
+        !self.state
+    }
+}
+
+impl Default for Crc32 {
+    fn default() -> Crc32 {
+        Crc32::new()
+    }
+}
+
+pub fn checksum(data: &[u8]) -> u32 {
+    let mut crc = Crc32::new();
+    crc.update(data);
+    crc.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn known_answers() {
+        assert_eq!(checksum(b""), 0);
+        // The standard check value for CRC-32/ISO-HDLC
+        assert_eq!(checksum(b"123456789"), 0xCBF43926);
+        assert_eq!(checksum(b"The quick brown fox jumps over the lazy dog"), 0x414FA339);
+    }
+
+    #[test]
+    fn updates_in_pieces_match() {
+        let mut crc = Crc32::new();
+        crc.update(b"12345");
+        crc.update(b"");
+        crc.update(b"6789");
+        assert_eq!(crc.finish(), 0xCBF43926);
+    }
+}
@@ -0,0 +1,81 @@
+#![allow(dead_code)]
+use std::fmt;
+
+/// A modification time as zip headers store it: MS-DOS format, local time, two second resolution, years 1980-2107
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct DateTime {
+    year: u16,
+    month: u8,
+    day: u8,
+    hour: u8,
+    minute: u8,
+    second: u8
+}
+
+impl Default for DateTime {
+    /// 1980-01-01 00:00:00, the earliest time a zip can hold
+    fn default() -> DateTime {
+        DateTime { year: 1980, month: 1, day: 1, hour: 0, minute: 0, second: 0 }
+    }
+}
+
+impl DateTime {
+    /// None if any field is out of range. Odd seconds are rounded down, since DOS times count in twos.
+    pub fn from_date_and_time(year: u16, month: u8, day: u8, hour: u8, minute: u8, second: u8) -> Option<DateTime> {
+        let valid = (1980..=2107).contains(&year) && (1..=12).contains(&month) && (1..=31).contains(&day)
+            && hour < 24 && minute < 60 && second < 60;
+        if !valid {
+            return None;
+        }
+        Some(DateTime { year, month, day, hour, minute, second: second & !1 })
+    }
+
+    /// Decodes the date and time fields of a header, None if they hold an impossible time
+    pub fn from_dos(date: u16, time: u16) -> Option<DateTime> {
+        DateTime::from_date_and_time(
+            (date >> 9) + 1980,
+            ((date >> 5) & 0x0f) as u8,
+            (date & 0x1f) as u8,
+            (time >> 11) as u8,
+            ((time >> 5) & 0x3f) as u8,
+            ((time & 0x1f) * 2) as u8
+        )
+    }
+
+    /// The (date, time) header fields
+    pub fn to_dos(self) -> (u16, u16) {
+        let date = ((self.year - 1980) << 9) | ((self.month as u16) << 5) | self.day as u16;
+        let time = ((self.hour as u16) << 11) | ((self.minute as u16) << 5) | (self.second as u16 / 2);
+        (date, time)
+    }
+
+    pub fn year(self) -> u16 {
+        self.year
+    }
+
+    pub fn month(self) -> u8 {
+        self.month
+    }
+
+    pub fn day(self) -> u8 {
+        self.day
+    }
+
+    pub fn hour(self) -> u8 {
+        self.hour
+    }
+
+    pub fn minute(self) -> u8 {
+        self.minute
+    }
+
+    pub fn second(self) -> u8 {
+        self.second
+    }
+}
+
+impl fmt::Display for DateTime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:04}-{:02}-{:02} {:02}:{:02}:{:02}", self.year, self.month, self.day, self.hour, self.minute, self.second)
+    }
+}
@@ -0,0 +1,92 @@
+#![allow(dead_code)]
+use std::collections::BTreeMap;
+use crate::ziparchive::ZipArchive;
+use crate::zipentry::ZipEntry;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DedupOptions {
+    /// Read and compare the actual contents instead of trusting crc32 and size alone.
+    /// Entries that can't be read (encrypted, unsupported method) are left out of confirmed groups.
+    pub confirm_contents: bool
+}
+
+/// One copy of some duplicated content
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DuplicateEntry {
+    /// Filename of the archive the entry is in
+    pub archive: String,
+    pub name: String
+}
+
+/// An entry along with the filename of the archive it's in
+type Candidate<'a> = (&'a str, &'a ZipEntry);
+
+/// Entries that (as far as we checked) all hold the same content
+#[derive(Debug, Clone)]
+pub struct DuplicateGroup {
+    pub crc32: u32,
+    pub size: u64,
+    pub entries: Vec<DuplicateEntry>
+}
+
+impl DuplicateGroup {
+    /// Uncompressed bytes that could be saved by keeping only one copy
+    pub fn wasted_bytes(&self) -> u64 {
+        self.size * (self.entries.len() as u64 - 1)
+    }
+}
+
+/// Groups files with the same content across any number of archives, biggest waste first.
+/// Directories and empty files are ignored.
+pub fn find_duplicates(archives: &[&ZipArchive], options: &DedupOptions) -> Vec<DuplicateGroup> {
+    let mut candidates: BTreeMap<(u32, u64), Vec<Candidate>> = BTreeMap::new();
+    for archive in archives {
+        for entry in archive.entries() {
+            if entry.is_dir() || entry.uncompressed_size() == 0 {
+                continue;
+            }
+            candidates.entry((entry.crc32(), entry.uncompressed_size())).or_default().push((archive.filename(), entry));
+        }
+    }
+
+    let mut groups = Vec::new();
+    for ((crc32, size), entries) in candidates {
+        if entries.len() < 2 {
+            continue;
+        }
+
+        let matching_sets = if options.confirm_contents { split_by_contents(entries) } else { vec![entries] };
+        for set in matching_sets.into_iter().filter(|set| set.len() > 1) {
+            let entries = set.into_iter()
+                .map(|(archive, entry)| DuplicateEntry { archive: archive.to_string(), name: entry.name() })
+                .collect();
+            groups.push(DuplicateGroup { crc32, size, entries });
+        }
+    }
+
+    groups.sort_by_key(|group| std::cmp::Reverse(group.wasted_bytes()));
+    groups
+}
+
+/// Splits entries that share a crc32 and size into sets with byte-for-byte identical contents
+fn split_by_contents(entries: Vec<Candidate>) -> Vec<Vec<Candidate>> {
+    let mut sets: Vec<(Vec<u8>, Vec<Candidate>)> = Vec::new();
+    for (archive, entry) in entries {
+        let data = match entry.read() {
+            Ok(data) => data,
+            Err(_) => continue
+        };
+        match sets.iter_mut().find(|(contents, _)| *contents == data) {
+            Some((_, set)) => set.push((archive, entry)),
+            None => sets.push((data, vec![(archive, entry)]))
+        }
+    }
+    sets.into_iter().map(|(_, set)| set).collect()
+}
+
+impl ZipArchive<'_> {
+    /// Groups entries in this archive that hold the same content
+    pub fn find_duplicates(&self, options: &DedupOptions) -> Vec<DuplicateGroup> {
+        find_duplicates(&[self], options)
+    }
+}
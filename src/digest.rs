@@ -0,0 +1,40 @@
+#![allow(dead_code)]
+use crate::sha256::{self, Sha256};
+use crate::ziparchive::ZipArchive;
+use crate::ziperror::{ZipError, ZipResult};
+
+impl ZipArchive<'_> {
+    /// A SHA-256 over what the archive holds, rather than how it was written: entry names in sorted order, each
+    /// with the hash of its uncompressed contents. Timestamps, attributes, comments, entry order, compression and
+    /// layout are all ignored, so archives from different tools compare equal when they hold the same files.
+    /// Directory entries only count when they're empty, since some tools write the implied ones and others don't.
+    pub fn content_digest(&self) -> ZipResult<[u8; 32]> {
+        if let Some(broken) = self.broken_entries().first() {
+            return Err(ZipError::InvalidArchive(format!("Can't digest an archive with broken entries: {}", broken.error)));
+        }
+
+        let mut entries: Vec<_> = self.entries().iter().collect();
+        entries.sort_by_key(|entry| entry.name_raw());
+
+        let mut hasher = Sha256::new();
+        for (i, entry) in entries.iter().enumerate() {
+            let name = entry.name_raw();
+            // Sorted, anything under a directory comes straight after it
+            let implied = entries.get(i + 1).is_some_and(|next| next.name_raw().starts_with(name));
+            if entry.is_dir() && implied {
+                continue;
+            }
+
+            // Length prefixed so no two different listings serialize the same
+            hasher.update(&(name.len() as u64).to_le_bytes());
+            hasher.update(name);
+            if entry.is_dir() {
+                hasher.update(b"D");
+            } else {
+                hasher.update(b"F");
+                hasher.update(&sha256::digest(&entry.read()?));
+            }
+        }
+        Ok(hasher.finish())
+    }
+}
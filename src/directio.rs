@@ -0,0 +1,170 @@
+#![allow(dead_code)]
+// Reading an archive past the page cache, so going through one once doesn't push everything else on the machine out
+// of the cache. On Linux that's O_DIRECT, which only allows reads of whole aligned blocks into aligned
+// memory, so reads go through an aligned buffer of their own. On macOS it's F_NOCACHE, which needs nothing special.
+// Anywhere else, or on filesystems that refuse (tmpfs, some network mounts), the file is read normally.
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::ops::Range;
+
+/// O_DIRECT reads have to start and end on the device's logical block boundaries, which are never bigger than a page
+const ALIGNMENT: usize = 4096;
+/// How much is read from the file at a time while bypassing the cache
+const CHUNK: usize = 1024 * 1024;
+
+#[cfg(target_os = "linux")]
+mod sys {
+    use std::fs::File;
+    use std::os::unix::io::AsRawFd;
+
+    const F_GETFL: i32 = 3;
+    const F_SETFL: i32 = 4;
+    #[cfg(any(target_arch = "arm", target_arch = "aarch64"))]
+    const O_DIRECT: i32 = 0o200000;
+    #[cfg(any(target_arch = "powerpc", target_arch = "powerpc64"))]
+    const O_DIRECT: i32 = 0o400000;
+    #[cfg(any(target_arch = "mips", target_arch = "mips64"))]
+    const O_DIRECT: i32 = 0o100000;
+    #[cfg(not(any(target_arch = "arm", target_arch = "aarch64", target_arch = "powerpc", target_arch = "powerpc64",
+        target_arch = "mips", target_arch = "mips64")))]
+    const O_DIRECT: i32 = 0o40000;
+
+    extern "C" {
+        fn fcntl(fd: i32, cmd: i32, ...) -> i32;
+    }
+
+    /// Turns O_DIRECT on or off for file, false if the filesystem won't have it
+    pub fn set_direct(file: &File, direct: bool) -> bool {
+        unsafe {
+            let flags = fcntl(file.as_raw_fd(), F_GETFL);
+            if flags < 0 {
+                return false;
+            }
+            let flags = if direct { flags | O_DIRECT } else { flags & !O_DIRECT };
+            fcntl(file.as_raw_fd(), F_SETFL, flags) == 0
+        }
+    }
+}
+
+/// Reads a file with as little help from the page cache as the platform allows
+#[derive(Debug)]
+pub struct DirectReader {
+    file: File,
+    position: u64,
+    /// Set while the file is open with O_DIRECT, and so has to be read in aligned blocks
+    aligned: bool,
+    /// Has ALIGNMENT bytes to spare, so there's an aligned CHUNK somewhere in it
+    buffer: Vec<u8>,
+    /// What part of the file is in the aligned chunk of buffer
+    buffered: Range<u64>
+}
+
+impl DirectReader {
+    /// Stops file being cached where that's possible. Never fails: a file that can't bypass the cache is read
+    /// through it like any other.
+    pub fn new(file: File) -> DirectReader {
+        #[cfg(target_os = "linux")]
+        let aligned = sys::set_direct(&file, true);
+        #[cfg(not(target_os = "linux"))]
+        let aligned = false;
+
+        #[cfg(target_os = "macos")]
+        {
+            use std::os::unix::io::AsRawFd;
+
+            const F_NOCACHE: i32 = 48;
+            extern "C" {
+                fn fcntl(fd: i32, cmd: i32, ...) -> i32;
+            }
+            unsafe {
+                fcntl(file.as_raw_fd(), F_NOCACHE, 1);
+            }
+        }
+
+        DirectReader {
+            file,
+            position: 0,
+            aligned,
+            buffer: if aligned { vec![0; CHUNK + ALIGNMENT] } else { Vec::new() },
+            buffered: 0..0
+        }
+    }
+
+    /// Whether reads really are bypassing the cache with O_DIRECT
+    pub fn is_direct(&self) -> bool {
+        self.aligned
+    }
+
+    /// Reads the aligned chunk holding position into the buffer
+    fn fill(&mut self) -> io::Result<()> {
+        let start = self.position - self.position % ALIGNMENT as u64;
+        let skew = self.buffer.as_ptr().align_offset(ALIGNMENT);
+        let chunk = &mut self.buffer[skew..skew + CHUNK];
+        self.file.seek(SeekFrom::Start(start))?;
+        let mut filled = 0;
+        // A short read is the end of the file, after which the rest of the chunk is left unfilled
+        while filled < CHUNK {
+            match self.file.read(&mut chunk[filled..]) {
+                Ok(0) => break,
+                Ok(read) => filled += read,
+                Err(why) if why.kind() == io::ErrorKind::Interrupted => {},
+                Err(why) => return Err(why)
+            }
+            if filled % ALIGNMENT != 0 {
+                break;
+            }
+        }
+        self.buffered = start..start + filled as u64;
+        Ok(())
+    }
+
+    /// Gives up on O_DIRECT after the filesystem turned a read down, going back to reading through the cache
+    fn fall_back(&mut self) {
+        #[cfg(target_os = "linux")]
+        sys::set_direct(&self.file, false);
+        self.aligned = false;
+        self.buffer = Vec::new();
+        self.buffered = 0..0;
+    }
+}
+
+impl Read for DirectReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.aligned && !self.buffered.contains(&self.position) {
+            match self.fill() {
+                Ok(()) => {},
+                Err(why) if why.kind() == io::ErrorKind::InvalidInput => self.fall_back(),
+                Err(why) => return Err(why)
+            }
+        }
+        if !self.aligned {
+            self.file.seek(SeekFrom::Start(self.position))?;
+            let read = self.file.read(buf)?;
+            self.position += read as u64;
+            return Ok(read);
+        }
+
+        // Past the end of the file the buffer doesn't reach position, and there's nothing to read
+        if !self.buffered.contains(&self.position) {
+            return Ok(0);
+        }
+        let skew = self.buffer.as_ptr().align_offset(ALIGNMENT);
+        let from = skew + (self.position - self.buffered.start) as usize;
+        let length = buf.len().min((self.buffered.end - self.position) as usize);
+        buf[..length].copy_from_slice(&self.buffer[from..from + length]);
+        self.position += length as u64;
+        Ok(length)
+    }
+}
+
+impl Seek for DirectReader {
+    fn seek(&mut self, position: SeekFrom) -> io::Result<u64> {
+        let target = match position {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(delta) => self.file.metadata()?.len().checked_add_signed(delta),
+            SeekFrom::Current(delta) => self.position.checked_add_signed(delta)
+        };
+        self.position = target.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Seek to before the start of the file"))?;
+        Ok(self.position)
+    }
+}
@@ -0,0 +1,310 @@
+#![allow(dead_code)]
+use std::borrow::Cow;
+use std::fs::File;
+use std::io;
+use std::io::{BufRead, Cursor, Read, Seek, SeekFrom, Write};
+use std::ops::Range;
+use crate::extract::Deadline;
+use crate::ziparchive::ZipArchive;
+use crate::zipentry::{ZipEntry, CompressionMethod};
+use crate::ziperror::{ZipError, ZipResult, Unsupported};
+
+/// Reads per call when copying an entry out in one go. Big enough that a copy is a handful of syscalls.
+const COPY_BUFFER: usize = 256 * 1024;
+/// Most copied file to file at a time, so a deadline gets checked between pieces of a big entry
+const COPY_CHUNK: u64 = 16 * 1024 * 1024;
+
+/// Random access to a stored (uncompressed, unencrypted) entry, reading straight from the archive file.
+/// Positions are relative to the start of the entry, and get translated to offsets in the archive.
+/// The crc isn't checked, since the data may never be read in order.
+#[derive(Debug)]
+pub struct StoredEntryReader {
+    file: File,
+    /// Archive offset of the first byte of the entry's data
+    data_start: u64,
+    size: u64,
+    position: u64
+}
+
+impl StoredEntryReader {
+    pub fn len(&self) -> u64 {
+        self.size
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    /// Copies everything from the current position to the end of the entry into output, in large reads straight
+    /// from the archive file. Returns the number of bytes copied.
+    pub fn copy_to<W: Write>(&mut self, mut output: W) -> io::Result<u64> {
+        let remaining = self.size.saturating_sub(self.position);
+        let mut buffer = vec![0; COPY_BUFFER.min(remaining as usize)];
+        self.file.seek(SeekFrom::Start(self.data_start + self.position))?;
+
+        let mut copied = 0;
+        while copied < remaining {
+            let wanted = buffer.len().min((remaining - copied) as usize);
+            let read = self.file.read(&mut buffer[..wanted])?;
+            if read == 0 {
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Archive ended part way through the entry"));
+            }
+            output.write_all(&buffer[..read])?;
+            copied += read as u64;
+            self.position += read as u64;
+        }
+        Ok(copied)
+    }
+}
+
+/// Read and Seek over an entry's contents, borrowing the archive rather than holding a lock or file handle on it, so
+/// any number can be open at once across threads. Stored entries are read in place from the data loaded at open
+/// (crc checked up front); anything else is decoded into memory first.
+#[derive(Debug, Clone)]
+pub struct EntryReader<'a> {
+    data: Cursor<Cow<'a, [u8]>>
+}
+
+impl EntryReader<'_> {
+    pub fn len(&self) -> u64 {
+        self.data.get_ref().len() as u64
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.get_ref().is_empty()
+    }
+}
+
+impl Read for EntryReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.data.read(buf)
+    }
+}
+
+impl BufRead for EntryReader<'_> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.data.fill_buf()
+    }
+
+    fn consume(&mut self, amount: usize) {
+        self.data.consume(amount)
+    }
+}
+
+impl Seek for EntryReader<'_> {
+    fn seek(&mut self, position: SeekFrom) -> io::Result<u64> {
+        self.data.seek(position)
+    }
+}
+
+impl ZipEntry {
+    pub fn reader(&self) -> ZipResult<EntryReader<'_>> {
+        let as_is = self.compression_method() == CompressionMethod::Stored && !self.is_encrypted() && !self.is_truncated();
+        let data = if as_is {
+            self.check_crc(self.raw_data())?;
+            Cow::Borrowed(self.raw_data())
+        } else {
+            Cow::Owned(self.read()?)
+        };
+        Ok(EntryReader { data: Cursor::new(data) })
+    }
+}
+
+impl Read for StoredEntryReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.position >= self.size {
+            return Ok(0);
+        }
+        let wanted = buf.len().min((self.size - self.position) as usize);
+        self.file.seek(SeekFrom::Start(self.data_start + self.position))?;
+        let read = self.file.read(&mut buf[..wanted])?;
+        self.position += read as u64;
+        Ok(read)
+    }
+}
+
+impl Seek for StoredEntryReader {
+    fn seek(&mut self, position: SeekFrom) -> io::Result<u64> {
+        let target = match position {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.size.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset)
+        };
+        match target {
+            Some(target) => {
+                self.position = target;
+                Ok(target)
+            },
+            None => Err(io::Error::new(io::ErrorKind::InvalidInput, "Seek to a negative position"))
+        }
+    }
+}
+
+/// The archive range holding a stored entry's contents as-is, or UnsupportedMethod if they're compressed or encrypted
+pub(crate) fn stored_range(entry: &ZipEntry) -> ZipResult<Range<u64>> {
+    let range = entry.data_range()?;
+    if entry.is_encrypted() {
+        return Err(entry.unsupported(Unsupported::Encryption(entry.encryption()), entry.raw_data().to_vec()));
+    }
+    let method = entry.compression_method();
+    if method != CompressionMethod::Stored {
+        return Err(entry.unsupported(Unsupported::Compression(method), entry.raw_data().to_vec()));
+    }
+    Ok(range)
+}
+
+/// Copies part of the archive into output at its current position, letting the kernel move the bytes where it can.
+/// Stops with TimedOut between pieces once deadline passes.
+pub(crate) fn copy_range_to(archive: &mut File, range: Range<u64>, output: &mut File, deadline: Option<&Deadline>) -> ZipResult<u64> {
+    let length = range.end - range.start;
+    #[cfg(target_os = "linux")]
+    {
+        if let Some(copied) = copy_file_range(archive, range.start, length, output, deadline)? {
+            return Ok(copied);
+        }
+    }
+
+    archive.seek(SeekFrom::Start(range.start))?;
+    let mut copied = 0;
+    while copied < length {
+        if let Some(deadline) = deadline {
+            deadline.check()?;
+        }
+        let piece = io::copy(&mut archive.take(COPY_CHUNK.min(length - copied)), output)?;
+        if piece == 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Archive ended part way through the entry").into());
+        }
+        copied += piece;
+    }
+    Ok(copied)
+}
+
+/// Returns None when the filesystems involved don't support copy_file_range, so the caller can copy normally
+#[cfg(target_os = "linux")]
+fn copy_file_range(archive: &File, start: u64, length: u64, output: &File, deadline: Option<&Deadline>) -> ZipResult<Option<u64>> {
+    use std::os::unix::io::AsRawFd;
+
+    extern "C" {
+        fn copy_file_range(fd_in: i32, off_in: *mut i64, fd_out: i32, off_out: *mut i64, len: usize, flags: u32) -> isize;
+    }
+
+    let mut offset = start as i64;
+    let mut copied = 0;
+    while copied < length {
+        if let Some(deadline) = deadline {
+            deadline.check()?;
+        }
+        let piece = COPY_CHUNK.min(length - copied) as usize;
+        let result = unsafe {
+            copy_file_range(archive.as_raw_fd(), &mut offset, output.as_raw_fd(), std::ptr::null_mut(), piece, 0)
+        };
+        if result < 0 {
+            let error = io::Error::last_os_error();
+            return match error.raw_os_error() {
+                // Not supported here (old kernel, cross-filesystem, odd file types). Nothing was written yet, so fall back.
+                Some(errno) if copied == 0 && [EXDEV, ENOSYS, EINVAL, EOPNOTSUPP].contains(&errno) => Ok(None),
+                _ => Err(error.into())
+            };
+        }
+        if result == 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Archive ended part way through the entry").into());
+        }
+        copied += result as u64;
+    }
+    Ok(Some(copied))
+}
+
+/// Access patterns the kernel can be told about, see posix_fadvise(2)
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) enum Advice {
+    /// Read far ahead, the file will be read front to back
+    Sequential,
+    /// Start reading this range in the background, it'll be needed soon
+    WillNeed
+}
+
+/// Passes an access hint for part of the archive to the kernel. Only does anything on Linux, and failures are
+/// ignored since it's only a hint. As with posix_fadvise, an empty range means everything from its start to the end of the file.
+pub(crate) fn advise(archive: &File, range: Range<u64>, advice: Advice) {
+    #[cfg(target_os = "linux")]
+    {
+        use std::os::unix::io::AsRawFd;
+
+        const POSIX_FADV_SEQUENTIAL: i32 = 2;
+        const POSIX_FADV_WILLNEED: i32 = 3;
+        extern "C" {
+            fn posix_fadvise(fd: i32, offset: i64, len: i64, advice: i32) -> i32;
+        }
+
+        let advice = match advice {
+            Advice::Sequential => POSIX_FADV_SEQUENTIAL,
+            Advice::WillNeed => POSIX_FADV_WILLNEED
+        };
+        unsafe {
+            posix_fadvise(archive.as_raw_fd(), range.start as i64, (range.end - range.start) as i64, advice);
+        }
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = (archive, range, advice);
+    }
+}
+
+#[cfg(target_os = "linux")]
+const EXDEV: i32 = 18;
+#[cfg(target_os = "linux")]
+const ENOSYS: i32 = 38;
+#[cfg(target_os = "linux")]
+const EINVAL: i32 = 22;
+#[cfg(target_os = "linux")]
+const EOPNOTSUPP: i32 = 95;
+
+impl ZipArchive<'_> {
+    /// Opens a stored entry for random access without loading it into memory.
+    /// Compressed or encrypted entries can't be seeked this way and return UnsupportedMethod.
+    pub fn open_seekable(&self, name: &str) -> ZipResult<StoredEntryReader> {
+        let entry = self.by_name(name).ok_or_else(|| ZipError::FileNotFound(name.to_string()))?;
+        let range = stored_range(entry)?;
+
+        Ok(StoredEntryReader {
+            file: File::open(self.filename())?,
+            data_start: range.start,
+            size: range.end - range.start,
+            position: 0
+        })
+    }
+
+    /// Opens an entry by name for reading. Only needs &self, so entries can be opened from many threads at once or
+    /// from an archive kept in a shared structure.
+    pub fn open_entry(&self, name: &str) -> ZipResult<EntryReader<'_>> {
+        self.by_name(name).ok_or_else(|| ZipError::FileNotFound(name.to_string()))?.reader()
+    }
+
+    /// Writes a stored entry's contents to output straight from the archive file.
+    /// On Linux this is a copy_file_range, so the data never passes through userspace; elsewhere it's a plain copy.
+    /// The crc isn't checked. Returns the number of bytes written.
+    pub fn write_entry_to(&self, name: &str, output: &mut File) -> ZipResult<u64> {
+        let entry = self.by_name(name).ok_or_else(|| ZipError::FileNotFound(name.to_string()))?;
+        let range = stored_range(entry)?;
+        let mut archive = File::open(self.filename())?;
+        copy_range_to(&mut archive, range, output, None)
+    }
+
+    /// Writes an entry's contents into any writer, such as an HTTP response body.
+    /// Stored entries go out straight from the data loaded at open, after checking their crc, without another copy;
+    /// anything else is decoded first. Returns the number of bytes written.
+    pub fn extract_entry_to_writer<W: Write>(&self, name: &str, mut output: W) -> ZipResult<u64> {
+        let entry = self.by_name(name).ok_or_else(|| ZipError::FileNotFound(name.to_string()))?;
+        let as_is = entry.compression_method() == CompressionMethod::Stored && !entry.is_encrypted() && !entry.is_truncated();
+        if as_is {
+            let data = entry.raw_data();
+            entry.check_crc(data)?;
+            output.write_all(data)?;
+            return Ok(data.len() as u64);
+        }
+
+        let data = entry.read()?;
+        output.write_all(&data)?;
+        Ok(data.len() as u64)
+    }
+}
@@ -0,0 +1,1445 @@
+#![allow(dead_code)]
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf, Component};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use crate::entryreader::{self, Advice};
+use crate::journal::{self, ExtractJournal};
+use crate::sandbox;
+use crate::freespace;
+use crate::ziparchive::{ZipArchive, BrokenEntry};
+use crate::zipentry::{ZipEntry, CompressionMethod};
+#[cfg(windows)]
+use crate::attributes::DosAttributes;
+use crate::ziperror::{Context, Structure, ZipError, ZipResult};
+
+/// Maps an entry name (after strip_components) to the path it should be written to under dest, or None to skip it
+pub type RenameFn = Arc<dyn Fn(&str) -> Option<String> + Send + Sync>;
+
+/// Line ending conversion for entries flagged as text. Binary entries are always written untouched.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum LineEndings {
+    #[default]
+    Unchanged,
+    /// CRLF becomes LF
+    Lf,
+    /// Lone LF becomes CRLF
+    Crlf
+}
+
+impl LineEndings {
+    pub(crate) fn convert(self, data: Vec<u8>) -> Vec<u8> {
+        match self {
+            LineEndings::Unchanged => data,
+            LineEndings::Lf => {
+                let mut converted = Vec::with_capacity(data.len());
+                for (i, &byte) in data.iter().enumerate() {
+                    if byte == b'\r' && data.get(i + 1) == Some(&b'\n') {
+                        continue;
+                    }
+                    converted.push(byte);
+                }
+                converted
+            },
+            LineEndings::Crlf => {
+                let mut converted = Vec::with_capacity(data.len());
+                for (i, &byte) in data.iter().enumerate() {
+                    if byte == b'\n' && (i == 0 || data[i - 1] != b'\r') {
+                        converted.push(b'\r');
+                    }
+                    converted.push(byte);
+                }
+                converted
+            }
+        }
+    }
+}
+
+/// What to do with the permissions stored with each entry
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum Permissions {
+    /// Apply the read, write and execute bits as stored, leaving out setuid, setgid and sticky
+    #[default]
+    Stored,
+    /// Apply them as stored, setuid, setgid and sticky bits included. Only for archives that are trusted.
+    StoredWithSpecialBits,
+    /// Apply the read, write and execute bits with the ones the process umask clears taken out, as if the file had
+    /// been created with that mode
+    Umask,
+    /// Apply them with these bits taken out (a umask of the caller's choosing)
+    Mask(u32),
+    /// Leave them alone, so files and directories get the same permissions as anything else the process creates
+    Ignore
+}
+
+#[cfg(unix)]
+fn process_umask() -> u32 {
+    #[cfg(any(target_os = "macos", target_os = "ios", target_os = "freebsd", target_os = "openbsd", target_os = "netbsd"))]
+    type Mode = u16;
+    #[cfg(not(any(target_os = "macos", target_os = "ios", target_os = "freebsd", target_os = "openbsd", target_os = "netbsd")))]
+    type Mode = u32;
+    extern "C" {
+        fn umask(mask: Mode) -> Mode;
+    }
+    // The only way to read it is to set it, so put it straight back
+    unsafe {
+        let mask = umask(0o022);
+        umask(mask);
+        mask as u32
+    }
+}
+
+impl Permissions {
+    /// The mode to give a file whose entry stored mode, None to leave it as created
+    pub(crate) fn apply(self, mode: u32) -> Option<u32> {
+        match self {
+            Permissions::Stored => Some(mode & 0o777),
+            Permissions::StoredWithSpecialBits => Some(mode & 0o7777),
+            #[cfg(unix)]
+            Permissions::Umask => Some(mode & 0o777 & !process_umask()),
+            // No umask to take out away from Unix
+            #[cfg(not(unix))]
+            Permissions::Umask => Some(mode & 0o777),
+            Permissions::Mask(mask) => Some(mode & 0o7777 & !mask),
+            Permissions::Ignore => None
+        }
+    }
+}
+
+/// What to do with symlink entries
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum LinkPolicy {
+    /// Write a regular file holding the target path, as tools that don't know about links do
+    #[default]
+    AsFile,
+    Skip,
+    /// Create a real link. Where that isn't allowed (Windows without developer mode or the symlink privilege) the
+    /// entry is skipped and reported rather than failing the extraction.
+    Create,
+    /// Create a real link, or write a copy of what it points to where a link can't be made
+    CreateOrCopy,
+    /// Write a copy of what the link points to, when that's a file in the same archive
+    Copy
+}
+
+/// How a symlink entry was handled, under any policy but AsFile
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LinkResult {
+    Created,
+    Copied,
+    /// Left out, and why
+    Skipped(String)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinkOutcome {
+    pub name: String,
+    pub target: String,
+    pub result: LinkResult
+}
+
+/// Follow chains of links at most this far when copying
+pub(crate) const MAX_LINK_DEPTH: usize = 8;
+
+/// Where a link's target is within the archive, None if it's absolute or climbs out of the archive's root.
+/// Stepping back out of another link with ".." is refused too, since on disk that goes back from wherever the other
+/// link pointed rather than from where it sits.
+pub(crate) fn resolve_link(name: &str, target: &str, is_link: impl Fn(&str) -> bool) -> Option<String> {
+    if target.starts_with(['/', '\\']) || target.contains(':') {
+        return None;
+    }
+    let mut parts: Vec<&str> = name.trim_end_matches('/').split('/').collect();
+    parts.pop();
+    for part in target.split(['/', '\\']) {
+        match part {
+            "" | "." => {},
+            ".." => {
+                if is_link(&parts.join("/")) {
+                    return None;
+                }
+                parts.pop()?;
+            },
+            part => parts.push(part)
+        }
+    }
+    Some(parts.join("/"))
+}
+
+/// Files take up whole blocks on disk. 4K is the common size, and a guess is all this needs to be.
+const ESTIMATED_BLOCK_SIZE: u64 = 4096;
+
+/// How much disk an extraction takes, from ZipArchive::extracted_size
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct SpaceEstimate {
+    /// The uncompressed sizes added up
+    pub data_bytes: u64,
+    /// Roughly what the filesystem adds: the rest of each file's last block, and a block per directory
+    pub overhead_bytes: u64
+}
+
+impl SpaceEstimate {
+    pub fn total(&self) -> u64 {
+        self.data_bytes.saturating_add(self.overhead_bytes)
+    }
+}
+
+/// What to do about an entry whose file is already there
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConflictAction {
+    Overwrite,
+    Skip,
+    /// Write the entry under this name instead, relative to dest like the rename callback's names
+    Rename(String)
+}
+
+/// Called with an entry's name and the path it would be written to when something is already there, so a front end
+/// can ask the user (unzip's replace/skip/rename prompt, or a GUI dialog). Remembering answers like "all" or "none"
+/// is up to the handler.
+pub type ConflictHandler = Arc<dyn Fn(&str, &Path) -> ConflictAction + Send + Sync>;
+
+/// What to do with entries whose names differ only in case (README.md and readme.md), which would overwrite each
+/// other on Windows and macOS
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum CaseCollisions {
+    /// Write them all, so whichever comes last wins where the filesystem ignores case
+    #[default]
+    LastWins,
+    /// Refuse: without continue_on_error nothing is written, with it the later entries fail
+    Error,
+    /// Write the later entries as "readme (1).md" and so on
+    Rename
+}
+
+/// An entry whose name only differs in case from one extracted before it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CaseCollision {
+    pub name: String,
+    pub other: String,
+    /// Where it was written instead, with CaseCollisions::Rename
+    pub renamed: Option<String>
+}
+
+/// "dir/name.ext" as "dir/name (n).ext"
+fn numbered(output_name: &str, n: usize) -> String {
+    let (dir, file) = match output_name.rfind('/') {
+        Some(i) => output_name.split_at(i + 1),
+        None => ("", output_name)
+    };
+    match file.rfind('.').filter(|&i| i > 0) {
+        Some(i) => format!("{}{} ({}){}", dir, &file[..i], n, &file[i..]),
+        None => format!("{}{} ({})", dir, file, n)
+    }
+}
+
+/// Entries that land on the same path as an earlier one once case is ignored, in extraction order and with their
+/// header offsets, since two entries can have exactly the same name. The ones that can be renamed (files) get a name
+/// nothing else folds to. Directories that coincide just merge, so they don't count.
+fn find_case_collisions(entries: &[&ZipEntry], options: &ExtractOptions) -> Vec<(u64, CaseCollision)> {
+    let mut seen: HashMap<String, (String, bool)> = HashMap::new();
+    let mut collisions = Vec::new();
+    for entry in entries {
+        let output_name = match options.output_name(&entry.name()) {
+            Some(output_name) => output_name.trim_end_matches('/').to_string(),
+            None => continue
+        };
+        let folded = output_name.to_lowercase();
+        let other = match seen.get(&folded) {
+            Some((other, other_is_dir)) if !(*other_is_dir && entry.is_dir()) => other.clone(),
+            Some(_) => continue,
+            None => {
+                seen.insert(folded, (entry.name(), entry.is_dir()));
+                continue;
+            }
+        };
+
+        let mut renamed = None;
+        if options.case_collisions == CaseCollisions::Rename && !entry.is_dir() {
+            let new_name = (1..).map(|n| numbered(&output_name, n)).find(|name| !seen.contains_key(&name.to_lowercase())).unwrap();
+            seen.insert(new_name.to_lowercase(), (entry.name(), false));
+            renamed = Some(new_name);
+        }
+        collisions.push((entry.header_offset(), CaseCollision { name: entry.name(), other, renamed }));
+    }
+    collisions
+}
+
+/// What the case collision policy does to an extraction
+pub(crate) struct CasePlan {
+    pub(crate) collisions: Vec<CaseCollision>,
+    /// Entries CaseCollisions::Error refuses by header offset, and the entry each collides with
+    pub(crate) refused: HashMap<u64, String>,
+    /// New output names by header offset for the entries CaseCollisions::Rename moves
+    pub(crate) renamed: HashMap<u64, String>
+}
+
+pub(crate) fn plan_case_collisions(ordered: &[Result<&ZipEntry, &BrokenEntry>], options: &ExtractOptions) -> CasePlan {
+    let loaded: Vec<&ZipEntry> = ordered.iter().filter_map(|entry| entry.ok()).collect();
+    let found = find_case_collisions(&loaded, options);
+    let refused = found.iter()
+        .filter(|_| options.case_collisions == CaseCollisions::Error)
+        .map(|(offset, collision)| (*offset, collision.other.clone()))
+        .collect();
+    let renamed = found.iter()
+        .filter_map(|(offset, collision)| Some((*offset, collision.renamed.clone()?)))
+        .collect();
+    let collisions = found.into_iter().map(|(_, collision)| collision).collect();
+    CasePlan { collisions, refused, renamed }
+}
+
+fn entry_offset(entry: &Result<&ZipEntry, &BrokenEntry>) -> u64 {
+    match entry {
+        Ok(entry) => entry.header_offset(),
+        Err(broken) => broken.offset
+    }
+}
+
+/// What extract_all would do with an entry
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PlannedAction {
+    /// Write a new file or make a new directory
+    Create,
+    /// Replace a file that's already there
+    Overwrite,
+    /// The directory is already there
+    Exists,
+    /// Left out by strip_components or the rename callback
+    Skip,
+    /// The journal says a previous run finished it
+    Resume,
+    /// A symlink, handled according to options.links
+    Link,
+    /// It would fail, and why
+    Fail(String)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlannedEntry {
+    pub name: String,
+    /// Where it would be written, None when it's skipped or has nowhere safe to go
+    pub output: Option<PathBuf>,
+    pub action: PlannedAction,
+    /// Bytes it would write
+    pub size: u64,
+    /// The permissions it would be given, where they're set from a stored Unix mode
+    pub mode: Option<u32>
+}
+
+/// Everything extract_all would do, from ZipArchive::extract_all_dry_run
+#[derive(Debug, Clone, Default)]
+pub struct ExtractPlan {
+    /// In the order they'd be extracted
+    pub entries: Vec<PlannedEntry>,
+    pub case_collisions: Vec<CaseCollision>,
+    /// Only with preserve_ownership, when running as root
+    pub ownership: Vec<OwnershipChange>,
+    /// Total size of the files that would be written
+    pub bytes_to_write: u64,
+    /// Total size of the existing files they'd replace
+    pub bytes_replaced: u64
+}
+
+impl ExtractPlan {
+    /// Whether extract_all would get through without failures
+    pub fn is_clean(&self) -> bool {
+        !self.entries.iter().any(|entry| matches!(entry.action, PlannedAction::Fail(_)))
+    }
+}
+
+/// The mode apply_attributes would set
+fn planned_mode(entry: &ZipEntry, options: &ExtractOptions) -> Option<u32> {
+    #[cfg(unix)]
+    {
+        entry.unix_mode().and_then(|mode| options.permissions.apply(mode))
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = (entry, options);
+        None
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct ExtractOptions {
+    /// Keep going past broken headers and unreadable entries, collecting them in the report instead of stopping
+    pub continue_on_error: bool,
+    /// Write out whatever is readable of an entry cut short by the end of the file, instead of failing it
+    pub extract_truncated_prefix: bool,
+    /// Leading path components to drop from every entry name, like tar --strip-components.
+    /// Entries with nothing left after stripping are skipped.
+    pub strip_components: usize,
+    pub rename: Option<RenameFn>,
+    pub line_endings: LineEndings,
+    /// Ask the OS to start reading each entry's data while the one before it is being written
+    pub prefetch: bool,
+    /// Extract in central directory order instead of the order entries sit in the archive
+    pub preserve_directory_order: bool,
+    /// Keep a record of finished entries here, so a rerun after an interruption skips them and picks up
+    /// a partly written stored entry where it stopped. The file is removed once an extraction finishes cleanly.
+    /// This saves redoing the writes only: the archive's entry data is still all read into memory when it opens.
+    pub journal: Option<PathBuf>,
+    /// Before skipping an entry the journal says is done, check the file on disk still has the right crc
+    pub verify_resumed: bool,
+    /// Give files the uid and gid stored with them (Info-ZIP's Unix extra fields). Only done when running as root,
+    /// since nobody else can give files away; see ZipArchive::ownership_plan for what it would change.
+    pub preserve_ownership: bool,
+    pub permissions: Permissions,
+    /// Create every directory and file through directory handles, never following a symlink below dest, so one
+    /// planted there can't redirect a write outside it. Linux only; partly written files are rewritten rather than
+    /// resumed in this mode.
+    pub sandboxed: bool,
+    pub links: LinkPolicy,
+    pub case_collisions: CaseCollisions,
+    /// Check the destination has room for everything before writing anything, failing with InsufficientSpace if not.
+    /// Skipped where the free space can't be found out.
+    pub check_free_space: bool,
+    /// Asked what to do when a file is already where an entry goes. Without one it's overwritten.
+    pub on_conflict: Option<ConflictHandler>,
+    /// Longest any one entry may take to decode and write out, so an entry built to eat CPU can't stall the rest.
+    /// One that runs over is abandoned with TimedOut and whatever was written of it removed; continue_on_error then
+    /// decides whether extraction carries on with the next.
+    pub entry_time_limit: Option<Duration>
+}
+
+impl ExtractOptions {
+    pub fn strip_components(mut self, components: usize) -> ExtractOptions {
+        self.strip_components = components;
+        self
+    }
+
+    pub fn rename<F>(mut self, rename: F) -> ExtractOptions where F: Fn(&str) -> Option<String> + Send + Sync + 'static {
+        self.rename = Some(Arc::new(rename));
+        self
+    }
+
+    pub fn on_conflict<F>(mut self, handler: F) -> ExtractOptions where F: Fn(&str, &Path) -> ConflictAction + Send + Sync + 'static {
+        self.on_conflict = Some(Arc::new(handler));
+        self
+    }
+
+    /// Where an entry goes relative to dest, or None if it's skipped
+    pub(crate) fn output_name(&self, name: &str) -> Option<String> {
+        let stripped: Vec<&str> = name.split('/').skip(self.strip_components).collect();
+        let stripped = stripped.join("/");
+        if stripped.trim_matches('/').is_empty() {
+            return None;
+        }
+
+        match &self.rename {
+            Some(rename) => rename(&stripped),
+            None => Some(stripped)
+        }
+    }
+}
+
+impl fmt::Debug for ExtractOptions {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ExtractOptions")
+            .field("continue_on_error", &self.continue_on_error)
+            .field("extract_truncated_prefix", &self.extract_truncated_prefix)
+            .field("strip_components", &self.strip_components)
+            .field("rename", &self.rename.as_ref().map(|_| "<callback>"))
+            .field("line_endings", &self.line_endings)
+            .field("prefetch", &self.prefetch)
+            .field("preserve_directory_order", &self.preserve_directory_order)
+            .field("journal", &self.journal)
+            .field("verify_resumed", &self.verify_resumed)
+            .field("preserve_ownership", &self.preserve_ownership)
+            .field("permissions", &self.permissions)
+            .field("sandboxed", &self.sandboxed)
+            .field("links", &self.links)
+            .field("case_collisions", &self.case_collisions)
+            .field("check_free_space", &self.check_free_space)
+            .field("on_conflict", &self.on_conflict.as_ref().map(|_| "<callback>"))
+            .field("entry_time_limit", &self.entry_time_limit)
+            .finish()
+    }
+}
+
+/// An entry extract_all couldn't write out
+#[derive(Debug)]
+pub struct ExtractFailure {
+    pub name: String,
+    pub error: ZipError
+}
+
+/// An entry that was only partly written because the archive ends part way through it
+#[derive(Debug, Clone)]
+pub struct TruncatedEntry {
+    pub name: String,
+    pub written_bytes: u64,
+    pub missing_bytes: u64
+}
+
+#[derive(Debug, Default)]
+pub struct ExtractReport {
+    pub extracted: Vec<String>,
+    pub truncated: Vec<TruncatedEntry>,
+    /// Entries left out by strip_components or the rename callback
+    pub skipped: Vec<String>,
+    /// Entries a previous run already finished, according to the journal
+    pub resumed: Vec<String>,
+    /// Symlink entries and what became of them, unless the policy was to write them as files
+    pub links: Vec<LinkOutcome>,
+    /// Entries whose names only differ in case from an earlier one's, whatever the policy did about them
+    pub case_collisions: Vec<CaseCollision>,
+    pub failures: Vec<ExtractFailure>
+}
+
+/// What happened to a single entry
+pub(crate) enum Outcome {
+    Extracted,
+    Truncated(TruncatedEntry),
+    Skipped,
+    /// Already finished by a previous run, according to the journal
+    Resumed,
+    Link(LinkOutcome)
+}
+
+impl ExtractReport {
+    pub fn is_clean(&self) -> bool {
+        self.failures.is_empty() && self.truncated.is_empty()
+    }
+}
+
+/// The path components of name, refusing anything that would escape the directory it's extracted to (.., absolute
+/// paths, drive letters)
+pub(crate) fn output_parts(name: &str) -> ZipResult<Vec<&str>> {
+    let mut parts = Vec::new();
+    for part in name.split(['/', '\\']) {
+        match Path::new(part).components().next() {
+            None | Some(Component::CurDir) => continue,
+            Some(Component::Normal(_)) if !part.contains(':') => parts.push(part),
+            _ => return Err(ZipError::UnsafePath(name.to_string()))
+        }
+    }
+    Ok(parts)
+}
+
+/// Works out where an entry goes under dest, refusing anything that would escape it (.., absolute paths, drive letters)
+pub fn entry_output_path(dest: &Path, name: &str) -> ZipResult<PathBuf> {
+    let mut output = dest.to_path_buf();
+    output.extend(output_parts(name)?);
+    Ok(output)
+}
+
+/// Where an extraction writes each entry, worked out before anything's written so that extract_all and
+/// extract_all_to check paths and links in one place. Everything goes by output path, after strip_components and
+/// renaming, since that's where links end up and what their targets are relative to.
+pub(crate) struct OutputPlan<'a> {
+    options: &'a ExtractOptions,
+    /// Output names by header offset for entries moved out of the way of a case collision
+    renamed: HashMap<u64, String>,
+    /// The entry written at each output path, '/' separated
+    by_output: HashMap<String, &'a ZipEntry>,
+    /// Output paths of the symlink entries
+    links: HashSet<String>
+}
+
+impl<'a> OutputPlan<'a> {
+    /// Plans entries with options, writing the ones in renamed (from plan_case_collisions) under their new names
+    pub(crate) fn new(entries: impl IntoIterator<Item = &'a ZipEntry>, options: &'a ExtractOptions, renamed: HashMap<u64, String>) -> OutputPlan<'a> {
+        let mut by_output = HashMap::new();
+        let mut links = HashSet::new();
+        for entry in entries {
+            let output_name = renamed.get(&entry.header_offset()).cloned().or_else(|| options.output_name(&entry.name()));
+            let output = match output_name.map(|name| output_parts(&name).map(|parts| parts.join("/"))) {
+                Some(Ok(output)) if !output.is_empty() => output,
+                _ => continue
+            };
+            if entry.is_symlink() {
+                links.insert(output.clone());
+            }
+            by_output.entry(output).or_insert(entry);
+        }
+        OutputPlan { options, renamed, by_output, links }
+    }
+
+    pub(crate) fn options(&self) -> &'a ExtractOptions {
+        self.options
+    }
+
+    /// Where entry goes, '/' separated, or None if it's left out
+    pub(crate) fn output_name(&self, entry: &ZipEntry) -> ZipResult<Option<String>> {
+        let output_name = self.renamed.get(&entry.header_offset()).cloned().or_else(|| self.options.output_name(&entry.name()));
+        match output_name {
+            Some(output_name) => self.check(&entry.name(), &output_name),
+            None => Ok(None)
+        }
+    }
+
+    /// output_name's path under dest
+    pub(crate) fn output_path(&self, entry: &ZipEntry, dest: &Path) -> ZipResult<Option<PathBuf>> {
+        match self.output_name(entry)? {
+            Some(output_name) => Ok(Some(entry_output_path(dest, &output_name)?)),
+            None => Ok(None)
+        }
+    }
+
+    /// Checks the entry called name can be written at output_name, refusing anything that would escape the
+    /// destination. While links are being made, that includes anything under one of the archive's own links, which
+    /// would be written wherever the link points.
+    pub(crate) fn check(&self, name: &str, output_name: &str) -> ZipResult<Option<String>> {
+        let parts = output_parts(output_name)?;
+        let makes_links = matches!(self.options.links, LinkPolicy::Create | LinkPolicy::CreateOrCopy);
+        if makes_links && (1..parts.len()).any(|end| self.links.contains(&parts[..end].join("/"))) {
+            return Err(ZipError::UnsafePath(name.to_string()));
+        }
+        Ok(Some(parts.join("/")).filter(|output| !output.is_empty()))
+    }
+
+    /// Handles a symlink entry written at output, under any policy but AsFile. make_link makes the link, given the
+    /// target and whether it points at a directory, and copy writes out the file it points to instead. Both are
+    /// passed to, so they can share whatever they write with.
+    pub(crate) fn link<T: ?Sized>(&self, entry: &ZipEntry, output: &str, to: &mut T,
+                                  make_link: impl FnOnce(&mut T, &str, bool) -> io::Result<()>,
+                                  copy: impl FnOnce(&mut T, &ZipEntry) -> ZipResult<()>) -> ZipResult<Outcome> {
+        let target = String::from_utf8_lossy(&entry.read()?).into_owned();
+        let outcome = |result| Ok(Outcome::Link(LinkOutcome { name: entry.name(), target: target.clone(), result }));
+        let resolved = match self.resolve(output, &target) {
+            Some(resolved) => resolved,
+            None => return outcome(LinkResult::Skipped("it points outside the destination".to_string()))
+        };
+        if self.options.links == LinkPolicy::Skip {
+            return outcome(LinkResult::Skipped("symlinks are being skipped".to_string()));
+        }
+        if matches!(self.options.links, LinkPolicy::Create | LinkPolicy::CreateOrCopy) {
+            let is_dir = self.by_output.get(&resolved).is_some_and(|entry| entry.is_dir());
+            match make_link(to, &target, is_dir) {
+                Ok(()) => return outcome(LinkResult::Created),
+                Err(error) if !matches!(error.kind(), io::ErrorKind::PermissionDenied | io::ErrorKind::Unsupported) => return Err(error.into()),
+                Err(_) if self.options.links == LinkPolicy::Create => {
+                    return outcome(LinkResult::Skipped("symlinks can't be made here".to_string()));
+                },
+                Err(_) => {}
+            }
+        }
+        let source = match self.link_source(&resolved)? {
+            Some(source) => source,
+            None => return outcome(LinkResult::Skipped(format!("{} isn't a file in the archive", resolved)))
+        };
+        copy(to, source)?;
+        outcome(LinkResult::Copied)
+    }
+
+    /// The output path a link written at output points to, None if that's outside the destination
+    fn resolve(&self, output: &str, target: &str) -> Option<String> {
+        resolve_link(output, target, |path| self.links.contains(path))
+    }
+
+    /// The file a link to resolved copies, following links on from it as far as MAX_LINK_DEPTH. None when that isn't
+    /// a file in the archive.
+    fn link_source(&self, resolved: &str) -> ZipResult<Option<&'a ZipEntry>> {
+        let mut path = resolved.to_string();
+        for _ in 0..MAX_LINK_DEPTH {
+            match self.by_output.get(&path) {
+                Some(link) if link.is_symlink() => match self.resolve(&path, &String::from_utf8_lossy(&link.read()?)) {
+                    Some(next) => path = next,
+                    None => return Ok(None)
+                },
+                _ => break
+            }
+        }
+        Ok(self.by_output.get(&path).copied().filter(|source| !source.is_dir() && !source.is_symlink()))
+    }
+}
+
+/// Applies the permissions stored with an entry, reading them the way the system that made it wrote them
+fn apply_attributes(entry: &ZipEntry, output: &Path, options: &ExtractOptions) -> ZipResult<()> {
+    if options.permissions == Permissions::Ignore {
+        return Ok(());
+    }
+
+    #[cfg(unix)]
+    {
+        if let Some(mode) = entry.unix_mode().and_then(|mode| options.permissions.apply(mode)) {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(output, fs::Permissions::from_mode(mode))?;
+            return Ok(());
+        }
+    }
+
+    #[cfg(windows)]
+    {
+        if let Some(dos_attributes) = entry.dos_attributes() {
+            set_dos_attributes(output, dos_attributes)?;
+            return Ok(());
+        }
+    }
+
+    if entry.is_read_only() {
+        let mut permissions = fs::metadata(output)?.permissions();
+        permissions.set_readonly(true);
+        fs::set_permissions(output, permissions)?;
+    }
+    Ok(())
+}
+
+/// A chown that extraction would make, from ZipArchive::ownership_plan
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OwnershipChange {
+    pub path: PathBuf,
+    pub uid: u32,
+    pub gid: u32,
+    /// The (uid, gid) of what's there now, None if nothing is yet
+    pub current: Option<(u32, u32)>
+}
+
+#[cfg(unix)]
+pub(crate) fn is_root() -> bool {
+    extern "C" {
+        fn geteuid() -> u32;
+    }
+    unsafe { geteuid() == 0 }
+}
+
+/// Gives the output the entry's stored owner, when asked to and allowed to. Symlinks themselves are changed, not
+/// what they point at.
+fn apply_ownership(entry: &ZipEntry, output: &Path, options: &ExtractOptions) -> ZipResult<()> {
+    #[cfg(unix)]
+    {
+        if let Some((uid, gid)) = entry.unix_owner().filter(|_| options.preserve_ownership && is_root()) {
+            std::os::unix::fs::lchown(output, Some(uid), Some(gid))?;
+        }
+    }
+    Ok(())
+}
+
+/// Hidden and system can't be set through std, so this goes straight to the Win32 call
+#[cfg(windows)]
+fn set_dos_attributes(output: &Path, dos_attributes: DosAttributes) -> std::io::Result<()> {
+    use std::os::windows::ffi::OsStrExt;
+
+    const FILE_ATTRIBUTE_NORMAL: u32 = 0x80;
+    extern "system" {
+        fn SetFileAttributesW(file_name: *const u16, file_attributes: u32) -> i32;
+    }
+
+    let wide_path: Vec<u16> = output.as_os_str().encode_wide().chain(Some(0)).collect();
+    let bits = match dos_attributes.to_bits() as u32 {
+        0 => FILE_ATTRIBUTE_NORMAL,
+        bits => bits
+    };
+
+    if unsafe { SetFileAttributesW(wide_path.as_ptr(), bits) } == 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Stored entries that don't need converting can be copied file to file without passing through a buffer
+fn can_copy_directly(entry: &ZipEntry, options: &ExtractOptions) -> bool {
+    let converted = entry.is_text() && options.line_endings != LineEndings::Unchanged;
+    entry.compression_method() == CompressionMethod::Stored && !entry.is_encrypted() && !entry.is_truncated() && !converted
+}
+
+/// Where an entry gets written under dest, or None if it's skipped
+fn output_path(entry: &ZipEntry, dest: &Path, options: &ExtractOptions) -> ZipResult<Option<PathBuf>> {
+    match options.output_name(&entry.name()) {
+        Some(output_name) => Ok(Some(entry_output_path(dest, &output_name)?)),
+        None => Ok(None)
+    }
+}
+
+/// Asks options.on_conflict about an entry whose output already exists. None when there's no conflict (or nobody to
+/// ask), so the entry's written as usual. A directory entry landing on an existing directory isn't a conflict.
+fn resolve_conflict(entry: &ZipEntry, dest: &Path, plan: &OutputPlan) -> ZipResult<Option<ConflictAction>> {
+    let handler = match &plan.options().on_conflict {
+        Some(handler) => handler,
+        None => return Ok(None)
+    };
+    let output = match plan.output_path(entry, dest)? {
+        Some(output) => output,
+        None => return Ok(None)
+    };
+    match fs::symlink_metadata(&output) {
+        Ok(existing) if !(entry.is_dir() && existing.is_dir()) => Ok(Some(handler(&entry.name(), &output))),
+        _ => Ok(None)
+    }
+}
+
+/// Whether what a previous run wrote for an entry is still there and intact
+fn output_matches(entry: &ZipEntry, dest: &Path, plan: &OutputPlan) -> bool {
+    match plan.output_path(entry, dest) {
+        Ok(Some(output)) if entry.is_dir() => output.is_dir(),
+        Ok(Some(output)) => journal::file_crc32(&output).ok() == Some(entry.crc32()),
+        Ok(None) => true,
+        Err(_) => false
+    }
+}
+
+/// Creates the directory for an entry, and any above it
+fn create_output_dir(dest: &Path, output: &Path, options: &ExtractOptions) -> io::Result<()> {
+    if options.sandboxed {
+        sandbox::create_dir_all(dest, output.strip_prefix(dest).unwrap_or(output))?;
+        return Ok(());
+    }
+    fs::create_dir_all(output)
+}
+
+/// Creates the file an entry is written to, along with the directories it goes in
+fn create_output(dest: &Path, output: &Path, options: &ExtractOptions) -> io::Result<File> {
+    if options.sandboxed {
+        return sandbox::create_file(dest, output.strip_prefix(dest).unwrap_or(output));
+    }
+    if let Some(parent) = output.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    File::create(output)
+}
+
+/// Reopens a file an interrupted run was part way through writing, if what's there matches the start of the entry.
+/// Returns it ready to append to, along with how much of the entry is already written.
+fn reopen_partial(entry: &ZipEntry, output: &Path) -> io::Result<Option<(File, u64)>> {
+    let data = entry.raw_data();
+    let mut existing = match File::open(output) {
+        Ok(existing) => existing,
+        Err(_) => return Ok(None)
+    };
+    let written = existing.metadata()?.len();
+    if written >= data.len() as u64 {
+        return Ok(None);
+    }
+
+    let mut buffer = vec![0; 64 * 1024];
+    let mut compared = 0;
+    while compared < written as usize {
+        let read = existing.read(&mut buffer)?;
+        if read == 0 || buffer[..read] != data[compared..compared + read] {
+            return Ok(None);
+        }
+        compared += read;
+    }
+    // Not opened for appending, copy_file_range refuses O_APPEND outputs
+    let mut file = OpenOptions::new().write(true).open(output)?;
+    file.seek(SeekFrom::End(0))?;
+    Ok(Some((file, written)))
+}
+
+/// Makes a link, replacing one a previous run left. Windows needs to know whether it points at a directory.
+pub(crate) fn create_link(output: &Path, target: &str, is_dir: bool) -> io::Result<()> {
+    if fs::symlink_metadata(output).is_ok_and(|metadata| metadata.file_type().is_symlink()) {
+        fs::remove_file(output)?;
+    }
+    #[cfg(unix)]
+    {
+        let _ = is_dir;
+        std::os::unix::fs::symlink(target, output)
+    }
+    #[cfg(windows)]
+    {
+        let target = target.replace('/', "\\");
+        let created = if is_dir {
+            std::os::windows::fs::symlink_dir(&target, output)
+        } else {
+            std::os::windows::fs::symlink_file(&target, output)
+        };
+        // ERROR_PRIVILEGE_NOT_HELD, without developer mode or SeCreateSymbolicLinkPrivilege
+        match created {
+            Err(error) if error.raw_os_error() == Some(1314) => Err(io::Error::new(io::ErrorKind::PermissionDenied, error)),
+            created => created
+        }
+    }
+    #[cfg(not(any(unix, windows)))]
+    {
+        let _ = (target, is_dir);
+        Err(io::Error::new(io::ErrorKind::PermissionDenied, "symlinks aren't supported here"))
+    }
+}
+
+/// When an entry has to be finished by, for ExtractOptions::entry_time_limit
+pub(crate) struct Deadline<'a> {
+    name: &'a str,
+    limit: Option<Duration>,
+    started: Instant
+}
+
+impl Deadline<'_> {
+    /// Starts the clock on the entry called name
+    pub(crate) fn start<'a>(name: &'a str, options: &ExtractOptions) -> Deadline<'a> {
+        Deadline { name, limit: options.entry_time_limit, started: Instant::now() }
+    }
+
+    pub(crate) fn check(&self) -> ZipResult<()> {
+        match self.limit {
+            Some(limit) if self.started.elapsed() > limit => Err(ZipError::TimedOut { name: self.name.to_string(), limit }),
+            _ => Ok(())
+        }
+    }
+
+    /// Checks the deadline, removing what was written to output if it's passed
+    fn check_written(&self, output: &Path) -> ZipResult<()> {
+        self.check().inspect_err(|_| {
+            let _ = fs::remove_file(output);
+        })
+    }
+}
+
+/// Writes data out a piece at a time, stopping as soon as the deadline passes
+pub(crate) fn write_within<W: Write + ?Sized>(file: &mut W, data: &[u8], deadline: &Deadline) -> ZipResult<()> {
+    for piece in data.chunks(64 * 1024) {
+        deadline.check()?;
+        file.write_all(piece)?;
+    }
+    deadline.check()
+}
+
+/// Writes a single entry out to output. Returns the truncation details if only a prefix could be written.
+/// archive is the open archive file, used to copy stored entries directly when it's available.
+/// With resume set, a partial file left by an interrupted run is carried on from rather than rewritten.
+fn extract_entry(entry: &ZipEntry, dest: &Path, output: &Path, options: &ExtractOptions, archive: Option<&mut File>, resume: bool) -> ZipResult<Outcome> {
+    let name = entry.name();
+    let deadline = Deadline::start(&name, options);
+
+    if entry.is_dir() {
+        create_output_dir(dest, output, options)?;
+        apply_ownership(entry, output, options)?;
+        return Ok(Outcome::Extracted);
+    }
+
+    if let Some(archive) = archive.filter(|_| can_copy_directly(entry, options)) {
+        entry.check_crc(entry.raw_data())?;
+        let range = entry.data_range()?;
+        let partial = if resume && !options.sandboxed { reopen_partial(entry, output)? } else { None };
+        let (mut file, written) = match partial {
+            Some(partial) => partial,
+            None => (create_output(dest, output, options)?, 0)
+        };
+        let copied = entryreader::copy_range_to(archive, range.start + written..range.end, &mut file, Some(&deadline));
+        drop(file);
+        match copied {
+            Err(timed_out @ ZipError::TimedOut { .. }) => {
+                let _ = fs::remove_file(output);
+                return Err(timed_out);
+            },
+            copied => copied.context(Some(&name), Structure::Data, range.start + written)?
+        };
+        deadline.check_written(output)?;
+        apply_attributes(entry, output, options)?;
+        apply_ownership(entry, output, options)?;
+        return Ok(Outcome::Extracted);
+    }
+
+    let partial = entry.is_truncated() && options.extract_truncated_prefix;
+    let mut data = if partial { entry.read_available()? } else { entry.read()? };
+    if entry.is_text() {
+        data = options.line_endings.convert(data);
+    }
+    deadline.check()?;
+    let mut file = create_output(dest, output, options)?;
+    let written = write_within(&mut file, &data, &deadline);
+    drop(file);
+    if matches!(written, Err(ZipError::TimedOut { .. })) {
+        let _ = fs::remove_file(output);
+    }
+    written?;
+    apply_attributes(entry, output, options)?;
+    apply_ownership(entry, output, options)?;
+
+    if partial {
+        return Ok(Outcome::Truncated(TruncatedEntry { name, written_bytes: data.len() as u64, missing_bytes: entry.missing_bytes() }));
+    }
+    Ok(Outcome::Extracted)
+}
+
+/// Makes the link for a symlink entry written at output (output_name under dest), or whatever else plan says to do
+/// with it
+fn extract_link(plan: &OutputPlan, entry: &ZipEntry, dest: &Path, output_name: &str, output: &Path) -> ZipResult<Outcome> {
+    let options = plan.options();
+    plan.link(entry, output_name, &mut (), |_, target, is_dir| {
+        if let Some(parent) = output.parent() {
+            create_output_dir(dest, parent, options)?;
+        }
+        create_link(output, target, is_dir)
+    }, |_, source| {
+        create_output(dest, output, options)?.write_all(&source.read()?)?;
+        apply_attributes(source, output, options)
+    })
+}
+
+impl ZipArchive<'_> {
+    /// Extracts every entry under dest, in the order their data appears in the archive so reads stay sequential.
+    /// Without continue_on_error the first failure is returned as the error; with it, failures end up in the report.
+    pub fn extract_all<P: AsRef<Path>>(&self, dest: P, options: &ExtractOptions) -> ZipResult<ExtractReport> {
+        let dest = dest.as_ref();
+        // Copying straight from the archive file would read it again past the rate limit, or through the page cache
+        // direct IO kept it out of, and the data's already in memory
+        let mut archive = File::open(self.filename()).ok().filter(|_| self.max_read_rate().is_none() && !self.direct_io());
+        let mut journal = match &options.journal {
+            Some(path) => Some(ExtractJournal::open(path)?),
+            None => None
+        };
+
+        if options.check_free_space {
+            let needed = self.extracted_size(|entry| options.output_name(&entry.name()).is_some()).total();
+            if let Some(available) = freespace::available_space(dest)?.filter(|&available| available < needed) {
+                return Err(ZipError::InsufficientSpace { needed, available });
+            }
+        }
+
+        // Entries in offset order mean the archive gets read front to back, so the kernel can read well ahead
+        let ordered = self.extraction_order(options);
+        let in_offset_order = ordered.windows(2).all(|pair| entry_offset(&pair[0]) <= entry_offset(&pair[1]));
+        if let Some(archive) = archive.as_ref().filter(|_| in_offset_order) {
+            entryreader::advise(archive, 0..0, Advice::Sequential);
+        }
+
+        let report = self.run_extraction(ordered, options, |plan, entry, next| {
+            let options = plan.options();
+            if let (true, Some(archive), Some(next)) = (options.prefetch, archive.as_ref(), next) {
+                if let Ok(range) = next.data_range() {
+                    entryreader::advise(archive, range, Advice::WillNeed);
+                }
+            }
+
+            let completed = journal.as_ref().is_some_and(|journal| journal.is_completed(entry));
+            if completed && (!options.verify_resumed || output_matches(entry, dest, plan)) {
+                return Ok(Outcome::Resumed);
+            }
+            let resume = journal.as_ref().is_some_and(|journal| journal.was_started(entry));
+            if let Some(journal) = journal.as_mut() {
+                journal.start(entry)?;
+            }
+
+            let conflict = if resume { None } else { resolve_conflict(entry, dest, plan)? };
+            let output_name = match conflict {
+                Some(ConflictAction::Skip) => None,
+                Some(ConflictAction::Rename(new_name)) => plan.check(&entry.name(), &new_name)?,
+                _ => plan.output_name(entry)?
+            };
+            let result = match output_name {
+                None => Ok(Outcome::Skipped),
+                Some(output_name) => {
+                    let output = entry_output_path(dest, &output_name)?;
+                    if entry.is_symlink() && options.links != LinkPolicy::AsFile {
+                        extract_link(plan, entry, dest, &output_name, &output)
+                    } else {
+                        extract_entry(entry, dest, &output, options, archive.as_mut(), resume)
+                    }
+                }
+            };
+            if let (Ok(Outcome::Extracted | Outcome::Link(_)), Some(journal)) = (&result, journal.as_mut()) {
+                journal.finish(entry)?;
+            }
+            result
+        })?;
+
+        if let Some(journal) = journal.filter(|_| report.is_clean()) {
+            journal.remove()?;
+        }
+        Ok(report)
+    }
+
+    /// What extract_all and extract_all_to have in common: case collisions, the output plan, metrics and the
+    /// report, for the entries in ordered. write_entry does the rest for each entry, and is shown the one after it
+    /// to prefetch.
+    pub(crate) fn run_extraction<F>(&self, ordered: Vec<Result<&ZipEntry, &BrokenEntry>>, options: &ExtractOptions, mut write_entry: F) -> ZipResult<ExtractReport>
+        where F: FnMut(&OutputPlan, &ZipEntry, Option<&ZipEntry>) -> ZipResult<Outcome> {
+        let mut report = ExtractReport::default();
+        let case_plan = plan_case_collisions(&ordered, options);
+        if let (false, false, Some(first)) = (options.continue_on_error, case_plan.refused.is_empty(), case_plan.collisions.first()) {
+            return Err(ZipError::CaseCollision { name: first.name.clone(), other: first.other.clone() });
+        }
+        let refused = case_plan.refused;
+        report.case_collisions = case_plan.collisions;
+        let plan = OutputPlan::new(ordered.iter().filter_map(|entry| entry.ok()), options, case_plan.renamed);
+
+        let mut entries = ordered.iter().peekable();
+        while let Some(entry) = entries.next() {
+            let (name, result) = match entry {
+                Ok(entry) if refused.contains_key(&entry.header_offset()) => {
+                    let other = refused[&entry.header_offset()].clone();
+                    (entry.name(), Err(ZipError::CaseCollision { name: entry.name(), other }))
+                },
+                Ok(entry) => {
+                    let next = entries.peek().and_then(|next| next.as_ref().ok().copied());
+                    let started = Instant::now();
+                    let result = write_entry(&plan, entry, next);
+                    if let (Ok(Outcome::Extracted), Some(metrics)) = (&result, self.metrics()) {
+                        metrics.entry_extracted(&entry.name(), entry.uncompressed_size(), started.elapsed());
+                    }
+                    (entry.name(), result)
+                },
+                Err(broken) => {
+                    let name = broken.name.clone().unwrap_or_default();
+                    (name, Err(ZipError::InvalidArchive(broken.error.to_string())))
+                }
+            };
+
+            match result {
+                Ok(Outcome::Extracted) => report.extracted.push(name),
+                Ok(Outcome::Truncated(truncated)) => report.truncated.push(truncated),
+                Ok(Outcome::Skipped) => report.skipped.push(name),
+                Ok(Outcome::Resumed) => report.resumed.push(name),
+                Ok(Outcome::Link(link)) => report.links.push(link),
+                Err(error) if options.continue_on_error => report.failures.push(ExtractFailure { name, error }),
+                Err(error) => return Err(error)
+            }
+        }
+        Ok(report)
+    }
+
+    /// The disk space the entries filter accepts would take once extracted: their uncompressed sizes (ZIP64 sizes
+    /// included) plus an estimate of what the filesystem adds on top
+    pub fn extracted_size<F>(&self, filter: F) -> SpaceEstimate where F: Fn(&ZipEntry) -> bool {
+        let mut estimate = SpaceEstimate::default();
+        for entry in self.entries().iter().filter(|entry| filter(entry)) {
+            if entry.is_dir() {
+                estimate.overhead_bytes += ESTIMATED_BLOCK_SIZE;
+                continue;
+            }
+            let size = entry.uncompressed_size();
+            estimate.data_bytes = estimate.data_bytes.saturating_add(size);
+            estimate.overhead_bytes += (ESTIMATED_BLOCK_SIZE - size % ESTIMATED_BLOCK_SIZE) % ESTIMATED_BLOCK_SIZE;
+        }
+        estimate
+    }
+
+    /// Entries in the order extract_all writes them
+    pub(crate) fn extraction_order(&self, options: &ExtractOptions) -> Vec<Result<&ZipEntry, &BrokenEntry>> {
+        let mut ordered: Vec<_> = self.entries_lossy().collect();
+        if !options.preserve_directory_order {
+            ordered.sort_by_key(entry_offset);
+        }
+        ordered
+    }
+
+    /// Works out everything extract_all would do with these options (where each entry goes, what it overwrites,
+    /// the permissions and owners it gets, how much gets written) without creating or changing anything.
+    /// Where extract_all would stop at the first failure, the plan carries on and marks every entry that would fail.
+    pub fn extract_all_dry_run<P: AsRef<Path>>(&self, dest: P, options: &ExtractOptions) -> ZipResult<ExtractPlan> {
+        let dest = dest.as_ref();
+        let mut plan = ExtractPlan::default();
+        let journal = match &options.journal {
+            Some(path) => Some(ExtractJournal::read(path)?),
+            None => None
+        };
+
+        let ordered = self.extraction_order(options);
+        let case_plan = plan_case_collisions(&ordered, options);
+        plan.case_collisions = case_plan.collisions;
+        let outputs = OutputPlan::new(ordered.iter().filter_map(|entry| entry.ok()), options, case_plan.renamed);
+
+        for entry in ordered {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(broken) => {
+                    let name = broken.name.clone().unwrap_or_default();
+                    let action = PlannedAction::Fail(broken.error.to_string());
+                    plan.entries.push(PlannedEntry { name, output: None, action, size: 0, mode: None });
+                    continue;
+                }
+            };
+            let name = entry.name();
+            let size = if entry.is_dir() { 0 } else { entry.uncompressed_size() };
+            let mode = planned_mode(entry, options);
+            let mut planned = |output, action| plan.entries.push(PlannedEntry { name: name.clone(), output, action, size, mode });
+
+            if let Some(other) = case_plan.refused.get(&entry.header_offset()) {
+                planned(None, PlannedAction::Fail(ZipError::CaseCollision { name: name.clone(), other: other.clone() }.to_string()));
+                continue;
+            }
+            let output = match outputs.output_path(entry, dest) {
+                Ok(Some(output)) => output,
+                Ok(None) => {
+                    planned(None, PlannedAction::Skip);
+                    continue;
+                },
+                Err(error) => {
+                    planned(None, PlannedAction::Fail(error.to_string()));
+                    continue;
+                }
+            };
+            let completed = journal.as_ref().is_some_and(|journal| journal.is_completed(entry));
+            if completed && (!options.verify_resumed || output_matches(entry, dest, &outputs)) {
+                planned(Some(output), PlannedAction::Resume);
+                continue;
+            }
+            if entry.is_symlink() && options.links != LinkPolicy::AsFile {
+                planned(Some(output), PlannedAction::Link);
+                continue;
+            }
+
+            let existing = fs::symlink_metadata(&output).ok();
+            let action = match existing {
+                None => PlannedAction::Create,
+                Some(existing) if entry.is_dir() && existing.is_dir() => PlannedAction::Exists,
+                Some(_) if entry.is_dir() => PlannedAction::Fail(format!("{} is already there and isn't a directory", output.display())),
+                Some(existing) if existing.is_dir() => PlannedAction::Fail(format!("{} is already there as a directory", output.display())),
+                Some(existing) => {
+                    plan.bytes_replaced += existing.len();
+                    PlannedAction::Overwrite
+                }
+            };
+            if !matches!(action, PlannedAction::Fail(_)) {
+                plan.bytes_to_write += size;
+            }
+            plan.entries.push(PlannedEntry { name, output: Some(output), action, size, mode });
+        }
+
+        #[cfg(unix)]
+        {
+            if options.preserve_ownership && is_root() {
+                plan.ownership = self.ownership_plan(dest, options)?;
+            }
+        }
+        Ok(plan)
+    }
+
+    /// The ownership changes extract_all would make with preserve_ownership set and running as root, without
+    /// touching anything: every entry with a stored owner whose output doesn't exist yet or belongs to someone else.
+    /// Always empty off Unix.
+    pub fn ownership_plan<P: AsRef<Path>>(&self, dest: P, options: &ExtractOptions) -> ZipResult<Vec<OwnershipChange>> {
+        let mut plan = Vec::new();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            for entry in self.entries() {
+                let (uid, gid) = match entry.unix_owner() {
+                    Some(owner) => owner,
+                    None => continue
+                };
+                let path = match output_path(entry, dest.as_ref(), options)? {
+                    Some(path) => path,
+                    None => continue
+                };
+                let current = fs::symlink_metadata(&path).ok().map(|metadata| (metadata.uid(), metadata.gid()));
+                if current != Some((uid, gid)) {
+                    plan.push(OwnershipChange { path, uid, gid, current });
+                }
+            }
+        }
+        Ok(plan)
+    }
+
+    /// Reads every entry filter accepts into memory, keyed by name, for the "load all the small config files" case.
+    /// Directories are skipped. Fails with SizeLimitExceeded rather than read more than max_total bytes in all.
+    pub fn extract_to_memory<F>(&self, filter: F, max_total: u64) -> ZipResult<HashMap<String, Vec<u8>>> where F: Fn(&ZipEntry) -> bool {
+        let mut contents = HashMap::new();
+        let mut total: u64 = 0;
+        for entry in self.entries().iter().filter(|entry| !entry.is_dir() && filter(entry)) {
+            let too_big = ZipError::SizeLimitExceeded { name: entry.name(), limit: max_total };
+            // Checked before reading, going by the header, so an oversized entry never gets decoded,
+            // then again by what was actually read, in case the header lied
+            if total.saturating_add(entry.uncompressed_size()) > max_total {
+                return Err(too_big);
+            }
+            let data = entry.read()?;
+            total += data.len() as u64;
+            if total > max_total {
+                return Err(too_big);
+            }
+            contents.insert(entry.name(), data);
+        }
+        Ok(contents)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Write};
+    use super::*;
+    use crate::sink::FsSink;
+    use crate::zipwriter::{FileOptions, ZipWriter};
+
+    /// An archive whose top/esc links back out of wherever top/ is extracted, with a file to write through it
+    fn escaping_archive() -> Vec<u8> {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        writer.add_directory("top/", FileOptions::default()).unwrap();
+        writer.add_symlink("top/esc", "..", FileOptions::default()).unwrap();
+        writer.add_symlink("top/inside", "sub", FileOptions::default()).unwrap();
+        writer.add_directory("top/sub/", FileOptions::default()).unwrap();
+        writer.start_file("top/esc/pwned", FileOptions::default()).unwrap();
+        writer.write_all(b"outside").unwrap();
+        writer.start_file("top/inside/through", FileOptions::default()).unwrap();
+        writer.write_all(b"through a link").unwrap();
+        writer.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn case_collisions_are_told_apart_by_entry() {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, contents) in [("README.md", "upper"), ("a.txt", "first"), ("readme.md", "lower"), ("a.txt", "second")] {
+            writer.start_file(name, FileOptions::default()).unwrap();
+            writer.write_all(contents.as_bytes()).unwrap();
+        }
+        let archive = ZipArchive::parse_bytes(&writer.finish().unwrap().into_inner()).unwrap();
+        let root = std::env::temp_dir().join(format!("rip-extract-case-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let read = |policy: &str, name: &str| fs::read_to_string(root.join(policy).join(name)).ok();
+
+        let options = |case_collisions| ExtractOptions { case_collisions, ..ExtractOptions::default() };
+        let last_wins = archive.extract_all(root.join("last"), &options(CaseCollisions::LastWins)).unwrap();
+        let renamed = archive.extract_all(root.join("rename"), &options(CaseCollisions::Rename)).unwrap();
+        let refused = archive.extract_all(root.join("error"), &options(CaseCollisions::Error));
+        let continued = archive.extract_all(root.join("continue"), &ExtractOptions { continue_on_error: true, ..options(CaseCollisions::Error) }).unwrap();
+        let contents = [
+            [read("last", "README.md"), read("last", "readme.md"), read("last", "a.txt")],
+            [read("rename", "README.md"), read("rename", "readme (1).md"), read("rename", "a (1).txt")],
+            [read("continue", "README.md"), read("continue", "readme.md"), read("continue", "a.txt")]
+        ];
+        let first_renamed = read("rename", "a.txt");
+        let error_wrote = root.join("error").exists();
+        fs::remove_dir_all(&root).unwrap();
+
+        let own = |value: &str| Some(value.to_string());
+        let collided = |report: &ExtractReport| report.case_collisions.iter().map(|collision| (collision.name.clone(), collision.other.clone())).collect::<Vec<_>>();
+        let expected = vec![("readme.md".to_string(), "README.md".to_string()), ("a.txt".to_string(), "a.txt".to_string())];
+        assert_eq!(collided(&last_wins), expected);
+        assert_eq!(contents[0], [own("upper"), own("lower"), own("second")]);
+
+        assert_eq!(collided(&renamed), expected);
+        let new_names: Vec<_> = renamed.case_collisions.iter().map(|collision| collision.renamed.clone()).collect();
+        assert_eq!(new_names, [own("readme (1).md"), own("a (1).txt")]);
+        assert_eq!(contents[1], [own("upper"), own("lower"), own("second")]);
+        assert_eq!(first_renamed, own("first"));
+
+        assert!(matches!(refused, Err(ZipError::CaseCollision { name, other }) if name == "readme.md" && other == "README.md"));
+        assert!(!error_wrote);
+        let failed: Vec<&str> = continued.failures.iter().map(|failure| failure.name.as_str()).collect();
+        assert_eq!(failed, ["readme.md", "a.txt"]);
+        assert_eq!(contents[2], [own("upper"), None, own("first")]);
+    }
+
+    #[test]
+    fn entries_past_their_time_limit_are_abandoned() {
+        let root = std::env::temp_dir().join(format!("rip-extract-deadline-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        // Stored, so they're copied file to file
+        for name in ["first.bin", "second.bin"] {
+            writer.start_file(name, FileOptions::default()).unwrap();
+            writer.write_all(&vec![b'x'; 200 * 1024]).unwrap();
+        }
+        let path = root.join("slow.zip");
+        fs::write(&path, writer.finish().unwrap().into_inner()).unwrap();
+        let path = path.to_str().unwrap().to_string();
+        let archive = ZipArchive::new(&path);
+        let options = ExtractOptions { entry_time_limit: Some(Duration::from_nanos(1)), ..ExtractOptions::default() };
+
+        let stopped = archive.extract_all(root.join("stopped"), &options);
+        let continued = archive.extract_all(root.join("continued"), &ExtractOptions { continue_on_error: true, ..options }).unwrap();
+        let left_behind = ["first.bin", "second.bin"].iter().any(|name| root.join("continued").join(name).exists());
+        fs::remove_dir_all(&root).unwrap();
+
+        assert!(matches!(stopped, Err(ZipError::TimedOut { name, .. }) if name == "first.bin"));
+        let failed: Vec<(&str, bool)> = continued.failures.iter()
+            .map(|failure| (failure.name.as_str(), matches!(failure.error, ZipError::TimedOut { .. })))
+            .collect();
+        assert_eq!(failed, [("first.bin", true), ("second.bin", true)]);
+        assert!(!left_behind);
+    }
+
+    #[test]
+    fn special_bits_need_asking_for() {
+        let mode = 0o6755;
+        assert_eq!(Permissions::default(), Permissions::Stored);
+        assert_eq!(Permissions::Stored.apply(mode), Some(0o755));
+        assert_eq!(Permissions::StoredWithSpecialBits.apply(mode), Some(0o6755));
+        assert_eq!(Permissions::StoredWithSpecialBits.apply(0o104755), Some(0o4755));
+        #[cfg(unix)]
+        assert_eq!(Permissions::Umask.apply(mode), Some(0o755 & !process_umask()));
+        assert_eq!(Permissions::Mask(0o022).apply(0o1777), Some(0o1755));
+        assert_eq!(Permissions::Mask(0o7022).apply(mode), Some(0o755));
+        assert_eq!(Permissions::Ignore.apply(mode), None);
+    }
+
+    #[test]
+    fn links_are_checked_where_they_land() {
+        let root = std::env::temp_dir().join(format!("rip-extract-links-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let dest = root.join("dest");
+        let archive = ZipArchive::parse_bytes(&escaping_archive()).unwrap();
+        let options = ExtractOptions { links: LinkPolicy::Create, continue_on_error: true, ..ExtractOptions::default() }.strip_components(1);
+
+        let report = archive.extract_all(&dest, &options).unwrap();
+        let escaped = root.join("pwned").exists();
+        let through = dest.join("sub/through").exists();
+        fs::remove_dir_all(&root).unwrap();
+
+        assert!(!escaped);
+        assert!(!through);
+        assert_eq!(report.links[0].result, LinkResult::Skipped("it points outside the destination".to_string()));
+        let failed: Vec<&str> = report.failures.iter().map(|failure| failure.name.as_str()).collect();
+        assert_eq!(failed, ["top/esc/pwned", "top/inside/through"]);
+        assert!(report.failures.iter().all(|failure| matches!(failure.error, ZipError::UnsafePath(_))));
+    }
+
+    #[test]
+    fn sinks_get_the_same_checks() {
+        let root = std::env::temp_dir().join(format!("rip-sink-links-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let dest = root.join("dest");
+        let archive = ZipArchive::parse_bytes(&escaping_archive()).unwrap();
+        let options = ExtractOptions { links: LinkPolicy::Create, continue_on_error: true, ..ExtractOptions::default() }.strip_components(1);
+
+        let report = archive.extract_all_to(&mut FsSink::new(&dest), &options).unwrap();
+        let escaped = root.join("pwned").exists();
+        let through = dest.join("sub/through").exists();
+        fs::remove_dir_all(&root).unwrap();
+
+        assert!(!escaped);
+        assert!(!through);
+        let failed: Vec<&str> = report.failures.iter().map(|failure| failure.name.as_str()).collect();
+        assert_eq!(failed, ["top/esc/pwned", "top/inside/through"]);
+    }
+
+    #[test]
+    fn resumes_from_the_journal() {
+        let root = std::env::temp_dir().join(format!("rip-extract-resume-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let dest = root.join("dest");
+        fs::create_dir_all(&dest).unwrap();
+        let path = root.join("archive.zip");
+        let mut writer = ZipWriter::new(File::create(&path).unwrap());
+        writer.start_file("done.txt", FileOptions::default()).unwrap();
+        writer.write_all(b"finished last time").unwrap();
+        writer.start_file("partial.bin", FileOptions::default()).unwrap();
+        writer.write_all(&[7; 10000]).unwrap();
+        writer.start_file("untouched.txt", FileOptions::default()).unwrap();
+        writer.write_all(b"never reached").unwrap();
+        writer.finish().unwrap();
+
+        // What a run killed part way through partial.bin leaves behind
+        let archive = ZipArchive::new(path.to_str().unwrap());
+        let [done, partial, _] = archive.entries() else { panic!("expected three entries") };
+        let journal = root.join("journal");
+        fs::write(&journal, format!("started {0}\ndone {0} {1:08x}\nstarted {2}\n", done.header_offset(), done.crc32(), partial.header_offset())).unwrap();
+        fs::write(dest.join("done.txt"), b"changed since").unwrap();
+        fs::write(dest.join("partial.bin"), [7; 4000]).unwrap();
+
+        let options = ExtractOptions { journal: Some(journal.clone()), ..ExtractOptions::default() };
+        let report = archive.extract_all(&dest, &options).unwrap();
+        let kept = fs::read(dest.join("done.txt")).unwrap();
+        let resumed = fs::read(dest.join("partial.bin")).unwrap();
+        let journal_left = journal.exists();
+
+        // Checking what's there catches the file changed since
+        fs::write(&journal, format!("done {} {:08x}\n", done.header_offset(), done.crc32())).unwrap();
+        let verified = ExtractOptions { journal: Some(journal.clone()), verify_resumed: true, ..ExtractOptions::default() };
+        let rewritten = archive.extract_all(&dest, &verified).unwrap();
+        let repaired = fs::read(dest.join("done.txt")).unwrap();
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(report.resumed, ["done.txt"]);
+        assert_eq!(report.extracted, ["partial.bin", "untouched.txt"]);
+        assert_eq!(kept, b"changed since");
+        assert_eq!(resumed, [7; 10000]);
+        assert!(!journal_left);
+        assert!(rewritten.resumed.is_empty());
+        assert_eq!(repaired, b"finished last time");
+    }
+
+    #[test]
+    fn dry_run_refuses_writing_through_links() {
+        let archive = ZipArchive::parse_bytes(&escaping_archive()).unwrap();
+        let options = ExtractOptions { links: LinkPolicy::Create, ..ExtractOptions::default() }.strip_components(1);
+        let plan = archive.extract_all_dry_run(std::env::temp_dir().join("rip-dry-run-nowhere"), &options).unwrap();
+        let failed: Vec<&str> = plan.entries.iter().filter(|planned| matches!(planned.action, PlannedAction::Fail(_))).map(|planned| planned.name.as_str()).collect();
+        assert_eq!(failed, ["top/esc/pwned", "top/inside/through"]);
+    }
+}
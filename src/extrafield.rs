@@ -0,0 +1,95 @@
+#![allow(dead_code)]
+// Extra field header ids: https://libzip.org/specifications/extrafld.txt
+
+/// ZIP64 extended information (sizes and offsets that overflow the 32 bit header fields)
+pub const ZIP64_EXTENDED_INFO: u16 = 0x0001;
+/// NTFS modification, access and creation times, in 100ns ticks
+pub const NTFS_TIMES: u16 = 0x000A;
+/// PKWARE's Unix record: access and modification times, uid, gid, then link target or device numbers
+pub const PKWARE_UNIX: u16 = 0x000D;
+/// Info-ZIP's extended timestamp ("UT"): flags, then Unix modification, access and creation times
+pub const EXTENDED_TIMESTAMP: u16 = 0x5455;
+/// Android's alignment padding: the alignment as a u16, then zeros (used by zipalign and apksigner)
+pub const ALIGNMENT_PADDING: u16 = 0xD935;
+/// WinZip AES encryption data, present on every entry using compression method 99
+pub const AES_EXTRA_DATA: u16 = 0x9901;
+/// Info-ZIP's Unix uid and gid ("ux"): a version byte, then each id as a size byte and that many bytes
+pub const INFOZIP_UNIX_OWNER: u16 = 0x7875;
+/// Info-ZIP's older Unix record ("Ux"): 16 bit uid and gid, only present in full in the local header
+pub const INFOZIP_UNIX_OWNER_OLD: u16 = 0x7855;
+/// Info-ZIP's Unicode path ("up"): a UTF-8 copy of the name, which readers prefer over the header's name
+pub const INFOZIP_UNICODE_PATH: u16 = 0x7075;
+
+/// Iterates the (header id, data) records packed into an extra field.
+/// Stops at the first record whose length runs past the end of the field.
+pub struct ExtraFieldIter<'a> {
+    data: &'a [u8]
+}
+
+impl<'a> ExtraFieldIter<'a> {
+    pub fn new(data: &'a [u8]) -> ExtraFieldIter<'a> {
+        ExtraFieldIter { data }
+    }
+}
+
+impl<'a> Iterator for ExtraFieldIter<'a> {
+    type Item = (u16, &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.data.len() < 4 {
+            return None;
+        }
+
+        let id = u16::from_le_bytes([self.data[0], self.data[1]]);
+        let size = u16::from_le_bytes([self.data[2], self.data[3]]) as usize;
+        if self.data.len() < 4 + size {
+            self.data = &[];
+            return None;
+        }
+
+        let record = &self.data[4..4 + size];
+        self.data = &self.data[4 + size..];
+        Some((id, record))
+    }
+}
+
+/// Returns the data of the first record with the given header id
+pub fn find(extra_field: &[u8], id: u16) -> Option<&[u8]> {
+    ExtraFieldIter::new(extra_field).find(|(record_id, _)| *record_id == id).map(|(_, data)| data)
+}
+
+/// A copy of the extra field with every record of the given header id left out
+pub fn without(extra_field: &[u8], id: u16) -> Vec<u8> {
+    let mut kept = Vec::new();
+    for (record_id, data) in ExtraFieldIter::new(extra_field).filter(|(record_id, _)| *record_id != id) {
+        push(&mut kept, record_id, data);
+    }
+    kept
+}
+
+/// Appends a record to an extra field
+pub fn push(extra_field: &mut Vec<u8>, id: u16, data: &[u8]) {
+    extra_field.extend_from_slice(&id.to_le_bytes());
+    extra_field.extend_from_slice(&(data.len() as u16).to_le_bytes());
+    extra_field.extend_from_slice(data);
+}
+
+/// The (uid, gid) from an INFOZIP_UNIX_OWNER record. None for an unknown version or ids too big for a u32.
+pub fn parse_unix_owner(data: &[u8]) -> Option<(u32, u32)> {
+    if data.first() != Some(&1) {
+        return None;
+    }
+    let mut rest = &data[1..];
+    let mut ids = [0u32; 2];
+    for id in ids.iter_mut() {
+        let (&size, after) = rest.split_first()?;
+        let bytes = after.get(..size as usize)?;
+        // Little-endian and any width, but anything past four bytes has to be zero to fit
+        if bytes.iter().skip(4).any(|&byte| byte != 0) {
+            return None;
+        }
+        *id = bytes.iter().take(4).rev().fold(0, |value, &byte| (value << 8) | byte as u32);
+        rest = &after[size as usize..];
+    }
+    Some((ids[0], ids[1]))
+}
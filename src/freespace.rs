@@ -0,0 +1,66 @@
+#![allow(dead_code)]
+// How much room is left on the filesystem a path is on, for checking an extraction will fit before starting it
+use std::io;
+use std::path::Path;
+
+/// The nearest ancestor of path that exists, since the destination usually hasn't been created yet
+fn existing_ancestor(path: &Path) -> &Path {
+    path.ancestors().find(|ancestor| ancestor.exists()).unwrap_or(Path::new("."))
+}
+
+#[cfg(all(target_os = "linux", target_pointer_width = "64"))]
+#[repr(C)]
+#[derive(Default)]
+struct StatVfs {
+    f_bsize: u64,
+    f_frsize: u64,
+    f_blocks: u64,
+    f_bfree: u64,
+    f_bavail: u64,
+    f_files: u64,
+    f_ffree: u64,
+    f_favail: u64,
+    f_fsid: u64,
+    f_flag: u64,
+    f_namemax: u64,
+    /// Reserved space, which glibc and musl size differently
+    spare: [u64; 8]
+}
+
+/// Bytes available to an unprivileged user on the filesystem holding path, None where we can't ask
+#[cfg(all(target_os = "linux", target_pointer_width = "64"))]
+pub fn available_space(path: &Path) -> io::Result<Option<u64>> {
+    use std::os::unix::ffi::OsStrExt;
+    extern "C" {
+        fn statvfs(path: *const std::os::raw::c_char, buf: *mut StatVfs) -> i32;
+    }
+
+    let path = std::ffi::CString::new(existing_ancestor(path).as_os_str().as_bytes())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "Path contains a NUL byte"))?;
+    let mut stats = StatVfs::default();
+    if unsafe { statvfs(path.as_ptr(), &mut stats) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(Some(stats.f_bavail.saturating_mul(stats.f_frsize)))
+}
+
+#[cfg(windows)]
+pub fn available_space(path: &Path) -> io::Result<Option<u64>> {
+    use std::os::windows::ffi::OsStrExt;
+    extern "system" {
+        fn GetDiskFreeSpaceExW(directory: *const u16, available: *mut u64, total: *mut u64, free: *mut u64) -> i32;
+    }
+
+    let wide_path: Vec<u16> = existing_ancestor(path).as_os_str().encode_wide().chain(Some(0)).collect();
+    let mut available = 0;
+    if unsafe { GetDiskFreeSpaceExW(wide_path.as_ptr(), &mut available, std::ptr::null_mut(), std::ptr::null_mut()) } == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(Some(available))
+}
+
+#[cfg(not(any(all(target_os = "linux", target_pointer_width = "64"), windows)))]
+pub fn available_space(path: &Path) -> io::Result<Option<u64>> {
+    let _ = existing_ancestor(path);
+    Ok(None)
+}
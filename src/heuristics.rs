@@ -0,0 +1,146 @@
+#![allow(dead_code)]
+// Per-entry signs of packed or tampered content, the kind of thing archive scanners look for before a deeper look
+use std::fmt;
+use std::path::Path;
+use crate::options::ReaderOptions;
+use crate::sniff::{self, FileType};
+use crate::ziparchive::{self, ZipArchive};
+use crate::zipentry::{ZipEntry, CompressionMethod};
+use crate::ziperror::{ZipError, ZipResult};
+
+/// Entries smaller than this don't say much about their entropy or compressibility
+const MIN_SAMPLE: u64 = 256;
+/// Stored data this close to 8 bits per byte is compressed or encrypted already
+const HIGH_ENTROPY: f64 = 7.9;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Indicator {
+    /// Compression saved less than 2%, which means the data was compressed or encrypted before it went in
+    Incompressible { compressed: u64, uncompressed: u64 },
+    /// Stored data that looks random but isn't a format known to be compressed
+    HighEntropy { bits_per_byte: f64 },
+    /// The data descriptor after the data disagrees with the central directory
+    DescriptorMismatch { field: &'static str, central: u64, descriptor: u64 },
+    /// The data doesn't match the crc the archive gives for it
+    CrcMismatch { expected: u32, actual: u32 },
+    /// The contents are a type that doesn't go with the name's extension, like an executable called report.pdf
+    ExtensionMismatch { detected: FileType }
+}
+
+impl fmt::Display for Indicator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Indicator::Incompressible { compressed, uncompressed } => write!(f, "compressed {} bytes to {}", uncompressed, compressed),
+            Indicator::HighEntropy { bits_per_byte } => write!(f, "{:.2} bits of entropy per byte", bits_per_byte),
+            Indicator::DescriptorMismatch { field, central, descriptor } =>
+                write!(f, "central directory and data descriptor disagree on {} ({:#x} != {:#x})", field, central, descriptor),
+            Indicator::CrcMismatch { expected, actual } => write!(f, "crc32 {:08x} != {:08x}", actual, expected),
+            Indicator::ExtensionMismatch { detected } => write!(f, "contents are {}", detected.mime)
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EntryIndicator {
+    pub entry: String,
+    pub indicator: Indicator
+}
+
+impl fmt::Display for EntryIndicator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.entry, self.indicator)
+    }
+}
+
+/// Shannon entropy in bits per byte, 0 for empty data
+pub fn entropy(data: &[u8]) -> f64 {
+    let mut counts = [0u64; 256];
+    for &byte in data {
+        counts[byte as usize] += 1;
+    }
+    let total = data.len() as f64;
+    counts.iter().filter(|&&count| count > 0).map(|&count| {
+        let p = count as f64 / total;
+        -p * p.log2()
+    }).sum()
+}
+
+fn descriptor_mismatches(entry: &ZipEntry, file: &mut ziparchive::ArchiveReader) -> Vec<Indicator> {
+    let data_end = match entry.data_range() {
+        Ok(range) => range.end,
+        Err(_) => return Vec::new()
+    };
+    let descriptor = match ziparchive::read_data_descriptor(file, &entry.local, data_end) {
+        Some(descriptor) => descriptor,
+        None => return Vec::new()
+    };
+
+    let fields = [
+        ("crc32", entry.crc32() as u64, descriptor.crc32 as u64),
+        ("compressed size", entry.compressed_size(), descriptor.compressed_size),
+        ("uncompressed size", entry.uncompressed_size(), descriptor.uncompressed_size)
+    ];
+    fields.iter().filter(|(_, central, descriptor)| central != descriptor)
+        .map(|&(field, central, descriptor)| Indicator::DescriptorMismatch { field, central, descriptor })
+        .collect()
+}
+
+fn content_indicators(entry: &ZipEntry) -> Vec<Indicator> {
+    let mut indicators = Vec::new();
+    let data = match entry.read() {
+        Ok(data) => data,
+        Err(ZipError::CrcMismatch { expected, actual, .. }) => {
+            indicators.push(Indicator::CrcMismatch { expected, actual });
+            return indicators;
+        },
+        // Encrypted or compressed with something we can't decode, so there's nothing to look at
+        Err(_) => return indicators
+    };
+
+    let detected = sniff::sniff(&data);
+    if detected.matches_name(&entry.name()) == Some(false) {
+        indicators.push(Indicator::ExtensionMismatch { detected });
+    }
+    // Images, archives and the like are meant to look random; it's unexplained randomness that stands out
+    let known_packed = !detected.extensions.is_empty();
+    if entry.compression_method() == CompressionMethod::Stored && !known_packed && data.len() as u64 >= MIN_SAMPLE {
+        let bits_per_byte = entropy(&data);
+        if bits_per_byte >= HIGH_ENTROPY {
+            indicators.push(Indicator::HighEntropy { bits_per_byte });
+        }
+    }
+    indicators
+}
+
+impl ZipArchive<'_> {
+    /// Looks through every entry for signs of packed or tampered content: compression that achieved nothing,
+    /// random-looking stored data, data descriptors that contradict the central directory, crc mismatches and
+    /// contents that don't fit the extension. Entries that can't be decoded only get the checks that don't need
+    /// their contents. Data descriptors are read from the archive file, so they're skipped for archives from
+    /// parse_bytes.
+    pub fn entry_indicators(&self) -> ZipResult<Vec<EntryIndicator>> {
+        let mut file = if self.filename().is_empty() {
+            None
+        } else {
+            Some(ziparchive::open_reader(Path::new(self.filename()), &ReaderOptions::default())?)
+        };
+
+        let mut found = Vec::new();
+        for entry in self.entries().iter().filter(|entry| !entry.is_dir()) {
+            let mut indicators = Vec::new();
+            let (compressed, uncompressed) = (entry.compressed_size(), entry.uncompressed_size());
+            if entry.compression_method() != CompressionMethod::Stored && uncompressed >= MIN_SAMPLE
+                && compressed.saturating_mul(100) >= uncompressed.saturating_mul(98) {
+                indicators.push(Indicator::Incompressible { compressed, uncompressed });
+            }
+            if let Some(file) = file.as_mut() {
+                indicators.extend(descriptor_mismatches(entry, file));
+            }
+            indicators.extend(content_indicators(entry));
+
+            let name = entry.name();
+            found.extend(indicators.into_iter().map(|indicator| EntryIndicator { entry: name.clone(), indicator }));
+        }
+        Ok(found)
+    }
+}
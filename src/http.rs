@@ -0,0 +1,102 @@
+#![allow(dead_code)]
+// Helpers for serving archive entries over HTTP: validators (RFC 9110 section 8.8) and single byte ranges (section 14)
+use std::ops::Range;
+use crate::datetime::DateTime;
+use crate::entryreader;
+use crate::ziparchive::ZipArchive;
+use crate::zipentry::ZipEntry;
+use crate::ziperror::{ZipError, ZipResult};
+
+const WEEKDAYS: [&str; 7] = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+/// What a Range header asks for, worked out against the entry's size
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RangeRequest {
+    /// No usable range (missing, malformed, or several ranges at once), so send the whole entry with 200
+    Full,
+    /// Send this part with 206 and a Content-Range
+    Partial(Range<u64>),
+    /// Nothing in the entry matches, answer 416
+    Unsatisfiable
+}
+
+/// Days since 1970-01-01 for a proleptic Gregorian date, from Howard Hinnant's days_from_civil
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// Formats a time as an HTTP date, like "Sun, 06 Nov 1994 08:49:37 GMT".
+/// Zip times carry no time zone, so the time is taken to be UTC.
+pub fn http_date(time: DateTime) -> String {
+    let days = days_from_civil(time.year() as i64, time.month() as i64, time.day() as i64);
+    // 1970-01-01 was a Thursday
+    let weekday = WEEKDAYS[(days + 3).rem_euclid(7) as usize];
+    format!("{}, {:02} {} {} {:02}:{:02}:{:02} GMT", weekday, time.day(), MONTHS[time.month() as usize - 1], time.year(),
+        time.hour(), time.minute(), time.second())
+}
+
+/// Parses a Range header value ("bytes=0-99", "bytes=500-", "bytes=-500") against an entry of size bytes
+pub fn parse_range(header: &str, size: u64) -> RangeRequest {
+    let spec = match header.trim().strip_prefix("bytes=") {
+        Some(spec) if !spec.contains(',') => spec.trim(),
+        _ => return RangeRequest::Full
+    };
+    let (first, last) = match spec.split_once('-') {
+        Some(parts) => parts,
+        None => return RangeRequest::Full
+    };
+
+    let range = match (first.parse::<u64>(), last.parse::<u64>()) {
+        // The last n bytes
+        (Err(_), Ok(suffix)) if first.is_empty() => {
+            if suffix == 0 {
+                return RangeRequest::Unsatisfiable;
+            }
+            size.saturating_sub(suffix)..size
+        },
+        (Ok(first), Err(_)) if last.is_empty() => first..size,
+        (Ok(first), Ok(last)) if first <= last => first..size.min(last.saturating_add(1)),
+        _ => return RangeRequest::Full
+    };
+    if range.start >= size {
+        return RangeRequest::Unsatisfiable;
+    }
+    RangeRequest::Partial(range)
+}
+
+/// The Content-Range value for a partial response, "bytes 0-99/1000"
+pub fn content_range(range: &Range<u64>, size: u64) -> String {
+    format!("bytes {}-{}/{}", range.start, range.end.saturating_sub(1), size)
+}
+
+impl ZipEntry {
+    /// A strong ETag from the crc and size. Any change to the contents that keeps both is vanishingly unlikely.
+    pub fn etag(&self) -> String {
+        format!("\"{:08x}-{:x}\"", self.crc32(), self.uncompressed_size())
+    }
+
+    /// The modification time as a Last-Modified value, None if the entry's time is invalid
+    pub fn http_last_modified(&self) -> Option<String> {
+        self.last_modified().map(http_date)
+    }
+}
+
+impl ZipArchive<'_> {
+    /// Part of a stored entry's contents, straight from the loaded data without decoding anything.
+    /// Compressed or encrypted entries return UnsupportedMethod, as a range of them can't be had without decoding
+    /// everything before it. The range is clipped to the entry; the crc isn't checked, as only part is being read.
+    pub fn entry_range(&self, name: &str, range: Range<u64>) -> ZipResult<&[u8]> {
+        let entry = self.by_name(name).ok_or_else(|| ZipError::FileNotFound(name.to_string()))?;
+        entryreader::stored_range(entry)?;
+        let data = entry.raw_data();
+        let end = (range.end as usize).min(data.len());
+        let start = (range.start as usize).min(end);
+        Ok(&data[start..end])
+    }
+}
@@ -32,19 +32,19 @@ pub struct HuffmanNode {
 
 impl Ord for HuffmanNode {
     fn cmp(&self, other: &Self) -> Ordering {
-        return self.freq_value.cmp(&other.freq_value);
+        self.freq_value.cmp(&other.freq_value)
     }
 }
 
 impl PartialOrd for HuffmanNode {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        return Some(self.cmp(other));
+        Some(self.cmp(other))
     }
 }
 
 impl PartialEq for HuffmanNode {
     fn eq (&self, other: &Self) -> bool {
-        return self.freq_value == other.freq_value;
+        self.freq_value == other.freq_value
     }
 }
 
@@ -56,13 +56,12 @@ impl HuffmanNode {
 
         // create a frequency map, and build each huffman node
         for c in data.chars() {
-            if !freq_map.contains_key(&c){
-                freq_map.insert(c, HuffmanNode {freq_value: 1, value: Some(c), left: None, right: None});
-            }
-            else{
-                let mut item = freq_map.get_mut(&c).unwrap();
+            if let std::collections::hash_map::Entry::Vacant(e) = freq_map.entry(c) {
+                e.insert(HuffmanNode {freq_value: 1, value: Some(c), left: None, right: None});
+            } else {
+                let item = freq_map.get_mut(&c).unwrap();
 
-                item.freq_value = item.freq_value + 1;
+                item.freq_value += 1;
             }
         }
 
@@ -73,7 +72,7 @@ impl HuffmanNode {
         while let Some(node1) = min_heap.pop() {
             let tmp_node2 = min_heap.pop();
 
-            if !tmp_node2.is_some(){
+            if tmp_node2.is_none(){
                 return node1.0;
             }
 
@@ -90,7 +89,7 @@ impl HuffmanNode {
         }
 
         // should never get down here.
-        return HuffmanNode {freq_value: 1, value: Some('d'), left: None, right: None}
+        HuffmanNode {freq_value: 1, value: Some('d'), left: None, right: None}
     }
 }
 
@@ -98,7 +97,7 @@ pub fn gen_codes(root_node: &HuffmanNode) -> Vec<HuffCode>{
     let mut out_codes: Vec<HuffCode> = Vec::new();
 
     recurse_codes(root_node, &mut out_codes, "".to_string(), 0, 0);
-    return out_codes;
+    out_codes
 }
 
 pub fn gen_code_map(root_node: &HuffmanNode) -> HashMap<char, HuffCode> {
@@ -109,15 +108,14 @@ pub fn gen_code_map(root_node: &HuffmanNode) -> HashMap<char, HuffCode> {
         out_map.insert(code.val, code);
     }
 
-    return out_map;
+    out_map
 
 }
 
 fn recurse_codes(node: &HuffmanNode, codes: &mut Vec<HuffCode>, location_str: String, location: u64, depth: u8){
 
     let loc_clone = location_str.to_owned();
-    if node.value.is_some() {
-        let char_val = node.value.unwrap();
+    if let Some(char_val) = node.value {
         codes.push(HuffCode {val: char_val, bitlength: depth, code: location, code_str: loc_clone.clone()})
     }
 
@@ -127,12 +125,12 @@ fn recurse_codes(node: &HuffmanNode, codes: &mut Vec<HuffCode>, location_str: St
     let left_code = location << 1;
     let right_code = (location << 1) | 1;
 
-    if node.left.is_some() {
-        recurse_codes(&node.left.as_ref().unwrap(), codes, left_code_str, left_code, depth + 1)
+    if let Some(left) = node.left.as_ref() {
+        recurse_codes(left, codes, left_code_str, left_code, depth + 1)
     }
 
-    if node.right.is_some() {
-        recurse_codes(&node.right.as_ref().unwrap(), codes, right_code_str, right_code, depth + 1);
+    if let Some(right) = node.right.as_ref() {
+        recurse_codes(right, codes, right_code_str, right_code, depth + 1);
     }
 
 }
@@ -141,17 +139,17 @@ fn recurse_codes(node: &HuffmanNode, codes: &mut Vec<HuffCode>, location_str: St
 // Each HuffCode has a u64 code (which stores the actual binary data)
 // and u8 bitlength, which determines the length of the u64 code we are taking.
 // We are trying to concatenate all of these into a single vector of u8s.
-pub fn codes_to_bin(codes: &mut Vec<HuffCode>) -> Vec<u8> {
+pub fn codes_to_bin(codes: &mut [HuffCode]) -> Vec<u8> {
     let mut output_tmp:Vec<u8> = Vec::new();
-    let most_significant = 0x8000000000000000 as u64;
+    let most_significant = 0x8000000000000000_u64;
 
-    for huffCode in codes.into_iter() {
-        let mut code = huffCode.code;
+    for huff_code in codes.iter_mut() {
+        let mut code = huff_code.code;
         let mut index = 0;
 
-        code = code << (64 - huffCode.bitlength);
+        code <<= 64 - huff_code.bitlength;
 
-        while index < huffCode.bitlength {
+        while index < huff_code.bitlength {
             if code & most_significant == most_significant {
                 output_tmp.push(1);
             }
@@ -159,7 +157,7 @@ pub fn codes_to_bin(codes: &mut Vec<HuffCode>) -> Vec<u8> {
                 output_tmp.push(0);
             }
             index += 1;
-            code = code << 1;
+            code <<= 1;
         }
     }
     
@@ -170,16 +168,16 @@ pub fn codes_to_bin(codes: &mut Vec<HuffCode>) -> Vec<u8> {
     while index < output_tmp.len() {
         // println!("{}", output_tmp[index]);
         if output_tmp[index] == 1 {
-            tmp_byte = tmp_byte | 1;
+            tmp_byte |= 1;
         }
         else {
-            tmp_byte = tmp_byte & 0b11111110;
+            tmp_byte &= 0b11111110;
         }
 
         if index % 8 == 7 || index + 1 == output_tmp.len() {
             // println!("Pushing!: {:08b} @ i:{}", tmp_byte, index);
             if index + 1 == output_tmp.len() {
-                tmp_byte = tmp_byte << (8 - (output_tmp.len() % 8));
+                tmp_byte <<= 8 - (output_tmp.len() % 8);
             }
             output.push(tmp_byte);
             tmp_byte = 0;
@@ -188,7 +186,7 @@ pub fn codes_to_bin(codes: &mut Vec<HuffCode>) -> Vec<u8> {
         index += 1;
 
 
-        tmp_byte = tmp_byte << 1;
+        tmp_byte <<= 1;
     }
 
     // println!("==========\nOutput binary");
@@ -201,5 +199,5 @@ pub fn codes_to_bin(codes: &mut Vec<HuffCode>) -> Vec<u8> {
     // }
     // println!();
 
-    return output;
+    output
 }
\ No newline at end of file
@@ -0,0 +1,157 @@
+#![allow(dead_code)]
+// .gitignore-style exclude rules: https://git-scm.com/docs/gitignore#_pattern_format
+use std::fs;
+use std::io;
+use std::path::Path;
+
+#[derive(Debug, Clone)]
+struct Rule {
+    pattern: String,
+    /// '!' rules re-include something an earlier rule excluded
+    negated: bool,
+    /// A trailing '/' means the rule only matches directories
+    dir_only: bool,
+    /// Rules with a '/' anywhere but the end match from their base directory, the rest match a name at any depth
+    anchored: bool,
+    /// The directory (relative to the walk root, ending in '/') of the .gitignore the rule came from, empty for the root
+    base: String
+}
+
+/// An ordered list of exclude rules. Like git, the last rule that matches a path decides whether it's excluded.
+#[derive(Debug, Clone, Default)]
+pub struct IgnoreRules {
+    rules: Vec<Rule>
+}
+
+impl IgnoreRules {
+    pub fn new() -> IgnoreRules {
+        IgnoreRules::default()
+    }
+
+    /// Builds rules from patterns that apply from the walk root
+    pub fn from_patterns<S: AsRef<str>>(patterns: &[S]) -> IgnoreRules {
+        let mut rules = IgnoreRules::new();
+        for pattern in patterns {
+            rules.add(pattern.as_ref(), "");
+        }
+        rules
+    }
+
+    /// Adds one line of a .gitignore. base is the relative directory the file was found in ("" or "sub/dir/").
+    pub fn add(&mut self, line: &str, base: &str) {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            return;
+        }
+
+        let (negated, line) = match line.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, line.strip_prefix('\\').unwrap_or(line))
+        };
+        let (dir_only, line) = match line.strip_suffix('/') {
+            Some(rest) => (true, rest),
+            None => (false, line)
+        };
+        let anchored = line.contains('/');
+        let pattern = line.trim_start_matches('/').to_string();
+        if pattern.is_empty() {
+            return;
+        }
+
+        self.rules.push(Rule { pattern, negated, dir_only, anchored, base: base.to_string() });
+    }
+
+    /// Reads a .gitignore file, adding its rules relative to base
+    pub fn add_file(&mut self, path: &Path, base: &str) -> io::Result<()> {
+        let contents = fs::read_to_string(path)?;
+        for line in contents.lines() {
+            self.add(line, base);
+        }
+        Ok(())
+    }
+
+    /// Whether the '/' separated path (relative to the walk root) should be left out
+    pub fn is_excluded(&self, path: &str, is_dir: bool) -> bool {
+        let mut excluded = false;
+        for rule in &self.rules {
+            if rule.dir_only && !is_dir {
+                continue;
+            }
+            let relative = match path.strip_prefix(rule.base.as_str()) {
+                Some(relative) => relative,
+                None => continue
+            };
+            let target = if rule.anchored { relative } else { relative.rsplit('/').next().unwrap_or(relative) };
+            if glob_match(rule.pattern.as_bytes(), target.as_bytes()) {
+                excluded = !rule.negated;
+            }
+        }
+        excluded
+    }
+}
+
+/// Shell-style glob match where '*' and '?' stop at '/', and '**' crosses directories
+pub fn glob_match(pattern: &[u8], text: &[u8]) -> bool {
+    match pattern.first() {
+        None => text.is_empty(),
+        Some(b'*') if pattern.get(1) == Some(&b'*') => {
+            let rest = &pattern[2..];
+            match rest.strip_prefix(b"/") {
+                // "**/" matches zero or more whole directories
+                Some(after_slash) => (0..=text.len())
+                    .filter(|&i| i == 0 || text[i - 1] == b'/')
+                    .any(|i| glob_match(after_slash, &text[i..])),
+                None => (0..=text.len()).any(|i| glob_match(rest, &text[i..]))
+            }
+        },
+        Some(b'*') => {
+            let mut i = 0;
+            loop {
+                if glob_match(&pattern[1..], &text[i..]) {
+                    return true;
+                }
+                if i == text.len() || text[i] == b'/' {
+                    return false;
+                }
+                i += 1;
+            }
+        },
+        Some(b'?') => !text.is_empty() && text[0] != b'/' && glob_match(&pattern[1..], &text[1..]),
+        Some(b'[') => match match_class(&pattern[1..], text.first().copied()) {
+            Some((true, consumed)) => glob_match(&pattern[1 + consumed..], &text[1..]),
+            Some((false, _)) => false,
+            // No closing ']', treat the '[' literally
+            None => text.first() == Some(&b'[') && glob_match(&pattern[1..], &text[1..])
+        },
+        Some(b'\\') if pattern.len() > 1 => text.first() == Some(&pattern[1]) && glob_match(&pattern[2..], &text[1..]),
+        Some(c) => text.first() == Some(c) && glob_match(&pattern[1..], &text[1..])
+    }
+}
+
+/// Matches one character against a [...] class (pattern starts just after the '[').
+/// Returns whether it matched and how many pattern bytes the class used, or None if the class never closes.
+fn match_class(pattern: &[u8], c: Option<u8>) -> Option<(bool, usize)> {
+    let negated = matches!(pattern.first(), Some(b'!') | Some(b'^'));
+    let mut i = if negated { 1 } else { 0 };
+    let mut matched = false;
+    let mut first = true;
+
+    while i < pattern.len() {
+        if pattern[i] == b']' && !first {
+            let matched = c.is_some_and(|c| c != b'/' && matched != negated);
+            return Some((matched, i + 1));
+        }
+        first = false;
+
+        let low = pattern[i];
+        if pattern.get(i + 1) == Some(&b'-') && pattern.get(i + 2).is_some_and(|high| *high != b']') {
+            let high = pattern[i + 2];
+            matched |= c.is_some_and(|c| low <= c && c <= high);
+            i += 3;
+        } else {
+            matched |= c == Some(low);
+            i += 1;
+        }
+    }
+    None
+}
@@ -0,0 +1,97 @@
+#![allow(dead_code)]
+// A record of how far extract_all got, so an interrupted extraction can pick up where it stopped.
+// One line per event, appended as it happens:
+//   started <header offset>
+//   done <header offset> <crc32 as 8 hex digits>
+// Entries are keyed by where their local header sits, which stays unique even when names repeat.
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use crate::crc32::Crc32;
+use crate::zipentry::ZipEntry;
+
+#[derive(Debug)]
+pub(crate) struct ExtractJournal {
+    path: PathBuf,
+    /// None for a journal opened with read, which only answers questions
+    file: Option<File>,
+    /// Header offset to the crc the entry had when it was finished
+    completed: HashMap<u64, u32>,
+    /// Entries that were started but never finished, so may have a partial file on disk
+    started: Vec<u64>
+}
+
+impl ExtractJournal {
+    /// Opens the journal at path, reading back anything a previous run recorded. Lines that don't parse
+    /// (say the last one, cut short by a crash) are ignored.
+    pub(crate) fn open(path: &Path) -> io::Result<ExtractJournal> {
+        let mut journal = ExtractJournal::read(path)?;
+        journal.file = Some(OpenOptions::new().create(true).append(true).open(path)?);
+        Ok(journal)
+    }
+
+    /// Reads back what a previous run recorded without creating or changing anything
+    pub(crate) fn read(path: &Path) -> io::Result<ExtractJournal> {
+        let mut completed = HashMap::new();
+        let mut started = Vec::new();
+        if path.exists() {
+            for line in fs::read_to_string(path)?.lines() {
+                let mut fields = line.split(' ');
+                match (fields.next(), fields.next().and_then(|offset| offset.parse().ok()), fields.next()) {
+                    (Some("started"), Some(offset), None) => started.push(offset),
+                    (Some("done"), Some(offset), Some(crc)) => if let Ok(crc) = u32::from_str_radix(crc, 16) {
+                        completed.insert(offset, crc);
+                    },
+                    _ => {}
+                }
+            }
+        }
+        started.retain(|offset| !completed.contains_key(offset));
+
+        Ok(ExtractJournal { path: path.to_path_buf(), file: None, completed, started })
+    }
+
+    /// Whether a previous run finished this entry. A different crc means the archive changed since.
+    pub(crate) fn is_completed(&self, entry: &ZipEntry) -> bool {
+        self.completed.get(&entry.header_offset()) == Some(&entry.crc32())
+    }
+
+    /// Whether a previous run was part way through this entry when it stopped
+    pub(crate) fn was_started(&self, entry: &ZipEntry) -> bool {
+        self.started.contains(&entry.header_offset())
+    }
+
+    pub(crate) fn start(&mut self, entry: &ZipEntry) -> io::Result<()> {
+        match self.file.as_mut() {
+            Some(file) => writeln!(file, "started {}", entry.header_offset()),
+            None => Ok(())
+        }
+    }
+
+    pub(crate) fn finish(&mut self, entry: &ZipEntry) -> io::Result<()> {
+        match self.file.as_mut() {
+            Some(file) => writeln!(file, "done {} {:08x}", entry.header_offset(), entry.crc32()),
+            None => Ok(())
+        }
+    }
+
+    /// Deletes the journal once there's nothing left to resume
+    pub(crate) fn remove(self) -> io::Result<()> {
+        drop(self.file);
+        fs::remove_file(&self.path)
+    }
+}
+
+/// Crc of a file already on disk, for checking what a previous run wrote
+pub(crate) fn file_crc32(path: &Path) -> io::Result<u32> {
+    let mut file = File::open(path)?;
+    let mut crc = Crc32::new();
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        match file.read(&mut buffer)? {
+            0 => return Ok(crc.finish()),
+            read => crc.update(&buffer[..read])
+        }
+    }
+}
@@ -0,0 +1,78 @@
+#![allow(dead_code)]
+// A read-only key-value view of an archive, for applications that ship assets or data in a zip and only ever want
+// "the bytes for this name". Keys are entry names; directories aren't keys.
+use std::collections::HashMap;
+use crate::options::ReaderOptions;
+use crate::ziparchive::ZipArchive;
+use crate::zipentry::ZipEntry;
+use crate::ziperror::ZipResult;
+
+#[derive(Debug, Clone)]
+pub struct ArchiveKv<'a> {
+    archive: ZipArchive<'a>,
+    /// Name bytes to position in archive.entries(). Where names repeat the first one wins, as with by_name.
+    index: HashMap<Vec<u8>, usize>
+}
+
+impl<'a> ArchiveKv<'a> {
+    pub fn open(path: &'a str) -> ZipResult<ArchiveKv<'a>> {
+        ArchiveKv::open_with_options(path, &ReaderOptions::default())
+    }
+
+    pub fn open_with_options(path: &'a str, options: &ReaderOptions) -> ZipResult<ArchiveKv<'a>> {
+        Ok(ArchiveKv::from_archive(ZipArchive::with_options(path, options)?))
+    }
+
+    /// Indexes an archive that's already open
+    pub fn from_archive(archive: ZipArchive<'a>) -> ArchiveKv<'a> {
+        let mut index = HashMap::with_capacity(archive.entries().len());
+        for (position, entry) in archive.entries().iter().enumerate().filter(|(_, entry)| !entry.is_dir()) {
+            index.entry(entry.name_raw().to_vec()).or_insert(position);
+        }
+        ArchiveKv { archive, index }
+    }
+
+    fn entry(&self, key: &str) -> Option<&ZipEntry> {
+        self.index.get(key.as_bytes()).map(|&position| &self.archive.entries()[position])
+    }
+
+    pub fn contains(&self, key: &str) -> bool {
+        self.index.contains_key(key.as_bytes())
+    }
+
+    /// The value for key, decoded now and checked against its crc. None when there's no such key, and also when
+    /// the value can't be read (damaged, encrypted, an unsupported method): try_get says which.
+    pub fn get(&self, key: &str) -> Option<Vec<u8>> {
+        self.try_get(key).ok().flatten()
+    }
+
+    /// get, with the reason a value that's there couldn't be read
+    pub fn try_get(&self, key: &str) -> ZipResult<Option<Vec<u8>>> {
+        self.entry(key).map(ZipEntry::read).transpose()
+    }
+
+    /// How big key's value is, without decoding it
+    pub fn value_len(&self, key: &str) -> Option<u64> {
+        self.entry(key).map(ZipEntry::uncompressed_size)
+    }
+
+    /// Every key, in the order the archive lists them
+    pub fn keys(&self) -> impl Iterator<Item = String> + '_ {
+        self.archive.entries().iter().enumerate()
+            .filter(move |(position, entry)| self.index.get(entry.name_raw()) == Some(position))
+            .map(|(_, entry)| entry.name())
+    }
+
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    /// The archive underneath, for anything the key-value view leaves out
+    pub fn archive(&self) -> &ZipArchive<'a> {
+        &self.archive
+    }
+}
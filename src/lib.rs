@@ -0,0 +1,57 @@
+// The zip reading and writing library the rip command line is built on; main.rs is only the CLI over it. The
+// testutil feature adds the testutil module, for downstream test suites to build against.
+pub mod ziparchive;
+pub mod huffman;
+pub mod zipentry;
+pub mod extrafield;
+pub mod ziperror;
+pub mod crc32;
+pub mod zipcrypto;
+pub mod password;
+pub mod extract;
+pub mod options;
+pub mod validate;
+pub mod version;
+pub mod zipwriter;
+pub mod attributes;
+pub mod ignore;
+pub mod walk;
+pub mod dedup;
+pub mod entryreader;
+pub mod pool;
+pub mod warnings;
+pub mod datetime;
+pub mod assetpack;
+pub mod kv;
+pub mod cp437;
+pub mod spill;
+pub mod journal;
+pub mod sink;
+pub mod contentstore;
+pub mod source;
+pub mod ratelimit;
+pub mod directio;
+pub mod metrics;
+pub mod sha256;
+pub mod blake3;
+pub mod digest;
+pub mod http;
+pub mod tree;
+pub mod search;
+pub mod sniff;
+pub mod security;
+pub mod heuristics;
+pub mod bytes;
+pub mod cab;
+pub mod cfb;
+pub mod ota;
+pub mod xxhash;
+pub mod lz4;
+pub mod sandbox;
+pub mod freespace;
+pub mod changelog;
+pub mod synth;
+pub mod catalog;
+pub mod cache;
+#[cfg(feature = "testutil")]
+pub mod testutil;
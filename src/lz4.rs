@@ -0,0 +1,467 @@
+#![allow(dead_code)]
+// The LZ4 frame format, as written by the lz4 command line tool
+// https://github.com/lz4/lz4/blob/dev/doc/lz4_Frame_format.md
+// https://github.com/lz4/lz4/blob/dev/doc/lz4_Block_format.md
+use std::convert::TryInto;
+use std::io::{self, Read, Write};
+use crate::xxhash::{self, Xxh32};
+
+const FRAME_MAGIC: u32 = 0x184D2204;
+/// Skippable frames use magics 0x184D2A50 to 0x184D2A5F
+const SKIPPABLE_MAGIC: u32 = 0x184D2A50;
+
+const FLAG_VERSION: u8 = 0b01 << 6;
+const FLAG_INDEPENDENT_BLOCKS: u8 = 1 << 5;
+const FLAG_BLOCK_CHECKSUM: u8 = 1 << 4;
+const FLAG_CONTENT_SIZE: u8 = 1 << 3;
+const FLAG_CONTENT_CHECKSUM: u8 = 1 << 2;
+const FLAG_DICTIONARY_ID: u8 = 1;
+
+/// Set on a block's size when the block is stored uncompressed
+const BLOCK_UNCOMPRESSED: u32 = 1 << 31;
+
+/// Blocks we write are at most 64KB (block maximum size code 4)
+const BLOCK_SIZE: usize = 64 * 1024;
+const BLOCK_SIZE_CODE: u8 = 4;
+/// How far back a match can reach, and so how much earlier output linked blocks can refer to
+const WINDOW: usize = 64 * 1024;
+
+const MIN_MATCH: usize = 4;
+/// The last five bytes of a block are always literals
+const LAST_LITERALS: usize = 5;
+/// The last match has to start at least this far from the end of the block
+const MATCH_LIMIT: usize = 12;
+const HASH_BITS: u32 = 12;
+
+fn invalid(why: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, why)
+}
+
+fn read_u32(data: &[u8], position: usize) -> u32 {
+    u32::from_le_bytes(data[position..position + 4].try_into().unwrap())
+}
+
+fn hash(sequence: u32) -> usize {
+    (sequence.wrapping_mul(2654435761) >> (32 - HASH_BITS)) as usize
+}
+
+/// Appends a length's extra bytes: as many 255s as fit, then the rest
+fn push_length(output: &mut Vec<u8>, mut length: usize) {
+    while length >= 255 {
+        output.push(255);
+        length -= 255;
+    }
+    output.push(length as u8);
+}
+
+fn push_sequence(output: &mut Vec<u8>, literals: &[u8], offset: u16, match_length: usize) {
+    let literal_nibble = literals.len().min(15) as u8;
+    let match_nibble = (match_length - MIN_MATCH).min(15) as u8;
+    output.push((literal_nibble << 4) | match_nibble);
+    if literals.len() >= 15 {
+        push_length(output, literals.len() - 15);
+    }
+    output.extend_from_slice(literals);
+    output.extend_from_slice(&offset.to_le_bytes());
+    if match_length - MIN_MATCH >= 15 {
+        push_length(output, match_length - MIN_MATCH - 15);
+    }
+}
+
+/// Compresses one independent block with a single-probe hash table: quick, and close to what lz4 -1 manages
+pub fn compress_block(input: &[u8]) -> Vec<u8> {
+    let mut output = Vec::with_capacity(input.len() / 2 + 16);
+    let mut table = vec![usize::MAX; 1 << HASH_BITS];
+    let mut anchor = 0;
+    let mut position = 0;
+
+    if input.len() > MATCH_LIMIT {
+        let match_end = input.len() - LAST_LITERALS;
+        while position < input.len() - MATCH_LIMIT {
+            let sequence = read_u32(input, position);
+            let slot = hash(sequence);
+            let candidate = table[slot];
+            table[slot] = position;
+            if candidate == usize::MAX || position - candidate > u16::MAX as usize || read_u32(input, candidate) != sequence {
+                position += 1;
+                continue;
+            }
+
+            let mut length = MIN_MATCH;
+            while position + length < match_end && input[candidate + length] == input[position + length] {
+                length += 1;
+            }
+            push_sequence(&mut output, &input[anchor..position], (position - candidate) as u16, length);
+            position += length;
+            anchor = position;
+        }
+    }
+
+    // Whatever's left goes out as a final run of literals with no match
+    let literals = &input[anchor..];
+    output.push((literals.len().min(15) as u8) << 4);
+    if literals.len() >= 15 {
+        push_length(&mut output, literals.len() - 15);
+    }
+    output.extend_from_slice(literals);
+    output
+}
+
+/// Reads a length's extra bytes, starting from the 15 in its nibble
+fn read_length(input: &[u8], position: &mut usize) -> io::Result<usize> {
+    let mut length = 15usize;
+    loop {
+        let byte = *input.get(*position).ok_or_else(|| invalid("LZ4 block ends inside a length".to_string()))?;
+        *position += 1;
+        length = length.checked_add(byte as usize).ok_or_else(|| invalid("LZ4 length overflows".to_string()))?;
+        if byte != 255 {
+            return Ok(length);
+        }
+    }
+}
+
+/// Decompresses a block, appending to output. Matches may reach back into what's already in output, which is how
+/// linked blocks refer to the ones before them. Refuses to grow the block past max_length bytes.
+pub fn decompress_block(input: &[u8], output: &mut Vec<u8>, max_length: usize) -> io::Result<()> {
+    let block_start = output.len();
+    let mut position = 0;
+    while position < input.len() {
+        let token = input[position];
+        position += 1;
+
+        let mut literal_length = (token >> 4) as usize;
+        if literal_length == 15 {
+            literal_length = read_length(input, &mut position)?;
+        }
+        let literals = input.get(position..position.saturating_add(literal_length))
+            .ok_or_else(|| invalid("LZ4 literals run past the end of the block".to_string()))?;
+        if output.len() - block_start + literal_length > max_length {
+            return Err(invalid("LZ4 block decompresses to more than its maximum size".to_string()));
+        }
+        output.extend_from_slice(literals);
+        position += literal_length;
+        // The last sequence is only literals
+        if position == input.len() {
+            break;
+        }
+
+        let offset = input.get(position..position + 2).map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]) as usize)
+            .ok_or_else(|| invalid("LZ4 block ends inside a match offset".to_string()))?;
+        position += 2;
+        if offset == 0 || offset > output.len() {
+            return Err(invalid(format!("LZ4 match offset {} reaches before the start of the data", offset)));
+        }
+        let mut match_length = (token & 0x0F) as usize;
+        if match_length == 15 {
+            match_length = read_length(input, &mut position)?;
+        }
+        match_length += MIN_MATCH;
+        if output.len() - block_start + match_length > max_length {
+            return Err(invalid("LZ4 block decompresses to more than its maximum size".to_string()));
+        }
+        // Matches may overlap what they're producing, so they're copied a byte at a time
+        let start = output.len() - offset;
+        for i in 0..match_length {
+            let byte = output[start + i];
+            output.push(byte);
+        }
+    }
+    Ok(())
+}
+
+/// Writes an LZ4 frame with independent 64KB blocks and a content checksum. Call finish to write the end of the
+/// frame; dropping the encoder without it leaves the frame incomplete.
+#[derive(Debug)]
+pub struct Lz4Encoder<W: Write> {
+    inner: W,
+    block: Vec<u8>,
+    checksum: Xxh32,
+    header_written: bool
+}
+
+impl<W: Write> Lz4Encoder<W> {
+    pub fn new(inner: W) -> Lz4Encoder<W> {
+        Lz4Encoder { inner, block: Vec::with_capacity(BLOCK_SIZE), checksum: Xxh32::new(0), header_written: false }
+    }
+
+    fn write_header(&mut self) -> io::Result<()> {
+        if self.header_written {
+            return Ok(());
+        }
+        let descriptor = [FLAG_VERSION | FLAG_INDEPENDENT_BLOCKS | FLAG_CONTENT_CHECKSUM, BLOCK_SIZE_CODE << 4];
+        let header_checksum = (xxhash::xxh32(&descriptor, 0) >> 8) as u8;
+        self.inner.write_all(&FRAME_MAGIC.to_le_bytes())?;
+        self.inner.write_all(&descriptor)?;
+        self.inner.write_all(&[header_checksum])?;
+        self.header_written = true;
+        Ok(())
+    }
+
+    fn flush_block(&mut self) -> io::Result<()> {
+        self.write_header()?;
+        if self.block.is_empty() {
+            return Ok(());
+        }
+        let compressed = compress_block(&self.block);
+        if compressed.len() < self.block.len() {
+            self.inner.write_all(&(compressed.len() as u32).to_le_bytes())?;
+            self.inner.write_all(&compressed)?;
+        } else {
+            self.inner.write_all(&(self.block.len() as u32 | BLOCK_UNCOMPRESSED).to_le_bytes())?;
+            self.inner.write_all(&self.block)?;
+        }
+        self.block.clear();
+        Ok(())
+    }
+
+    /// Writes any buffered data, the end mark and the content checksum, and hands back the writer
+    pub fn finish(mut self) -> io::Result<W> {
+        self.flush_block()?;
+        self.inner.write_all(&0u32.to_le_bytes())?;
+        self.inner.write_all(&self.checksum.finish().to_le_bytes())?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for Lz4Encoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let take = (BLOCK_SIZE - self.block.len()).min(buf.len());
+        self.block.extend_from_slice(&buf[..take]);
+        self.checksum.update(&buf[..take]);
+        if self.block.len() == BLOCK_SIZE {
+            self.flush_block()?;
+        }
+        Ok(take)
+    }
+
+    /// Only flushes the inner writer. Buffered data stays put until its block fills or the frame is finished, as
+    /// cutting blocks short would cost compression.
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// The parts of a frame header that matter while decoding
+#[derive(Debug, Clone, Copy)]
+struct FrameInfo {
+    independent: bool,
+    block_checksum: bool,
+    content_checksum: bool,
+    max_block: usize
+}
+
+/// Reads LZ4 frames, including several concatenated frames and skippable frames between them
+#[derive(Debug)]
+pub struct Lz4Decoder<R: Read> {
+    inner: R,
+    frame: Option<FrameInfo>,
+    checksum: Xxh32,
+    /// Decoded data, with up to WINDOW bytes of earlier output in front for linked blocks to refer to
+    output: Vec<u8>,
+    /// How much of output has been handed out
+    position: usize,
+    finished: bool
+}
+
+impl<R: Read> Lz4Decoder<R> {
+    pub fn new(inner: R) -> Lz4Decoder<R> {
+        Lz4Decoder { inner, frame: None, checksum: Xxh32::new(0), output: Vec::new(), position: 0, finished: false }
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    fn read_u32(&mut self) -> io::Result<u32> {
+        let mut bytes = [0u8; 4];
+        self.inner.read_exact(&mut bytes)?;
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    /// Reads up to the next frame header. Returns false at the end of the input.
+    fn start_frame(&mut self) -> io::Result<bool> {
+        loop {
+            let mut magic = [0u8; 4];
+            let read = read_full(&mut self.inner, &mut magic)?;
+            if read == 0 {
+                return Ok(false);
+            }
+            if read < 4 {
+                return Err(invalid("Truncated LZ4 frame magic".to_string()));
+            }
+            let magic = u32::from_le_bytes(magic);
+            if magic & 0xFFFFFFF0 == SKIPPABLE_MAGIC {
+                let length = self.read_u32()? as u64;
+                io::copy(&mut self.inner.by_ref().take(length), &mut io::sink())?;
+                continue;
+            }
+            if magic != FRAME_MAGIC {
+                return Err(invalid(format!("Not an LZ4 frame (magic {:#010x})", magic)));
+            }
+            break;
+        }
+
+        let mut descriptor = vec![0u8; 2];
+        self.inner.read_exact(&mut descriptor)?;
+        let (flags, block_descriptor) = (descriptor[0], descriptor[1]);
+        if flags & 0b1100_0000 != FLAG_VERSION {
+            return Err(invalid(format!("Unsupported LZ4 frame version {}", flags >> 6)));
+        }
+        if flags & FLAG_DICTIONARY_ID != 0 {
+            return Err(io::Error::new(io::ErrorKind::Unsupported, "LZ4 frames with a dictionary are not supported"));
+        }
+        let max_block = match (block_descriptor >> 4) & 0x07 {
+            4 => 64 * 1024,
+            5 => 256 * 1024,
+            6 => 1024 * 1024,
+            7 => 4 * 1024 * 1024,
+            other => return Err(invalid(format!("Invalid LZ4 block maximum size code {}", other)))
+        };
+        if flags & FLAG_CONTENT_SIZE != 0 {
+            let mut content_size = [0u8; 8];
+            self.inner.read_exact(&mut content_size)?;
+            descriptor.extend_from_slice(&content_size);
+        }
+        let mut header_checksum = [0u8; 1];
+        self.inner.read_exact(&mut header_checksum)?;
+        if header_checksum[0] != (xxhash::xxh32(&descriptor, 0) >> 8) as u8 {
+            return Err(invalid("LZ4 frame header checksum mismatch".to_string()));
+        }
+
+        self.frame = Some(FrameInfo {
+            independent: flags & FLAG_INDEPENDENT_BLOCKS != 0,
+            block_checksum: flags & FLAG_BLOCK_CHECKSUM != 0,
+            content_checksum: flags & FLAG_CONTENT_CHECKSUM != 0,
+            max_block
+        });
+        self.checksum = Xxh32::new(0);
+        self.output.clear();
+        self.position = 0;
+        Ok(true)
+    }
+
+    /// Decodes the next block into output. Returns false when there's nothing left at all.
+    fn next_block(&mut self) -> io::Result<bool> {
+        let frame = match self.frame {
+            Some(frame) => frame,
+            None => {
+                if !self.start_frame()? {
+                    return Ok(false);
+                }
+                self.frame.unwrap()
+            }
+        };
+
+        let size = self.read_u32()?;
+        if size == 0 {
+            if frame.content_checksum && self.read_u32()? != self.checksum.finish() {
+                return Err(invalid("LZ4 content checksum mismatch".to_string()));
+            }
+            self.frame = None;
+            return Ok(true);
+        }
+
+        let length = (size & !BLOCK_UNCOMPRESSED) as usize;
+        if length > frame.max_block {
+            return Err(invalid(format!("LZ4 block of {} bytes is over the frame's {} byte maximum", length, frame.max_block)));
+        }
+        let mut block = vec![0u8; length];
+        self.inner.read_exact(&mut block)?;
+        if frame.block_checksum && self.read_u32()? != xxhash::xxh32(&block, 0) {
+            return Err(invalid("LZ4 block checksum mismatch".to_string()));
+        }
+
+        // Keep the tail of earlier output for linked blocks, drop everything else that's been read
+        let keep = if frame.independent { 0 } else { self.output.len().min(WINDOW) };
+        self.output.drain(..self.output.len() - keep);
+        self.position = keep;
+        if size & BLOCK_UNCOMPRESSED != 0 {
+            self.output.extend_from_slice(&block);
+        } else {
+            decompress_block(&block, &mut self.output, frame.max_block)?;
+        }
+        self.checksum.update(&self.output[self.position..]);
+        Ok(true)
+    }
+}
+
+/// Like read_exact, but stops quietly at the end of the input and says how much it got
+fn read_full<R: Read>(reader: &mut R, buffer: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        match reader.read(&mut buffer[filled..]) {
+            Ok(0) => break,
+            Ok(read) => filled += read,
+            Err(why) if why.kind() == io::ErrorKind::Interrupted => continue,
+            Err(why) => return Err(why)
+        }
+    }
+    Ok(filled)
+}
+
+impl<R: Read> Read for Lz4Decoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.position == self.output.len() {
+            if self.finished || !self.next_block()? {
+                self.finished = true;
+                return Ok(0);
+            }
+        }
+        let available = &self.output[self.position..];
+        let take = available.len().min(buf.len());
+        buf[..take].copy_from_slice(&available[..take]);
+        self.position += take;
+        Ok(take)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decompress(frame: &[u8]) -> io::Result<Vec<u8>> {
+        let mut data = Vec::new();
+        Lz4Decoder::new(frame).read_to_end(&mut data)?;
+        Ok(data)
+    }
+
+    /// `lz4 -9` of "one two three, " four times over and a newline, content checksum and all
+    const FROM_LZ4_TOOL: &[u8] = &[
+        0x04, 0x22, 0x4d, 0x18, 0x64, 0x40, 0xa7, 0x1a, 0x00, 0x00, 0x00, 0xff,
+        0x00, 0x6f, 0x6e, 0x65, 0x20, 0x74, 0x77, 0x6f, 0x20, 0x74, 0x68, 0x72,
+        0x65, 0x65, 0x2c, 0x20, 0x0f, 0x00, 0x14, 0x50, 0x68, 0x72, 0x65, 0x65,
+        0x0a, 0x00, 0x00, 0x00, 0x00, 0x88, 0xcd, 0xbd, 0xe4
+    ];
+
+    #[test]
+    fn reads_the_lz4_tools_frames() {
+        let expected = b"one two three, one two three, one two three, one two three\n";
+        assert_eq!(decompress(FROM_LZ4_TOOL).unwrap(), expected);
+
+        let mut damaged = FROM_LZ4_TOOL.to_vec();
+        damaged[20] ^= 1;
+        assert!(decompress(&damaged).is_err());
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut data = Vec::new();
+        for i in 0..200_000u32 {
+            match i % 3 {
+                0 => data.extend_from_slice(b"repeated text "),
+                _ => data.push(((i * 31) >> 3) as u8)
+            }
+        }
+        let mut encoder = Lz4Encoder::new(Vec::new());
+        for piece in data.chunks(7000) {
+            encoder.write_all(piece).unwrap();
+        }
+        let frame = encoder.finish().unwrap();
+        assert!(frame.len() < data.len());
+        assert_eq!(decompress(&frame).unwrap(), data);
+
+        let empty = Lz4Encoder::new(Vec::new()).finish().unwrap();
+        assert_eq!(decompress(&empty).unwrap(), b"");
+    }
+}
@@ -1,16 +1,25 @@
-mod ziparchive;
-mod huffman;
+mod bench;
+mod cli;
+mod completions;
+mod config;
+mod progress;
 use std::path::Path;
 use std::fs::File;
 use std::io::prelude::*;
-use crate::huffman::HuffmanNode;
+use std::process;
 
 // Zip compression_method flags: https://users.cs.jmu.edu/buchhofp/forensics/formats/pkzip.html
 // RFC for DEFLATE https://tools.ietf.org/html/rfc1951
 // https://www2.cs.duke.edu/csed/poop/huff/info/
 
 fn main() {
-    let _ = ziparchive::ZipArchive::new("./resources/testarchive.zip");
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if !args.is_empty() {
+        process::exit(cli::run(&args));
+    }
+
+    let archive = rip::ziparchive::ZipArchive::new("./resources/testarchive.zip");
+    println!("Required features: {:?}", archive.requires_features());
     //y.print_all_data();
     test_huffman("red.txt");
 }
@@ -26,16 +35,16 @@ fn test_huffman(resource_file: &str){
     file.read_to_string(&mut file_data).expect("Couldn't read file");
 
     
-    let huffman_tree_root = huffman::HuffmanNode::new(&file_data);
-    let code_map = huffman::gen_code_map(&huffman_tree_root);
+    let huffman_tree_root = rip::huffman::HuffmanNode::new(&file_data);
+    let code_map = rip::huffman::gen_code_map(&huffman_tree_root);
 
-    let mut codes_to_write:Vec<huffman::HuffCode> = Vec::new();
+    let mut codes_to_write:Vec<rip::huffman::HuffCode> = Vec::new();
 
     for code_key in file_data.chars() {
         codes_to_write.push(code_map[&code_key].clone())
     }
 
-    let compressed = huffman::codes_to_bin(&mut codes_to_write);
+    let compressed = rip::huffman::codes_to_bin(&mut codes_to_write);
 
     println!("Compressed! Orig: {}bytes Compressed: {}bytes", file_data.len(), compressed.len())
 
@@ -0,0 +1,166 @@
+#![allow(dead_code)]
+use std::fmt;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Hooks the reader and writer call as they work, for exporting throughput and latency.
+/// Everything has an empty default, so implementations only need the ones they care about.
+pub trait Metrics: fmt::Debug + Send + Sync {
+    /// Bytes read from an archive file
+    fn bytes_read(&self, _bytes: u64) {}
+    /// Bytes written to an archive's output
+    fn bytes_written(&self, _bytes: u64) {}
+    /// An archive's headers and data finished loading
+    fn archive_opened(&self, _entries: usize, _elapsed: Duration) {}
+    /// An entry was written out by extract_all. size is its uncompressed size.
+    fn entry_extracted(&self, _name: &str, _size: u64, _elapsed: Duration) {}
+    /// An entry was written to an archive, timed from start_file (or the raw copy starting) to its data being out
+    fn entry_written(&self, _name: &str, _size: u64, _elapsed: Duration) {}
+    /// ZipWriter::finish wrote the central directory, elapsed is how long that took
+    fn archive_finished(&self, _entries: usize, _elapsed: Duration) {}
+}
+
+pub type SharedMetrics = Arc<dyn Metrics>;
+
+/// A ready made Metrics that just adds everything up, for polling from an exporter
+#[derive(Debug, Default)]
+pub struct MetricsCounters {
+    bytes_read: AtomicU64,
+    bytes_written: AtomicU64,
+    archives_opened: AtomicU64,
+    open_nanos: AtomicU64,
+    entries_extracted: AtomicU64,
+    extract_nanos: AtomicU64,
+    entries_written: AtomicU64,
+    write_nanos: AtomicU64,
+    archives_finished: AtomicU64
+}
+
+/// The counters at one point in time
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MetricsSnapshot {
+    pub bytes_read: u64,
+    pub bytes_written: u64,
+    pub archives_opened: u64,
+    pub open_time: Duration,
+    pub entries_extracted: u64,
+    pub extract_time: Duration,
+    pub entries_written: u64,
+    pub write_time: Duration,
+    pub archives_finished: u64
+}
+
+impl MetricsCounters {
+    pub fn new() -> MetricsCounters {
+        MetricsCounters::default()
+    }
+
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        MetricsSnapshot {
+            bytes_read: load(&self.bytes_read),
+            bytes_written: load(&self.bytes_written),
+            archives_opened: load(&self.archives_opened),
+            open_time: Duration::from_nanos(load(&self.open_nanos)),
+            entries_extracted: load(&self.entries_extracted),
+            extract_time: Duration::from_nanos(load(&self.extract_nanos)),
+            entries_written: load(&self.entries_written),
+            write_time: Duration::from_nanos(load(&self.write_nanos)),
+            archives_finished: load(&self.archives_finished)
+        }
+    }
+}
+
+fn add(counter: &AtomicU64, value: u64) {
+    counter.fetch_add(value, Ordering::Relaxed);
+}
+
+fn add_time(counter: &AtomicU64, elapsed: Duration) {
+    add(counter, elapsed.as_nanos().min(u64::MAX as u128) as u64);
+}
+
+impl Metrics for MetricsCounters {
+    fn bytes_read(&self, bytes: u64) {
+        add(&self.bytes_read, bytes);
+    }
+
+    fn bytes_written(&self, bytes: u64) {
+        add(&self.bytes_written, bytes);
+    }
+
+    fn archive_opened(&self, _entries: usize, elapsed: Duration) {
+        add(&self.archives_opened, 1);
+        add_time(&self.open_nanos, elapsed);
+    }
+
+    fn entry_extracted(&self, _name: &str, _size: u64, elapsed: Duration) {
+        add(&self.entries_extracted, 1);
+        add_time(&self.extract_nanos, elapsed);
+    }
+
+    fn entry_written(&self, _name: &str, _size: u64, elapsed: Duration) {
+        add(&self.entries_written, 1);
+        add_time(&self.write_nanos, elapsed);
+    }
+
+    fn archive_finished(&self, _entries: usize, _elapsed: Duration) {
+        add(&self.archives_finished, 1);
+    }
+}
+
+/// Wraps a reader or writer, reporting the bytes that go through it
+#[derive(Debug)]
+pub struct Metered<T> {
+    inner: T,
+    metrics: Option<SharedMetrics>
+}
+
+impl<T> Metered<T> {
+    pub fn new(inner: T, metrics: Option<SharedMetrics>) -> Metered<T> {
+        Metered { inner, metrics }
+    }
+
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    pub fn metrics(&self) -> Option<&SharedMetrics> {
+        self.metrics.as_ref()
+    }
+}
+
+impl<R: Read> Read for Metered<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        if let Some(metrics) = &self.metrics {
+            metrics.bytes_read(read as u64);
+        }
+        Ok(read)
+    }
+}
+
+impl<S: Seek> Seek for Metered<S> {
+    fn seek(&mut self, position: SeekFrom) -> io::Result<u64> {
+        self.inner.seek(position)
+    }
+}
+
+impl<W: Write> Write for Metered<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        if let Some(metrics) = &self.metrics {
+            metrics.bytes_written(written as u64);
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
@@ -0,0 +1,73 @@
+#![allow(dead_code)]
+use std::path::PathBuf;
+use crate::metrics::SharedMetrics;
+
+/// The EOF record is 22 bytes, followed by a comment of up to 65535 bytes
+pub const DEFAULT_EOCD_SEARCH_WINDOW: u64 = 22 + 65535;
+
+/// Buffer size for reading and writing archives. Larger buffers cut the number of round trips, which matters most
+/// on network filesystems.
+pub const DEFAULT_IO_BUFFER: usize = 64 * 1024;
+
+/// Knobs for how an archive is opened
+#[derive(Debug, Clone)]
+pub struct ReaderOptions {
+    /// How many bytes back from the end of the file to look for the EOF record
+    pub eocd_search_window: u64,
+    /// Accept data appended after the end of the archive (installer payloads, torrent padding, ...).
+    /// The amount found is reported by ZipArchive::trailing_bytes either way.
+    pub allow_trailing_garbage: bool,
+    /// Bytes read from the archive at a time while loading headers
+    pub io_buffer: usize,
+    /// Cap on bytes per second read from the archive, so background jobs don't saturate a disk or network mount.
+    /// None reads as fast as the storage allows.
+    pub max_read_rate: Option<u64>,
+    /// Read the archive past the page cache (O_DIRECT on Linux, F_NOCACHE on macOS), so going through an archive on a
+    /// shared server doesn't evict everyone else's files. Entry data is still held in memory once loaded, so this
+    /// doesn't make archives bigger than memory readable. Where the platform or filesystem doesn't allow it the
+    /// archive is quietly read the usual way.
+    pub direct_io: bool,
+    /// Told about bytes read, the archive being opened and entries being extracted
+    pub metrics: Option<SharedMetrics>
+}
+
+impl Default for ReaderOptions {
+    fn default() -> ReaderOptions {
+        ReaderOptions {
+            eocd_search_window: DEFAULT_EOCD_SEARCH_WINDOW,
+            allow_trailing_garbage: true,
+            io_buffer: DEFAULT_IO_BUFFER,
+            max_read_rate: None,
+            direct_io: false,
+            metrics: None
+        }
+    }
+}
+
+/// Knobs for how an archive is written
+#[derive(Debug, Clone)]
+pub struct WriterOptions {
+    /// Bytes collected before each write to the underlying writer
+    pub io_buffer: usize,
+    /// Entries are held until they're finished so the header can be written first. Past this many bytes they're
+    /// held in a temp file instead of memory. None keeps everything in memory.
+    pub spill_threshold: Option<u64>,
+    /// Where spilled entries go, the system temp directory if None
+    pub spill_dir: Option<PathBuf>,
+    /// Cap on bytes per second written out, None for no limit
+    pub max_write_rate: Option<u64>,
+    /// Told about bytes written, each entry going out and the archive being finished
+    pub metrics: Option<SharedMetrics>
+}
+
+impl Default for WriterOptions {
+    fn default() -> WriterOptions {
+        WriterOptions {
+            io_buffer: DEFAULT_IO_BUFFER,
+            spill_threshold: None,
+            spill_dir: None,
+            max_write_rate: None,
+            metrics: None
+        }
+    }
+}
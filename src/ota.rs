@@ -0,0 +1,270 @@
+#![allow(dead_code)]
+// Helpers for Android OTA and ROM zips: the properties and metadata files they carry, the payload hashes listed in
+// them, and writing partition images out as sparse files
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{Seek, SeekFrom, Write};
+use std::path::Path;
+use crate::bytes::{slice_at, u16_at, u32_at};
+use crate::sha256;
+use crate::ziparchive::ZipArchive;
+use crate::ziperror::{ZipError, ZipResult};
+
+pub const PAYLOAD: &str = "payload.bin";
+pub const PAYLOAD_PROPERTIES: &str = "payload_properties.txt";
+pub const METADATA: &str = "META-INF/com/android/metadata";
+
+/// Blocks of an image that are all zeros are skipped rather than written, leaving holes in the output
+const SPARSE_BLOCK: usize = 4096;
+
+const SPARSE_MAGIC: u32 = 0xED26FF3A;
+const CHUNK_RAW: u16 = 0xCAC1;
+const CHUNK_FILL: u16 = 0xCAC2;
+const CHUNK_DONT_CARE: u16 = 0xCAC3;
+const CHUNK_CRC32: u16 = 0xCAC4;
+
+/// A hash from payload_properties.txt checked against the payload
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HashCheck {
+    /// The property the expected hash came from, FILE_HASH or METADATA_HASH
+    pub key: String,
+    pub expected: Vec<u8>,
+    pub actual: [u8; 32]
+}
+
+impl HashCheck {
+    pub fn matches(&self) -> bool {
+        self.expected == self.actual
+    }
+}
+
+/// Parses the key=value lines of payload_properties.txt and the OTA metadata file. Blank lines and lines without
+/// an '=' are skipped; later keys win.
+pub fn parse_properties(text: &str) -> BTreeMap<String, String> {
+    text.lines()
+        .filter_map(|line| line.split_once('='))
+        .map(|(key, value)| (key.trim().to_string(), value.trim().to_string()))
+        .collect()
+}
+
+/// Standard base64 with optional padding, None if anything else is in there
+pub fn base64_decode(text: &str) -> Option<Vec<u8>> {
+    let mut output = Vec::with_capacity(text.len() / 4 * 3);
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for byte in text.trim_end_matches('=').bytes() {
+        let value = match byte {
+            b'A'..=b'Z' => byte - b'A',
+            b'a'..=b'z' => byte - b'a' + 26,
+            b'0'..=b'9' => byte - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => return None
+        };
+        buffer = (buffer << 6) | value as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            output.push((buffer >> bits) as u8);
+        }
+    }
+    Some(output)
+}
+
+/// Writes data at the output's current position, seeking over whole blocks of zeros instead of writing them
+fn write_sparse(output: &mut File, data: &[u8]) -> ZipResult<()> {
+    for block in data.chunks(SPARSE_BLOCK) {
+        if block.iter().all(|&byte| byte == 0) {
+            output.seek(SeekFrom::Current(block.len() as i64))?;
+        } else {
+            output.write_all(block)?;
+        }
+    }
+    Ok(())
+}
+
+/// Expands an Android sparse image (the format fastboot flashes) into output, leaving "don't care" chunks and
+/// zero fills as holes. Returns the expanded size.
+fn unsparse(image: &[u8], output: &mut File) -> ZipResult<u64> {
+    let header_length = u16_at(image, 8)? as usize;
+    let chunk_header_length = u16_at(image, 10)? as usize;
+    let block_size = u32_at(image, 12)? as u64;
+    let total_blocks = u32_at(image, 16)? as u64;
+    let chunk_count = u32_at(image, 20)?;
+
+    let mut offset = header_length;
+    for _ in 0..chunk_count {
+        let chunk_type = u16_at(image, offset)?;
+        let blocks = u32_at(image, offset + 4)? as u64;
+        let chunk_length = u32_at(image, offset + 8)? as usize;
+        let body = slice_at(image, offset + chunk_header_length, chunk_length.saturating_sub(chunk_header_length))?;
+        let length = blocks * block_size;
+        match chunk_type {
+            CHUNK_RAW => {
+                if body.len() as u64 != length {
+                    return Err(ZipError::InvalidArchive(format!("Sparse image raw chunk at {:#X} holds {} bytes, expected {}", offset, body.len(), length)));
+                }
+                write_sparse(output, body)?;
+            },
+            CHUNK_FILL => {
+                let pattern = slice_at(body, 0, 4)?;
+                if pattern == [0, 0, 0, 0] {
+                    output.seek(SeekFrom::Current(length as i64))?;
+                } else {
+                    let block = pattern.repeat(block_size as usize / 4);
+                    for _ in 0..blocks {
+                        output.write_all(&block)?;
+                    }
+                }
+            },
+            CHUNK_DONT_CARE => {
+                output.seek(SeekFrom::Current(length as i64))?;
+            },
+            CHUNK_CRC32 => {},
+            other => return Err(ZipError::InvalidArchive(format!("Unknown sparse image chunk type {:#06x} at {:#X}", other, offset)))
+        }
+        offset += chunk_length;
+    }
+    Ok(total_blocks * block_size)
+}
+
+impl ZipArchive<'_> {
+    fn properties_entry(&self, name: &str) -> ZipResult<BTreeMap<String, String>> {
+        let entry = self.by_name(name).ok_or_else(|| ZipError::FileNotFound(name.to_string()))?;
+        Ok(parse_properties(&String::from_utf8_lossy(&entry.read()?)))
+    }
+
+    /// payload_properties.txt from an A/B OTA package (FILE_HASH, FILE_SIZE, METADATA_HASH, METADATA_SIZE)
+    pub fn payload_properties(&self) -> ZipResult<BTreeMap<String, String>> {
+        self.properties_entry(PAYLOAD_PROPERTIES)
+    }
+
+    /// META-INF/com/android/metadata, which names the devices and builds the package applies to
+    pub fn ota_metadata(&self) -> ZipResult<BTreeMap<String, String>> {
+        self.properties_entry(METADATA)
+    }
+
+    /// Checks payload.bin against the SHA-256 hashes in payload_properties.txt: FILE_HASH over the whole payload
+    /// and METADATA_HASH over its first METADATA_SIZE bytes. Hashes that aren't listed aren't checked.
+    pub fn verify_payload(&self) -> ZipResult<Vec<HashCheck>> {
+        let properties = self.payload_properties()?;
+        let payload = self.by_name(PAYLOAD).ok_or_else(|| ZipError::FileNotFound(PAYLOAD.to_string()))?.read()?;
+
+        let expected = |key: &str| -> ZipResult<Option<Vec<u8>>> {
+            properties.get(key).map(|value| base64_decode(value)
+                .ok_or_else(|| ZipError::InvalidArchive(format!("{} in {} isn't valid base64", key, PAYLOAD_PROPERTIES))))
+                .transpose()
+        };
+
+        let mut checks = Vec::new();
+        if let Some(expected) = expected("FILE_HASH")? {
+            checks.push(HashCheck { key: "FILE_HASH".to_string(), expected, actual: sha256::digest(&payload) });
+        }
+        if let Some(expected) = expected("METADATA_HASH")? {
+            let size = properties.get("METADATA_SIZE").and_then(|size| size.parse::<usize>().ok())
+                .ok_or_else(|| ZipError::InvalidArchive(format!("METADATA_HASH without a usable METADATA_SIZE in {}", PAYLOAD_PROPERTIES)))?;
+            let metadata = payload.get(..size)
+                .ok_or_else(|| ZipError::InvalidArchive(format!("METADATA_SIZE {} is past the end of {}", size, PAYLOAD)))?;
+            checks.push(HashCheck { key: "METADATA_HASH".to_string(), expected, actual: sha256::digest(metadata) });
+        }
+        Ok(checks)
+    }
+
+    /// Writes a partition image from a ROM zip (boot.img, system.img, ...) to path as a sparse file: Android sparse
+    /// images are expanded, with their skipped and zero-filled chunks left as holes, and plain images have their
+    /// all-zero blocks skipped. Returns the size of the image written.
+    pub fn extract_partition<P: AsRef<Path>>(&self, name: &str, path: P) -> ZipResult<u64> {
+        let entry = self.by_name(name).ok_or_else(|| ZipError::FileNotFound(name.to_string()))?;
+        let image = entry.read()?;
+        let mut output = File::create(path)?;
+
+        let size = if u32_at(&image, 0).ok() == Some(SPARSE_MAGIC) {
+            unsparse(&image, &mut output)?
+        } else {
+            write_sparse(&mut output, &image)?;
+            image.len() as u64
+        };
+        // Holes at the end only count once the length is set
+        output.set_len(size)?;
+        Ok(size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io::{Cursor, Write};
+    use super::*;
+    use crate::zipwriter::{FileOptions, ZipWriter};
+
+    fn package(files: &[(&str, &[u8])]) -> ZipArchive<'static> {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, contents) in files {
+            writer.start_file(name, FileOptions::default()).unwrap();
+            writer.write_all(contents).unwrap();
+        }
+        ZipArchive::parse_bytes(&writer.finish().unwrap().into_inner()).unwrap()
+    }
+
+    #[test]
+    fn payload_hashes_are_checked() {
+        // FILE_HASH is the hash of nothing at all, so it doesn't match; METADATA_HASH is the hash of "abc"
+        let properties = b"FILE_HASH=47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU=\nFILE_SIZE=6\n\
+            METADATA_HASH=ungWv48Bz+pBQUDeXa4iI7ADYaOWF3qctBD/YfIAFa0=\nMETADATA_SIZE=3\n";
+        let archive = package(&[
+            (PAYLOAD, b"abcdef"),
+            (PAYLOAD_PROPERTIES, properties),
+            (METADATA, b"ota-type=AB\npre-device=sargo\n\n")
+        ]);
+
+        assert_eq!(archive.payload_properties().unwrap()["FILE_SIZE"], "6");
+        assert_eq!(archive.ota_metadata().unwrap()["pre-device"], "sargo");
+        let checks: Vec<(String, bool)> = archive.verify_payload().unwrap().into_iter().map(|check| (check.key.clone(), check.matches())).collect();
+        assert_eq!(checks, [("FILE_HASH".to_string(), false), ("METADATA_HASH".to_string(), true)]);
+
+        let broken = package(&[(PAYLOAD, b"abc"), (PAYLOAD_PROPERTIES, b"FILE_HASH=not base64!")]);
+        assert!(matches!(broken.verify_payload(), Err(ZipError::InvalidArchive(_))));
+        assert!(matches!(package(&[]).verify_payload(), Err(ZipError::FileNotFound(_))));
+    }
+
+    #[test]
+    fn sparse_images_are_expanded() {
+        let chunk = |chunk_type: u16, blocks: u32, body: &[u8]| {
+            [&chunk_type.to_le_bytes()[..], &[0, 0], &blocks.to_le_bytes(), &(12 + body.len() as u32).to_le_bytes(), body].concat()
+        };
+        let mut image = Vec::new();
+        for (value, width) in [(SPARSE_MAGIC, 4), (1, 2), (0, 2), (28, 2), (12, 2), (4096, 4), (5, 4), (4, 4), (0, 4)] {
+            image.extend_from_slice(&value.to_le_bytes()[..width]);
+        }
+        image.extend(chunk(CHUNK_RAW, 1, &[b'x'; 4096]));
+        image.extend(chunk(CHUNK_FILL, 1, &[1, 2, 3, 4]));
+        image.extend(chunk(CHUNK_DONT_CARE, 2, &[]));
+        image.extend(chunk(CHUNK_FILL, 1, &[0, 0, 0, 0]));
+        let plain = [vec![0; 8192], b"boot".to_vec()].concat();
+        let archive = package(&[("system.img", &image), ("boot.img", &plain)]);
+
+        let root = std::env::temp_dir().join(format!("rip-ota-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        let system_size = archive.extract_partition("system.img", root.join("system.img")).unwrap();
+        let boot_size = archive.extract_partition("boot.img", root.join("boot.img")).unwrap();
+        let system = fs::read(root.join("system.img")).unwrap();
+        let boot = fs::read(root.join("boot.img")).unwrap();
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(system_size, 5 * 4096);
+        assert_eq!(system.len(), 5 * 4096);
+        assert!(system[..4096].iter().all(|&byte| byte == b'x'));
+        assert_eq!(&system[4096..4104], &[1, 2, 3, 4, 1, 2, 3, 4]);
+        assert!(system[8192..].iter().all(|&byte| byte == 0));
+        assert_eq!((boot_size, boot), (plain.len() as u64, plain));
+    }
+
+    #[test]
+    fn base64_decodes_with_and_without_padding() {
+        assert_eq!(base64_decode("aGVsbG8=").unwrap(), b"hello");
+        assert_eq!(base64_decode("aGVsbG8").unwrap(), b"hello");
+        assert_eq!(base64_decode("").unwrap(), b"");
+        assert_eq!(base64_decode("aGV*"), None);
+    }
+}
@@ -0,0 +1,58 @@
+#![allow(dead_code)]
+use std::collections::HashMap;
+
+/// Supplies passwords for encrypted entries, one entry at a time.
+/// `attempt` counts how many passwords have already been rejected for this entry, so a provider can
+/// prompt again or move on to the next candidate. Returning None gives up on the entry.
+pub trait PasswordProvider {
+    fn password(&mut self, entry_name: &str, attempt: u32) -> Option<Vec<u8>>;
+}
+
+impl<F> PasswordProvider for F where F: FnMut(&str, u32) -> Option<Vec<u8>> {
+    fn password(&mut self, entry_name: &str, attempt: u32) -> Option<Vec<u8>> {
+        self(entry_name, attempt)
+    }
+}
+
+/// One password for the whole archive, offered once per entry
+#[derive(Debug, Clone)]
+pub struct ArchivePassword(pub Vec<u8>);
+
+impl PasswordProvider for ArchivePassword {
+    fn password(&mut self, _entry_name: &str, attempt: u32) -> Option<Vec<u8>> {
+        if attempt == 0 { Some(self.0.clone()) } else { None }
+    }
+}
+
+/// Passwords keyed by entry name, with an optional fallback for everything else.
+/// Unlisted entries get the fallback first, then each listed password in turn.
+#[derive(Debug, Clone, Default)]
+pub struct PasswordMap {
+    passwords: HashMap<String, Vec<u8>>,
+    fallback: Option<Vec<u8>>
+}
+
+impl PasswordMap {
+    pub fn new() -> PasswordMap {
+        PasswordMap::default()
+    }
+
+    pub fn insert(&mut self, entry_name: &str, password: &[u8]) {
+        self.passwords.insert(entry_name.to_string(), password.to_vec());
+    }
+
+    pub fn set_fallback(&mut self, password: &[u8]) {
+        self.fallback = Some(password.to_vec());
+    }
+}
+
+impl PasswordProvider for PasswordMap {
+    fn password(&mut self, entry_name: &str, attempt: u32) -> Option<Vec<u8>> {
+        if let Some(password) = self.passwords.get(entry_name) {
+            return if attempt == 0 { Some(password.clone()) } else { None };
+        }
+
+        let mut candidates = self.fallback.iter().chain(self.passwords.values());
+        candidates.nth(attempt as usize).cloned()
+    }
+}
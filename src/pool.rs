@@ -0,0 +1,102 @@
+#![allow(dead_code)]
+use std::io;
+use std::path::Path;
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use crate::extract::{ExtractOptions, ExtractReport};
+use crate::options::ReaderOptions;
+use crate::ziparchive::ZipArchive;
+use crate::ziperror::{ZipError, ZipResult};
+
+type Task = Box<dyn FnOnce(&ReaderOptions) + Send>;
+
+/// A fixed set of worker threads for extracting lots of archives, so services handling a steady stream of small
+/// archives don't start threads per archive. Dropping the pool waits for queued work to finish.
+pub struct ArchivePool {
+    sender: Option<mpsc::Sender<Task>>,
+    workers: Vec<thread::JoinHandle<()>>
+}
+
+/// An extraction queued on an ArchivePool
+pub struct PendingExtract {
+    receiver: mpsc::Receiver<ZipResult<ExtractReport>>
+}
+
+impl PendingExtract {
+    /// Blocks until the archive has been extracted
+    pub fn wait(self) -> ZipResult<ExtractReport> {
+        self.receiver.recv().unwrap_or_else(|_| Err(ZipError::Io(io::Error::other("Extraction worker panicked"))))
+    }
+}
+
+impl ArchivePool {
+    pub fn new(threads: usize) -> ArchivePool {
+        ArchivePool::with_options(threads, ReaderOptions::default())
+    }
+
+    /// Every archive is opened with reader_options. At least one thread is always started.
+    pub fn with_options(threads: usize, reader_options: ReaderOptions) -> ArchivePool {
+        let (sender, receiver) = mpsc::channel::<Task>();
+        let receiver = Arc::new(Mutex::new(receiver));
+
+        let workers = (0..threads.max(1)).map(|_| {
+            let receiver = Arc::clone(&receiver);
+            let reader_options = reader_options.clone();
+            thread::spawn(move || loop {
+                let task = match receiver.lock() {
+                    Ok(receiver) => receiver.recv(),
+                    Err(_) => return
+                };
+                match task {
+                    Ok(task) => task(&reader_options),
+                    Err(_) => return
+                }
+            })
+        }).collect();
+
+        ArchivePool { sender: Some(sender), workers }
+    }
+
+    pub fn threads(&self) -> usize {
+        self.workers.len()
+    }
+
+    /// Queues archive to be extracted under dest
+    pub fn extract<P: AsRef<Path>, Q: AsRef<Path>>(&self, archive: P, dest: Q, options: &ExtractOptions) -> PendingExtract {
+        let archive = archive.as_ref().to_path_buf();
+        let dest = dest.as_ref().to_path_buf();
+        let options = options.clone();
+        let (result_sender, receiver) = mpsc::channel();
+
+        let task: Task = Box::new(move |reader_options| {
+            let result = match archive.to_str() {
+                Some(filename) => ZipArchive::with_options(filename, reader_options)
+                    .and_then(|opened| opened.extract_all(&dest, &options)),
+                None => Err(ZipError::Io(io::Error::new(io::ErrorKind::InvalidInput, format!("{} isn't valid UTF-8", archive.display()))))
+            };
+            let _ = result_sender.send(result);
+        });
+
+        if let Some(sender) = &self.sender {
+            let _ = sender.send(task);
+        }
+        PendingExtract { receiver }
+    }
+
+    /// Extracts each (archive, dest) pair, returning the results in the same order
+    pub fn extract_many<I, P, Q>(&self, jobs: I, options: &ExtractOptions) -> Vec<ZipResult<ExtractReport>>
+        where I: IntoIterator<Item = (P, Q)>, P: AsRef<Path>, Q: AsRef<Path> {
+        let pending: Vec<PendingExtract> = jobs.into_iter().map(|(archive, dest)| self.extract(archive, dest, options)).collect();
+        pending.into_iter().map(PendingExtract::wait).collect()
+    }
+}
+
+impl Drop for ArchivePool {
+    fn drop(&mut self) {
+        // Closing the channel lets each worker finish what's queued and then exit
+        self.sender.take();
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
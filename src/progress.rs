@@ -0,0 +1,163 @@
+#![allow(dead_code)]
+// Progress for the command line, drawn on stderr from the Metrics hooks the library calls as it works.
+// A bar with the overall count and bytes sits on the last line; at higher verbosity each finished entry gets a line
+// printed above it, the way indicatif's bars behave.
+use std::fmt;
+use std::io::{self, IsTerminal, Write};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use rip::metrics::Metrics;
+
+/// How much the command line prints, from -q, -v and -vv
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum Verbosity {
+    /// Errors only
+    Quiet,
+    /// A progress bar while working and a summary at the end
+    #[default]
+    Normal,
+    /// Each entry's name as it's done
+    Verbose,
+    /// Each entry's size and how long it took too
+    Debug
+}
+
+const BAR_WIDTH: usize = 30;
+/// Redraw at most this often, so a run of tiny entries doesn't spend its time on the terminal
+const REDRAW_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Default)]
+struct State {
+    total_entries: usize,
+    total_bytes: u64,
+    done_entries: usize,
+    done_bytes: u64,
+    current: String,
+    last_draw: Option<Instant>,
+    /// Whether the bar is on screen and needs clearing before anything else is printed
+    drawn: bool
+}
+
+/// Follows entries being extracted or written and draws how far along things are
+pub struct Progress {
+    verbosity: Verbosity,
+    /// Only draw the bar when stderr is a terminal, piped output just gets the lines
+    draw_bar: bool,
+    state: Mutex<State>
+}
+
+impl fmt::Debug for Progress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Progress").field("verbosity", &self.verbosity).field("draw_bar", &self.draw_bar).finish()
+    }
+}
+
+/// A byte count the way people read them: 1.5 MiB rather than 1572864
+pub fn human_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 { format!("{} B", bytes) } else { format!("{:.1} {}", value, UNITS[unit]) }
+}
+
+impl Progress {
+    pub fn new(verbosity: Verbosity) -> Progress {
+        Progress { verbosity, draw_bar: verbosity > Verbosity::Quiet && io::stderr().is_terminal(), state: Mutex::new(State::default()) }
+    }
+
+    pub fn verbosity(&self) -> Verbosity {
+        self.verbosity
+    }
+
+    /// What the whole job adds up to, once it's known
+    pub fn set_total(&self, entries: usize, bytes: u64) {
+        let mut state = self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        state.total_entries = entries;
+        state.total_bytes = bytes;
+    }
+
+    /// Prints a line above the bar
+    pub fn println(&self, line: &str) {
+        let mut state = self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let mut stderr = io::stderr().lock();
+        if state.drawn {
+            let _ = write!(stderr, "\r\x1b[K");
+            state.drawn = false;
+        }
+        let _ = writeln!(stderr, "{}", line);
+        if self.draw_bar {
+            self.draw(&mut state, &mut stderr);
+        }
+    }
+
+    /// Takes the bar off the screen, for when the job's over
+    pub fn finish(&self) {
+        let mut state = self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        if state.drawn {
+            let _ = write!(io::stderr(), "\r\x1b[K");
+            state.drawn = false;
+        }
+    }
+
+    fn draw(&self, state: &mut State, out: &mut impl Write) {
+        // Long names get their front cut off, the end of a path says more about it
+        let current = match state.current.char_indices().rev().nth(29) {
+            Some((at, _)) => format!("...{}", &state.current[at..]),
+            None => state.current.clone()
+        };
+        // Without a total (names coming in on stdin, say) there's nothing to fill a bar against, just count up
+        if state.total_entries == 0 {
+            let _ = write!(out, "\r\x1b[K{} entries  {}  {}", state.done_entries, human_bytes(state.done_bytes), current);
+            let _ = out.flush();
+            state.drawn = true;
+            state.last_draw = Some(Instant::now());
+            return;
+        }
+
+        let fraction = if state.total_bytes > 0 {
+            state.done_bytes as f64 / state.total_bytes as f64
+        } else {
+            state.done_entries as f64 / state.total_entries as f64
+        }.min(1.0);
+        let filled = (fraction * BAR_WIDTH as f64) as usize;
+        let bar: String = (0..BAR_WIDTH).map(|at| if at < filled { '=' } else if at == filled { '>' } else { ' ' }).collect();
+        let _ = write!(out, "\r\x1b[K[{}] {:>3}%  {}/{}  {}/{}  {}", bar, (fraction * 100.0) as u32,
+            state.done_entries, state.total_entries, human_bytes(state.done_bytes), human_bytes(state.total_bytes), current);
+        let _ = out.flush();
+        state.drawn = true;
+        state.last_draw = Some(Instant::now());
+    }
+
+    fn entry_done(&self, name: &str, size: u64, elapsed: Duration) {
+        let mut state = self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        state.done_entries += 1;
+        state.done_bytes += size;
+        state.current = name.to_string();
+        drop(state);
+
+        match self.verbosity {
+            Verbosity::Quiet => return,
+            Verbosity::Normal => {},
+            Verbosity::Verbose => self.println(name),
+            Verbosity::Debug => self.println(&format!("{}  ({}, {:.1?})", name, human_bytes(size), elapsed))
+        }
+        let mut state = self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        if self.draw_bar && state.last_draw.is_none_or(|drawn| drawn.elapsed() >= REDRAW_INTERVAL) {
+            self.draw(&mut state, &mut io::stderr().lock());
+        }
+    }
+}
+
+impl Metrics for Progress {
+    fn entry_extracted(&self, name: &str, size: u64, elapsed: Duration) {
+        self.entry_done(name, size, elapsed);
+    }
+
+    fn entry_written(&self, name: &str, size: u64, elapsed: Duration) {
+        self.entry_done(name, size, elapsed);
+    }
+}
@@ -0,0 +1,82 @@
+#![allow(dead_code)]
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Paces IO to an average number of bytes per second, measured from the first byte
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    bytes_per_second: u64,
+    started: Instant,
+    transferred: u64
+}
+
+impl RateLimiter {
+    pub fn new(bytes_per_second: u64) -> RateLimiter {
+        RateLimiter { bytes_per_second: bytes_per_second.max(1), started: Instant::now(), transferred: 0 }
+    }
+
+    /// Counts bytes that were just transferred, sleeping for as long as that puts things ahead of the rate
+    pub fn consume(&mut self, bytes: u64) {
+        self.transferred += bytes;
+        let due = Duration::from_secs_f64(self.transferred as f64 / self.bytes_per_second as f64);
+        let elapsed = self.started.elapsed();
+        if due > elapsed {
+            thread::sleep(due - elapsed);
+        }
+    }
+}
+
+/// Wraps a reader or writer, holding it to a rate when there is one
+#[derive(Debug)]
+pub struct Throttled<T> {
+    inner: T,
+    limiter: Option<RateLimiter>
+}
+
+impl<T> Throttled<T> {
+    /// bytes_per_second of None (or 0) leaves the IO unlimited
+    pub fn new(inner: T, bytes_per_second: Option<u64>) -> Throttled<T> {
+        Throttled { inner, limiter: bytes_per_second.filter(|&rate| rate > 0).map(RateLimiter::new) }
+    }
+
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    fn consume(&mut self, bytes: usize) {
+        if let Some(limiter) = self.limiter.as_mut() {
+            limiter.consume(bytes as u64);
+        }
+    }
+}
+
+impl<R: Read> Read for Throttled<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.consume(read);
+        Ok(read)
+    }
+}
+
+impl<S: Seek> Seek for Throttled<S> {
+    fn seek(&mut self, position: SeekFrom) -> io::Result<u64> {
+        self.inner.seek(position)
+    }
+}
+
+impl<W: Write> Write for Throttled<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.consume(written);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
@@ -0,0 +1,128 @@
+#![allow(dead_code)]
+// Creating files under a destination without ever following a symlink inside it. Each path component is opened
+// relative to the directory before it with O_NOFOLLOW, so a symlink planted under the destination (by an earlier
+// entry or by someone racing the extraction) can't redirect a write outside it, whatever the names look like.
+use std::ffi::OsStr;
+use std::fs::File;
+use std::io;
+use std::path::{Component, Path};
+
+#[cfg(target_os = "linux")]
+mod sys {
+    use std::os::raw::{c_char, c_int, c_uint};
+
+    pub const AT_FDCWD: c_int = -100;
+    pub const O_RDONLY: c_int = 0;
+    pub const O_WRONLY: c_int = 0o1;
+    pub const O_CREAT: c_int = 0o100;
+    pub const O_TRUNC: c_int = 0o1000;
+    pub const O_CLOEXEC: c_int = 0o2000000;
+    #[cfg(any(target_arch = "aarch64", target_arch = "arm"))]
+    pub const O_DIRECTORY: c_int = 0o40000;
+    #[cfg(any(target_arch = "aarch64", target_arch = "arm"))]
+    pub const O_NOFOLLOW: c_int = 0o100000;
+    #[cfg(not(any(target_arch = "aarch64", target_arch = "arm")))]
+    pub const O_DIRECTORY: c_int = 0o200000;
+    #[cfg(not(any(target_arch = "aarch64", target_arch = "arm")))]
+    pub const O_NOFOLLOW: c_int = 0o400000;
+    pub const ENOTDIR: i32 = 20;
+    pub const ELOOP: i32 = 40;
+
+    extern "C" {
+        pub fn openat(dirfd: c_int, path: *const c_char, flags: c_int, ...) -> c_int;
+        pub fn mkdirat(dirfd: c_int, path: *const c_char, mode: c_uint) -> c_int;
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn c_name(name: &OsStr) -> io::Result<std::ffi::CString> {
+    use std::os::unix::ffi::OsStrExt;
+    std::ffi::CString::new(name.as_bytes()).map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "Path contains a NUL byte"))
+}
+
+/// The plain names making up relative, refusing anything that could climb out of wherever it's resolved from
+fn components(relative: &Path) -> io::Result<Vec<&OsStr>> {
+    relative.components().map(|component| match component {
+        Component::Normal(name) => Ok(name),
+        _ => Err(io::Error::new(io::ErrorKind::InvalidInput, format!("{} isn't a plain relative path", relative.display())))
+    }).collect()
+}
+
+#[cfg(target_os = "linux")]
+fn open_at(dir: Option<&File>, name: &OsStr, flags: i32, mode: u32) -> io::Result<File> {
+    use std::os::unix::io::{AsRawFd, FromRawFd};
+    let path = c_name(name)?;
+    let dirfd = dir.map_or(sys::AT_FDCWD, |dir| dir.as_raw_fd());
+    let fd = unsafe { sys::openat(dirfd, path.as_ptr(), flags | sys::O_CLOEXEC, mode) };
+    if fd < 0 {
+        let error = io::Error::last_os_error();
+        if error.raw_os_error() == Some(sys::ELOOP) && flags & sys::O_NOFOLLOW != 0 {
+            return Err(io::Error::other(format!("Refusing to follow the symlink at {}", Path::new(name).display())));
+        }
+        return Err(error);
+    }
+    Ok(unsafe { File::from_raw_fd(fd) })
+}
+
+/// Opens (creating if needed) the directory name inside dir, failing rather than following a symlink
+#[cfg(target_os = "linux")]
+fn descend(dir: &File, name: &OsStr) -> io::Result<File> {
+    use std::os::unix::io::AsRawFd;
+    let flags = sys::O_RDONLY | sys::O_DIRECTORY | sys::O_NOFOLLOW;
+    match open_at(Some(dir), name, flags, 0) {
+        Err(error) if error.kind() == io::ErrorKind::NotFound => {
+            let path = c_name(name)?;
+            if unsafe { sys::mkdirat(dir.as_raw_fd(), path.as_ptr(), 0o777) } < 0 {
+                let error = io::Error::last_os_error();
+                if error.kind() != io::ErrorKind::AlreadyExists {
+                    return Err(error);
+                }
+            }
+            open_at(Some(dir), name, flags, 0)
+        },
+        // O_DIRECTORY gets in first when the name is a symlink
+        Err(error) if error.raw_os_error() == Some(sys::ENOTDIR) => {
+            Err(io::Error::other(format!("Refusing to descend into {}, it's a symlink or not a directory", Path::new(name).display())))
+        },
+        result => result
+    }
+}
+
+/// Creates dest if it's missing, then every directory of relative under it, returning the innermost one.
+/// dest itself is trusted and may be a symlink; nothing under it is.
+#[cfg(target_os = "linux")]
+pub fn create_dir_all(dest: &Path, relative: &Path) -> io::Result<File> {
+    std::fs::create_dir_all(dest)?;
+    let mut dir = open_at(None, dest.as_os_str(), sys::O_RDONLY | sys::O_DIRECTORY, 0)?;
+    for name in components(relative)? {
+        dir = descend(&dir, name)?;
+    }
+    Ok(dir)
+}
+
+/// Creates (or truncates) the file at relative under dest, creating the directories on the way
+#[cfg(target_os = "linux")]
+pub fn create_file(dest: &Path, relative: &Path) -> io::Result<File> {
+    let names = components(relative)?;
+    let (name, parents) = names.split_last()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "No file name to create"))?;
+    let parent = create_dir_all(dest, &parents.iter().collect::<std::path::PathBuf>())?;
+    open_at(Some(&parent), name, sys::O_WRONLY | sys::O_CREAT | sys::O_TRUNC | sys::O_NOFOLLOW, 0o666)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn unsupported() -> io::Error {
+    io::Error::new(io::ErrorKind::Unsupported, "Sandboxed extraction is only available on Linux")
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn create_dir_all(_dest: &Path, relative: &Path) -> io::Result<File> {
+    components(relative)?;
+    Err(unsupported())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn create_file(_dest: &Path, relative: &Path) -> io::Result<File> {
+    components(relative)?;
+    Err(unsupported())
+}
@@ -0,0 +1,96 @@
+#![allow(dead_code)]
+use std::thread;
+use crate::ignore::glob_match;
+use crate::ziparchive::ZipArchive;
+use crate::zipentry::ZipEntry;
+
+/// Decides whether a line matches, returning where in the line the match starts
+pub type Matcher<'m> = dyn Fn(&[u8]) -> Option<usize> + Sync + 'm;
+
+#[derive(Debug, Clone, Default)]
+pub struct SearchOptions {
+    /// Only search entries whose names match this glob. Without a '/' it's matched against the last path
+    /// component, like a .gitignore pattern, so "*.json" finds JSON files at any depth.
+    pub name_filter: Option<String>,
+    /// Entries searched at once, 0 or 1 to search one at a time
+    pub threads: usize
+}
+
+/// A matching line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchMatch {
+    pub entry: String,
+    /// Counting from 1
+    pub line_number: usize,
+    /// Offset of the match in the entry's contents
+    pub offset: u64,
+    /// The line, without its line ending. Bytes that aren't UTF-8 are replaced.
+    pub line: String
+}
+
+/// A matcher for a fixed string, optionally ignoring ASCII case
+pub fn literal(pattern: &str, ignore_case: bool) -> impl Fn(&[u8]) -> Option<usize> + Sync {
+    let pattern = if ignore_case { pattern.to_ascii_lowercase() } else { pattern.to_string() }.into_bytes();
+    move |line: &[u8]| {
+        if pattern.is_empty() {
+            return Some(0);
+        }
+        line.windows(pattern.len()).position(|window| {
+            if ignore_case { window.eq_ignore_ascii_case(&pattern) } else { window == pattern.as_slice() }
+        })
+    }
+}
+
+fn name_matches(filter: &str, name: &str) -> bool {
+    let target = if filter.contains('/') { name } else { name.trim_end_matches('/').rsplit('/').next().unwrap_or(name) };
+    glob_match(filter.as_bytes(), target.as_bytes())
+}
+
+fn search_entry(entry: &ZipEntry, matcher: &Matcher) -> Vec<SearchMatch> {
+    // Entries that can't be read (encrypted, unsupported method, damaged) have nothing to match
+    let data = match entry.read() {
+        Ok(data) => data,
+        Err(_) => return Vec::new()
+    };
+
+    let mut matches = Vec::new();
+    let mut line_start = 0;
+    // A final line ending doesn't start another line
+    let text = data.strip_suffix(b"\n").unwrap_or(&data);
+    for (index, raw_line) in text.split(|&byte| byte == b'\n').enumerate() {
+        let line = raw_line.strip_suffix(b"\r").unwrap_or(raw_line);
+        if let Some(position) = matcher(line) {
+            matches.push(SearchMatch {
+                entry: entry.name(),
+                line_number: index + 1,
+                offset: (line_start + position) as u64,
+                line: String::from_utf8_lossy(line).into_owned()
+            });
+        }
+        line_start += raw_line.len() + 1;
+    }
+    matches
+}
+
+impl ZipArchive<'_> {
+    /// Runs every line of the matching entries through matcher, without extracting anything to disk.
+    /// Matches come back in entry order, then line order. Directories and entries that can't be read are skipped.
+    pub fn search(&self, options: &SearchOptions, matcher: &Matcher) -> Vec<SearchMatch> {
+        let entries: Vec<&ZipEntry> = self.entries().iter()
+            .filter(|entry| !entry.is_dir())
+            .filter(|entry| options.name_filter.as_ref().is_none_or(|filter| name_matches(filter, &entry.name())))
+            .collect();
+
+        if options.threads <= 1 || entries.len() <= 1 {
+            return entries.into_iter().flat_map(|entry| search_entry(entry, matcher)).collect();
+        }
+
+        let chunk_size = entries.len().div_ceil(options.threads);
+        thread::scope(|scope| {
+            let workers: Vec<_> = entries.chunks(chunk_size).map(|chunk| {
+                scope.spawn(move || chunk.iter().flat_map(|entry| search_entry(entry, matcher)).collect::<Vec<_>>())
+            }).collect();
+            workers.into_iter().flat_map(|worker| worker.join().unwrap_or_default()).collect()
+        })
+    }
+}
@@ -0,0 +1,352 @@
+#![allow(dead_code)]
+// One pass over an archive for everything that makes it unsafe to accept from an untrusted source
+use std::cmp::Reverse;
+use std::fmt;
+use crate::sniff;
+use crate::ziparchive::ZipArchive;
+use crate::zipentry::ZipEntry;
+
+/// Names Windows reserves for devices, with or without an extension
+const DEVICE_NAMES: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL",
+    "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8", "COM9",
+    "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9"
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    /// Unusual but harmless on its own
+    Info,
+    /// Worth a look, or a problem on some systems
+    Warning,
+    /// Reject the archive
+    Critical
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Info => write!(f, "info"),
+            Severity::Warning => write!(f, "warning"),
+            Severity::Critical => write!(f, "critical")
+        }
+    }
+}
+
+/// Limits the analysis holds an archive to
+#[derive(Debug, Clone)]
+pub struct SecurityPolicy {
+    /// Uncompressed to compressed ratio an entry may have
+    pub max_ratio: u64,
+    /// Declared uncompressed size of all entries together
+    pub max_total_size: u64,
+    pub max_entries: usize,
+    /// Directories an entry's path may go down
+    pub max_path_depth: usize,
+    /// Zips inside zips the archive may hold, 0 to flag any nested archive
+    pub max_nesting_depth: usize
+}
+
+impl Default for SecurityPolicy {
+    fn default() -> SecurityPolicy {
+        SecurityPolicy {
+            max_ratio: 100,
+            max_total_size: 4 * 1024 * 1024 * 1024,
+            max_entries: 65535,
+            max_path_depth: 32,
+            max_nesting_depth: 2
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FindingKind {
+    /// A ".." component that would climb out of the extraction directory
+    PathTraversal,
+    /// A leading '/' or '\', or a drive letter
+    AbsolutePath,
+    /// A component Windows treats as a device (CON, NUL, COM1, ...)
+    DeviceName { component: String },
+    /// Directories deeper than the policy allows
+    DeepPath { depth: usize },
+    /// The entry's data shares bytes with another entry's, the trick behind non-recursive zip bombs
+    OverlappingEntries { other: String },
+    /// Expands more than the policy allows
+    HighRatio { ratio: u64 },
+    /// The entries declare more data than the policy allows
+    TotalSizeExceeded { total: u64 },
+    TooManyEntries { count: usize },
+    /// Zips nested deeper than the policy allows
+    NestedArchive { depth: usize },
+    /// A symlink, which could point anywhere once extracted
+    Symlink,
+    /// Couldn't be loaded at all
+    BrokenEntry { reason: String }
+}
+
+impl FindingKind {
+    pub fn severity(&self) -> Severity {
+        match self {
+            FindingKind::PathTraversal | FindingKind::AbsolutePath | FindingKind::OverlappingEntries { .. }
+                | FindingKind::TotalSizeExceeded { .. } | FindingKind::NestedArchive { .. } => Severity::Critical,
+            FindingKind::DeviceName { .. } | FindingKind::DeepPath { .. } | FindingKind::HighRatio { .. }
+                | FindingKind::TooManyEntries { .. } | FindingKind::BrokenEntry { .. } => Severity::Warning,
+            FindingKind::Symlink => Severity::Info
+        }
+    }
+}
+
+impl fmt::Display for FindingKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FindingKind::PathTraversal => write!(f, "path climbs out of the extraction directory"),
+            FindingKind::AbsolutePath => write!(f, "absolute path"),
+            FindingKind::DeviceName { component } => write!(f, "{} is a Windows device name", component),
+            FindingKind::DeepPath { depth } => write!(f, "path is {} directories deep", depth),
+            FindingKind::OverlappingEntries { other } => write!(f, "data overlaps {}", other),
+            FindingKind::HighRatio { ratio } => write!(f, "expands {} times", ratio),
+            FindingKind::TotalSizeExceeded { total } => write!(f, "entries add up to {} bytes", total),
+            FindingKind::TooManyEntries { count } => write!(f, "{} entries", count),
+            FindingKind::NestedArchive { depth } => write!(f, "archives nested {} deep", depth),
+            FindingKind::Symlink => write!(f, "symlink"),
+            FindingKind::BrokenEntry { reason } => write!(f, "couldn't be loaded: {}", reason)
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SecurityFinding {
+    /// The entry the finding is about, None for the archive as a whole
+    pub entry: Option<String>,
+    pub kind: FindingKind
+}
+
+impl SecurityFinding {
+    pub fn severity(&self) -> Severity {
+        self.kind.severity()
+    }
+}
+
+impl fmt::Display for SecurityFinding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.entry {
+            Some(entry) => write!(f, "{}: {}: {}", self.severity(), entry, self.kind),
+            None => write!(f, "{}: {}", self.severity(), self.kind)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SecurityReport {
+    pub findings: Vec<SecurityFinding>
+}
+
+impl SecurityReport {
+    /// The most serious finding's severity, None if nothing was found
+    pub fn worst(&self) -> Option<Severity> {
+        self.findings.iter().map(SecurityFinding::severity).max()
+    }
+
+    /// Whether nothing reaches threshold. Upload services would usually pass Severity::Critical.
+    pub fn is_acceptable(&self, threshold: Severity) -> bool {
+        self.worst().is_none_or(|worst| worst < threshold)
+    }
+}
+
+fn check_name(name: &str, policy: &SecurityPolicy) -> Vec<FindingKind> {
+    let mut kinds = Vec::new();
+    let bytes = name.as_bytes();
+    let has_drive = bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':';
+    if name.starts_with(['/', '\\']) || has_drive {
+        kinds.push(FindingKind::AbsolutePath);
+    }
+
+    let components: Vec<&str> = name.split(['/', '\\']).filter(|component| !component.is_empty() && *component != ".").collect();
+    if components.contains(&"..") {
+        kinds.push(FindingKind::PathTraversal);
+    }
+    for component in &components {
+        let stem = component.split('.').next().unwrap_or(component).trim_end();
+        if DEVICE_NAMES.iter().any(|device| device.eq_ignore_ascii_case(stem)) {
+            kinds.push(FindingKind::DeviceName { component: component.to_string() });
+        }
+    }
+
+    // The last component of a file isn't a directory
+    let depth = components.len().saturating_sub(if name.ends_with('/') { 0 } else { 1 });
+    if depth > policy.max_path_depth {
+        kinds.push(FindingKind::DeepPath { depth });
+    }
+    kinds
+}
+
+/// Entries whose local header and data overlap another entry's, each paired with the first entry it overlaps
+fn overlapping(entries: &[ZipEntry]) -> Vec<(String, &ZipEntry)> {
+    let mut spans: Vec<(u64, u64, &ZipEntry)> = entries.iter()
+        .filter_map(|entry| entry.data_range().ok().map(|data| (entry.header_offset(), data.end, entry)))
+        .collect();
+    spans.sort_by_key(|&(start, end, _)| (start, end));
+
+    let mut found = Vec::new();
+    let mut furthest: Option<(u64, &ZipEntry)> = None;
+    for (start, end, entry) in spans {
+        if let Some((_, other)) = furthest.filter(|&(furthest_end, _)| start < furthest_end) {
+            found.push((entry.name(), other));
+        }
+        if furthest.is_none_or(|(furthest_end, _)| end > furthest_end) {
+            furthest = Some((end, entry));
+        }
+    }
+    found
+}
+
+/// How many levels of zips are inside this one, not counting itself
+fn nesting_depth(archive: &ZipArchive, limit: usize) -> usize {
+    let mut deepest = 0;
+    for entry in archive.entries() {
+        if entry.is_dir() || !entry.sniff_type().is_ok_and(|file_type| file_type == sniff::ZIP) {
+            continue;
+        }
+        let inner = match entry.read().and_then(|data| ZipArchive::parse_bytes(&data)) {
+            Ok(inner) => inner,
+            Err(_) => continue
+        };
+        // No need to dig past the point where the limit is already broken
+        let depth = if limit == 0 { 1 } else { 1 + nesting_depth(&inner, limit - 1) };
+        deepest = deepest.max(depth);
+        if deepest > limit {
+            break;
+        }
+    }
+    deepest
+}
+
+impl ZipArchive<'_> {
+    /// Runs every safety check against the archive and reports what it finds, so an upload can be accepted or
+    /// rejected in one call. Nothing is written anywhere; only entries that look like zips are decoded, to check
+    /// how deep they nest.
+    pub fn analyze_security(&self, policy: &SecurityPolicy) -> SecurityReport {
+        let mut findings = Vec::new();
+        let entries = self.entries();
+
+        let count = entries.len() + self.broken_entries().len();
+        if count > policy.max_entries {
+            findings.push(SecurityFinding { entry: None, kind: FindingKind::TooManyEntries { count } });
+        }
+        let total = entries.iter().fold(0u64, |total, entry| total.saturating_add(entry.uncompressed_size()));
+        if total > policy.max_total_size {
+            findings.push(SecurityFinding { entry: None, kind: FindingKind::TotalSizeExceeded { total } });
+        }
+
+        for entry in entries {
+            let name = entry.name();
+            let mut kinds = check_name(&name, policy);
+            if entry.is_symlink() {
+                kinds.push(FindingKind::Symlink);
+            }
+            if !entry.is_dir() {
+                let ratio = entry.uncompressed_size() / entry.compressed_size().max(1);
+                if ratio > policy.max_ratio {
+                    kinds.push(FindingKind::HighRatio { ratio });
+                }
+            }
+            findings.extend(kinds.into_iter().map(|kind| SecurityFinding { entry: Some(name.clone()), kind }));
+        }
+        for (entry, other) in overlapping(entries) {
+            findings.push(SecurityFinding { entry: Some(entry), kind: FindingKind::OverlappingEntries { other: other.name() } });
+        }
+        // The loader refuses an entry that starts inside another's data, so a zip bomb's overlapping entries mostly
+        // show up here, with headers inside an entry that did load
+        for broken in self.broken_entries() {
+            let inside = entries.iter().find(|entry| {
+                entry.data_range().is_ok_and(|data| (entry.header_offset()..data.end).contains(&broken.offset))
+            });
+            let kind = match inside {
+                Some(other) => FindingKind::OverlappingEntries { other: other.name() },
+                None => FindingKind::BrokenEntry { reason: broken.error.to_string() }
+            };
+            findings.push(SecurityFinding { entry: broken.name.clone(), kind });
+        }
+
+        let depth = nesting_depth(self, policy.max_nesting_depth);
+        if depth > policy.max_nesting_depth {
+            findings.push(SecurityFinding { entry: None, kind: FindingKind::NestedArchive { depth } });
+        }
+        // Most serious first
+        findings.sort_by_key(|finding| Reverse(finding.severity()));
+        SecurityReport { findings }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Write};
+    use super::*;
+    use crate::zipwriter::{FileOptions, ZipWriter};
+
+    fn archive_of(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, contents) in files {
+            writer.start_file(name, FileOptions::default()).unwrap();
+            writer.write_all(contents).unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn every_check_reports() {
+        let innermost = archive_of(&[("core.txt", b"core")]);
+        let inner = archive_of(&[("innermost.zip", &innermost)]);
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        let files: [(&str, &[u8]); 5] = [
+            ("../evil.txt", b"up"),
+            ("/etc/passwd", b"root"),
+            ("a/b/c/deep.txt", b"deep"),
+            ("docs/CON.txt", b"device"),
+            ("nested.zip", &inner)
+        ];
+        for (name, contents) in files {
+            writer.start_file(name, FileOptions::default()).unwrap();
+            writer.write_all(contents).unwrap();
+        }
+        writer.add_symlink("link", "/etc", FileOptions::default()).unwrap();
+        let archive = ZipArchive::parse_bytes(&writer.finish().unwrap().into_inner()).unwrap();
+        let policy = SecurityPolicy { max_ratio: 100, max_total_size: 10, max_entries: 5, max_path_depth: 2, max_nesting_depth: 1 };
+
+        let report = archive.analyze_security(&policy);
+        let found = |entry: Option<&str>, kind: FindingKind| report.findings.contains(&SecurityFinding { entry: entry.map(str::to_string), kind });
+        assert!(found(Some("../evil.txt"), FindingKind::PathTraversal));
+        assert!(found(Some("/etc/passwd"), FindingKind::AbsolutePath));
+        assert!(found(Some("a/b/c/deep.txt"), FindingKind::DeepPath { depth: 3 }));
+        assert!(found(Some("docs/CON.txt"), FindingKind::DeviceName { component: "CON.txt".to_string() }));
+        assert!(found(Some("link"), FindingKind::Symlink));
+        assert!(found(None, FindingKind::TooManyEntries { count: 6 }));
+        assert!(found(None, FindingKind::NestedArchive { depth: 2 }));
+        assert!(report.findings.iter().any(|finding| matches!(finding.kind, FindingKind::TotalSizeExceeded { .. })));
+        assert!(!report.findings.iter().any(|finding| matches!(finding.kind, FindingKind::HighRatio { .. } | FindingKind::OverlappingEntries { .. })));
+
+        assert_eq!(report.worst(), Some(Severity::Critical));
+        assert_eq!(report.findings[0].severity(), Severity::Critical);
+        assert!(!report.is_acceptable(Severity::Critical));
+
+        let clean = ZipArchive::parse_bytes(&archive_of(&[("docs/readme.txt", b"fine")])).unwrap();
+        let report = clean.analyze_security(&SecurityPolicy::default());
+        assert!(report.findings.is_empty());
+        assert!(report.is_acceptable(Severity::Info));
+    }
+
+    #[test]
+    fn overlapping_entries_are_critical() {
+        let mut data = archive_of(&[("first", &[b'x'; 1000]), ("second", b"second")]);
+        let archive = ZipArchive::parse_bytes(&data).unwrap();
+        let first_data = archive.entries()[0].data_range().unwrap().start as u32;
+        let second_record = archive.entries()[1].record.start_offset as usize;
+        data[second_record + 42..second_record + 46].copy_from_slice(&(first_data + 10).to_le_bytes());
+        let archive = ZipArchive::parse_bytes(&data).unwrap();
+
+        let report = archive.analyze_security(&SecurityPolicy::default());
+        let overlap = SecurityFinding { entry: Some("second".to_string()), kind: FindingKind::OverlappingEntries { other: "first".to_string() } };
+        assert_eq!(report.findings, [overlap]);
+        assert_eq!(report.worst(), Some(Severity::Critical));
+    }
+}
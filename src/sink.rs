@@ -0,0 +1,187 @@
+#![allow(dead_code)]
+// Extraction to somewhere other than a directory: an object store, an in-memory filesystem, a content-addressed
+// store. extract_all_to walks the archive with the same ordering, renaming, path and link safety as extract_all, and
+// hands each entry to an ExtractSink under a relative path that can't escape it.
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use crate::datetime::DateTime;
+use crate::extract::{self, Deadline, ExtractOptions, ExtractReport, LinkPolicy, Outcome, OutputPlan, TruncatedEntry};
+use crate::ziparchive::ZipArchive;
+use crate::zipentry::ZipEntry;
+use crate::ziperror::{ZipError, ZipResult};
+
+/// What an entry says about its output besides the contents
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EntryMetadata {
+    /// Permission bits, as ExtractOptions::permissions leaves them. None to leave them as created.
+    pub mode: Option<u32>,
+    pub read_only: bool,
+    /// For sinks that keep times. The filesystem sink leaves them alone, as extract_all does.
+    pub last_modified: Option<DateTime>,
+    /// The stored (uid, gid), with ExtractOptions::preserve_ownership
+    pub owner: Option<(u32, u32)>
+}
+
+impl EntryMetadata {
+    fn of(entry: &ZipEntry, options: &ExtractOptions) -> EntryMetadata {
+        EntryMetadata {
+            mode: entry.unix_mode().filter(|_| !entry.is_dir()).and_then(|mode| options.permissions.apply(mode)),
+            read_only: entry.is_read_only() && !entry.is_dir(),
+            last_modified: entry.last_modified(),
+            owner: entry.unix_owner().filter(|_| options.preserve_ownership)
+        }
+    }
+}
+
+/// Where extract_all_to writes entries. Paths are relative, '/' separated, and already checked: nothing in them
+/// climbs out with "..", starts at a root or names a drive.
+pub trait ExtractSink {
+    fn create_dir(&mut self, path: &str) -> io::Result<()>;
+
+    /// Somewhere to write a file's contents, creating whatever directories the path needs. The file is finished
+    /// when the writer is dropped.
+    fn create_file(&mut self, path: &str) -> io::Result<Box<dyn Write + '_>>;
+
+    /// Called for every directory and file after it's created
+    fn set_metadata(&mut self, path: &str, metadata: &EntryMetadata) -> io::Result<()>;
+
+    /// Makes a link at path pointing at target, with LinkPolicy::Create or CreateOrCopy. is_dir says whether the
+    /// target is a directory in the archive. Sinks that can't hold links leave this failing with Unsupported, and
+    /// the link is skipped or copied as the policy says.
+    fn symlink(&mut self, path: &str, target: &str, is_dir: bool) -> io::Result<()> {
+        let _ = (path, target, is_dir);
+        Err(io::Error::new(io::ErrorKind::Unsupported, "this sink can't hold links"))
+    }
+
+    /// Throws away a file that was given up part way through (see ExtractOptions::entry_time_limit). Does nothing
+    /// unless the sink can take a file back.
+    fn abandon_file(&mut self, path: &str) -> io::Result<()> {
+        let _ = path;
+        Ok(())
+    }
+}
+
+/// The sink for a directory on disk
+#[derive(Debug, Clone)]
+pub struct FsSink {
+    dest: PathBuf
+}
+
+impl FsSink {
+    pub fn new<P: AsRef<Path>>(dest: P) -> FsSink {
+        FsSink { dest: dest.as_ref().to_path_buf() }
+    }
+
+    fn path(&self, path: &str) -> PathBuf {
+        let mut output = self.dest.clone();
+        output.extend(path.split('/'));
+        output
+    }
+}
+
+impl ExtractSink for FsSink {
+    fn create_dir(&mut self, path: &str) -> io::Result<()> {
+        fs::create_dir_all(self.path(path))
+    }
+
+    fn create_file(&mut self, path: &str) -> io::Result<Box<dyn Write + '_>> {
+        let output = self.path(path);
+        if let Some(parent) = output.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        Ok(Box::new(File::create(output)?))
+    }
+
+    fn set_metadata(&mut self, path: &str, metadata: &EntryMetadata) -> io::Result<()> {
+        let output = self.path(path);
+        #[cfg(unix)]
+        {
+            if let Some((uid, gid)) = metadata.owner.filter(|_| extract::is_root()) {
+                std::os::unix::fs::lchown(&output, Some(uid), Some(gid))?;
+            }
+            if let Some(mode) = metadata.mode {
+                use std::os::unix::fs::PermissionsExt;
+                return fs::set_permissions(&output, fs::Permissions::from_mode(mode));
+            }
+        }
+        if metadata.read_only {
+            let mut permissions = fs::metadata(&output)?.permissions();
+            permissions.set_readonly(true);
+            fs::set_permissions(&output, permissions)?;
+        }
+        Ok(())
+    }
+
+    fn symlink(&mut self, path: &str, target: &str, is_dir: bool) -> io::Result<()> {
+        let output = self.path(path);
+        if let Some(parent) = output.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        extract::create_link(&output, target, is_dir)
+    }
+
+    fn abandon_file(&mut self, path: &str) -> io::Result<()> {
+        fs::remove_file(self.path(path))
+    }
+}
+
+/// Writes data to a new file at path, giving it up if the deadline passes part way
+fn write_file<S: ExtractSink + ?Sized>(sink: &mut S, path: &str, data: &[u8], deadline: &Deadline) -> ZipResult<()> {
+    let mut file = sink.create_file(path)?;
+    let written = extract::write_within(&mut file, data, deadline);
+    drop(file);
+    if matches!(written, Err(ZipError::TimedOut { .. })) {
+        let _ = sink.abandon_file(path);
+    }
+    written
+}
+
+impl ZipArchive<'_> {
+    /// extract_all for any ExtractSink. Entries are ordered, renamed, stripped, checked for unsafe paths, case
+    /// collisions, links escaping the destination and files written through links, converted and timed by the same
+    /// code as extract_all's. The options
+    /// that only mean something for a directory on disk (journal, sandboxed, prefetch, check_free_space and
+    /// on_conflict) are ignored.
+    pub fn extract_all_to<S: ExtractSink + ?Sized>(&self, sink: &mut S, options: &ExtractOptions) -> ZipResult<ExtractReport> {
+        self.run_extraction(self.extraction_order(options), options, |plan, entry, _| write_to_sink(plan, entry, sink))
+    }
+}
+
+/// Writes one entry to sink, where plan puts it
+fn write_to_sink<S: ExtractSink + ?Sized>(plan: &OutputPlan, entry: &ZipEntry, sink: &mut S) -> ZipResult<Outcome> {
+    let name = entry.name();
+    let options = plan.options();
+    let deadline = Deadline::start(&name, options);
+    let path = match plan.output_name(entry)? {
+        Some(path) => path,
+        None => return Ok(Outcome::Skipped)
+    };
+
+    if entry.is_dir() {
+        sink.create_dir(&path)?;
+        sink.set_metadata(&path, &EntryMetadata::of(entry, options))?;
+        return Ok(Outcome::Extracted);
+    }
+
+    if entry.is_symlink() && options.links != LinkPolicy::AsFile {
+        return plan.link(entry, &path, sink, |sink, target, is_dir| sink.symlink(&path, target, is_dir), |sink, source| {
+            write_file(sink, &path, &source.read()?, &deadline)?;
+            Ok(sink.set_metadata(&path, &EntryMetadata::of(source, options))?)
+        });
+    }
+
+    let partial = entry.is_truncated() && options.extract_truncated_prefix;
+    let mut data = if partial { entry.read_available()? } else { entry.read()? };
+    if entry.is_text() {
+        data = options.line_endings.convert(data);
+    }
+    deadline.check()?;
+    write_file(sink, &path, &data, &deadline)?;
+    sink.set_metadata(&path, &EntryMetadata::of(entry, options))?;
+
+    if partial {
+        return Ok(Outcome::Truncated(TruncatedEntry { name, written_bytes: data.len() as u64, missing_bytes: entry.missing_bytes() }));
+    }
+    Ok(Outcome::Extracted)
+}
@@ -0,0 +1,111 @@
+#![allow(dead_code)]
+// File type detection from magic numbers, for triaging archives of unknown provenance
+use crate::zipentry::{ZipEntry, CompressionMethod};
+use crate::ziperror::ZipResult;
+
+/// How much of an entry gets looked at. tar's magic is the furthest in, at 257.
+const SNIFF_LENGTH: usize = 512;
+
+/// A detected file type
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileType {
+    pub mime: &'static str,
+    /// Extensions files of this type usually have, lowercase without the dot. Empty for the generic fallbacks.
+    pub extensions: &'static [&'static str]
+}
+
+pub const TEXT: FileType = FileType { mime: "text/plain", extensions: &[] };
+pub const BINARY: FileType = FileType { mime: "application/octet-stream", extensions: &[] };
+pub const ZIP: FileType = FileType { mime: "application/zip", extensions: &["zip", "jar", "apk", "docx", "xlsx", "pptx", "odt", "epub", "whl"] };
+
+/// (offset, magic, type)
+const SIGNATURES: &[(usize, &[u8], FileType)] = &[
+    (0, b"\x89PNG\r\n\x1a\n", FileType { mime: "image/png", extensions: &["png"] }),
+    (0, b"\xff\xd8\xff", FileType { mime: "image/jpeg", extensions: &["jpg", "jpeg"] }),
+    (0, b"GIF87a", FileType { mime: "image/gif", extensions: &["gif"] }),
+    (0, b"GIF89a", FileType { mime: "image/gif", extensions: &["gif"] }),
+    (0, b"BM", FileType { mime: "image/bmp", extensions: &["bmp"] }),
+    (0, b"\x00\x00\x01\x00", FileType { mime: "image/x-icon", extensions: &["ico"] }),
+    (0, b"II*\x00", FileType { mime: "image/tiff", extensions: &["tif", "tiff"] }),
+    (0, b"MM\x00*", FileType { mime: "image/tiff", extensions: &["tif", "tiff"] }),
+    (8, b"WEBP", FileType { mime: "image/webp", extensions: &["webp"] }),
+    (8, b"WAVE", FileType { mime: "audio/wav", extensions: &["wav"] }),
+    (0, b"OggS", FileType { mime: "audio/ogg", extensions: &["ogg", "oga", "ogv", "opus"] }),
+    (0, b"fLaC", FileType { mime: "audio/flac", extensions: &["flac"] }),
+    (0, b"ID3", FileType { mime: "audio/mpeg", extensions: &["mp3"] }),
+    (4, b"ftyp", FileType { mime: "video/mp4", extensions: &["mp4", "m4a", "m4v", "mov", "heic"] }),
+    (0, b"%PDF-", FileType { mime: "application/pdf", extensions: &["pdf"] }),
+    (0, b"PK\x03\x04", ZIP),
+    (0, b"\x1f\x8b", FileType { mime: "application/gzip", extensions: &["gz", "tgz"] }),
+    (0, b"BZh", FileType { mime: "application/x-bzip2", extensions: &["bz2", "tbz2"] }),
+    (0, b"\xfd7zXZ\x00", FileType { mime: "application/x-xz", extensions: &["xz", "txz"] }),
+    (0, b"\x28\xb5\x2f\xfd", FileType { mime: "application/zstd", extensions: &["zst"] }),
+    (0, b"\x04\x22\x4d\x18", FileType { mime: "application/x-lz4", extensions: &["lz4"] }),
+    (0, b"7z\xbc\xaf\x27\x1c", FileType { mime: "application/x-7z-compressed", extensions: &["7z"] }),
+    (0, b"Rar!\x1a\x07", FileType { mime: "application/vnd.rar", extensions: &["rar"] }),
+    (257, b"ustar", FileType { mime: "application/x-tar", extensions: &["tar"] }),
+    (0, b"\x7fELF", FileType { mime: "application/x-elf", extensions: &["", "so", "o", "elf"] }),
+    (0, b"MZ", FileType { mime: "application/vnd.microsoft.portable-executable", extensions: &["exe", "dll", "sys"] }),
+    (0, b"\xcf\xfa\xed\xfe", FileType { mime: "application/x-mach-binary", extensions: &["", "dylib"] }),
+    (0, b"\xfe\xed\xfa\xcf", FileType { mime: "application/x-mach-binary", extensions: &["", "dylib"] }),
+    (0, b"\xca\xfe\xba\xbe", FileType { mime: "application/java-vm", extensions: &["class"] }),
+    (0, b"\x00asm", FileType { mime: "application/wasm", extensions: &["wasm"] }),
+    (0, b"SQLite format 3\x00", FileType { mime: "application/vnd.sqlite3", extensions: &["sqlite", "sqlite3", "db"] }),
+    (0, b"wOFF", FileType { mime: "font/woff", extensions: &["woff"] }),
+    (0, b"wOF2", FileType { mime: "font/woff2", extensions: &["woff2"] }),
+    (0, b"OTTO", FileType { mime: "font/otf", extensions: &["otf"] }),
+    (0, b"\x00\x01\x00\x00\x00", FileType { mime: "font/ttf", extensions: &["ttf"] }),
+    (0, b"<?xml", FileType { mime: "application/xml", extensions: &["xml", "svg", "xhtml", "plist"] }),
+    (0, b"#!", FileType { mime: "text/x-script", extensions: &["", "sh", "py", "pl", "rb"] })
+];
+
+/// Works out a type from the first bytes of a file. Anything unrecognised is text if it looks like UTF-8 without
+/// control characters, binary otherwise.
+pub fn sniff(data: &[u8]) -> FileType {
+    let data = &data[..data.len().min(SNIFF_LENGTH)];
+    let found = SIGNATURES.iter().find(|(offset, magic, _)| data.get(*offset..offset + magic.len()) == Some(*magic));
+    if let Some((_, _, file_type)) = found {
+        return *file_type;
+    }
+
+    let lowered = data.iter().take(16).map(u8::to_ascii_lowercase).collect::<Vec<u8>>();
+    if lowered.starts_with(b"<!doctype html") || lowered.starts_with(b"<html") {
+        return FileType { mime: "text/html", extensions: &["html", "htm"] };
+    }
+
+    // The sample may cut a multi-byte character in half, which doesn't make it binary
+    let valid_up_to = match std::str::from_utf8(data) {
+        Ok(_) => data.len(),
+        Err(error) if error.error_len().is_none() => error.valid_up_to(),
+        Err(_) => return BINARY
+    };
+    let control = data[..valid_up_to].iter().any(|&byte| byte < 0x20 && !b"\t\n\r\x0c".contains(&byte));
+    if control { BINARY } else { TEXT }
+}
+
+impl FileType {
+    /// Whether name's extension is one this type usually has. None for the generic text and binary types, which
+    /// can't be judged by extension.
+    pub fn matches_name(&self, name: &str) -> Option<bool> {
+        if self.extensions.is_empty() {
+            return None;
+        }
+        let file_name = name.rsplit('/').next().unwrap_or(name);
+        let extension = match file_name.rsplit_once('.') {
+            Some((stem, extension)) if !stem.is_empty() => extension.to_ascii_lowercase(),
+            _ => String::new()
+        };
+        Some(self.extensions.contains(&extension.as_str()))
+    }
+}
+
+impl ZipEntry {
+    /// Detects the type of the entry's contents from their first bytes. Stored entries only have their first few
+    /// hundred bytes looked at; anything else is decoded in full first.
+    pub fn sniff_type(&self) -> ZipResult<FileType> {
+        if self.compression_method() == CompressionMethod::Stored && !self.is_encrypted() {
+            return Ok(sniff(self.raw_data()));
+        }
+        Ok(sniff(&self.read()?))
+    }
+}
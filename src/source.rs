@@ -0,0 +1,175 @@
+#![allow(dead_code)]
+// Building an archive from somewhere other than a directory: rows in a database, objects in a store, generated
+// content. An ArchiveSource hands over items one at a time and ZipWriter::add_source writes them, the mirror of
+// ExtractSink on the way out (see sink.rs).
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use crate::datetime::DateTime;
+use crate::walk::{self, SkipReason, SkippedLink, WalkEntry, WalkOptions};
+use crate::ziperror::ZipResult;
+use crate::zipwriter::{AddFailure, FileOptions, ZipWriter};
+
+/// What kind of entry an item becomes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SourceKind {
+    File,
+    Directory,
+    /// A link to this target, '/' separated
+    Symlink(String)
+}
+
+/// Something an ArchiveSource has for the archive
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceItem {
+    /// The entry name, '/' separated and relative
+    pub name: String,
+    pub kind: SourceKind
+}
+
+/// What an item's entry records besides its contents. Anything left None keeps what the options passed to
+/// add_source say.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct SourceMetadata {
+    pub last_modified: Option<DateTime>,
+    pub unix_permissions: Option<u32>,
+    pub comment: Option<String>,
+    /// The size of the contents, when it's known up front. Files of 4 GiB and over need it to get ZIP64 headers.
+    pub size: Option<u64>
+}
+
+/// Where ZipWriter::add_source gets entries from. Items come one at a time so a source can page through something
+/// too big to list first.
+pub trait ArchiveSource {
+    /// The next item to add, None once there are no more. An error skips that item, the source is asked again.
+    fn next_item(&mut self) -> Option<io::Result<SourceItem>>;
+
+    /// The contents of a file item
+    fn open(&mut self, item: &SourceItem) -> io::Result<Box<dyn Read + '_>>;
+
+    fn metadata(&mut self, item: &SourceItem) -> io::Result<SourceMetadata> {
+        let _ = item;
+        Ok(SourceMetadata::default())
+    }
+}
+
+/// The source for a directory on disk, walked up front the way add_dir_all walks it. Links the walk couldn't follow
+/// come last, as errors.
+#[derive(Debug, Clone)]
+pub struct FsSource {
+    found: std::vec::IntoIter<WalkEntry>,
+    skipped: std::vec::IntoIter<SkippedLink>,
+    /// Where each item given so far came from
+    paths: HashMap<String, PathBuf>
+}
+
+impl FsSource {
+    pub fn new<P: AsRef<Path>>(root: P, options: &WalkOptions) -> io::Result<FsSource> {
+        let walked = walk::walk_dir(root.as_ref(), options)?;
+        Ok(FsSource { found: walked.entries.into_iter(), skipped: walked.skipped.into_iter(), paths: HashMap::new() })
+    }
+
+    fn path(&self, item: &SourceItem) -> io::Result<&Path> {
+        self.paths.get(&item.name).map(PathBuf::as_path)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("{} isn't from this source", item.name)))
+    }
+}
+
+impl ArchiveSource for FsSource {
+    fn next_item(&mut self) -> Option<io::Result<SourceItem>> {
+        let found = match self.found.next() {
+            Some(found) => found,
+            None => {
+                let skipped = self.skipped.next()?;
+                let kind = if skipped.reason == SkipReason::Dangling { io::ErrorKind::NotFound } else { io::ErrorKind::Other };
+                return Some(Err(io::Error::new(kind, skipped.to_string())));
+            }
+        };
+        let kind = match &found.symlink_target {
+            Some(target) => SourceKind::Symlink(target.to_string_lossy().replace('\\', "/")),
+            None if found.is_dir => SourceKind::Directory,
+            None => SourceKind::File
+        };
+        self.paths.insert(found.name.clone(), found.path);
+        Some(Ok(SourceItem { name: found.name, kind }))
+    }
+
+    fn open(&mut self, item: &SourceItem) -> io::Result<Box<dyn Read + '_>> {
+        Ok(Box::new(File::open(self.path(item)?)?))
+    }
+
+    fn metadata(&mut self, item: &SourceItem) -> io::Result<SourceMetadata> {
+        // A stored link may well point at nothing, it's the link that's being described
+        let metadata = match item.kind {
+            SourceKind::Symlink(_) => std::fs::symlink_metadata(self.path(item)?)?,
+            _ => std::fs::metadata(self.path(item)?)?
+        };
+        #[cfg(unix)]
+        let unix_permissions = {
+            use std::os::unix::fs::PermissionsExt;
+            Some(metadata.permissions().mode() & 0o7777)
+        };
+        #[cfg(not(unix))]
+        let unix_permissions = None;
+        Ok(SourceMetadata { unix_permissions, size: Some(metadata.len()).filter(|_| metadata.is_file()), ..SourceMetadata::default() })
+    }
+}
+
+impl<W: Write> ZipWriter<W> {
+    /// Adds everything source has, in the order it gives them, with options for anything its metadata doesn't
+    /// say. An item the source fails to give, open or describe is collected in the returned list (with its name as
+    /// the path, when it has one) and skipped. Failing to write the archive stops the add, and so does a read failing
+    /// part way through a file, since its entry has already begun.
+    pub fn add_source<S: ArchiveSource + ?Sized>(&mut self, source: &mut S, options: FileOptions) -> ZipResult<Vec<AddFailure>> {
+        let mut failures = Vec::new();
+        while let Some(item) = source.next_item() {
+            let item = match item {
+                Ok(item) => item,
+                Err(error) => {
+                    failures.push(AddFailure { path: None, error });
+                    continue;
+                }
+            };
+            let failed = |error| AddFailure { path: Some(PathBuf::from(&item.name)), error };
+            let metadata = match source.metadata(&item) {
+                Ok(metadata) => metadata,
+                Err(error) => {
+                    failures.push(failed(error));
+                    continue;
+                }
+            };
+
+            let mut item_options = options.clone();
+            if let Some(time) = metadata.last_modified {
+                item_options = item_options.last_modified_time(time);
+            }
+            if let Some(permissions) = metadata.unix_permissions {
+                item_options = item_options.unix_permissions(permissions);
+            }
+            if let Some(comment) = &metadata.comment {
+                item_options = item_options.comment(comment);
+            }
+            if metadata.size.is_some_and(|size| size >= u32::MAX as u64) {
+                item_options = item_options.large_file(true);
+            }
+
+            match &item.kind {
+                SourceKind::Directory => self.add_directory(&item.name, item_options)?,
+                SourceKind::Symlink(target) => self.add_symlink(&item.name, target, item_options)?,
+                SourceKind::File => {
+                    let mut contents = match source.open(&item) {
+                        Ok(contents) => contents,
+                        Err(error) => {
+                            failures.push(failed(error));
+                            continue;
+                        }
+                    };
+                    self.start_file(&item.name, item_options)?;
+                    io::copy(&mut contents, self)?;
+                }
+            }
+        }
+        Ok(failures)
+    }
+}
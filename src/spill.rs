@@ -0,0 +1,90 @@
+#![allow(dead_code)]
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use crate::crc32::Crc32;
+
+static SPILL_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Holds an entry's data while it's being built. Stays in memory until it grows past the threshold,
+/// then moves to a temp file that's deleted when the buffer is dropped.
+#[derive(Debug)]
+pub(crate) struct SpillBuffer {
+    memory: Vec<u8>,
+    file: Option<(File, PathBuf)>,
+    /// None to never spill
+    threshold: Option<u64>,
+    dir: PathBuf,
+    len: u64,
+    crc: Crc32
+}
+
+impl SpillBuffer {
+    pub(crate) fn new(threshold: Option<u64>, dir: PathBuf) -> SpillBuffer {
+        SpillBuffer { memory: Vec::new(), file: None, threshold, dir, len: 0, crc: Crc32::new() }
+    }
+
+    pub(crate) fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The crc of everything written so far
+    pub(crate) fn crc32(&self) -> u32 {
+        self.crc.finish()
+    }
+
+    pub(crate) fn is_spilled(&self) -> bool {
+        self.file.is_some()
+    }
+
+    pub(crate) fn push(&mut self, data: &[u8]) -> io::Result<()> {
+        self.crc.update(data);
+        self.len += data.len() as u64;
+
+        if self.file.is_none() && self.threshold.is_some_and(|threshold| self.len > threshold) {
+            self.spill()?;
+        }
+        match &mut self.file {
+            Some((file, _)) => file.write_all(data),
+            None => {
+                self.memory.extend_from_slice(data);
+                Ok(())
+            }
+        }
+    }
+
+    /// Moves what's in memory so far out to a new temp file
+    fn spill(&mut self) -> io::Result<()> {
+        let name = format!("rip-spill-{}-{}", std::process::id(), SPILL_COUNTER.fetch_add(1, Ordering::Relaxed));
+        let path = self.dir.join(name);
+        let mut file = OpenOptions::new().read(true).write(true).create_new(true).open(&path)?;
+        file.write_all(&self.memory)?;
+        self.memory = Vec::new();
+        self.file = Some((file, path));
+        Ok(())
+    }
+
+    pub(crate) fn copy_to<W: Write>(&mut self, output: &mut W) -> io::Result<()> {
+        match &mut self.file {
+            Some((file, _)) => {
+                file.seek(SeekFrom::Start(0))?;
+                io::copy(file, output)?;
+                Ok(())
+            },
+            None => output.write_all(&self.memory)
+        }
+    }
+}
+
+impl Drop for SpillBuffer {
+    fn drop(&mut self) {
+        if let Some((_, path)) = self.file.take() {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
@@ -0,0 +1,243 @@
+#![allow(dead_code)]
+// Deterministic synthetic archives, the generator behind `rip bench`, for projects that want fixtures built by their
+// tests rather than binary blobs committed next to them. The same spec always gives the same bytes. corrupt damages
+// an archive the ways real ones get damaged, for testing what happens to them.
+use std::io::{Cursor, Write};
+use crate::ziparchive::ZipArchive;
+use crate::zipentry::CompressionMethod;
+use crate::ziperror::{ZipError, ZipResult};
+use crate::zipwriter::{FileOptions, ZipWriter};
+
+/// xorshift64, so everything generated depends on nothing but the seed. testutil generates with it too.
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: u64
+}
+
+impl Rng {
+    /// Seeds are spread out first, so nearby seeds (and 0) don't start off alike
+    pub fn new(seed: u64) -> Rng {
+        Rng { state: seed.wrapping_mul(0x9E3779B97F4A7C15).max(1) }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        self.state
+    }
+
+    /// A number in min..=max
+    pub fn between(&mut self, min: u64, max: u64) -> u64 {
+        if max <= min { min } else { min + self.next_u64() % (max - min + 1) }
+    }
+
+    /// A number in 0..bound
+    pub fn below(&mut self, bound: u64) -> u64 {
+        self.next_u64() % bound.max(1)
+    }
+
+    pub fn chance(&mut self, percent: u64) -> bool {
+        self.below(100) < percent
+    }
+}
+
+/// Contents of size bytes in 64 byte blocks, each either a fixed line of text (with chance compressibility in 100)
+/// or noise
+pub fn data(seed: u64, size: usize, compressibility: u8) -> Vec<u8> {
+    const FILLER: &[u8; 64] = b"The quick brown fox jumps over the lazy dog. needle 0123456789\n ";
+    let mut rng = Rng::new(seed);
+    let mut data = Vec::with_capacity(size);
+    while data.len() < size {
+        let block = (size - data.len()).min(FILLER.len());
+        if rng.next_u64() % 100 < compressibility as u64 {
+            data.extend_from_slice(&FILLER[..block]);
+        } else {
+            data.extend((0..block).map(|_| rng.next_u64() as u8));
+        }
+    }
+    data
+}
+
+/// How big generated entries are
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SizeDistribution {
+    Fixed(usize),
+    /// Evenly spread between min and max, inclusive
+    Uniform { min: usize, max: usize },
+    /// Spread evenly over the orders of magnitude between min and max, so mostly small entries and a few big ones,
+    /// like a source tree
+    LogUniform { min: usize, max: usize }
+}
+
+impl SizeDistribution {
+    fn sample(self, rng: &mut Rng) -> usize {
+        match self {
+            SizeDistribution::Fixed(size) => size,
+            SizeDistribution::Uniform { min, max } => rng.between(min as u64, max as u64) as usize,
+            SizeDistribution::LogUniform { min, max } => {
+                let (low, high) = (((min.max(1)) as f64).ln(), (max.max(min).max(1) as f64).ln());
+                let fraction = rng.next_u64() as f64 / u64::MAX as f64;
+                ((low + (high - low) * fraction).exp().round() as usize).clamp(min, max.max(min))
+            }
+        }
+    }
+}
+
+/// What to generate. Entries are named dir-N/entry-NNNNN.bin, spread over directories.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArchiveSpec {
+    pub seed: u64,
+    pub entries: usize,
+    pub sizes: SizeDistribution,
+    /// Rough percentage of each entry that's repeated text rather than noise, 0 to 100
+    pub compressibility: u8,
+    /// Directories the entries are spread over, each with its own directory entry. 0 puts everything at the top.
+    pub directories: usize,
+    /// Compression methods the entries are labelled with, taken in turn. Only Stored can really be written, so
+    /// entries given another method hold stored bytes under that method's number: enough to exercise the paths
+    /// that handle methods we can't read, which is all they'd reach anyway. Empty means all Stored.
+    pub methods: Vec<CompressionMethod>,
+    /// Indices of entries whose crc32 is wrong in both headers, so reading them fails with CrcMismatch
+    pub bad_crc_entries: Vec<usize>
+}
+
+impl Default for ArchiveSpec {
+    fn default() -> ArchiveSpec {
+        ArchiveSpec {
+            seed: 1,
+            entries: 10,
+            sizes: SizeDistribution::Fixed(1024),
+            compressibility: 50,
+            directories: 0,
+            methods: Vec::new(),
+            bad_crc_entries: Vec::new()
+        }
+    }
+}
+
+impl ArchiveSpec {
+    pub fn entry_name(&self, index: usize) -> String {
+        match self.directories {
+            0 => format!("entry-{:05}.bin", index),
+            directories => format!("dir-{}/entry-{:05}.bin", index % directories, index)
+        }
+    }
+
+    /// The size of every entry, in order
+    pub fn entry_sizes(&self) -> Vec<usize> {
+        let mut rng = Rng::new(self.seed);
+        (0..self.entries).map(|_| self.sizes.sample(&mut rng)).collect()
+    }
+
+    /// What entry index holds, as it reads back (for a Stored entry with a good crc)
+    pub fn entry_data(&self, index: usize) -> Vec<u8> {
+        let size = self.entry_sizes().get(index).copied().unwrap_or(0);
+        data(self.seed.wrapping_add(index as u64 + 1), size, self.compressibility)
+    }
+
+    /// Builds the archive
+    pub fn build(&self) -> ZipResult<Vec<u8>> {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        for directory in 0..self.directories.min(self.entries) {
+            writer.add_directory(&format!("dir-{}/", directory), FileOptions::default())?;
+        }
+        for (index, size) in self.entry_sizes().into_iter().enumerate() {
+            writer.start_file(&self.entry_name(index), FileOptions::default().large_file(size as u64 >= u32::MAX as u64))?;
+            writer.write_all(&data(self.seed.wrapping_add(index as u64 + 1), size, self.compressibility))?;
+        }
+        let mut bytes = writer.finish()?.into_inner();
+
+        let relabeled = self.methods.iter().any(|&method| method != CompressionMethod::Stored);
+        if relabeled || !self.bad_crc_entries.is_empty() {
+            self.patch_headers(&mut bytes)?;
+        }
+        Ok(bytes)
+    }
+
+    /// Changes the method and crc fields the writer had no way to be asked for, in both headers of each entry
+    fn patch_headers(&self, bytes: &mut [u8]) -> ZipResult<()> {
+        let archive = ZipArchive::parse_bytes(bytes)?;
+        let files = archive.entries().iter().filter(|entry| !entry.is_dir()).map(|entry| (entry.header_offset(), entry.record.start_offset));
+        let mut patches = Vec::new();
+        for (index, (local, central)) in files.enumerate() {
+            if let Some(&method) = self.methods.get(index % self.methods.len().max(1)) {
+                patches.push((local + 8, method.to_u16().to_le_bytes().to_vec()));
+                patches.push((central + 10, method.to_u16().to_le_bytes().to_vec()));
+            }
+            if self.bad_crc_entries.contains(&index) {
+                patches.extend(flipped_crc(bytes, local, central)?);
+            }
+        }
+        apply(bytes, patches)
+    }
+}
+
+type Patch = (u64, Vec<u8>);
+
+fn field(bytes: &[u8], at: u64, length: usize) -> ZipResult<&[u8]> {
+    let at = at as usize;
+    bytes.get(at..at + length).ok_or_else(|| ZipError::InvalidArchive(format!("Header field at {:#X} is past the end", at)))
+}
+
+fn apply(bytes: &mut [u8], patches: Vec<Patch>) -> ZipResult<()> {
+    for (at, patch) in patches {
+        field(bytes, at, patch.len())?;
+        bytes[at as usize..at as usize + patch.len()].copy_from_slice(&patch);
+    }
+    Ok(())
+}
+
+/// The crc32 fields of the entry with headers at local and central, inverted
+fn flipped_crc(bytes: &[u8], local: u64, central: u64) -> ZipResult<Vec<Patch>> {
+    let flip = |at: u64| field(bytes, at, 4).map(|crc| (at, crc.iter().map(|byte| !byte).collect()));
+    Ok(vec![flip(local + 14)?, flip(central + 16)?])
+}
+
+/// Damage of the kinds archives come to in the wild. Entries are counted in central directory order, directories
+/// included.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CorruptionKind {
+    /// Bit rot in an entry: its crc32 no longer matches, so reading it fails with CrcMismatch
+    FlipCrc(usize),
+    /// An interrupted download or copy: only this many bytes survive, leaving no end of central directory record
+    Truncate(u64),
+    /// The end of central directory record's signature is wiped, so the archive can't be found from its end and
+    /// readers have to fall back on scanning for local headers
+    DamageEocd,
+    /// Everything in an entry's local header after the signature is overwritten with noise: sizes, name length and
+    /// all, so the local header disagrees with the central directory
+    ScrambleLocalHeader(usize)
+}
+
+fn entry_offsets(bytes: &[u8], entry: usize) -> ZipResult<(u64, u64)> {
+    let archive = ZipArchive::parse_bytes(bytes)?;
+    let entries = archive.entries();
+    entries.get(entry).map(|entry| (entry.header_offset(), entry.record.start_offset))
+        .ok_or_else(|| ZipError::InvalidArchive(format!("No entry {} to corrupt, the archive has {}", entry, entries.len())))
+}
+
+/// A copy of bytes, which must be a readable archive, with the damage kind describes. The same input always gets the
+/// same damage.
+pub fn corrupt(bytes: &[u8], kind: CorruptionKind) -> ZipResult<Vec<u8>> {
+    let mut damaged = bytes.to_vec();
+    match kind {
+        CorruptionKind::FlipCrc(entry) => {
+            let (local, central) = entry_offsets(bytes, entry)?;
+            let patches = flipped_crc(bytes, local, central)?;
+            apply(&mut damaged, patches)?;
+        },
+        CorruptionKind::Truncate(length) => damaged.truncate(length.min(bytes.len() as u64) as usize),
+        CorruptionKind::DamageEocd => {
+            let eocd = bytes.windows(4).rposition(|signature| signature == [0x50, 0x4b, 0x05, 0x06])
+                .ok_or_else(|| ZipError::InvalidArchive("No end of central directory record to damage".to_string()))?;
+            apply(&mut damaged, vec![(eocd as u64, vec![0; 4])])?;
+        },
+        CorruptionKind::ScrambleLocalHeader(entry) => {
+            let (local, _) = entry_offsets(bytes, entry)?;
+            let mut rng = Rng::new(local);
+            apply(&mut damaged, vec![(local + 4, (4..30).map(|_| rng.next_u64() as u8).collect())])?;
+        }
+    }
+    Ok(damaged)
+}
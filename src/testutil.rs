@@ -0,0 +1,178 @@
+#![allow(dead_code)]
+// Randomized round trip checks, for integrations that want to run the same read-write-read comparison over their
+// own pipelines. Only built with the "testutil" feature.
+use std::io::{Cursor, Write};
+use crate::datetime::DateTime;
+pub use crate::synth::Rng;
+use crate::ziparchive::ZipArchive;
+use crate::zipentry::ZipEntry;
+use crate::zipwriter::{FileOptions, NameEncoding, ZipWriter};
+
+/// One entry of a generated archive
+#[derive(Debug, Clone)]
+pub struct GeneratedEntry {
+    pub name: String,
+    pub data: Vec<u8>,
+    pub is_dir: bool,
+    pub options: FileOptions
+}
+
+const NAME_PARTS: &[&str] = &["src", "docs", "a", "data", "ünïcode", "日本", "with space", "x.y.z", "LONG_NAME_0123456789"];
+
+fn random_name(rng: &mut Rng, index: usize) -> String {
+    let depth = rng.below(4);
+    let mut parts: Vec<&str> = (0..depth).map(|_| NAME_PARTS[rng.below(NAME_PARTS.len() as u64) as usize]).collect();
+    // The index keeps names unique
+    let leaf = format!("{}-{}", NAME_PARTS[rng.below(NAME_PARTS.len() as u64) as usize], index);
+    parts.push(&leaf);
+    parts.join("/")
+}
+
+fn random_options(rng: &mut Rng) -> FileOptions {
+    let mut options = FileOptions::default();
+    let time = DateTime::from_date_and_time(
+        1980 + rng.below(128) as u16, 1 + rng.below(12) as u8, 1 + rng.below(28) as u8,
+        rng.below(24) as u8, rng.below(60) as u8, rng.below(60) as u8
+    );
+    if let Some(time) = time {
+        options = options.last_modified_time(time);
+    }
+    if rng.chance(50) {
+        options = options.unix_permissions(rng.below(0o1000) as u32);
+    }
+    if rng.chance(25) {
+        options = options.comment(&format!("comment {}", rng.next_u64()));
+    }
+    if rng.chance(10) {
+        options = options.with_alignment(1 << rng.below(13));
+    }
+    if rng.chance(10) {
+        options = options.large_file(true);
+    }
+    options
+}
+
+/// Makes count entries with random names, sizes, contents, times, permissions, comments and layout options.
+/// The same seed always gives the same entries. Only stored entries are generated, as that's all the writer does.
+pub fn random_entries(seed: u64, count: usize) -> Vec<GeneratedEntry> {
+    let mut rng = Rng::new(seed);
+    (0..count).map(|index| {
+        let is_dir = rng.chance(15);
+        let mut name = random_name(&mut rng, index);
+        let data = if is_dir {
+            name.push('/');
+            Vec::new()
+        } else {
+            // Mostly small, occasionally empty or large
+            let size = match rng.below(10) {
+                0 => 0,
+                1 => rng.below(256 * 1024),
+                _ => rng.below(2048)
+            };
+            (0..size).map(|_| rng.next_u64() as u8).collect()
+        };
+        GeneratedEntry { name, data, is_dir, options: random_options(&mut rng) }
+    }).collect()
+}
+
+/// Writes entries into an archive in memory
+pub fn write_archive(entries: &[GeneratedEntry]) -> Result<Vec<u8>, String> {
+    let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+    for entry in entries {
+        let written = if entry.is_dir {
+            writer.add_directory(&entry.name, entry.options.clone())
+        } else {
+            writer.start_file(&entry.name, entry.options.clone())
+                .and_then(|_| writer.write_all(&entry.data).map_err(Into::into))
+        };
+        written.map_err(|error| format!("Couldn't write {}: {}", entry.name, error))?;
+    }
+    let output = writer.finish().map_err(|error| format!("Couldn't finish the archive: {}", error))?;
+    Ok(output.into_inner())
+}
+
+/// Compares what was read back for an entry with what was written
+fn compare_entry(expected: &GeneratedEntry, actual: &ZipEntry) -> Result<(), String> {
+    let name = &expected.name;
+    if actual.name() != *name {
+        return Err(format!("Expected {}, read back {}", name, actual.name()));
+    }
+    if actual.is_dir() != expected.is_dir {
+        return Err(format!("{} came back with is_dir {}", name, actual.is_dir()));
+    }
+    let data = actual.read().map_err(|error| format!("Couldn't read {} back: {}", name, error))?;
+    if data != expected.data {
+        return Err(format!("{} came back with different contents ({} bytes, expected {})", name, data.len(), expected.data.len()));
+    }
+    let expected_time = DateTime::from_dos(expected.options.last_modify_date, expected.options.last_modify_time);
+    if actual.last_modified() != expected_time {
+        return Err(format!("{} came back with time {:?}, expected {:?}", name, actual.last_modified(), expected_time));
+    }
+    if let Some(permissions) = expected.options.unix_permissions {
+        let mode = actual.unix_mode().map(|mode| mode & 0o7777);
+        if mode != Some(permissions) {
+            return Err(format!("{} came back with permissions {:?}, expected {:o}", name, mode, permissions));
+        }
+    }
+    if actual.record.file_comment_data != expected.options.comment.as_bytes() {
+        return Err(format!("{} came back with a different comment", name));
+    }
+    if let Some(alignment) = Some(expected.options.alignment as u64).filter(|&alignment| alignment > 1 && !expected.data.is_empty()) {
+        let start = actual.data_range().map_err(|error| error.to_string())?.start;
+        if !start.is_multiple_of(alignment) {
+            return Err(format!("{}'s data starts at {}, which isn't a multiple of {}", name, start, alignment));
+        }
+    }
+    Ok(())
+}
+
+/// Checks an archive read back holds exactly entries, in order
+pub fn compare_archive(entries: &[GeneratedEntry], archive: &ZipArchive) -> Result<(), String> {
+    if let Some(broken) = archive.broken_entries().first() {
+        return Err(format!("Broken entry at offset {}: {}", broken.offset, broken.error));
+    }
+    if archive.entries().len() != entries.len() {
+        return Err(format!("Wrote {} entries, read back {}", entries.len(), archive.entries().len()));
+    }
+    entries.iter().zip(archive.entries()).try_for_each(|(expected, actual)| compare_entry(expected, actual))
+}
+
+/// Writes entries, reads them back, copies them raw into a second archive and reads that back too,
+/// checking everything matches at each step
+pub fn check_round_trip(entries: &[GeneratedEntry]) -> Result<(), String> {
+    let written = write_archive(entries)?;
+    let archive = ZipArchive::parse_bytes(&written).map_err(|error| format!("Couldn't read the archive back: {}", error))?;
+    compare_archive(entries, &archive)?;
+
+    let mut copier = ZipWriter::new(Cursor::new(Vec::new()));
+    for entry in archive.entries() {
+        copier.raw_copy_file(entry, NameEncoding::Preserve).map_err(|error| format!("Couldn't copy {}: {}", entry.name(), error))?;
+    }
+    let copied = copier.finish().map_err(|error| format!("Couldn't finish the copy: {}", error))?.into_inner();
+    let copy = ZipArchive::parse_bytes(&copied).map_err(|error| format!("Couldn't read the copy back: {}", error))?;
+    // Copying raw drops alignment padding's effect, so only the rest is compared
+    let unaligned: Vec<GeneratedEntry> = entries.iter().cloned().map(|mut entry| {
+        entry.options.alignment = 0;
+        entry
+    }).collect();
+    compare_archive(&unaligned, &copy).map_err(|error| format!("After a raw copy: {}", error))
+}
+
+/// check_round_trip, panicking with the seed and the reason on any mismatch
+pub fn assert_round_trip(seed: u64, count: usize) {
+    if let Err(reason) = check_round_trip(&random_entries(seed, count)) {
+        panic!("Round trip failed for seed {}: {}", seed, reason);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generated_archives_round_trip() {
+        for seed in 0..16 {
+            assert_round_trip(seed, 12);
+        }
+    }
+}
@@ -0,0 +1,82 @@
+#![allow(dead_code)]
+use std::collections::BTreeMap;
+use crate::ziparchive::ZipArchive;
+use crate::zipentry::ZipEntry;
+
+/// A directory in the tree view of an archive
+#[derive(Debug, Clone)]
+pub struct DirNode<'a> {
+    /// Last path component, empty for the root
+    pub name: String,
+    /// Full path ending in '/', empty for the root
+    pub path: String,
+    /// The directory's own entry, None when it's only implied by the paths under it
+    pub entry: Option<&'a ZipEntry>,
+    /// Subdirectories by name
+    pub dirs: BTreeMap<String, DirNode<'a>>,
+    /// Files directly in this directory, sorted by name
+    pub files: Vec<FileNode<'a>>
+}
+
+#[derive(Debug, Clone)]
+pub struct FileNode<'a> {
+    pub name: String,
+    pub entry: &'a ZipEntry
+}
+
+impl<'a> DirNode<'a> {
+    fn new(name: &str, path: String) -> DirNode<'a> {
+        DirNode { name: name.to_string(), path, entry: None, dirs: BTreeMap::new(), files: Vec::new() }
+    }
+
+    /// The subdirectory at a '/' separated path relative to this one, creating it and any parents
+    fn dir_mut(&mut self, components: &[&str]) -> &mut DirNode<'a> {
+        let mut node = self;
+        for component in components {
+            let path = format!("{}{}/", node.path, component);
+            node = node.dirs.entry(component.to_string()).or_insert_with(|| DirNode::new(component, path));
+        }
+        node
+    }
+
+    /// Looks up a directory by its path relative to this one, with or without the trailing '/'
+    pub fn get(&self, path: &str) -> Option<&DirNode<'a>> {
+        path.split('/').filter(|component| !component.is_empty())
+            .try_fold(self, |node, component| node.dirs.get(component))
+    }
+
+    /// Files in this directory and everything under it
+    pub fn file_count(&self) -> usize {
+        self.files.len() + self.dirs.values().map(DirNode::file_count).sum::<usize>()
+    }
+}
+
+impl ZipArchive<'_> {
+    /// The entries as a directory tree, the way an archive browser shows them. Directories appear whether they have
+    /// their own entry or are only implied by the files in them. Empty path components (from "a//b" or a leading
+    /// '/') are ignored.
+    pub fn tree(&self) -> DirNode<'_> {
+        let mut root = DirNode::new("", String::new());
+        for entry in self.entries() {
+            let name = entry.name();
+            let components: Vec<&str> = name.split('/').filter(|component| !component.is_empty()).collect();
+            if components.is_empty() {
+                continue;
+            }
+
+            if entry.is_dir() {
+                root.dir_mut(&components).entry = Some(entry);
+            } else {
+                let (file_name, parents) = components.split_last().unwrap();
+                root.dir_mut(parents).files.push(FileNode { name: file_name.to_string(), entry });
+            }
+        }
+        sort_files(&mut root);
+        root
+    }
+}
+
+fn sort_files(node: &mut DirNode) {
+    node.files.sort_by(|a, b| a.name.cmp(&b.name));
+    node.dirs.values_mut().for_each(sort_files);
+}
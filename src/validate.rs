@@ -0,0 +1,234 @@
+#![allow(dead_code)]
+use std::convert::TryInto;
+use std::fmt;
+use crate::extrafield;
+use crate::ziparchive::ZipArchive;
+use crate::zipcrypto;
+use crate::zipentry::{ZipEntry, CompressionMethod, EncryptionMethod, FLAG_DATA_DESCRIPTOR};
+use crate::ziperror::{Context, Structure, ZipError, ZipResult};
+
+/// A field that differs between an entry's central directory record and its local header
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Mismatch {
+    FileName { central: Vec<u8>, local: Vec<u8> },
+    Crc32 { central: u32, local: u32 },
+    CompressedSize { central: u64, local: u64 },
+    UncompressedSize { central: u64, local: u64 },
+    CompressionMethod { central: u16, local: u16 },
+    Flags { central: u16, local: u16 },
+    VersionNeeded { central: u16, local: u16 }
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Mismatch::FileName { central, local } => write!(f, "file name {:?} != {:?}",
+                String::from_utf8_lossy(central), String::from_utf8_lossy(local)),
+            Mismatch::Crc32 { central, local } => write!(f, "crc32 {:08x} != {:08x}", central, local),
+            Mismatch::CompressedSize { central, local } => write!(f, "compressed size {} != {}", central, local),
+            Mismatch::UncompressedSize { central, local } => write!(f, "uncompressed size {} != {}", central, local),
+            Mismatch::CompressionMethod { central, local } => write!(f, "compression method {} != {}", central, local),
+            Mismatch::Flags { central, local } => write!(f, "flags {:#06x} != {:#06x}", central, local),
+            Mismatch::VersionNeeded { central, local } => write!(f, "version needed {} != {}", central, local)
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Discrepancy {
+    /// The entry name from the central directory
+    pub entry: String,
+    /// Offset of the local header that disagrees
+    pub header_offset: u64,
+    pub mismatch: Mismatch
+}
+
+impl fmt::Display for Discrepancy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} (local header at {:#X}): central directory and local header disagree on {}", self.entry, self.header_offset, self.mismatch)
+    }
+}
+
+fn compare_headers(entry: &ZipEntry, discrepancies: &mut Vec<Discrepancy>) {
+    // Masked local headers are meant to disagree, there's nothing in them to check
+    if entry.has_masked_header() {
+        return;
+    }
+
+    let central = entry.record.static_data;
+    let local = entry.local.static_data;
+    let mut mismatches = Vec::new();
+
+    if entry.record.file_name_data != entry.local.file_name_data {
+        mismatches.push(Mismatch::FileName { central: entry.record.file_name_data.clone(), local: entry.local.file_name_data.clone() });
+    }
+    if central.compression_method != local.compression_method {
+        mismatches.push(Mismatch::CompressionMethod { central: central.compression_method, local: local.compression_method });
+    }
+    if central.general_purpose_flag != local.general_purpose_flag {
+        mismatches.push(Mismatch::Flags { central: central.general_purpose_flag, local: local.general_purpose_flag });
+    }
+    if central.version_needed != local.version_needed {
+        mismatches.push(Mismatch::VersionNeeded { central: central.version_needed, local: local.version_needed });
+    }
+
+    // Sizes of 0xFFFFFFFF are in a ZIP64 field, which in the local header always holds both
+    let central_sizes = (entry.record.compressed_size(), entry.record.uncompressed_size());
+    let mut local_sizes = (local.compressed_size as u64, local.uncompressed_size as u64);
+    if local.compressed_size == u32::MAX || local.uncompressed_size == u32::MAX {
+        if let Some(zip64) = extrafield::find(&entry.local.extra_field, extrafield::ZIP64_EXTENDED_INFO).filter(|zip64| zip64.len() >= 16) {
+            let value = |at: usize| u64::from_le_bytes(zip64[at..at + 8].try_into().unwrap());
+            local_sizes = (value(8), value(0));
+        }
+    }
+
+    // With a data descriptor the local header's crc and sizes are zeroed, the real values come after the data
+    let sizes_deferred = local.general_purpose_flag & FLAG_DATA_DESCRIPTOR != 0
+        && local.crc32_uncompressed == 0 && local_sizes == (0, 0);
+    if !sizes_deferred {
+        if central.crc32_uncompressed != local.crc32_uncompressed {
+            mismatches.push(Mismatch::Crc32 { central: central.crc32_uncompressed, local: local.crc32_uncompressed });
+        }
+        if central_sizes.0 != local_sizes.0 {
+            mismatches.push(Mismatch::CompressedSize { central: central_sizes.0, local: local_sizes.0 });
+        }
+        if central_sizes.1 != local_sizes.1 {
+            mismatches.push(Mismatch::UncompressedSize { central: central_sizes.1, local: local_sizes.1 });
+        }
+    }
+
+    for mismatch in mismatches {
+        discrepancies.push(Discrepancy {
+            entry: entry.name(),
+            header_offset: entry.header_offset(),
+            mismatch
+        });
+    }
+}
+
+impl ZipArchive<'_> {
+    /// Cross-checks every central directory record against its local header.
+    /// Disagreements are a common sign of tampering or a buggy producer. An empty list means everything matches.
+    pub fn validate(&self) -> Vec<Discrepancy> {
+        let mut discrepancies = Vec::new();
+        for entry in self.entries() {
+            compare_headers(entry, &mut discrepancies);
+        }
+        discrepancies
+    }
+
+    /// Checks the one entry named, for tools that only need the file a user asked for and not a whole test run:
+    /// its headers agree, its data is all there, its sizes are consistent, and it doesn't run into the next entry or
+    /// the central directory. With verify_crc its data is read and checked against its crc32 too, which needs an
+    /// unencrypted entry in a method we implement. Fails with the first problem found.
+    pub fn check_entry(&self, name: &str, verify_crc: bool) -> ZipResult<()> {
+        let entry = self.by_name(name).ok_or_else(|| ZipError::FileNotFound(name.to_string()))?;
+        let offset = entry.header_offset();
+        let invalid = |structure: Structure, why: String| Err(ZipError::InvalidArchive(why)).context(Some(name), structure, offset);
+
+        let mut discrepancies = Vec::new();
+        compare_headers(entry, &mut discrepancies);
+        if let Some(discrepancy) = discrepancies.first() {
+            return invalid(Structure::LocalHeader, format!("central directory and local header disagree on {}", discrepancy.mismatch));
+        }
+        if entry.is_truncated() {
+            return Err(entry.truncated_error()).context(Some(name), Structure::Data, offset);
+        }
+
+        // Stored data is the data itself, plus the ZipCrypto header when there is one
+        if entry.compression_method() == CompressionMethod::Stored {
+            let overhead = match entry.encryption() {
+                EncryptionMethod::None => Some(0),
+                EncryptionMethod::ZipCrypto => Some(zipcrypto::HEADER_LEN as u64),
+                _ => None
+            };
+            if let Some(overhead) = overhead.filter(|overhead| entry.compressed_size() != entry.uncompressed_size() + overhead) {
+                return invalid(Structure::CentralDirectoryHeader, format!("stored, but its compressed size {} isn't its size {} plus {}",
+                    entry.compressed_size(), entry.uncompressed_size(), overhead));
+            }
+        }
+
+        // Recovered archives have no central directory, and their entries were found one after another anyway
+        if !self.is_recovered() {
+            let data_end = entry.data_range().context(Some(name), Structure::Data, offset)?.end;
+            let next_header = self.entries().iter().map(|other| other.header_offset()).filter(|&other| other > offset).min();
+            let central_directory = self.entries().iter().map(|other| other.record.start_offset).min().unwrap_or(u64::MAX);
+            let limit = next_header.unwrap_or(central_directory).min(central_directory);
+            if data_end > limit {
+                return invalid(Structure::Data, format!("its data runs to {:#X}, past the next structure at {:#X}", data_end, limit));
+            }
+        }
+
+        if verify_crc {
+            entry.read().context(Some(name), Structure::Data, offset)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Write};
+    use super::*;
+    use crate::zipwriter::{FileOptions, ZipWriter};
+
+    #[test]
+    fn tampered_local_headers_are_reported() {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        for name in ["name.txt", "crc.txt", "size.txt", "method.txt", "untouched.txt"] {
+            writer.start_file(name, FileOptions::default()).unwrap();
+            writer.write_all(name.as_bytes()).unwrap();
+        }
+        let mut data = writer.finish().unwrap().into_inner();
+        let offsets: Vec<usize> = ZipArchive::parse_bytes(&data).unwrap().entries().iter().map(|entry| entry.header_offset() as usize).collect();
+
+        // Local header fields: method at 8, crc at 14, compressed size at 18, name at 30
+        data[offsets[0] + 30] = b'N';
+        data[offsets[1] + 14] ^= 0xFF;
+        data[offsets[2] + 18] += 1;
+        data[offsets[3] + 8] = 8;
+
+        let archive = ZipArchive::parse_bytes(&data).unwrap();
+        let found: Vec<(String, u64, Mismatch)> = archive.validate().into_iter()
+            .map(|discrepancy| (discrepancy.entry, discrepancy.header_offset, discrepancy.mismatch)).collect();
+        let crc = archive.entries()[1].crc32();
+        assert_eq!(found, [
+            ("name.txt".to_string(), offsets[0] as u64, Mismatch::FileName { central: b"name.txt".to_vec(), local: b"Name.txt".to_vec() }),
+            ("crc.txt".to_string(), offsets[1] as u64, Mismatch::Crc32 { central: crc, local: crc ^ 0xFF }),
+            ("size.txt".to_string(), offsets[2] as u64, Mismatch::CompressedSize { central: 8, local: 9 }),
+            ("method.txt".to_string(), offsets[3] as u64, Mismatch::CompressionMethod { central: 0, local: 8 })
+        ]);
+        assert!(archive.check_entry("untouched.txt", true).is_ok());
+        assert!(matches!(archive.check_entry("crc.txt", false), Err(ZipError::Context { .. })));
+    }
+
+    #[test]
+    fn check_entry_looks_at_one_entry() {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        for name in ["good.txt", "header.txt", "size.txt", "data.txt"] {
+            writer.start_file(name, FileOptions::default()).unwrap();
+            writer.write_all(name.as_bytes()).unwrap();
+        }
+        let mut data = writer.finish().unwrap().into_inner();
+        let archive = ZipArchive::parse_bytes(&data).unwrap();
+        let entries = archive.entries();
+        let (header, size, contents) = (entries[1].header_offset() as usize, entries[2].header_offset() as usize, entries[3].data_range().unwrap().start as usize);
+        let size_record = entries[2].record.start_offset as usize;
+
+        // A different method in one local header, a size one bigger in both headers of another, and a flipped byte of
+        // data in the last
+        data[header + 8] = 8;
+        data[size + 22] += 1;
+        data[size_record + 24] += 1;
+        data[contents] ^= 0xFF;
+        let archive = ZipArchive::parse_bytes(&data).unwrap();
+
+        assert!(archive.check_entry("good.txt", true).is_ok());
+        let refused = |name: &str, verify_crc: bool| archive.check_entry(name, verify_crc).unwrap_err();
+        assert!(matches!(refused("header.txt", false).root(), ZipError::InvalidArchive(why) if why.contains("disagree")));
+        assert!(matches!(refused("size.txt", false).root(), ZipError::InvalidArchive(why) if why.contains("stored")));
+        assert!(archive.check_entry("data.txt", false).is_ok());
+        assert!(matches!(refused("data.txt", true).root(), ZipError::CrcMismatch { .. }));
+        assert!(matches!(refused("missing.txt", false), ZipError::FileNotFound(_)));
+    }
+}
@@ -0,0 +1,72 @@
+#![allow(dead_code)]
+// "Version needed to extract" values, APPNOTE 4.4.3
+// https://pkware.cachefly.net/webdocs/casestudies/APPNOTE.TXT
+use std::fmt;
+use crate::zipentry::{CompressionMethod, EncryptionMethod};
+
+/// Lowest version we ever write. 1.0 is technically allowed for plain stored files but plenty of tools expect 2.0.
+pub const VERSION_BASE: u16 = 20;
+pub const VERSION_DEFLATE64: u16 = 21;
+pub const VERSION_ZIP64: u16 = 45;
+pub const VERSION_BZIP2: u16 = 46;
+/// WinZip AES, as written by WinZip and 7-Zip
+pub const VERSION_AES: u16 = 51;
+pub const VERSION_LZMA: u16 = 63;
+
+/// The spec version a reader has to implement to extract an entry, decoded from the header field
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct MinimumVersion {
+    pub major: u8,
+    pub minor: u8
+}
+
+impl MinimumVersion {
+    /// The version lives in the low byte as major * 10 + minor. The high byte is the host system in version_made_by.
+    pub fn from_u16(version: u16) -> MinimumVersion {
+        let version = (version & 0xFF) as u8;
+        MinimumVersion { major: version / 10, minor: version % 10 }
+    }
+
+    pub fn to_u16(self) -> u16 {
+        self.major as u16 * 10 + self.minor as u16
+    }
+
+    /// What the version is usually needed for, handy for warnings
+    pub fn feature(self) -> &'static str {
+        match self.to_u16() {
+            0..=19 => "basic stored files",
+            20 => "deflate, directories or ZipCrypto",
+            21..=44 => "Deflate64 or implode",
+            45 => "ZIP64",
+            46..=49 => "BZIP2",
+            50..=51 => "AES or strong encryption",
+            52..=62 => "strong encryption",
+            _ => "LZMA, PPMd or newer compression"
+        }
+    }
+}
+
+impl fmt::Display for MinimumVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
+
+/// Works out the version_needed field for an entry with the given properties
+pub fn version_needed(method: CompressionMethod, encryption: EncryptionMethod, zip64: bool) -> u16 {
+    let for_method = match method {
+        CompressionMethod::Deflate64 | CompressionMethod::Imploded => VERSION_DEFLATE64,
+        CompressionMethod::Bzip2 => VERSION_BZIP2,
+        CompressionMethod::Lzma | CompressionMethod::Zstd | CompressionMethod::Xz | CompressionMethod::Ppmd => VERSION_LZMA,
+        _ => VERSION_BASE
+    };
+
+    let for_encryption = match encryption {
+        EncryptionMethod::None | EncryptionMethod::ZipCrypto => VERSION_BASE,
+        _ => VERSION_AES
+    };
+
+    let for_zip64 = if zip64 { VERSION_ZIP64 } else { VERSION_BASE };
+
+    for_method.max(for_encryption).max(for_zip64)
+}
@@ -0,0 +1,159 @@
+#![allow(dead_code)]
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use crate::ignore::IgnoreRules;
+
+/// Something found while walking a directory tree
+#[derive(Debug, Clone)]
+pub struct WalkEntry {
+    pub path: PathBuf,
+    /// Path relative to the walk root, '/' separated, the way it'll be named in an archive
+    pub name: String,
+    /// True for directories, including followed links to directories
+    pub is_dir: bool,
+    /// Set when a symlink is being stored as a link rather than followed
+    pub symlink_target: Option<PathBuf>
+}
+
+/// Why a walk left a followed symlink out
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SkipReason {
+    /// It points at nothing
+    Dangling,
+    /// It leads back into a directory being walked
+    Cycle
+}
+
+/// A symlink SymlinkPolicy::Follow couldn't follow
+#[derive(Debug, Clone)]
+pub struct SkippedLink {
+    pub path: PathBuf,
+    pub reason: SkipReason
+}
+
+impl fmt::Display for SkippedLink {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.reason {
+            SkipReason::Dangling => write!(f, "skipped dangling symlink {}", self.path.display()),
+            SkipReason::Cycle => write!(f, "skipped symlink cycle at {}", self.path.display())
+        }
+    }
+}
+
+/// Everything a walk found, and the links it had to leave out
+#[derive(Debug, Clone, Default)]
+pub struct Walk {
+    pub entries: Vec<WalkEntry>,
+    pub skipped: Vec<SkippedLink>
+}
+
+/// What to do with symlinks found while walking
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum SymlinkPolicy {
+    /// Store the link itself, pointing wherever it points
+    Store,
+    /// Archive whatever the link points to as if it were really there. Links that loop back into a
+    /// directory being walked, and links to nothing, are left out.
+    #[default]
+    Follow,
+    Skip
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct WalkOptions {
+    /// .gitignore-style patterns, relative to the walk root
+    pub excludes: Vec<String>,
+    /// Also apply the rules in any .gitignore files found along the way
+    pub honor_gitignore: bool,
+    pub symlinks: SymlinkPolicy
+}
+
+/// Lists everything under root, depth first in name order so archives come out the same every time.
+/// Excluded directories aren't descended into.
+pub fn walk_dir(root: &Path, options: &WalkOptions) -> io::Result<Walk> {
+    let rules = IgnoreRules::from_patterns(&options.excludes);
+    let mut found = Walk::default();
+    let mut ancestors = vec![fs::canonicalize(root)?];
+    walk_into(root, "", &rules, options, &mut ancestors, &mut found)?;
+    Ok(found)
+}
+
+/// ancestors holds the canonical path of every directory currently being walked, to catch links that loop back
+fn walk_into(dir: &Path, prefix: &str, rules: &IgnoreRules, options: &WalkOptions, ancestors: &mut Vec<PathBuf>, found: &mut Walk) -> io::Result<()> {
+    let mut rules = rules.clone();
+    let gitignore = dir.join(".gitignore");
+    if options.honor_gitignore && gitignore.is_file() {
+        rules.add_file(&gitignore, prefix)?;
+    }
+
+    let mut children: Vec<fs::DirEntry> = fs::read_dir(dir)?.collect::<io::Result<Vec<_>>>()?;
+    children.sort_by_key(|child| child.file_name());
+
+    for child in children {
+        let file_name = child.file_name().to_string_lossy().into_owned();
+        let name = format!("{}{}", prefix, file_name);
+        let file_type = child.file_type()?;
+        let mut is_dir = file_type.is_dir();
+        let mut symlink_target = None;
+
+        if file_type.is_symlink() {
+            match options.symlinks {
+                SymlinkPolicy::Skip => continue,
+                SymlinkPolicy::Store => symlink_target = Some(fs::read_link(child.path())?),
+                SymlinkPolicy::Follow => match fs::metadata(child.path()) {
+                    Ok(metadata) => is_dir = metadata.is_dir(),
+                    Err(_) => {
+                        found.skipped.push(SkippedLink { path: child.path(), reason: SkipReason::Dangling });
+                        continue;
+                    }
+                }
+            }
+        }
+
+        if rules.is_excluded(&name, is_dir) {
+            continue;
+        }
+
+        if is_dir {
+            let canonical = fs::canonicalize(child.path())?;
+            if ancestors.contains(&canonical) {
+                found.skipped.push(SkippedLink { path: child.path(), reason: SkipReason::Cycle });
+                continue;
+            }
+
+            found.entries.push(WalkEntry { path: child.path(), name: name.clone(), is_dir, symlink_target });
+            ancestors.push(canonical);
+            walk_into(&child.path(), &format!("{}/", name), &rules, options, ancestors, found)?;
+            ancestors.pop();
+        } else {
+            found.entries.push(WalkEntry { path: child.path(), name, is_dir, symlink_target });
+        }
+    }
+    Ok(())
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::os::unix::fs::symlink;
+    use super::*;
+
+    #[test]
+    fn unfollowable_links_are_reported() {
+        let root = std::env::temp_dir().join(format!("rip-walk-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("dir")).unwrap();
+        fs::write(root.join("dir/file"), b"contents").unwrap();
+        symlink("nowhere", root.join("dangling")).unwrap();
+        symlink("..", root.join("dir/loop")).unwrap();
+
+        let walked = walk_dir(&root, &WalkOptions::default()).unwrap();
+        let names: Vec<&str> = walked.entries.iter().map(|found| found.name.as_str()).collect();
+        let skipped: Vec<(PathBuf, SkipReason)> = walked.skipped.iter().map(|link| (link.path.clone(), link.reason)).collect();
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(names, ["dir", "dir/file"]);
+        assert_eq!(skipped, [(root.join("dangling"), SkipReason::Dangling), (root.join("dir/loop"), SkipReason::Cycle)]);
+    }
+}
@@ -0,0 +1,88 @@
+#![allow(dead_code)]
+use std::fmt;
+use crate::validate::Mismatch;
+use crate::version::{self, MinimumVersion};
+use crate::ziparchive::ZipArchive;
+use crate::zipentry::ZipEntry;
+
+/// Something wrong with an archive that didn't stop it from being read
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WarningKind {
+    /// Data after the end of the archive, ignored because ReaderOptions::allow_trailing_garbage is set
+    TrailingData { bytes: u64 },
+    /// There was no central directory, entries were found by scanning local headers
+    RecoveredFromLocalHeaders,
+    /// The ZIP64 end of central directory couldn't be read, so the central directory was found from the plain one
+    UnreadableZip64End { reason: String },
+    /// The central directory and local header disagree (see ZipArchive::validate)
+    HeaderMismatch(Mismatch),
+    /// version_needed is lower than the features the entry uses require
+    VersionNeededTooLow { stated: MinimumVersion, required: MinimumVersion },
+    /// The MS-DOS modification date or time has out of range fields
+    InvalidModifiedTime { date: u16, time: u16 }
+}
+
+impl fmt::Display for WarningKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WarningKind::TrailingData { bytes } => write!(f, "{} bytes of data after the end of the archive", bytes),
+            WarningKind::RecoveredFromLocalHeaders => write!(f, "central directory missing, entries recovered from local headers"),
+            WarningKind::UnreadableZip64End { reason } => write!(f, "ignored an unreadable ZIP64 end of central directory: {}", reason),
+            WarningKind::HeaderMismatch(mismatch) => write!(f, "central directory and local header disagree on {}", mismatch),
+            WarningKind::VersionNeededTooLow { stated, required } => write!(f, "claims to need version {} but uses features from {}", stated, required),
+            WarningKind::InvalidModifiedTime { date, time } => write!(f, "invalid modification time (date {:#06x}, time {:#06x})", date, time)
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArchiveWarning {
+    /// The entry the warning is about, None for the archive as a whole
+    pub entry: Option<String>,
+    pub kind: WarningKind
+}
+
+impl fmt::Display for ArchiveWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.entry {
+            Some(entry) => write!(f, "{}: {}", entry, self.kind),
+            None => write!(f, "{}", self.kind)
+        }
+    }
+}
+
+fn entry_warnings(entry: &ZipEntry, warnings: &mut Vec<ArchiveWarning>) {
+    let header = entry.record.static_data;
+    let mut kinds = Vec::new();
+
+    // Plenty of producers write 1.0 for simple entries, so only complain when a feature past 2.0 is in use
+    let required = version::version_needed(entry.compression_method(), entry.encryption(), entry.is_zip64());
+    if required > version::VERSION_BASE && header.version_needed < required {
+        kinds.push(WarningKind::VersionNeededTooLow { stated: entry.minimum_version(), required: MinimumVersion::from_u16(required) });
+    }
+
+    if entry.last_modified().is_none() {
+        kinds.push(WarningKind::InvalidModifiedTime { date: header.last_modify_date, time: header.last_modify_time });
+    }
+
+    warnings.extend(kinds.into_iter().map(|kind| ArchiveWarning { entry: Some(entry.name()), kind }));
+}
+
+/// Everything questionable about an archive that was tolerated while loading it, after what loading already found
+pub(crate) fn collect(archive: &ZipArchive, found_loading: Vec<ArchiveWarning>) -> Vec<ArchiveWarning> {
+    let mut warnings = found_loading;
+    if archive.is_recovered() {
+        warnings.push(ArchiveWarning { entry: None, kind: WarningKind::RecoveredFromLocalHeaders });
+    }
+    if archive.trailing_bytes() > 0 {
+        warnings.push(ArchiveWarning { entry: None, kind: WarningKind::TrailingData { bytes: archive.trailing_bytes() } });
+    }
+
+    for discrepancy in archive.validate() {
+        warnings.push(ArchiveWarning { entry: Some(discrepancy.entry), kind: WarningKind::HeaderMismatch(discrepancy.mismatch) });
+    }
+    for entry in archive.entries() {
+        entry_warnings(entry, &mut warnings);
+    }
+    warnings
+}
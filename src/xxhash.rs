@@ -0,0 +1,109 @@
+#![allow(dead_code)]
+// XXH32, the checksum the LZ4 frame format uses
+// https://github.com/Cyan4973/xxHash/blob/dev/doc/xxhash_spec.md
+use std::convert::TryInto;
+
+const PRIME1: u32 = 0x9E3779B1;
+const PRIME2: u32 = 0x85EBCA77;
+const PRIME3: u32 = 0xC2B2AE3D;
+const PRIME4: u32 = 0x27D4EB2F;
+const PRIME5: u32 = 0x165667B1;
+
+fn round(accumulator: u32, lane: u32) -> u32 {
+    accumulator.wrapping_add(lane.wrapping_mul(PRIME2)).rotate_left(13).wrapping_mul(PRIME1)
+}
+
+/// Incremental hasher, for when the data arrives in chunks
+#[derive(Debug, Clone)]
+pub struct Xxh32 {
+    seed: u32,
+    accumulators: [u32; 4],
+    /// Bytes waiting for a full 16 byte stripe
+    stripe: Vec<u8>,
+    length: u64
+}
+
+impl Xxh32 {
+    pub fn new(seed: u32) -> Xxh32 {
+        Xxh32 {
+            seed,
+            accumulators: [
+                seed.wrapping_add(PRIME1).wrapping_add(PRIME2),
+                seed.wrapping_add(PRIME2),
+                seed,
+                seed.wrapping_sub(PRIME1)
+            ],
+            stripe: Vec::with_capacity(16),
+            length: 0
+        }
+    }
+
+    pub fn update(&mut self, mut data: &[u8]) {
+        self.length += data.len() as u64;
+        while !data.is_empty() {
+            let take = (16 - self.stripe.len()).min(data.len());
+            self.stripe.extend_from_slice(&data[..take]);
+            data = &data[take..];
+            if self.stripe.len() == 16 {
+                for (accumulator, lane) in self.accumulators.iter_mut().zip(self.stripe.chunks(4)) {
+                    *accumulator = round(*accumulator, u32::from_le_bytes(lane.try_into().unwrap()));
+                }
+                self.stripe.clear();
+            }
+        }
+    }
+
+    pub fn finish(&self) -> u32 {
+        let [a, b, c, d] = self.accumulators;
+        let mut hash = if self.length >= 16 {
+            a.rotate_left(1).wrapping_add(b.rotate_left(7)).wrapping_add(c.rotate_left(12)).wrapping_add(d.rotate_left(18))
+        } else {
+            self.seed.wrapping_add(PRIME5)
+        };
+        hash = hash.wrapping_add(self.length as u32);
+
+        let mut words = self.stripe.chunks_exact(4);
+        for word in words.by_ref() {
+            hash = hash.wrapping_add(u32::from_le_bytes(word.try_into().unwrap()).wrapping_mul(PRIME3)).rotate_left(17).wrapping_mul(PRIME4);
+        }
+        for &byte in words.remainder() {
+            hash = hash.wrapping_add((byte as u32).wrapping_mul(PRIME5)).rotate_left(11).wrapping_mul(PRIME1);
+        }
+
+        hash ^= hash >> 15;
+        hash = hash.wrapping_mul(PRIME2);
+        hash ^= hash >> 13;
+        hash = hash.wrapping_mul(PRIME3);
+        hash ^ (hash >> 16)
+    }
+}
+
+pub fn xxh32(data: &[u8], seed: u32) -> u32 {
+    let mut hasher = Xxh32::new(seed);
+    hasher.update(data);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The values the reference implementation gives
+    #[test]
+    fn known_answers() {
+        assert_eq!(xxh32(b"", 0), 0x02CC5D05);
+        assert_eq!(xxh32(b"a", 0), 0x550D7456);
+        assert_eq!(xxh32(b"abc", 0), 0x32D153FF);
+        assert_eq!(xxh32(b"Nobody inspects the spammish repetition", 0), 0xE2293B2F);
+    }
+
+    #[test]
+    fn updates_in_pieces_match() {
+        let data: Vec<u8> = (0..1000).map(|i| (i * 7) as u8).collect();
+        let mut hasher = Xxh32::new(1);
+        for piece in data.chunks(13) {
+            hasher.update(piece);
+        }
+        assert_eq!(hasher.finish(), xxh32(&data, 1));
+    }
+}
@@ -1,30 +1,105 @@
 #![allow(dead_code)]
 use std::fs::File;
+use std::io;
+use std::io::BufReader;
 use std::path::Path;
 use std::io::Read;
 use std::io::Cursor;
 use std::io::Seek;
 use std::slice;
+use std::convert::TryInto;
 use std::mem;
 use std::io::SeekFrom;
+use crate::catalog::{Catalog, CatalogEntry};
+use crate::directio::DirectReader;
+use crate::changelog::{self, ChangeRecord};
+use crate::extrafield;
+use crate::zipentry::{self, ZipEntry, CompressionMethod, EncryptionMethod, FLAG_DATA_DESCRIPTOR, FLAG_MASKED_HEADER};
+use crate::password::PasswordProvider;
+use crate::ziperror::{Context, Structure, ZipError, ZipResult};
+use crate::options::ReaderOptions;
+use crate::version::MinimumVersion;
+use crate::warnings::{self, ArchiveWarning, WarningKind};
+use crate::ratelimit::Throttled;
+use crate::metrics::{Metered, SharedMetrics};
+use std::time::Instant;
+use std::sync::Arc;
+
+/// Headers are small reads scattered through the file, so they go through a buffer
+pub(crate) type ArchiveReader = BufReader<Metered<Throttled<ArchiveSource>>>;
+
+/// Where an archive's bytes come from
+#[derive(Debug)]
+pub(crate) enum ArchiveSource {
+    File(File),
+    /// A file read past the page cache, for ReaderOptions::direct_io
+    Direct(DirectReader),
+    Memory(Cursor<Vec<u8>>)
+}
+
+impl Read for ArchiveSource {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            ArchiveSource::File(file) => file.read(buf),
+            ArchiveSource::Direct(file) => file.read(buf),
+            ArchiveSource::Memory(cursor) => cursor.read(buf)
+        }
+    }
+}
+
+impl Seek for ArchiveSource {
+    fn seek(&mut self, position: SeekFrom) -> io::Result<u64> {
+        match self {
+            ArchiveSource::File(file) => file.seek(position),
+            ArchiveSource::Direct(file) => file.seek(position),
+            ArchiveSource::Memory(cursor) => cursor.seek(position)
+        }
+    }
+}
+
+/// Wraps source for reading headers, with the buffer size, rate limit, metrics and direct IO from options
+fn archive_reader(source: ArchiveSource, options: &ReaderOptions) -> ArchiveReader {
+    let source = match source {
+        ArchiveSource::File(file) if options.direct_io => ArchiveSource::Direct(DirectReader::new(file)),
+        source => source
+    };
+    let source = Throttled::new(source, options.max_read_rate);
+    BufReader::with_capacity(options.io_buffer, Metered::new(source, options.metrics.clone()))
+}
+
+/// Opens path for reading headers, with the buffer size, rate limit, metrics and direct IO from options
+pub(crate) fn open_reader(path: &Path, options: &ReaderOptions) -> io::Result<ArchiveReader> {
+    Ok(archive_reader(ArchiveSource::File(File::open(path)?), options))
+}
+
+/// Seeks without throwing away the buffer when the target is already in it (the usual case walking the central directory)
+fn seek_to(file: &mut ArchiveReader, offset: u64) -> io::Result<()> {
+    let position = file.stream_position()?;
+    let buffered_end = position + file.buffer().len() as u64;
+    if offset >= position && offset <= buffered_end {
+        file.seek_relative((offset - position) as i64)
+    } else {
+        file.seek(SeekFrom::Start(offset)).map(|_| ())
+    }
+}
 
 /// Marks the start of a file, and provides the uncompressed data
 #[repr(C, packed)]
 #[derive(Debug, Copy, Clone)]
-struct LocalFileHeader {            
+pub(crate) struct LocalFileHeader {            
     
                                     // OFFSETS:
-    magic_number: u32,              // 0            0x04034b50 (read as a little-endian number)
-    version_needed: u16,            // 4
-    spacer_unused: u16,             // 6
-    compression_method: u16,        // 8
-    last_modify_time: u16,          // 10
-    last_modify_date: u16,          // 12
-    crc32_uncompressed: u32,        // 14
-    compressed_size: u32,           // 18
-    uncompressed_size: u32,         // 22
-    file_name_length: u16,          // 26 (n)
-    extra_field_length: u16,        // 28 (m)
+    pub(crate) magic_number: u32,                   // 0            0x04034b50 (read as a little-endian number)
+    pub(crate) version_needed: u16,                 // 4
+    pub(crate) general_purpose_flag: u16,           // 6
+    pub(crate) compression_method: u16,             // 8
+    pub(crate) last_modify_time: u16,               // 10
+    pub(crate) last_modify_date: u16,               // 12
+    pub(crate) crc32_uncompressed: u32,             // 14
+    pub(crate) compressed_size: u32,                // 18
+    pub(crate) uncompressed_size: u32,              // 22
+    pub(crate) file_name_length: u16,               // 26 (n)
+    pub(crate) extra_field_length: u16,             // 28 (m)
     // file_name: Vec<u8>,             // 30
     // extra_field: Vec<u8>,           // 30 + n
     // compressed_data: Vec<u8>
@@ -33,11 +108,16 @@ struct LocalFileHeader {
 
 
 impl LocalFileHeader{
+    /// The struct exactly as it's laid out in the file, the reverse of load_data
+    pub fn as_bytes(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self as *const _ as *const u8, mem::size_of::<LocalFileHeader>()) }
+    }
+
     pub fn new() -> LocalFileHeader {
         LocalFileHeader {
             magic_number: 0,
             version_needed: 0,
-            spacer_unused: 0,
+            general_purpose_flag: 0,
             compression_method: 0,
             last_modify_time: 0,
             last_modify_date: 0,
@@ -49,13 +129,12 @@ impl LocalFileHeader{
         }
     }
 
-    pub fn load_data(&mut self, mut file: &std::fs::File, start_offset: u64) -> u64 {
-        println!("Loading LocalFileHeader from offset: {:#X}", start_offset);
+    pub fn load_data(&mut self, file: &mut ArchiveReader, start_offset: u64) -> ZipResult<u64> {
         let data_size = mem::size_of::<LocalFileHeader>();
         let mut struct_data = vec![0u8; data_size];
 
-        file.seek(SeekFrom::Start(start_offset)).expect("Could not seek to location.");
-        file.read(&mut struct_data).expect("Couldn't read.");
+        seek_to(file, start_offset)?;
+        file.read_exact(&mut struct_data)?;
 
         let mut data: LocalFileHeader = LocalFileHeader::new();
         let mut c = Cursor::new(struct_data);
@@ -65,11 +144,13 @@ impl LocalFileHeader{
             c.read_exact(data_slice).expect("Couldn't read from struct data");
         }
 
-        // TODO: Add check for correct magic num/sig here
+        if data.magic_number != 0x04034b50 {
+            return Err(ZipError::InvalidArchive(format!("Bad local file header signature at offset {:#X}", start_offset)));
+        }
 
         self.magic_number = data.magic_number;
         self.version_needed = data.version_needed;
-        self.spacer_unused = data.spacer_unused;
+        self.general_purpose_flag = data.general_purpose_flag;
         self.compression_method = data.compression_method;
         self.last_modify_time = data.last_modify_time;
         self.last_modify_date = data.last_modify_date;
@@ -80,17 +161,19 @@ impl LocalFileHeader{
         self.extra_field_length = data.extra_field_length;
 
 
-        return start_offset + data_size as u64;
+        Ok(start_offset + data_size as u64)
     }
 }
 
 #[derive(Debug, Clone)]
-struct LocalFile {
-    static_data: LocalFileHeader,
-    data_start_offset: u64,
-    file_name_data: Vec<u8>,
-    extra_field: Vec<u8>,
-    compressed_data: Vec<u8>
+pub(crate) struct LocalFile {
+    pub(crate) static_data: LocalFileHeader,
+    pub(crate) data_start_offset: u64,
+    pub(crate) file_name_data: Vec<u8>,
+    pub(crate) extra_field: Vec<u8>,
+    pub(crate) compressed_data: Vec<u8>,
+    /// How much of the data was cut off by the end of the file
+    pub(crate) missing_bytes: u64
 }
 
 impl LocalFile {
@@ -100,76 +183,85 @@ impl LocalFile {
             data_start_offset: 0,
             file_name_data: Vec::new(),
             extra_field: Vec::new(),
-            compressed_data: Vec::new()
+            compressed_data: Vec::new(),
+            missing_bytes: 0
         }
     }
 
     /// Load metadata
     /// Returns the offset of the end (start_offset + static_data size + compressed_data_size)
-    pub fn load_metadata(&mut self, mut file: &std::fs::File, start_offset: u64) -> u64 {
+    pub fn load_metadata(&mut self, file: &mut ArchiveReader, start_offset: u64) -> ZipResult<u64> {
         let mut static_data = LocalFileHeader::new();
-        let end_o_static_data = static_data.load_data(&mut file, start_offset);
+        let end_o_static_data = static_data.load_data(file, start_offset)?;
 
         let mut file_name = vec![0; static_data.file_name_length as usize];
-        file.seek(SeekFrom::Start(end_o_static_data)).expect("Couldn't seek!");
-        file.read(&mut file_name).expect("Couldn't read");
+        seek_to(file, end_o_static_data)?;
+        file.read_exact(&mut file_name)?;
 
         let mut extra_field = vec![0; static_data.extra_field_length as usize];
-        file.read(&mut extra_field).expect("Couldn't read");
+        file.read_exact(&mut extra_field)?;
 
         self.static_data = static_data;
-        self.data_start_offset = static_data.file_name_length as u64 + static_data.extra_field_length as u64 + end_o_static_data as u64;
+        self.data_start_offset = static_data.file_name_length as u64 + static_data.extra_field_length as u64 + end_o_static_data;
         self.file_name_data = file_name;
         self.extra_field = extra_field;
 
-        return self.data_start_offset + self.static_data.compressed_size as u64;
+        Ok(self.data_start_offset + self.static_data.compressed_size as u64)
     }
 
-    /// Loads the compressed data for the current LocalFileHeader into memory
-    pub fn load_compressed_data(&mut self, mut file: &std::fs::File){
-        file.seek(SeekFrom::Start(self.data_start_offset)).expect("Couldn't seek");
-        let mut data = vec![0; self.static_data.compressed_size as usize];
-        file.read(&mut data).expect("Couldn't read");
+    /// Loads the compressed data for the current LocalFileHeader into memory.
+    /// The size comes from the caller since the local header's sizes are zero when a data descriptor is used.
+    /// If the file ends early, whatever is there gets loaded and the shortfall is recorded in missing_bytes.
+    pub fn load_compressed_data(&mut self, file: &mut ArchiveReader, compressed_size: u64) -> ZipResult<()> {
+        seek_to(file, self.data_start_offset)?;
+        let mut data = Vec::new();
+        file.by_ref().take(compressed_size).read_to_end(&mut data)?;
+        self.missing_bytes = compressed_size - data.len() as u64;
         self.compressed_data = data;
-
+        Ok(())
     }
 }
 
 /// The central directory record (CDR) is an expanded form of the local header
 #[repr(C, packed)]
 #[derive(Debug, Copy, Clone)]
-struct CentralDirectoryFileHeader {
+pub(crate) struct CentralDirectoryFileHeader {
     /// The Central Directory Contains multiple CDRs     
                                         // OFFSETS
-    magic_number: u32,                  // 0        0x02014b50 (Central directory file header signature)
-    version_made_by: u16,               // 4
-    version_needed: u16,                // 6
-    spacer_unused: u16,                 // 8
-    compression_method: u16,            // 10
-    last_modify_time: u16,              // 12
-    last_modify_date: u16,              // 14
-    crc32_uncompressed: u32,            // 16
-    compressed_size: u32,               // 20
-    uncompressed_size: u32,             // 24
-    file_name_length: u16,              // 28       (n)
-    extra_field_length: u16,            // 30       (m)
-    file_comment_length: u16,           // 32       (k)
-    disk_number_source: u16,            // 34
-    internal_file_attributes: u16,      // 36
-    external_file_attributes: u32,      // 38
-    relative_offset_localheader: u32,   // 42       Relative offset of local file header. This is the number of bytes between the start of the first disk on which the file occurs, and the start of the local file header.
+    pub(crate) magic_number: u32,                   // 0        0x02014b50 (Central directory file header signature)
+    pub(crate) version_made_by: u16,                // 4
+    pub(crate) version_needed: u16,                 // 6
+    pub(crate) general_purpose_flag: u16,           // 8
+    pub(crate) compression_method: u16,             // 10
+    pub(crate) last_modify_time: u16,               // 12
+    pub(crate) last_modify_date: u16,               // 14
+    pub(crate) crc32_uncompressed: u32,             // 16
+    pub(crate) compressed_size: u32,                // 20
+    pub(crate) uncompressed_size: u32,              // 24
+    pub(crate) file_name_length: u16,               // 28       (n)
+    pub(crate) extra_field_length: u16,             // 30       (m)
+    pub(crate) file_comment_length: u16,            // 32       (k)
+    pub(crate) disk_number_source: u16,             // 34
+    pub(crate) internal_file_attributes: u16,       // 36
+    pub(crate) external_file_attributes: u32,       // 38
+    pub(crate) relative_offset_localheader: u32,    // 42       Relative offset of local file header. This is the number of bytes between the start of the first disk on which the file occurs, and the start of the local file header.
     // filename: Vec<u8>,                  // 46
     // extra_field: Vec<u8>,               // 46 + n
     // file_comment: Vec<u8>               // 46 + n + m
 }
 
 impl CentralDirectoryFileHeader{
+    /// The struct exactly as it's laid out in the file, the reverse of load_data
+    pub fn as_bytes(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self as *const _ as *const u8, mem::size_of::<CentralDirectoryFileHeader>()) }
+    }
+
     pub fn new() -> CentralDirectoryFileHeader {
         CentralDirectoryFileHeader {
             magic_number: 0,
             version_made_by: 0,
             version_needed: 0,
-            spacer_unused: 0,
+            general_purpose_flag: 0,
             compression_method: 0,
             last_modify_time: 0,
             last_modify_date: 0,
@@ -188,13 +280,12 @@ impl CentralDirectoryFileHeader{
 
     /// Loads data into a CentralDirecotyFileHeader
     /// Returns where reading stopped. (offset + size of struct)
-    pub fn load_data(&mut self, mut file: &std::fs::File, start_offset: u64) -> u64{
-        println!("Loading CDFR from offset: {:#X}", start_offset);
+    pub fn load_data(&mut self, file: &mut ArchiveReader, start_offset: u64) -> ZipResult<u64> {
         let data_size = mem::size_of::<CentralDirectoryFileHeader>();
         let mut struct_data = vec![0u8; data_size];
 
-        file.seek(SeekFrom::Start(start_offset)).expect("Couldn't seek to start of CDFR");
-        file.read(&mut struct_data).expect("Couldn't read from file.");
+        seek_to(file, start_offset)?;
+        file.read_exact(&mut struct_data)?;
 
         let mut data: CentralDirectoryFileHeader = unsafe { mem::zeroed() };
 
@@ -205,8 +296,8 @@ impl CentralDirectoryFileHeader{
             c.read_exact(data_slice).expect("Couldn't read slice data into struct.");
         }
 
-        if data.magic_number != 33639248{
-            println!("\tError!! Magic number incorrect!");
+        if data.magic_number != 0x02014b50 {
+            return Err(ZipError::InvalidArchive(format!("Bad central directory header signature at offset {:#X}", start_offset)));
         }
 
         // println!("Got magic number: {:#X}", data.magic_number);
@@ -214,7 +305,7 @@ impl CentralDirectoryFileHeader{
         self.magic_number = data.magic_number;
         self.version_made_by = data.version_made_by;
         self.version_needed = data.version_needed;
-        self.spacer_unused = data.spacer_unused;
+        self.general_purpose_flag = data.general_purpose_flag;
         self.compression_method = data.compression_method;
         self.last_modify_time = data.last_modify_time;
         self.last_modify_date = data.last_modify_date;
@@ -229,25 +320,68 @@ impl CentralDirectoryFileHeader{
         self.external_file_attributes = data.external_file_attributes;
         self.relative_offset_localheader = data.relative_offset_localheader;
 
-        return start_offset + data_size as u64;
+        Ok(start_offset + data_size as u64)
+    }
+}
+
+/// Size of the data descriptor following an entry's data, 0 if it doesn't have one.
+/// The descriptor's signature is optional, so this looks for it at data_end.
+pub(crate) fn data_descriptor_length(file: &mut ArchiveReader, local: &LocalFile, data_end: u64) -> u64 {
+    if local.static_data.general_purpose_flag & FLAG_DATA_DESCRIPTOR == 0 {
+        return 0;
     }
+    let sizes = if extrafield::find(&local.extra_field, extrafield::ZIP64_EXTENDED_INFO).is_some() { 16 } else { 8 };
+    let mut signature = [0u8; 4];
+    let signed = seek_to(file, data_end).is_ok()
+        && file.read_exact(&mut signature).is_ok()
+        && signature == [0x50, 0x4b, 0x07, 0x08];
+    if signed { 4 + 4 + sizes } else { 4 + sizes }
+}
+
+/// The crc and sizes from the data descriptor after an entry's data
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct DataDescriptor {
+    pub(crate) crc32: u32,
+    pub(crate) compressed_size: u64,
+    pub(crate) uncompressed_size: u64
+}
+
+/// Reads the data descriptor at data_end, None if the entry doesn't have one or it's cut off
+pub(crate) fn read_data_descriptor(file: &mut ArchiveReader, local: &LocalFile, data_end: u64) -> Option<DataDescriptor> {
+    let length = data_descriptor_length(file, local, data_end);
+    if length == 0 {
+        return None;
+    }
+    let mut descriptor = vec![0u8; length as usize];
+    seek_to(file, data_end).ok()?;
+    file.read_exact(&mut descriptor).ok()?;
+
+    // Skip the signature when there is one, what's left is the crc then the two sizes
+    let fields = if descriptor.len() == 16 || descriptor.len() == 24 { &descriptor[4..] } else { &descriptor[..] };
+    let crc32 = u32::from_le_bytes(fields[0..4].try_into().unwrap());
+    let (compressed_size, uncompressed_size) = if fields.len() == 20 {
+        (u64::from_le_bytes(fields[4..12].try_into().unwrap()), u64::from_le_bytes(fields[12..20].try_into().unwrap()))
+    } else {
+        (u32::from_le_bytes(fields[4..8].try_into().unwrap()) as u64, u32::from_le_bytes(fields[8..12].try_into().unwrap()) as u64)
+    };
+    Some(DataDescriptor { crc32, compressed_size, uncompressed_size })
 }
 
 /// A wrapper around CentralDirectoryFileHeader so that we can pac the static stuff, and then manually fill the rest.
 /// Central Directory File Header Record (CDFHR)
 #[derive(Debug, Clone)]
-struct CDFHR {
-    static_data: CentralDirectoryFileHeader,
-    start_offset: u64,
-    end_offset: u64,
-    file_name_data: Vec<u8>,
-    extra_field_data: Vec<u8>,
-    file_comment_data: Vec<u8>
+pub(crate) struct Cdfhr {
+    pub(crate) static_data: CentralDirectoryFileHeader,
+    pub(crate) start_offset: u64,
+    pub(crate) end_offset: u64,
+    pub(crate) file_name_data: Vec<u8>,
+    pub(crate) extra_field_data: Vec<u8>,
+    pub(crate) file_comment_data: Vec<u8>
 }
 
-impl CDFHR {
-    pub fn new() -> CDFHR {
-        CDFHR {
+impl Cdfhr {
+    pub fn new() -> Cdfhr {
+        Cdfhr {
             static_data: CentralDirectoryFileHeader::new(),
             start_offset: 0,
             end_offset: 0,
@@ -257,11 +391,67 @@ impl CDFHR {
         }
     }
 
+    /// Sizes and local header offset, with any 0xFFFFFFFF placeholders filled in from the ZIP64 extra field.
+    /// The extra field only holds the values that overflowed, in this order.
+    fn zip64_values(&self) -> [u64; 3] {
+        let data = self.static_data;
+        let mut values = [data.uncompressed_size as u64, data.compressed_size as u64, data.relative_offset_localheader as u64];
+        if let Some(mut extra) = extrafield::find(&self.extra_field_data, extrafield::ZIP64_EXTENDED_INFO) {
+            for value in values.iter_mut().filter(|value| **value == u32::MAX as u64) {
+                if extra.len() < 8 {
+                    break;
+                }
+                *value = u64::from_le_bytes(extra[..8].try_into().unwrap());
+                extra = &extra[8..];
+            }
+        }
+        values
+    }
+
+    pub(crate) fn uncompressed_size(&self) -> u64 {
+        self.zip64_values()[0]
+    }
+
+    pub(crate) fn compressed_size(&self) -> u64 {
+        self.zip64_values()[1]
+    }
+
+    pub(crate) fn local_header_offset(&self) -> u64 {
+        self.zip64_values()[2]
+    }
+
+    /// Builds a stand-in record from a local header, for when the central directory is missing
+    pub fn from_local(local: &LocalFile, local_offset: u64) -> Cdfhr {
+        let header = local.static_data;
+        let mut static_data = CentralDirectoryFileHeader::new();
+        static_data.magic_number = 0x02014b50;
+        static_data.version_needed = header.version_needed;
+        static_data.general_purpose_flag = header.general_purpose_flag;
+        static_data.compression_method = header.compression_method;
+        static_data.last_modify_time = header.last_modify_time;
+        static_data.last_modify_date = header.last_modify_date;
+        static_data.crc32_uncompressed = header.crc32_uncompressed;
+        static_data.compressed_size = header.compressed_size;
+        static_data.uncompressed_size = header.uncompressed_size;
+        static_data.file_name_length = header.file_name_length;
+        static_data.extra_field_length = header.extra_field_length;
+        static_data.relative_offset_localheader = local_offset.min(u32::MAX as u64) as u32;
+
+        Cdfhr {
+            static_data,
+            start_offset: 0,
+            end_offset: 0,
+            file_name_data: local.file_name_data.clone(),
+            extra_field_data: local.extra_field.clone(),
+            file_comment_data: Vec::new()
+        }
+    }
+
     /// Loads the object calling it.
     /// Returns a u64 containg the end position after reading.
-    pub fn load_data(&mut self, mut file: &std::fs::File, start_offset: u64) -> u64{
+    pub fn load_data(&mut self, file: &mut ArchiveReader, start_offset: u64) -> ZipResult<u64> {
         let mut static_data = CentralDirectoryFileHeader::new();
-        let end_static_offset = static_data.load_data(&mut file, start_offset);
+        let end_static_offset = static_data.load_data(file, start_offset)?;
 
         
 
@@ -269,11 +459,11 @@ impl CDFHR {
         let mut extra_field_buf = vec![0; static_data.extra_field_length as usize];
         let mut file_comment_buf = vec![0; static_data.file_comment_length as usize];
 
-        file.seek(SeekFrom::Start(end_static_offset)).expect("Couldn't seek to end of static offset");
+        seek_to(file, end_static_offset)?;
 
-        file.read(&mut file_name_buf).expect("Couldn't read filename");
-        file.read(&mut extra_field_buf).expect("Couldn't read extra field");
-        file.read(&mut file_comment_buf).expect("Couldn't read file comment");
+        file.read_exact(&mut file_name_buf)?;
+        file.read_exact(&mut extra_field_buf)?;
+        file.read_exact(&mut file_comment_buf)?;
 
         self.static_data = static_data;
         self.start_offset = start_offset;
@@ -282,52 +472,51 @@ impl CDFHR {
         self.extra_field_data = extra_field_buf;
         self.file_comment_data = file_comment_buf;
 
-        return self.end_offset;
+        Ok(self.end_offset)
     }
 }
 
 /// After all the central directory entries comes the end of central directory (EOCD) record, which marks the end of the ZIP file
 #[repr(C, packed)]
 #[derive(Debug, Copy, Clone)]
-struct EndOfCentralDirectoryRecord {
+pub(crate) struct EndOfCentralDirectoryRecord {
 
 
                                         // OFFSETS
-    magic_number: u32,                  // 0        0x06054b50
-    number_of_current_disk: u16,        // 4
-    disk_where_cdr_starts: u16,         // 6
-    num_cdr_on_disk: u16,               // 8
-    total_cdr: u16,                     // 10
-    size_of_cdr: u32,                   // 12       Size of the Central Directory in Bytes
-    offset_cdr_start: u32,              // 16       Offset from the start of the archive where the CentralDirectory starts (in bytes, obvi)
-    comment_length: u16,                // 20       (n)
+    pub(crate) magic_number: u32,                   // 0        0x06054b50
+    pub(crate) number_of_current_disk: u16,         // 4
+    pub(crate) disk_where_cdr_starts: u16,          // 6
+    pub(crate) num_cdr_on_disk: u16,                // 8
+    pub(crate) total_cdr: u16,                      // 10
+    pub(crate) size_of_cdr: u32,                    // 12       Size of the Central Directory in Bytes
+    pub(crate) offset_cdr_start: u32,               // 16       Offset from the start of the archive where the CentralDirectory starts (in bytes, obvi)
+    pub(crate) comment_length: u16,                 // 20       (n)
     // comment: Vec<u8>                 Moved to wrapper EofRecord
 }
 
 #[derive(Debug, Clone)]
 /// Wrapper around EndOfCentralDirectoryRecord that allows us to manually fill the variably sized data
-struct EofRecord {
-    static_data: EndOfCentralDirectoryRecord,
-    start_offset: u64,
-    end_offset: u64,
-    comment: Vec<u8>,
+pub(crate) struct EofRecord {
+    pub(crate) static_data: EndOfCentralDirectoryRecord,
+    pub(crate) start_offset: u64,
+    pub(crate) end_offset: u64,
+    pub(crate) comment: Vec<u8>,
 }
 
 impl EofRecord {
-    pub fn new(mut file: &std::fs::File, offset_starting: u64) -> EofRecord {
+    pub fn new(file: &mut ArchiveReader, offset_starting: u64) -> ZipResult<EofRecord> {
         let mut static_data = EndOfCentralDirectoryRecord::new();
-        let end_offset = static_data.load_data(&mut file, offset_starting);
+        let end_offset = static_data.load_data(file, offset_starting)?;
         let mut comment_buf = vec![0; static_data.comment_length as usize];
-        file.seek(SeekFrom::Start(end_offset)).expect("Couldn't seek to EOF comment");
-        file.read(&mut comment_buf).expect("Error reading EOF comment");
+        seek_to(file, end_offset)?;
+        file.read_exact(&mut comment_buf)?;
 
-        return EofRecord{
-            static_data: static_data,
+        Ok(EofRecord{
+            static_data,
             start_offset: offset_starting,
-            end_offset: end_offset,
+            end_offset: end_offset + comment_buf.len() as u64,
             comment: comment_buf
-        }
-        
+        })
     }
 }
 
@@ -335,13 +524,12 @@ impl EndOfCentralDirectoryRecord {
     /// Reads a binary array into a struct, using the C representaion
     /// Returns a offset of where the reading ended
     /// https://stackoverflow.com/questions/25410028/how-to-read-a-struct-from-a-file-in-rust
-    pub fn load_data(&mut self, mut file: &std::fs::File, offset_starting: u64) -> u64{
-        println!("Loading EOF Record from offset: {:#X}", offset_starting);
+    pub fn load_data(&mut self, file: &mut ArchiveReader, offset_starting: u64) -> ZipResult<u64> {
         let data_size = mem::size_of::<EndOfCentralDirectoryRecord>();
         let mut struct_data = vec![0u8; data_size];
 
-        file.seek(SeekFrom::Start(offset_starting)).expect("Couldn't seek to start of EOF Record");
-        file.read(&mut struct_data).expect("Couldn't read from file.");
+        seek_to(file, offset_starting)?;
+        file.read_exact(&mut struct_data)?;
 
         let mut data: EndOfCentralDirectoryRecord = unsafe {mem::zeroed()};
         
@@ -362,7 +550,12 @@ impl EndOfCentralDirectoryRecord {
         self.offset_cdr_start = data.offset_cdr_start;
         self.comment_length = data.comment_length;
 
-        return offset_starting + data_size as u64;
+        Ok(offset_starting + data_size as u64)
+    }
+
+    /// The struct exactly as it's laid out in the file, the reverse of load_data
+    pub fn as_bytes(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self as *const _ as *const u8, mem::size_of::<EndOfCentralDirectoryRecord>()) }
     }
 
     pub fn new() -> EndOfCentralDirectoryRecord{
@@ -379,100 +572,660 @@ impl EndOfCentralDirectoryRecord {
     }
 }
 
+/// Everything a reader needs to support to fully open an archive
+#[derive(Debug, Clone, Default)]
+pub struct RequiredFeatures {
+    pub zip64: bool,
+    pub compression_methods: Vec<CompressionMethod>,
+    pub encryption_methods: Vec<EncryptionMethod>
+}
+
+impl RequiredFeatures {
+    /// Human readable reasons this crate can't open the archive yet. Empty if everything is supported. ZIP64 is read
+    /// and ZipCrypto entries open with a password, so neither counts against an archive.
+    pub fn unsupported(&self) -> Vec<String> {
+        let mut reasons = Vec::new();
+
+        for method in &self.compression_methods {
+            if !method.is_supported() {
+                reasons.push(format!("compression method {} is not supported", method));
+            }
+        }
+        for method in &self.encryption_methods {
+            if !matches!(method, EncryptionMethod::None | EncryptionMethod::ZipCrypto) {
+                reasons.push(format!("{} encryption is not supported", method));
+            }
+        }
+
+        reasons
+    }
+
+    pub fn is_supported(&self) -> bool {
+        self.unsupported().is_empty()
+    }
+}
+
+/// A central directory record or local header we couldn't parse
 #[derive(Debug)]
+pub struct BrokenEntry {
+    /// Position among the successfully parsed entries this one would have sat at
+    pub index: usize,
+    /// None when the central directory record itself was unreadable
+    pub name: Option<String>,
+    /// Offset of the structure that failed to parse
+    pub offset: u64,
+    pub error: ZipError
+}
+
+/// Walks every central directory record in order, good or bad
+pub struct LossyEntries<'a> {
+    entries: &'a [ZipEntry],
+    broken: &'a [BrokenEntry],
+    position: usize,
+    next_broken: usize
+}
+
+impl<'a> Iterator for LossyEntries<'a> {
+    type Item = Result<&'a ZipEntry, &'a BrokenEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(broken) = self.broken.get(self.next_broken) {
+            if broken.index <= self.position {
+                self.next_broken += 1;
+                return Some(Err(broken));
+            }
+        }
+
+        let entry = self.entries.get(self.position)?;
+        self.position += 1;
+        Some(Ok(entry))
+    }
+}
+
+/// An archive loaded into memory. Cloning is cheap, the entries and their data are shared rather than copied, and
+/// reading never touches the file again, so clones (or one archive shared by reference) can be read from any number
+/// of threads at once. Extraction opens its own handle on the file each time.
+#[derive(Debug, Clone)]
 pub struct ZipArchive<'a> {
     filename: &'a str,
-    local_file_data: Vec<LocalFile>,
-    central_records: Vec<CDFHR>,
-    eof_record: EofRecord
+    entries: Arc<[ZipEntry]>,
+    broken_entries: Arc<[BrokenEntry]>,
+    /// None when no EOF record was found and the entries were recovered by scanning local headers
+    eof_record: Option<Arc<EofRecord>>,
+    has_zip64_locator: bool,
+    /// Bytes after the end of the EOF record's comment
+    trailing_bytes: u64,
+    file_length: u64,
+    warnings: Arc<[ArchiveWarning]>,
+    /// ReaderOptions::max_read_rate, which extraction keeps to as well
+    max_read_rate: Option<u64>,
+    /// ReaderOptions::direct_io, which keeps extraction from going back to the file through the page cache
+    direct_io: bool,
+    metrics: Option<SharedMetrics>
 }
 
+// Entries are opened through &self from many threads at once, which only works while archives stay Send and Sync
+const _: fn() = || {
+    fn shareable<T: Send + Sync>() {}
+    shareable::<ZipArchive<'static>>();
+};
+
 
 impl ZipArchive<'_> {
 
-    /// Find the start offset of the EOFRecord
-    /// Returns u64 offset from start of file
-    fn find_eof_start_offset(mut file: &std::fs::File) -> u64{
-        let last_pos = match file.seek(SeekFrom::End(0)) {
-            Err(why) => panic!("Couldn't seek! {}", why.to_string()),
-            Ok(pos) => pos
-        };
+    /// Find the start offset of the EOFRecord, looking at most search_window bytes back from the end
+    /// Returns u64 offset from start of file, or None if there isn't one (usually a truncated download)
+    fn find_eof_start_offset(file: &mut ArchiveReader, search_window: u64) -> ZipResult<Option<u64>>{
+        let last_pos = file.seek(SeekFrom::End(0))?;
+        let window_start = last_pos.saturating_sub(search_window);
+
+        let mut window = Vec::new();
+        seek_to(file, window_start)?;
+        file.by_ref().take(last_pos - window_start).read_to_end(&mut window)?;
 
         let eof_record_num:[u8; 4] = [0x50, 0x4b, 0x05, 0x06]; // 0x06054b50 Reversed for lil-endian
 
-        let mut current_index: i64 = 1;
-        while current_index < last_pos as i64 { // basically, this loop moves the read position back 1 byte at a time from the end, until our
-            // four-byte buffer looks like the eof_record_num, which means we have found the start of the EOF record.
-            let mut buffer: [u8; 4] = [0x0; 4];
-            file.seek(SeekFrom::End(-current_index)).unwrap();
-            file.read(&mut buffer[..]).unwrap();
-            if &eof_record_num[..] == &buffer[..] {
-                println!("Found magic number for EOF structure at offset {:#X}", last_pos-current_index as u64);
-                break;
-            }
-            current_index = current_index + 1;
+        // Walk back from the end one byte at a time until the four bytes at our position look like the eof_record_num,
+        // which means we have found the start of the EOF record.
+        let found = window.windows(4).rposition(|bytes| bytes == eof_record_num);
+        Ok(found.map(|position| window_start + position as u64))
+    }
+
+    /// Creates a new ZipArchive given a filename. Panics if it can't be opened.
+    pub fn new(filename: &str) -> ZipArchive<'_>{
+        match ZipArchive::with_options(filename, &ReaderOptions::default()) {
+            Err(why) => panic!("Couldn't open {}: {}", filename, why),
+            Ok(archive) => archive
         }
+    }
 
-        let eofdirectory_offset: u64 = last_pos - current_index as u64;
-        return eofdirectory_offset;
+    /// Opens an archive, with control over how the end of the archive is located
+    pub fn with_options<'a>(filename: &'a str, options: &ReaderOptions) -> ZipResult<ZipArchive<'a>> {
+        let started = Instant::now();
+        let file = open_reader(Path::new(filename), options)?;
+        ZipArchive::load(filename, file, options, started)
     }
 
-    /// Creates a new ZipArchive given a filename
-    pub fn new(filename: &str) -> ZipArchive{
-        println!("New ZipArchive! {}", filename);
-        let path = Path::new(filename);
-        let mut file = match File::open(path) {
-            Err(why) => panic!("Couldn't open {}: {}", path.display(), why.to_string()),
-            Ok(file) => file
+    /// Reads an archive held in memory, such as an upload that hasn't touched the disk.
+    /// Malformed input of any kind comes back as an error or as broken entries, never a panic: every length and offset
+    /// is checked against the buffer, and memory use is bounded by the size of data (overlapping entries are refused
+    /// rather than loaded over and over). The archive has no filename, so anything that reopens the file is unavailable.
+    pub fn parse_bytes(data: &[u8]) -> ZipResult<ZipArchive<'static>> {
+        ZipArchive::parse_bytes_with_options(data.to_vec(), &ReaderOptions::default())
+    }
+
+    /// parse_bytes taking ownership of the data, with the buffer size, rate limit and metrics from options
+    pub fn parse_bytes_with_options(data: Vec<u8>, options: &ReaderOptions) -> ZipResult<ZipArchive<'static>> {
+        let started = Instant::now();
+        ZipArchive::load("", archive_reader(ArchiveSource::Memory(Cursor::new(data)), options), options, started)
+    }
+
+    /// Reads an archive from a file the caller already has open, such as one handed over a Unix socket or by a
+    /// sandbox broker. Where the file is positioned doesn't matter. Everything is read in before this returns and the
+    /// file is closed. As with parse_bytes there's no filename, so anything that reopens the file is unavailable.
+    pub fn from_file(file: File) -> ZipResult<ZipArchive<'static>> {
+        ZipArchive::from_file_with_options(file, &ReaderOptions::default())
+    }
+
+    pub fn from_file_with_options(file: File, options: &ReaderOptions) -> ZipResult<ZipArchive<'static>> {
+        let started = Instant::now();
+        ZipArchive::load("", archive_reader(ArchiveSource::File(file), options), options, started)
+    }
+
+    /// from_file for a raw descriptor, which is closed once the archive has been read
+    ///
+    /// # Safety
+    /// fd must be an open descriptor readable as a file, owned by the caller and not used or closed afterwards.
+    #[cfg(unix)]
+    pub unsafe fn from_raw_fd(fd: std::os::unix::io::RawFd) -> ZipResult<ZipArchive<'static>> {
+        use std::os::unix::io::FromRawFd;
+        ZipArchive::from_file(File::from_raw_fd(fd))
+    }
+
+    /// from_file for a raw handle, which is closed once the archive has been read
+    ///
+    /// # Safety
+    /// handle must be an open file handle with read access, owned by the caller and not used or closed afterwards.
+    #[cfg(windows)]
+    pub unsafe fn from_raw_handle(handle: std::os::windows::io::RawHandle) -> ZipResult<ZipArchive<'static>> {
+        use std::os::windows::io::FromRawHandle;
+        ZipArchive::from_file(File::from_raw_handle(handle))
+    }
+
+    fn load<'a>(filename: &'a str, mut file: ArchiveReader, options: &ReaderOptions, started: Instant) -> ZipResult<ZipArchive<'a>> {
+        let file_length = file.seek(SeekFrom::End(0))?;
+        let eofdirectory_offset = ZipArchive::find_eof_start_offset(&mut file, options.eocd_search_window)?;
+        let has_zip64_locator = eofdirectory_offset.is_some_and(|offset| ZipArchive::has_zip64_locator(&mut file, offset));
+        let mut found_loading = Vec::new();
+        let (entries, broken_entries, eof_record) = match eofdirectory_offset {
+            Some(offset) => {
+                let eof_record = EofRecord::new(&mut file, offset).context(None, Structure::EndOfCentralDirectory, offset)?;
+                let mut cdr_start = eof_record.static_data.offset_cdr_start as u64;
+                let mut cdr_count = eof_record.static_data.num_cdr_on_disk as u64;
+                if has_zip64_locator {
+                    match ZipArchive::read_zip64_end(&mut file, offset).context(None, Structure::EndOfCentralDirectory, offset - 20) {
+                        Ok((start, count)) => {
+                            cdr_start = start;
+                            cdr_count = count;
+                        },
+                        Err(why) => found_loading.push(ArchiveWarning { entry: None, kind: WarningKind::UnreadableZip64End { reason: why.to_string() } })
+                    }
+                }
+                if ZipArchive::is_central_directory_encrypted(&mut file, cdr_start) {
+                    return Err(ZipError::EncryptedCentralDirectory);
+                }
+                let (entries, broken_entries) = ZipArchive::load_central_directory(&mut file, cdr_start, cdr_count);
+                (entries, broken_entries, Some(eof_record))
+            },
+            None => {
+                let (entries, broken_entries) = ZipArchive::scan_local_headers(&mut file);
+                (entries, broken_entries, None)
+            }
         };
 
-        let eofdirectory_offset = ZipArchive::find_eof_start_offset(&mut file);
-        let eof_record = EofRecord::new(&mut file, eofdirectory_offset);
+        let trailing_bytes = eof_record.as_ref().map_or(0, |eof| file_length - eof.end_offset);
+        if trailing_bytes > 0 && !options.allow_trailing_garbage {
+            return Err(ZipError::InvalidArchive(format!("{} bytes of trailing data after the end of the archive", trailing_bytes)));
+        }
+
+        let mut archive = ZipArchive{
+            filename,
+            entries: entries.into(),
+            broken_entries: broken_entries.into(),
+            eof_record: eof_record.map(Arc::new),
+            has_zip64_locator,
+            trailing_bytes,
+            file_length,
+            warnings: Arc::from([]),
+            max_read_rate: options.max_read_rate,
+            direct_io: options.direct_io,
+            metrics: options.metrics.clone()
+        };
+        archive.warnings = warnings::collect(&archive, found_loading).into();
+        if let Some(metrics) = &archive.metrics {
+            metrics.archive_opened(archive.entries.len(), started.elapsed());
+        }
+        Ok(archive)
+    }
+
+    /// Opens an archive using a catalog exported from it earlier instead of its central directory, reading only the
+    /// local headers and data the catalog points to. The catalog has to be for this archive as it is now: one for an
+    /// archive of a different length is refused, and entries whose local headers don't match end up broken.
+    pub fn open_with_catalog(file: File, catalog: &Catalog, options: &ReaderOptions) -> ZipResult<ZipArchive<'static>> {
+        let started = Instant::now();
+        ZipArchive::load_with_catalog("", archive_reader(ArchiveSource::File(file), options), catalog, options, started)
+    }
+
+    /// open_with_catalog for the archive at filename, which (as with with_options) it keeps for reopening
+    pub fn with_catalog<'a>(filename: &'a str, catalog: &Catalog, options: &ReaderOptions) -> ZipResult<ZipArchive<'a>> {
+        let started = Instant::now();
+        let file = open_reader(Path::new(filename), options)?;
+        ZipArchive::load_with_catalog(filename, file, catalog, options, started)
+    }
 
-        let mut cdrs: Vec<CDFHR> = Vec::new();
-        let mut last_cdfr_offset: u64 = eof_record.static_data.offset_cdr_start as u64;
-        // Load the CDR structures
-        for _ in 0..eof_record.static_data.num_cdr_on_disk{
-            let mut cdfhr = CDFHR::new();
-            let new_offset = cdfhr.load_data(&mut file, last_cdfr_offset);
-            last_cdfr_offset = new_offset;
+    fn load_with_catalog<'a>(filename: &'a str, mut file: ArchiveReader, catalog: &Catalog, options: &ReaderOptions, started: Instant) -> ZipResult<ZipArchive<'a>> {
+        let file_length = file.seek(SeekFrom::End(0))?;
+        if file_length != catalog.archive_length {
+            return Err(ZipError::InvalidArchive(format!("The catalog is for an archive of {} bytes, this one is {}", catalog.archive_length, file_length)));
+        }
+
+        let cdrs = catalog.entries.iter().map(CatalogEntry::to_record).collect();
+        let (entries, broken_entries) = ZipArchive::load_local_files(&mut file, cdrs);
+        let eof_record = match catalog.eocd_offset {
+            Some(offset) => Some(EofRecord::new(&mut file, offset).context(None, Structure::EndOfCentralDirectory, offset)?),
+            None => None
+        };
+        let has_zip64_locator = catalog.eocd_offset.is_some_and(|offset| ZipArchive::has_zip64_locator(&mut file, offset));
+        let trailing_bytes = eof_record.as_ref().map_or(0, |eof| file_length.saturating_sub(eof.end_offset));
+
+        let mut archive = ZipArchive {
+            filename,
+            entries: entries.into(),
+            broken_entries: broken_entries.into(),
+            eof_record: eof_record.map(Arc::new),
+            has_zip64_locator,
+            trailing_bytes,
+            file_length,
+            warnings: Arc::from([]),
+            max_read_rate: options.max_read_rate,
+            direct_io: options.direct_io,
+            metrics: options.metrics.clone()
+        };
+        archive.warnings = warnings::collect(&archive, Vec::new()).into();
+        if let Some(metrics) = &archive.metrics {
+            metrics.archive_opened(archive.entries.len(), started.elapsed());
+        }
+        Ok(archive)
+    }
+
+    /// Loads every entry listed in the central directory.
+    /// Entries that fail to parse end up in the second list instead of stopping the load.
+    fn load_central_directory(file: &mut ArchiveReader, cdr_start: u64, cdr_count: u64) -> (Vec<ZipEntry>, Vec<BrokenEntry>) {
+        let mut cdrs: Vec<Cdfhr> = Vec::new();
+        let mut cdr_failure: Option<(u64, ZipError)> = None;
+        let mut last_cdfr_offset: u64 = cdr_start;
+        // Load the CDR structures. Each record's position depends on the one before, so the first bad one ends the walk.
+        for _ in 0..cdr_count {
+            let mut cdfhr = Cdfhr::new();
+            match cdfhr.load_data(file, last_cdfr_offset).context(None, Structure::CentralDirectoryHeader, last_cdfr_offset) {
+                Ok(new_offset) => last_cdfr_offset = new_offset,
+                Err(error) => {
+                    cdr_failure = Some((last_cdfr_offset, error));
+                    break;
+                }
+            }
             cdrs.push(cdfhr);
         }
 
-        let mut lfh: Vec<LocalFile> = Vec::new();
-        for cdr in &cdrs {
+        let (entries, mut broken_entries) = ZipArchive::load_local_files(file, cdrs);
+        if let Some((offset, error)) = cdr_failure {
+            broken_entries.push(BrokenEntry { index: entries.len(), name: None, offset, error });
+        }
+        (entries, broken_entries)
+    }
+
+    /// Loads the local header and data each central directory record points to, refusing any that overlap.
+    /// Entries that fail to load end up in the second list.
+    fn load_local_files(file: &mut ArchiveReader, cdrs: Vec<Cdfhr>) -> (Vec<ZipEntry>, Vec<BrokenEntry>) {
+        // Headers first, so every entry's span is known before any data is read
+        let mut loaded: Vec<ZipResult<LocalFile>> = cdrs.iter().map(|cdr| {
+            let mut localfile = LocalFile::new();
+            let name = String::from_utf8_lossy(&cdr.file_name_data).into_owned();
+            let local_offset = cdr.local_header_offset();
+            localfile.load_metadata(file, local_offset).context(Some(&name), Structure::LocalHeader, local_offset).map(|_| localfile)
+        }).collect();
+
+        // In offset order, an entry starting before the one ahead of it ends shares its bytes, the trick behind
+        // non-recursive zip bombs. Only the first keeps its data, so what's read never adds up to more than the file.
+        let mut by_offset: Vec<usize> = (0..cdrs.len()).filter(|&index| loaded[index].is_ok()).collect();
+        by_offset.sort_by_key(|&index| cdrs[index].local_header_offset());
+        let mut previous: Option<(u64, usize)> = None;
+        for index in by_offset {
+            let local_offset = cdrs[index].local_header_offset();
+            if let Some((previous_end, previous_index)) = previous.filter(|&(previous_end, _)| local_offset < previous_end) {
+                let name = String::from_utf8_lossy(&cdrs[index].file_name_data).into_owned();
+                let why = format!("Overlaps {}, whose data runs to {:#X}", String::from_utf8_lossy(&cdrs[previous_index].file_name_data), previous_end);
+                loaded[index] = Err(ZipError::InvalidArchive(why)).context(Some(&name), Structure::LocalHeader, local_offset);
+                continue;
+            }
+            if let Ok(localfile) = &loaded[index] {
+                previous = Some((localfile.data_start_offset.saturating_add(cdrs[index].compressed_size()), index));
+            }
+        }
+
+        let mut entries: Vec<ZipEntry> = Vec::new();
+        let mut broken_entries: Vec<BrokenEntry> = Vec::new();
+        for (cdr, localfile) in cdrs.into_iter().zip(loaded) {
+            let name = String::from_utf8_lossy(&cdr.file_name_data).into_owned();
+            let local_offset = cdr.local_header_offset();
+            let loaded = localfile.and_then(|mut localfile| {
+                localfile.load_compressed_data(file, cdr.compressed_size())
+                    .context(Some(&name), Structure::Data, localfile.data_start_offset)
+                    .map(|_| localfile)
+            });
+
+            match loaded {
+                Ok(localfile) => entries.push(ZipEntry::new(cdr, localfile)),
+                Err(error) => broken_entries.push(BrokenEntry {
+                    index: entries.len(),
+                    name: Some(name),
+                    offset: local_offset,
+                    error
+                })
+            }
+        }
+        (entries, broken_entries)
+    }
+
+    /// Walks local headers from the start of the file, for archives that lost their central directory.
+    /// Stops at the first thing that isn't a complete local header, or after an entry cut short by the end of the file.
+    pub(crate) fn scan_local_headers(file: &mut ArchiveReader) -> (Vec<ZipEntry>, Vec<BrokenEntry>) {
+        let local_header_num: [u8; 4] = [0x50, 0x4b, 0x03, 0x04]; // 0x04034b50
+        let mut entries: Vec<ZipEntry> = Vec::new();
+        let mut broken_entries: Vec<BrokenEntry> = Vec::new();
+        let mut offset: u64 = 0;
+
+        loop {
+            let mut buffer: [u8; 4] = [0x0; 4];
+            let at_header = seek_to(file, offset).is_ok()
+                && file.read_exact(&mut buffer).is_ok()
+                && buffer == local_header_num;
+            if !at_header {
+                break;
+            }
+
             let mut localfile = LocalFile::new();
-            localfile.load_metadata(&mut file, cdr.static_data.relative_offset_localheader as u64);
-            localfile.load_compressed_data(&mut file);
-            lfh.push(localfile);
+            if let Err(error) = localfile.load_metadata(file, offset).context(None, Structure::LocalHeader, offset) {
+                broken_entries.push(BrokenEntry { index: entries.len(), name: None, offset, error });
+                break;
+            }
+
+            if localfile.static_data.general_purpose_flag & FLAG_MASKED_HEADER != 0 {
+                broken_entries.push(BrokenEntry { index: entries.len(), name: None, offset, error: ZipError::EncryptedCentralDirectory });
+                break;
+            }
+
+            let name = String::from_utf8_lossy(&localfile.file_name_data).into_owned();
+            let record = Cdfhr::from_local(&localfile, offset);
+            let compressed_size = record.compressed_size();
+            if localfile.static_data.general_purpose_flag & FLAG_DATA_DESCRIPTOR != 0 && compressed_size == 0 {
+                let error = ZipError::InvalidArchive(format!("Can't find the end of {} without the central directory", name));
+                broken_entries.push(BrokenEntry { index: entries.len(), name: Some(name), offset, error });
+                break;
+            }
+
+            if let Err(error) = localfile.load_compressed_data(file, compressed_size).context(Some(&name), Structure::Data, localfile.data_start_offset) {
+                broken_entries.push(BrokenEntry { index: entries.len(), name: Some(name), offset, error });
+                break;
+            }
+
+            let truncated = localfile.missing_bytes > 0;
+            let data_end = localfile.data_start_offset.saturating_add(compressed_size);
+            offset = data_end + data_descriptor_length(file, &localfile, data_end);
+            entries.push(ZipEntry::new(record, localfile));
+            if truncated {
+                break;
+            }
+        }
+
+        (entries, broken_entries)
+    }
+
+    /// Follows the ZIP64 locator to the ZIP64 end of central directory record.
+    /// Returns the central directory's offset and entry count, which overflow the EOF record in big archives.
+    fn read_zip64_end(file: &mut ArchiveReader, eofdirectory_offset: u64) -> ZipResult<(u64, u64)> {
+        let mut locator = [0u8; 20];
+        seek_to(file, eofdirectory_offset - 20)?;
+        file.read_exact(&mut locator)?;
+        let record_offset = u64::from_le_bytes(locator[8..16].try_into().unwrap());
+
+        let mut record = [0u8; 56];
+        seek_to(file, record_offset)?;
+        file.read_exact(&mut record)?;
+        if record[..4] != [0x50, 0x4b, 0x06, 0x06] {
+            return Err(ZipError::InvalidArchive(format!("Bad ZIP64 end of central directory signature at offset {:#X}", record_offset)));
+        }
+
+        let total_entries = u64::from_le_bytes(record[32..40].try_into().unwrap());
+        let cdr_start = u64::from_le_bytes(record[48..56].try_into().unwrap());
+        Ok((cdr_start, total_entries))
+    }
+
+    /// An encrypted central directory is usually preceded by an archive extra data record (APPNOTE 4.3.11) holding the
+    /// decryption header. Failing that, it's encrypted if it isn't readable and the first local header is masked.
+    fn is_central_directory_encrypted(file: &mut ArchiveReader, cdr_start: u64) -> bool {
+        let archive_extra_data_num: [u8; 4] = [0x50, 0x4b, 0x06, 0x08]; // 0x08064b50
+        let central_directory_num: [u8; 4] = [0x50, 0x4b, 0x01, 0x02]; // 0x02014b50
+        let mut buffer: [u8; 4] = [0x0; 4];
+        if seek_to(file, cdr_start).is_err() || file.read_exact(&mut buffer).is_err() {
+            return false;
+        }
+        if buffer == archive_extra_data_num {
+            return true;
+        }
+
+        let mut first_local = LocalFileHeader::new();
+        buffer != central_directory_num
+            && first_local.load_data(file, 0).is_ok()
+            && first_local.general_purpose_flag & FLAG_MASKED_HEADER != 0
+    }
+
+    /// The ZIP64 end of central directory locator sits directly before the EOF record, when present
+    fn has_zip64_locator(file: &mut ArchiveReader, eofdirectory_offset: u64) -> bool {
+        let zip64_locator_num: [u8; 4] = [0x50, 0x4b, 0x06, 0x07]; // 0x07064b50
+        if eofdirectory_offset < 20 {
+            return false;
+        }
+
+        let mut buffer: [u8; 4] = [0x0; 4];
+        seek_to(file, eofdirectory_offset - 20).is_ok()
+            && file.read_exact(&mut buffer).is_ok()
+            && buffer == zip64_locator_num
+    }
+
+    pub fn filename(&self) -> &str {
+        self.filename
+    }
+
+    /// The archive comment's raw bytes, change log and all. Empty if there's no EOF record to hold one.
+    pub fn comment(&self) -> &[u8] {
+        self.eof_record.as_ref().map_or(&[], |eof| &eof.comment)
+    }
+
+    /// Changes logged in the archive comment by ZipWriter::record_change, oldest first
+    pub fn change_log(&self) -> Vec<ChangeRecord> {
+        changelog::parse(self.comment())
+    }
+
+    pub fn entries(&self) -> &[ZipEntry] {
+        &self.entries
+    }
+
+    /// Entries whose headers couldn't be parsed. They're left out of entries().
+    pub fn broken_entries(&self) -> &[BrokenEntry] {
+        &self.broken_entries
+    }
+
+    /// Every entry in central directory order, yielding an Err in place of each one that couldn't be parsed
+    pub fn entries_lossy(&self) -> LossyEntries<'_> {
+        LossyEntries {
+            entries: &self.entries,
+            broken: &self.broken_entries,
+            position: 0,
+            next_broken: 0
+        }
+    }
+
+    /// True when the central directory was missing and entries were recovered from local headers instead
+    pub fn is_recovered(&self) -> bool {
+        self.eof_record.is_none()
+    }
+
+    pub(crate) fn max_read_rate(&self) -> Option<u64> {
+        self.max_read_rate
+    }
+
+    pub(crate) fn direct_io(&self) -> bool {
+        self.direct_io
+    }
+
+    pub(crate) fn metrics(&self) -> Option<&SharedMetrics> {
+        self.metrics.as_ref()
+    }
+
+    /// Problems that were tolerated while loading the archive, for passing on to users.
+    /// Entries that couldn't be loaded at all are in broken_entries() instead.
+    pub fn warnings(&self) -> &[ArchiveWarning] {
+        &self.warnings
+    }
+
+    /// The archive's length in bytes, trailing data and all
+    pub fn archive_length(&self) -> u64 {
+        self.file_length
+    }
+
+    /// Where the end of central directory record starts, None when there wasn't one
+    pub fn eocd_offset(&self) -> Option<u64> {
+        self.eof_record.as_ref().map(|eof| eof.start_offset)
+    }
+
+    /// How many bytes of junk follow the end of the archive
+    pub fn trailing_bytes(&self) -> u64 {
+        self.trailing_bytes
+    }
+
+    /// Total bytes of entry data cut off by the end of the file
+    pub fn missing_bytes(&self) -> u64 {
+        self.entries.iter().map(|entry| entry.missing_bytes()).sum()
+    }
+
+    pub fn by_name(&self, name: &str) -> Option<&ZipEntry> {
+        self.entries.iter().find(|entry| entry.name_raw() == name.as_bytes())
+    }
+
+    /// The entry whose name is exactly these bytes, for names that aren't valid UTF-8
+    pub fn by_name_raw(&self, name: &[u8]) -> Option<&ZipEntry> {
+        self.entries.iter().find(|entry| entry.name_raw() == name)
+    }
+
+    /// The entry with a name as given by ZipEntry::name_escaped, such as one from `rip list --escaped`
+    pub fn by_escaped_name(&self, escaped: &str) -> Option<&ZipEntry> {
+        self.by_name_raw(&zipentry::unescape_name(escaped)?)
+    }
+
+    /// The entry whose local header is at offset, as given by ZipEntry::header_offset. Unlike a name, that's unique
+    /// within an archive and stays the same every time it's opened until it's rewritten, so catalogs can keep it to
+    /// get back to an entry without resolving its name again.
+    pub fn by_offset(&self, offset: u64) -> Option<&ZipEntry> {
+        self.entries.iter().find(|entry| entry.header_offset() == offset)
+    }
+
+    /// Every directory in the archive, whether it has its own entry or is only implied by the paths of the files in it.
+    /// Names end in '/', sorted so parents come before their children.
+    pub fn directories(&self) -> Vec<String> {
+        let mut directories: Vec<String> = Vec::new();
+        for entry in self.entries.iter() {
+            let name = entry.name();
+            let mut end = 0;
+            // Every '/' marks the end of a parent directory
+            while let Some(slash) = name[end..].find('/') {
+                end += slash + 1;
+                directories.push(name[..end].to_string());
+            }
+            if entry.is_dir() && !name.ends_with('/') {
+                directories.push(format!("{}/", name));
+            }
         }
+        directories.sort();
+        directories.dedup();
+        directories
+    }
+
+    /// Directories implied by entry paths that have no entry of their own
+    pub fn implicit_directories(&self) -> Vec<String> {
+        self.directories().into_iter()
+            .filter(|directory| !self.entries.iter().any(|entry| entry.is_dir() && entry.name().trim_end_matches('/') == directory.trim_end_matches('/')))
+            .collect()
+    }
+
+    /// Reads an entry by name, asking the provider for a password if the entry is encrypted
+    pub fn read_with_provider(&self, name: &str, provider: &mut dyn PasswordProvider) -> ZipResult<Vec<u8>> {
+        let entry = self.by_name(name).ok_or_else(|| ZipError::FileNotFound(name.to_string()))?;
+        entry.read_with_provider(provider)
+    }
 
-        println!("Zip metadata loaded.");
-        return ZipArchive{
-            filename: filename,
-            local_file_data: lfh,
-            central_records: cdrs,
-            eof_record: eof_record
+    /// The highest version needed by any entry, i.e. what a downstream reader must support to extract everything
+    pub fn minimum_version(&self) -> MinimumVersion {
+        self.entries.iter().map(|entry| entry.minimum_version()).max().unwrap_or(MinimumVersion { major: 0, minor: 0 })
+    }
+
+    /// Summarizes the capabilities needed to read every entry, so frontends can explain why an archive won't open
+    pub fn requires_features(&self) -> RequiredFeatures {
+        let eof_zip64 = self.eof_record.as_ref().is_some_and(|eof| {
+            let eof = eof.static_data;
+            eof.total_cdr == u16::MAX || eof.size_of_cdr == u32::MAX || eof.offset_cdr_start == u32::MAX
+        });
+        let mut features = RequiredFeatures {
+            zip64: self.has_zip64_locator || eof_zip64,
+            ..Default::default()
         };
+
+        for entry in self.entries.iter() {
+            features.zip64 |= entry.is_zip64();
+
+            let method = entry.compression_method();
+            if !features.compression_methods.contains(&method) {
+                features.compression_methods.push(method);
+            }
+
+            let encryption = entry.encryption();
+            if !features.encryption_methods.contains(&encryption) {
+                features.encryption_methods.push(encryption);
+            }
+        }
+
+        features
     }
 
-    pub fn print_eof(self){
+    #[cfg(test)]
+    pub(crate) fn print_eof(self){
         println!("EofRecord: {:#?}", self.eof_record);
     }
 
-    pub fn test_cdr_read(self){
-        let start_offset = self.eof_record.static_data.offset_cdr_start;
+    #[cfg(test)]
+    pub(crate) fn test_cdr_read(self){
+        let start_offset = self.eof_record.as_ref().expect("No EOF record").static_data.offset_cdr_start;
 
         let path = Path::new(self.filename);
-        let mut file = match File::open(path) {
-            Err(why) => panic!("Couldn't open {}: {}", path.display(), why.to_string()),
+        let mut file = match open_reader(path, &ReaderOptions::default()) {
+            Err(why) => panic!("Couldn't open {}: {}", path.display(), why),
             Ok(file) => file
         };
 
-        let mut x = CDFHR::new();
-        let mut y = CDFHR::new();
-        let _done = x.load_data(&mut file, start_offset as u64);
-        let _done2 = y.load_data(&mut file, _done as u64);
+        let mut x = Cdfhr::new();
+        let mut y = Cdfhr::new();
+        let _done = x.load_data(&mut file, start_offset as u64).expect("Couldn't read first CDFHR");
+        let _done2 = y.load_data(&mut file, _done).expect("Couldn't read second CDFHR");
         println!("Data1: {:#?}", x);
         let filename1 = std::str::from_utf8(&x.file_name_data).expect("Couldn't convert bytes to utf8");
         println!("Data1 file: {}", filename1);
@@ -481,7 +1234,38 @@ impl ZipArchive<'_> {
         println!("Data2 file: {}", filename2);
     }
 
-    pub fn print_all_data(self){
+    #[cfg(test)]
+    pub(crate) fn print_all_data(self){
         println!("Data: {:#?}", self);
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Write};
+    use super::*;
+    use crate::zipwriter::{FileOptions, ZipWriter};
+
+    #[test]
+    fn entries_starting_inside_another_are_refused() {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, size) in [("first", 1000), ("second", 10), ("third", 10)] {
+            writer.start_file(name, FileOptions::default()).unwrap();
+            writer.write_all(&vec![b'x'; size]).unwrap();
+        }
+        let mut data = writer.finish().unwrap().into_inner();
+        let archive = ZipArchive::parse_bytes(&data).unwrap();
+        let first_header = archive.entries()[0].header_offset() as u32;
+        let second_record = archive.entries()[1].record.start_offset as usize;
+
+        // Point second's central record at first's local header, the way a non-recursive zip bomb reuses one entry's data
+        data[second_record + 42..second_record + 46].copy_from_slice(&first_header.to_le_bytes());
+        let archive = ZipArchive::parse_bytes(&data).unwrap();
+
+        let names: Vec<String> = archive.entries().iter().map(|entry| entry.name()).collect();
+        assert_eq!(names, ["first", "third"]);
+        let [broken] = archive.broken_entries() else { panic!("expected one broken entry") };
+        assert_eq!((broken.name.as_deref(), broken.offset, broken.index), (Some("second"), first_header as u64, 1));
+        assert!(matches!(broken.error.root(), ZipError::InvalidArchive(why) if why.contains("Overlaps first")));
+    }
+}
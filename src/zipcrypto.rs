@@ -0,0 +1,129 @@
+#![allow(dead_code)]
+// Traditional PKWARE encryption, APPNOTE section 6.1
+// https://pkware.cachefly.net/webdocs/casestudies/APPNOTE.TXT
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::SystemTime;
+use crate::crc32;
+
+/// Every ZipCrypto entry starts with 12 bytes of encrypted header before the real data
+pub const HEADER_LEN: usize = 12;
+
+#[derive(Debug, Clone, Copy)]
+pub struct ZipCryptoKeys {
+    key0: u32,
+    key1: u32,
+    key2: u32
+}
+
+impl ZipCryptoKeys {
+    pub fn new(password: &[u8]) -> ZipCryptoKeys {
+        let mut keys = ZipCryptoKeys {
+            key0: 0x12345678,
+            key1: 0x23456789,
+            key2: 0x34567890
+        };
+        for byte in password {
+            keys.update(*byte);
+        }
+        keys
+    }
+
+    fn update(&mut self, byte: u8) {
+        self.key0 = crc32::update_byte(self.key0, byte);
+        self.key1 = self.key1.wrapping_add(self.key0 & 0xFF).wrapping_mul(134775813).wrapping_add(1);
+        self.key2 = crc32::update_byte(self.key2, (self.key1 >> 24) as u8);
+    }
+
+    fn stream_byte(&self) -> u8 {
+        let temp = (self.key2 | 2) & 0xFFFF;
+        (temp.wrapping_mul(temp ^ 1) >> 8) as u8
+    }
+
+    pub fn decrypt_byte(&mut self, byte: u8) -> u8 {
+        let plain = byte ^ self.stream_byte();
+        self.update(plain);
+        plain
+    }
+
+    pub fn encrypt_byte(&mut self, byte: u8) -> u8 {
+        let cipher = byte ^ self.stream_byte();
+        self.update(byte);
+        cipher
+    }
+
+    pub fn decrypt(&mut self, data: &mut [u8]) {
+        for byte in data.iter_mut() {
+            *byte = self.decrypt_byte(*byte);
+        }
+    }
+}
+
+/// Decrypts an entry's raw data with the given password.
+/// `check_byte` is the high byte of the crc (or of the mod time when a data descriptor is used), which the last header byte must match.
+/// Returns None if the password is wrong.
+pub fn decrypt_entry(raw: &[u8], password: &[u8], check_byte: u8) -> Option<Vec<u8>> {
+    if raw.len() < HEADER_LEN {
+        return None;
+    }
+
+    let mut keys = ZipCryptoKeys::new(password);
+    let mut header = [0u8; HEADER_LEN];
+    header.copy_from_slice(&raw[..HEADER_LEN]);
+    keys.decrypt(&mut header);
+
+    if header[HEADER_LEN - 1] != check_byte {
+        return None;
+    }
+
+    let mut data = raw[HEADER_LEN..].to_vec();
+    keys.decrypt(&mut data);
+    Some(data)
+}
+
+/// Encrypts data (already compressed) for an entry, the reverse of decrypt_entry.
+/// The header is 11 random bytes followed by check_byte. They come from std's hasher keys, which are seeded from the
+/// OS's random source: all ZipCrypto asks of them is not to repeat.
+pub fn encrypt_entry(data: &[u8], password: &[u8], check_byte: u8) -> Vec<u8> {
+    let mut header = [0u8; HEADER_LEN];
+    for chunk in header[..HEADER_LEN - 1].chunks_mut(8) {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u128(SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).map(|since| since.as_nanos()).unwrap_or(0));
+        chunk.copy_from_slice(&hasher.finish().to_le_bytes()[..chunk.len()]);
+    }
+    header[HEADER_LEN - 1] = check_byte;
+
+    let mut keys = ZipCryptoKeys::new(password);
+    header.iter().chain(data).map(|byte| keys.encrypt_byte(*byte)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// "attack at dawn\n" stored by `zip -0 -P hunter2`. It wrote a data descriptor, so the check byte is the high
+    /// byte of the mod time, 0x0C85.
+    const FROM_INFO_ZIP: &[u8] = &[
+        0x93, 0x5f, 0xd7, 0x28, 0x4d, 0x5e, 0xf4, 0xfd, 0xce, 0xc7, 0x86, 0x59,
+        0x9a, 0x1e, 0x7d, 0xc6, 0x05, 0xe8, 0x04, 0x18, 0xb9, 0xae, 0x3c, 0x1a,
+        0x5b, 0xc9, 0x2a
+    ];
+
+    #[test]
+    fn decrypts_info_zips_output() {
+        assert_eq!(decrypt_entry(FROM_INFO_ZIP, b"hunter2", 0x0C).unwrap(), b"attack at dawn\n");
+        assert_eq!(decrypt_entry(FROM_INFO_ZIP, b"hunter3", 0x0C), None);
+        assert_eq!(decrypt_entry(&FROM_INFO_ZIP[..HEADER_LEN - 1], b"hunter2", 0x0C), None);
+    }
+
+    #[test]
+    fn round_trip() {
+        let data = b"compressed or not, it's all bytes";
+        let encrypted = encrypt_entry(data, b"secret", 0xA5);
+        assert_eq!(encrypted.len(), HEADER_LEN + data.len());
+        assert_ne!(&encrypted[HEADER_LEN..], &data[..]);
+        assert_eq!(decrypt_entry(&encrypted, b"secret", 0xA5).unwrap(), data);
+        // A fresh header every time
+        assert_ne!(encrypt_entry(data, b"secret", 0xA5)[..HEADER_LEN], encrypted[..HEADER_LEN]);
+    }
+}
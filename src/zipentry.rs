@@ -0,0 +1,539 @@
+#![allow(dead_code)]
+use std::ffi::OsString;
+use std::fmt;
+use std::fmt::Write;
+use std::ops::Range;
+use crate::extrafield;
+use crate::attributes::{self, HostSystem, DosAttributes};
+use crate::zipcrypto;
+use crate::crc32;
+use crate::datetime::DateTime;
+use crate::version::MinimumVersion;
+use crate::password::{PasswordProvider, ArchivePassword};
+use crate::ziparchive::{Cdfhr, LocalFile};
+use crate::ziperror::{ZipError, ZipResult, Unsupported, UnsupportedEntry};
+
+// General purpose bit flags: https://pkware.cachefly.net/webdocs/casestudies/APPNOTE.TXT (4.4.4)
+pub const FLAG_ENCRYPTED: u16 = 1;
+pub const FLAG_DATA_DESCRIPTOR: u16 = 1 << 3;
+pub const FLAG_STRONG_ENCRYPTION: u16 = 1 << 6;
+pub const FLAG_UTF8: u16 = 1 << 11;
+/// Set when the central directory is encrypted: the local header's name, crc and sizes are masked out
+pub const FLAG_MASKED_HEADER: u16 = 1 << 13;
+
+/// A name with every byte that isn't part of printable UTF-8 (bad sequences, control characters) written as \xNN
+/// and backslashes doubled, so any name shows on one line and unescape_name gets the exact bytes back
+pub fn escape_name(name: &[u8]) -> String {
+    let mut escaped = String::with_capacity(name.len());
+    for chunk in name.utf8_chunks() {
+        for character in chunk.valid().chars() {
+            match character {
+                '\\' => escaped.push_str("\\\\"),
+                control if control.is_control() => {
+                    let mut encoded = [0; 4];
+                    for byte in control.encode_utf8(&mut encoded).bytes() {
+                        let _ = write!(escaped, "\\x{:02x}", byte);
+                    }
+                },
+                printable => escaped.push(printable)
+            }
+        }
+        for byte in chunk.invalid() {
+            let _ = write!(escaped, "\\x{:02x}", byte);
+        }
+    }
+    escaped
+}
+
+/// The bytes of a name escaped by escape_name, None if it isn't a valid escaping
+pub fn unescape_name(escaped: &str) -> Option<Vec<u8>> {
+    let mut name = Vec::with_capacity(escaped.len());
+    let mut rest = escaped;
+    while let Some(backslash) = rest.find('\\') {
+        name.extend_from_slice(&rest.as_bytes()[..backslash]);
+        rest = &rest[backslash + 1..];
+        if let Some(after) = rest.strip_prefix('\\') {
+            name.push(b'\\');
+            rest = after;
+        } else {
+            let hex = rest.strip_prefix('x')?.get(..2).filter(|hex| hex.bytes().all(|digit| digit.is_ascii_hexdigit()))?;
+            name.push(u8::from_str_radix(hex, 16).ok()?);
+            rest = &rest[3..];
+        }
+    }
+    name.extend_from_slice(rest.as_bytes());
+    Some(name)
+}
+
+/// Compression method 99 isn't a real method, it marks WinZip AES. The real method lives in the AES extra field.
+const METHOD_AES: u16 = 99;
+
+/// How an entry's data is encrypted
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum EncryptionMethod {
+    None,
+    /// Traditional PKWARE encryption
+    ZipCrypto,
+    Aes128,
+    Aes192,
+    Aes256,
+    /// Encrypted, but with something we can't identify (PKWARE strong encryption, unknown AES strength, ...)
+    Unknown
+}
+
+impl fmt::Display for EncryptionMethod {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            EncryptionMethod::None => "none",
+            EncryptionMethod::ZipCrypto => "ZipCrypto",
+            EncryptionMethod::Aes128 => "AES-128",
+            EncryptionMethod::Aes192 => "AES-192",
+            EncryptionMethod::Aes256 => "AES-256",
+            EncryptionMethod::Unknown => "unknown encryption"
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// The compression_method header field, decoded
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum CompressionMethod {
+    Stored,
+    Shrunk,
+    Imploded,
+    Deflated,
+    Deflate64,
+    Bzip2,
+    Lzma,
+    Zstd,
+    Xz,
+    Ppmd,
+    Unknown(u16)
+}
+
+impl CompressionMethod {
+    pub fn from_u16(method: u16) -> CompressionMethod {
+        match method {
+            0 => CompressionMethod::Stored,
+            1 => CompressionMethod::Shrunk,
+            6 => CompressionMethod::Imploded,
+            8 => CompressionMethod::Deflated,
+            9 => CompressionMethod::Deflate64,
+            12 => CompressionMethod::Bzip2,
+            14 => CompressionMethod::Lzma,
+            93 => CompressionMethod::Zstd,
+            95 => CompressionMethod::Xz,
+            98 => CompressionMethod::Ppmd,
+            other => CompressionMethod::Unknown(other)
+        }
+    }
+
+    pub fn to_u16(self) -> u16 {
+        match self {
+            CompressionMethod::Stored => 0,
+            CompressionMethod::Shrunk => 1,
+            CompressionMethod::Imploded => 6,
+            CompressionMethod::Deflated => 8,
+            CompressionMethod::Deflate64 => 9,
+            CompressionMethod::Bzip2 => 12,
+            CompressionMethod::Lzma => 14,
+            CompressionMethod::Zstd => 93,
+            CompressionMethod::Xz => 95,
+            CompressionMethod::Ppmd => 98,
+            CompressionMethod::Unknown(other) => other
+        }
+    }
+
+    /// Whether we can get the uncompressed bytes out of an entry using this method.
+    /// Only stored for now, the huffman module isn't a full DEFLATE implementation yet.
+    pub fn is_supported(self) -> bool {
+        self == CompressionMethod::Stored
+    }
+}
+
+impl fmt::Display for CompressionMethod {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CompressionMethod::Unknown(method) => write!(f, "unknown method {}", method),
+            known => write!(f, "{:?}", known)
+        }
+    }
+}
+
+/// A single file in a ZipArchive, pairing its central directory record with its local header and data
+#[derive(Debug, Clone)]
+pub struct ZipEntry {
+    pub(crate) record: Cdfhr,
+    pub(crate) local: LocalFile
+}
+
+impl ZipEntry {
+    pub(crate) fn new(record: Cdfhr, local: LocalFile) -> ZipEntry {
+        ZipEntry { record, local }
+    }
+
+    /// The file name, with any invalid UTF-8 replaced
+    pub fn name(&self) -> String {
+        String::from_utf8_lossy(&self.record.file_name_data).into_owned()
+    }
+
+    /// The file name exactly as stored in the central directory
+    pub fn name_raw(&self) -> &[u8] {
+        &self.record.file_name_data
+    }
+
+    /// The file name with undecodable bytes and control characters escaped, see escape_name
+    pub fn name_escaped(&self) -> String {
+        escape_name(&self.record.file_name_data)
+    }
+
+    /// The file name as the OS takes it. On Unix that's the stored bytes whatever they are, elsewhere names have to
+    /// be Unicode and invalid UTF-8 is replaced as in name().
+    pub fn name_os(&self) -> OsString {
+        #[cfg(unix)]
+        {
+            use std::os::unix::ffi::OsStringExt;
+            OsString::from_vec(self.record.file_name_data.clone())
+        }
+        #[cfg(not(unix))]
+        {
+            OsString::from(self.name())
+        }
+    }
+
+    pub fn compressed_size(&self) -> u64 {
+        self.record.compressed_size()
+    }
+
+    pub fn uncompressed_size(&self) -> u64 {
+        self.record.uncompressed_size()
+    }
+
+    pub fn crc32(&self) -> u32 {
+        self.record.static_data.crc32_uncompressed
+    }
+
+    /// None if the header holds an impossible date or time
+    pub fn last_modified(&self) -> Option<DateTime> {
+        DateTime::from_dos(self.record.static_data.last_modify_date, self.record.static_data.last_modify_time)
+    }
+
+    /// The system the entry was made on, from the upper byte of version_made_by
+    pub fn made_by_platform(&self) -> HostSystem {
+        HostSystem::from_u8((self.record.static_data.version_made_by >> 8) as u8)
+    }
+
+    /// The raw external_file_attributes field. Its meaning depends on made_by_platform().
+    pub fn external_attributes(&self) -> u32 {
+        self.record.static_data.external_file_attributes
+    }
+
+    /// The Unix st_mode (file type and permission bits), if the entry was made somewhere that stores one
+    pub fn unix_mode(&self) -> Option<u32> {
+        let mode = self.external_attributes() >> 16;
+        if self.made_by_platform().has_unix_mode() && mode != 0 { Some(mode) } else { None }
+    }
+
+    /// The Unix (uid, gid) from Info-ZIP's extra fields, if the producer recorded them
+    pub fn unix_owner(&self) -> Option<(u32, u32)> {
+        let owner = extrafield::find(&self.record.extra_field_data, extrafield::INFOZIP_UNIX_OWNER)
+            .or_else(|| extrafield::find(&self.local.extra_field, extrafield::INFOZIP_UNIX_OWNER))
+            .and_then(extrafield::parse_unix_owner);
+        owner.or_else(|| {
+            let old = extrafield::find(&self.local.extra_field, extrafield::INFOZIP_UNIX_OWNER_OLD)?;
+            let id = |at: usize| old.get(at..at + 2).map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]) as u32);
+            Some((id(0)?, id(2)?))
+        })
+    }
+
+    /// The DOS attribute byte, if the entry was made somewhere that stores one
+    pub(crate) fn dos_attribute_bits(&self) -> Option<u8> {
+        if self.made_by_platform().has_dos_attributes() { Some(self.external_attributes() as u8) } else { None }
+    }
+
+    /// Read-only/hidden/system/archive bits, for entries made on a system that records them
+    pub fn dos_attributes(&self) -> Option<DosAttributes> {
+        self.dos_attribute_bits().map(DosAttributes::from_bits)
+    }
+
+    /// True when the local header's values are masked because the central directory is encrypted
+    pub fn has_masked_header(&self) -> bool {
+        self.local.static_data.general_purpose_flag & FLAG_MASKED_HEADER != 0
+    }
+
+    /// Whether the producer flagged the entry as text in internal_file_attributes. It's only a hint, often a guess.
+    pub fn is_text(&self) -> bool {
+        self.record.static_data.internal_file_attributes & attributes::INTERNAL_TEXT != 0
+    }
+
+    pub fn is_dir(&self) -> bool {
+        if self.name_raw().ends_with(b"/") {
+            return true;
+        }
+        if let Some(mode) = self.unix_mode() {
+            return mode & attributes::UNIX_FILE_TYPE_MASK == attributes::UNIX_DIRECTORY;
+        }
+        self.dos_attribute_bits().is_some_and(|bits| bits & attributes::DOS_DIRECTORY != 0)
+    }
+
+    pub fn is_symlink(&self) -> bool {
+        self.unix_mode().is_some_and(|mode| mode & attributes::UNIX_FILE_TYPE_MASK == attributes::UNIX_SYMLINK)
+    }
+
+    /// Read-only from the Unix write bits when there's a mode, otherwise from the DOS read-only bit
+    pub fn is_read_only(&self) -> bool {
+        if let Some(mode) = self.unix_mode() {
+            return mode & 0o222 == 0;
+        }
+        self.dos_attribute_bits().is_some_and(|bits| bits & attributes::DOS_READ_ONLY != 0)
+    }
+
+    pub(crate) fn flags(&self) -> u16 {
+        self.record.static_data.general_purpose_flag
+    }
+
+    /// The compression method used on the data. For AES entries this is the method recorded in the AES extra field.
+    pub fn compression_method(&self) -> CompressionMethod {
+        let method = self.record.static_data.compression_method;
+        if method == METHOD_AES {
+            if let Some(aes) = self.aes_extra_field() {
+                if aes.len() >= 7 {
+                    return CompressionMethod::from_u16(u16::from_le_bytes([aes[5], aes[6]]));
+                }
+            }
+        }
+        CompressionMethod::from_u16(method)
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        self.flags() & FLAG_ENCRYPTED != 0
+    }
+
+    /// Works out how the entry is encrypted from the general purpose flags and the AES extra field
+    pub fn encryption(&self) -> EncryptionMethod {
+        if !self.is_encrypted() {
+            return EncryptionMethod::None;
+        }
+
+        if self.record.static_data.compression_method == METHOD_AES {
+            // AES extra field: version (2), vendor id "AE" (2), strength (1), actual compression method (2)
+            return match self.aes_extra_field().and_then(|aes| aes.get(4)) {
+                Some(1) => EncryptionMethod::Aes128,
+                Some(2) => EncryptionMethod::Aes192,
+                Some(3) => EncryptionMethod::Aes256,
+                _ => EncryptionMethod::Unknown
+            };
+        }
+
+        if self.flags() & FLAG_STRONG_ENCRYPTION != 0 {
+            return EncryptionMethod::Unknown;
+        }
+
+        EncryptionMethod::ZipCrypto
+    }
+
+    /// The spec version a reader needs to extract this entry, as claimed by the central directory
+    pub fn minimum_version(&self) -> MinimumVersion {
+        MinimumVersion::from_u16(self.record.static_data.version_needed)
+    }
+
+    /// True if any of the 32 bit size/offset fields overflowed, or a ZIP64 extra field is present
+    pub fn is_zip64(&self) -> bool {
+        let data = &self.record.static_data;
+        data.compressed_size == u32::MAX
+            || data.uncompressed_size == u32::MAX
+            || data.relative_offset_localheader == u32::MAX
+            || extrafield::find(&self.record.extra_field_data, extrafield::ZIP64_EXTENDED_INFO).is_some()
+    }
+
+    fn aes_extra_field(&self) -> Option<&[u8]> {
+        extrafield::find(&self.record.extra_field_data, extrafield::AES_EXTRA_DATA)
+            .or_else(|| extrafield::find(&self.local.extra_field, extrafield::AES_EXTRA_DATA))
+    }
+
+    /// The entry's data exactly as stored in the archive (still compressed and/or encrypted)
+    pub fn raw_data(&self) -> &[u8] {
+        &self.local.compressed_data
+    }
+
+    /// Offset of the entry's local header in the archive. It identifies the entry for as long as the archive isn't
+    /// rewritten, see ZipArchive::by_offset.
+    pub fn header_offset(&self) -> u64 {
+        self.record.local_header_offset()
+    }
+
+    /// Where the entry's data (after the local header, still compressed/encrypted) sits in the archive file,
+    /// for callers that want to mmap it or hand the range to sendfile. Fails if the data runs past the end of the file.
+    pub fn data_range(&self) -> ZipResult<Range<u64>> {
+        if self.is_truncated() {
+            return Err(self.truncated_error());
+        }
+        let start = self.local.data_start_offset;
+        Ok(start..start + self.compressed_size())
+    }
+
+    /// How many bytes of this entry's data are past the end of the file
+    pub fn missing_bytes(&self) -> u64 {
+        self.local.missing_bytes
+    }
+
+    pub fn is_truncated(&self) -> bool {
+        self.local.missing_bytes > 0
+    }
+
+    /// The part of a truncated entry that made it into the file.
+    /// Only possible for unencrypted stored entries, anything else needs the whole stream.
+    pub fn read_available(&self) -> ZipResult<Vec<u8>> {
+        if self.is_encrypted() || self.compression_method() != CompressionMethod::Stored {
+            return Err(self.truncated_error());
+        }
+        Ok(self.raw_data().to_vec())
+    }
+
+    pub(crate) fn truncated_error(&self) -> ZipError {
+        ZipError::Truncated { name: self.name(), missing_bytes: self.missing_bytes() }
+    }
+
+    /// Reads and decompresses an unencrypted entry
+    pub fn read(&self) -> ZipResult<Vec<u8>> {
+        if self.is_truncated() {
+            return Err(self.truncated_error());
+        }
+        if self.is_encrypted() {
+            return Err(ZipError::PasswordRequired(self.name()));
+        }
+        self.decompress(self.raw_data().to_vec())
+    }
+
+    /// Reads an entry that may be encrypted, using a single password
+    pub fn read_with_password(&self, password: &[u8]) -> ZipResult<Vec<u8>> {
+        self.read_with_provider(&mut ArchivePassword(password.to_vec()))
+    }
+
+    /// Reads an entry that may be encrypted, asking the provider for passwords until one works or it gives up
+    pub fn read_with_provider(&self, provider: &mut dyn PasswordProvider) -> ZipResult<Vec<u8>> {
+        if !self.is_encrypted() {
+            return self.read();
+        }
+
+        if self.is_truncated() {
+            return Err(self.truncated_error());
+        }
+
+        let encryption = self.encryption();
+        if encryption != EncryptionMethod::ZipCrypto {
+            return Err(self.unsupported(Unsupported::Encryption(encryption), self.raw_data().to_vec()));
+        }
+
+        let name = self.name();
+        let mut attempt = 0;
+        while let Some(password) = provider.password(&name, attempt) {
+            // The check byte lets about 1 wrong password in 256 through, so a crc mismatch rejects it too
+            if let Some(data) = zipcrypto::decrypt_entry(self.raw_data(), &password, self.zipcrypto_check_byte()) {
+                match self.decompress(data) {
+                    Err(ZipError::CrcMismatch { .. }) => {},
+                    result => return result
+                }
+            }
+            attempt += 1;
+        }
+
+        if attempt == 0 {
+            Err(ZipError::PasswordRequired(name))
+        } else {
+            Err(ZipError::InvalidPassword(name))
+        }
+    }
+
+    /// The entry's data with the ZipCrypto layer taken off, but still compressed, for handing to a writer as is.
+    /// Fails with InvalidPassword for the wrong password, and for AES or other encryption we can't undo.
+    pub fn decrypt_raw(&self, password: &[u8]) -> ZipResult<Vec<u8>> {
+        if self.is_truncated() {
+            return Err(self.truncated_error());
+        }
+        match self.encryption() {
+            EncryptionMethod::None => Ok(self.raw_data().to_vec()),
+            EncryptionMethod::ZipCrypto => zipcrypto::decrypt_entry(self.raw_data(), password, self.zipcrypto_check_byte())
+                .ok_or_else(|| ZipError::InvalidPassword(self.name())),
+            encryption => Err(self.unsupported(Unsupported::Encryption(encryption), self.raw_data().to_vec()))
+        }
+    }
+
+    /// The last byte of the ZipCrypto header is checked against the high byte of the crc,
+    /// or of the mod time when the crc isn't known until the data descriptor
+    pub(crate) fn zipcrypto_check_byte(&self) -> u8 {
+        if self.flags() & FLAG_DATA_DESCRIPTOR != 0 {
+            (self.local.static_data.last_modify_time >> 8) as u8
+        } else {
+            (self.crc32() >> 24) as u8
+        }
+    }
+
+    fn decompress(&self, data: Vec<u8>) -> ZipResult<Vec<u8>> {
+        let data = match self.compression_method() {
+            CompressionMethod::Stored => data,
+            method => return Err(self.unsupported(Unsupported::Compression(method), data))
+        };
+
+        self.check_crc(&data)?;
+        Ok(data)
+    }
+
+    pub(crate) fn check_crc(&self, data: &[u8]) -> ZipResult<()> {
+        let actual = crc32::checksum(data);
+        if actual != self.crc32() {
+            return Err(ZipError::CrcMismatch { name: self.name(), expected: self.crc32(), actual });
+        }
+        Ok(())
+    }
+
+    pub(crate) fn unsupported(&self, reason: Unsupported, data: Vec<u8>) -> ZipError {
+        ZipError::UnsupportedMethod(Box::new(UnsupportedEntry::new(reason, self, data)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ziparchive::ZipArchive;
+
+    const CONTENTS: &[u8] = b"the contents behind the password";
+
+    /// secret.txt holding CONTENTS, stored by `zip -X -0 -P right`
+    fn encrypted_archive() -> ZipArchive<'static> {
+        ZipArchive::new("resources/zipcrypto.zip")
+    }
+
+    /// A wrong password that still gets past the check byte
+    fn false_positive(entry: &ZipEntry) -> Vec<u8> {
+        (0..).map(|i| format!("wrong {}", i).into_bytes())
+            .find(|password| zipcrypto::decrypt_entry(entry.raw_data(), password, entry.zipcrypto_check_byte()).is_some())
+            .unwrap()
+    }
+
+    #[test]
+    fn provider_moves_on_after_a_false_positive_password() {
+        let archive = encrypted_archive();
+        let entry = &archive.entries()[0];
+        let candidates = [false_positive(entry), b"right".to_vec()];
+        let mut provider = |_: &str, attempt: u32| candidates.get(attempt as usize).cloned();
+        assert_eq!(entry.read_with_provider(&mut provider).unwrap(), CONTENTS);
+    }
+
+    #[test]
+    fn false_positive_password_alone_is_invalid() {
+        let archive = encrypted_archive();
+        let entry = &archive.entries()[0];
+        let wrong = false_positive(entry);
+        assert!(matches!(entry.read_with_password(&wrong), Err(ZipError::InvalidPassword(_))));
+    }
+
+    #[test]
+    fn no_password_is_required() {
+        let archive = encrypted_archive();
+        let entry = &archive.entries()[0];
+        assert!(matches!(entry.read(), Err(ZipError::PasswordRequired(_))));
+        let mut provider = |_: &str, _: u32| None;
+        assert!(matches!(entry.read_with_provider(&mut provider), Err(ZipError::PasswordRequired(_))));
+    }
+}
@@ -0,0 +1,220 @@
+#![allow(dead_code)]
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::time::Duration;
+use crate::zipentry::{ZipEntry, CompressionMethod, EncryptionMethod};
+
+#[derive(Debug)]
+pub enum ZipError {
+    Io(io::Error),
+    /// A structure in the archive is malformed
+    InvalidArchive(String),
+    /// The uncompressed data didn't match the crc stored for the entry
+    CrcMismatch { name: String, expected: u32, actual: u32 },
+    /// The file ends part way through the entry's data
+    Truncated { name: String, missing_bytes: u64 },
+    /// The entry's name would land outside the extraction directory
+    UnsafePath(String),
+    /// No entry with this name in the archive
+    FileNotFound(String),
+    /// The entry is encrypted and no password was given for it
+    PasswordRequired(String),
+    /// Every password offered for the entry was rejected
+    InvalidPassword(String),
+    /// The entry uses a method we don't implement. Still carries the entry and its bytes so they can be handed to another tool.
+    UnsupportedMethod(Box<UnsupportedEntry>),
+    /// The central directory is encrypted with PKWARE strong encryption (local headers masked by flag bit 13),
+    /// which we can't decrypt, so there's no way to list the entries
+    EncryptedCentralDirectory,
+    /// Reading name would take the total past a caller's size cap
+    SizeLimitExceeded { name: String, limit: u64 },
+    /// Something in a non-zip container (a cabinet's compression, say) that we don't implement, described
+    UnsupportedFeature(String),
+    /// Two entries would be written to the same file on a case-insensitive filesystem
+    CaseCollision { name: String, other: String },
+    /// The extraction needs more room than the destination has
+    InsufficientSpace { needed: u64, available: u64 },
+    /// Extracting the entry took longer than the limit on any one entry, so it was abandoned
+    TimedOut { name: String, limit: Duration },
+    /// Where in the archive another error happened: the entry (when it's known), the structure being read, and
+    /// the absolute offset of that structure
+    Context { entry: Option<String>, structure: Structure, offset: u64, source: Box<ZipError> }
+}
+
+/// The part of an archive being read when an error happened
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Structure {
+    EndOfCentralDirectory,
+    CentralDirectoryHeader,
+    LocalHeader,
+    Data,
+    DataDescriptor
+}
+
+impl fmt::Display for Structure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Structure::EndOfCentralDirectory => write!(f, "end of central directory record"),
+            Structure::CentralDirectoryHeader => write!(f, "central directory header"),
+            Structure::LocalHeader => write!(f, "local header"),
+            Structure::Data => write!(f, "data"),
+            Structure::DataDescriptor => write!(f, "data descriptor")
+        }
+    }
+}
+
+/// Broad categories of ZipError to match on, rather than the variants (which gain fields and grow in number) or the
+/// messages. The numbers are fixed for anything outside Rust that needs them: new kinds get new numbers, existing
+/// ones never change or get reused.
+#[non_exhaustive]
+#[repr(u32)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum ErrorKind {
+    /// Reading or writing failed for reasons outside the archive
+    Io = 1,
+    /// The archive is malformed, damaged or cut short
+    Corrupt = 2,
+    /// No entry by that name
+    NotFound = 3,
+    /// An encrypted entry and no password for it
+    PasswordRequired = 4,
+    /// None of the passwords given were right
+    BadPassword = 5,
+    /// A compression method, encryption scheme or format feature we don't implement
+    Unsupported = 6,
+    /// A size cap, a time limit or the space on disk would be exceeded
+    Limit = 7,
+    /// An entry would be written outside the destination
+    UnsafePath = 8,
+    /// Entries that would be written over each other
+    Conflict = 9
+}
+
+impl ZipError {
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            // Running out of archive part way through a structure means the archive is short, not that IO failed
+            ZipError::Io(why) if why.kind() == io::ErrorKind::UnexpectedEof => ErrorKind::Corrupt,
+            ZipError::Io(_) => ErrorKind::Io,
+            ZipError::InvalidArchive(_) | ZipError::CrcMismatch { .. } | ZipError::Truncated { .. } => ErrorKind::Corrupt,
+            ZipError::UnsafePath(_) => ErrorKind::UnsafePath,
+            ZipError::FileNotFound(_) => ErrorKind::NotFound,
+            ZipError::PasswordRequired(_) => ErrorKind::PasswordRequired,
+            ZipError::InvalidPassword(_) => ErrorKind::BadPassword,
+            ZipError::UnsupportedMethod(_) | ZipError::EncryptedCentralDirectory | ZipError::UnsupportedFeature(_) => ErrorKind::Unsupported,
+            ZipError::SizeLimitExceeded { .. } | ZipError::InsufficientSpace { .. } | ZipError::TimedOut { .. } => ErrorKind::Limit,
+            ZipError::CaseCollision { .. } => ErrorKind::Conflict,
+            ZipError::Context { source, .. } => source.kind()
+        }
+    }
+
+    /// The error underneath any context
+    pub fn root(&self) -> &ZipError {
+        match self {
+            ZipError::Context { source, .. } => source.root(),
+            error => error
+        }
+    }
+}
+
+/// Attaches where an error happened, unless it already says
+pub(crate) trait Context<T> {
+    fn context(self, entry: Option<&str>, structure: Structure, offset: u64) -> ZipResult<T>;
+}
+
+impl<T, E: Into<ZipError>> Context<T> for Result<T, E> {
+    fn context(self, entry: Option<&str>, structure: Structure, offset: u64) -> ZipResult<T> {
+        self.map_err(|error| match error.into() {
+            error @ ZipError::Context { .. } => error,
+            error => ZipError::Context { entry: entry.map(str::to_string), structure, offset, source: Box::new(error) }
+        })
+    }
+}
+
+/// Which part of an entry we couldn't handle
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Unsupported {
+    Compression(CompressionMethod),
+    Encryption(EncryptionMethod)
+}
+
+impl fmt::Display for Unsupported {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Unsupported::Compression(method) => write!(f, "compression method {} is not supported", method),
+            Unsupported::Encryption(method) => write!(f, "{} encryption is not supported", method)
+        }
+    }
+}
+
+/// An entry we stopped reading part way through, with everything needed to finish the job elsewhere
+#[derive(Debug, Clone)]
+pub struct UnsupportedEntry {
+    pub reason: Unsupported,
+    entry: ZipEntry,
+    data: Vec<u8>
+}
+
+impl UnsupportedEntry {
+    pub(crate) fn new(reason: Unsupported, entry: &ZipEntry, data: Vec<u8>) -> UnsupportedEntry {
+        UnsupportedEntry { reason, entry: entry.clone(), data }
+    }
+
+    /// The entry's metadata (name, sizes, crc, methods, raw stored bytes)
+    pub fn entry(&self) -> &ZipEntry {
+        &self.entry
+    }
+
+    /// The data as far as we got with it. Decrypted if we managed that, but still in the unsupported format.
+    /// Identical to entry().raw_data() when nothing could be applied.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn into_data(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+pub type ZipResult<T> = Result<T, ZipError>;
+
+impl fmt::Display for ZipError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ZipError::Io(why) => write!(f, "IO error: {}", why),
+            ZipError::InvalidArchive(why) => write!(f, "{}", why),
+            ZipError::CrcMismatch { name, expected, actual } => write!(f, "CRC mismatch for {}: expected {:08x}, got {:08x}", name, expected, actual),
+            ZipError::Truncated { name, missing_bytes } => write!(f, "{} is truncated, {} bytes missing", name, missing_bytes),
+            ZipError::UnsafePath(name) => write!(f, "Refusing to extract {} outside the destination", name),
+            ZipError::FileNotFound(name) => write!(f, "No entry named {} in archive", name),
+            ZipError::PasswordRequired(name) => write!(f, "{} is encrypted and needs a password", name),
+            ZipError::InvalidPassword(name) => write!(f, "Wrong password for {}", name),
+            ZipError::UnsupportedMethod(unsupported) => write!(f, "Can't read {}: {}", unsupported.entry().name(), unsupported.reason),
+            ZipError::EncryptedCentralDirectory => write!(f, "The central directory is encrypted (PKWARE strong encryption), which is not supported"),
+            ZipError::SizeLimitExceeded { name, limit } => write!(f, "Reading {} would go over the {} byte limit", name, limit),
+            ZipError::UnsupportedFeature(why) => write!(f, "{}", why),
+            ZipError::CaseCollision { name, other } => write!(f, "{} and {} differ only in case and would overwrite each other", name, other),
+            ZipError::InsufficientSpace { needed, available } => write!(f, "Extracting needs about {} bytes but only {} are free", needed, available),
+            ZipError::TimedOut { name, limit } => write!(f, "Gave up on {} after {:?}, the limit for one entry", name, limit),
+            ZipError::Context { entry: Some(entry), structure, offset, source } => write!(f, "{} (in the {} of {} at offset {:#X})", source, structure, entry, offset),
+            ZipError::Context { entry: None, structure, offset, source } => write!(f, "{} (in the {} at offset {:#X})", source, structure, offset)
+        }
+    }
+}
+
+impl Error for ZipError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ZipError::Io(why) => Some(why),
+            ZipError::Context { source, .. } => Some(source.as_ref()),
+            _ => None
+        }
+    }
+}
+
+impl From<io::Error> for ZipError {
+    fn from(why: io::Error) -> ZipError {
+        ZipError::Io(why)
+    }
+}
@@ -0,0 +1,1156 @@
+#![allow(dead_code)]
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::io::{BufWriter, Read, Seek, SeekFrom, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread;
+use crate::walk::{self, SkippedLink, WalkEntry, WalkOptions};
+use crate::options::WriterOptions;
+use crate::cp437;
+use crate::entryreader::EntryReader;
+use crate::changelog::{self, ChangeRecord};
+use crate::spill::SpillBuffer;
+use crate::ratelimit::Throttled;
+use crate::metrics::Metered;
+use std::time::Instant;
+use crate::datetime::DateTime;
+use crate::extrafield;
+use crate::version;
+use crate::attributes::{self, DosAttributes};
+use crate::options::ReaderOptions;
+use crate::ziparchive::{self, ZipArchive, LocalFileHeader, CentralDirectoryFileHeader, EndOfCentralDirectoryRecord, Cdfhr};
+use crate::zipcrypto;
+use crate::zipentry::{ZipEntry, CompressionMethod, EncryptionMethod, FLAG_ENCRYPTED, FLAG_UTF8, FLAG_DATA_DESCRIPTOR};
+use crate::ziperror::{Context, Structure, ZipError, ZipResult};
+
+/// Files add_walked reads ahead of the one being written. The reading stops to wait once this many are queued,
+/// so memory stays bounded however fast the walk is.
+const READ_AHEAD: usize = 4;
+
+/// Something add_walked couldn't add. The archive carries on without it.
+#[derive(Debug)]
+pub struct AddFailure {
+    /// None when the walk itself failed and there was no path to report
+    pub path: Option<PathBuf>,
+    pub error: io::Error
+}
+
+/// Upper byte is the host system (0 = MS-DOS), lower byte the spec version we follow (6.3)
+const VERSION_MADE_BY: u16 = 63;
+/// Same, but claiming Unix so readers look for a st_mode in the external attributes
+const VERSION_MADE_BY_UNIX: u16 = (3 << 8) | 63;
+
+/// Marks a 32 bit size or offset field whose real value is in the ZIP64 extra field
+const ZIP64_OVERFLOW: u32 = 0xFFFFFFFF;
+const ZIP64_EOCD_SIGNATURE: u32 = 0x06064b50;
+const ZIP64_LOCATOR_SIGNATURE: u32 = 0x07064b50;
+const DATA_DESCRIPTOR_SIGNATURE: u32 = 0x08074b50;
+
+/// How entry names are written when copying entries over from another archive
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum NameEncoding {
+    /// Keep the name bytes and the UTF-8 flag exactly as they were
+    #[default]
+    Preserve,
+    /// Rewrite names that aren't flagged as UTF-8 into UTF-8 and set the flag.
+    /// Names that aren't valid UTF-8 already are read as CP437, which is what the spec says they are.
+    TranscodeToUtf8
+}
+
+/// The central directory's ZIP64 record: just the fields that overflowed, in spec order. Empty if nothing did.
+pub(crate) fn central_zip64_record(sizes: Option<(u64, u64)>, offset: Option<u64>) -> Vec<u8> {
+    let mut fields = Vec::new();
+    if let Some((uncompressed, compressed)) = sizes {
+        fields.extend_from_slice(&uncompressed.to_le_bytes());
+        fields.extend_from_slice(&compressed.to_le_bytes());
+    }
+    if let Some(offset) = offset {
+        fields.extend_from_slice(&offset.to_le_bytes());
+    }
+
+    let mut record = Vec::new();
+    if !fields.is_empty() {
+        extrafield::push(&mut record, extrafield::ZIP64_EXTENDED_INFO, &fields);
+    }
+    record
+}
+
+/// Per-file settings for ZipWriter::start_file
+#[derive(Debug, Clone)]
+pub struct FileOptions {
+    /// MS-DOS format time, as stored in the header
+    pub last_modify_time: u16,
+    /// MS-DOS format date, as stored in the header
+    pub last_modify_date: u16,
+    /// Stored in the low byte of the external attributes, and applied by Windows extractors
+    pub dos_attributes: DosAttributes,
+    /// Flags the entry as text in internal_file_attributes, for extractors that convert line endings
+    pub is_text: bool,
+    /// Unix permission bits. Setting them marks the entry as made on Unix so extractors there apply them.
+    pub unix_permissions: Option<u32>,
+    pub comment: String,
+    /// Extra field records to write in both headers, already encoded. The ZIP64 record is managed by the writer.
+    pub extra_data: Vec<u8>,
+    /// Only Stored can be written for now
+    pub compression_method: CompressionMethod,
+    /// Has to be None for now, Stored has no levels
+    pub compression_level: Option<i32>,
+    /// Write ZIP64 sizes for this entry, needed for anything 4 GiB or over. Like the size, this has to be known
+    /// before the local header goes out, so it isn't turned on automatically.
+    pub large_file: bool,
+    /// Pad the local header so the entry's data starts at a multiple of this many bytes, 0 or 1 for no padding
+    pub alignment: u16
+}
+
+impl Default for FileOptions {
+    fn default() -> FileOptions {
+        let (last_modify_date, last_modify_time) = DateTime::default().to_dos();
+        FileOptions {
+            last_modify_time,
+            last_modify_date,
+            dos_attributes: DosAttributes::default(),
+            is_text: false,
+            unix_permissions: None,
+            comment: String::new(),
+            extra_data: Vec::new(),
+            compression_method: CompressionMethod::Stored,
+            compression_level: None,
+            large_file: false,
+            alignment: 0
+        }
+    }
+}
+
+impl FileOptions {
+    pub fn last_modified_time(mut self, time: DateTime) -> FileOptions {
+        let (date, time) = time.to_dos();
+        self.last_modify_date = date;
+        self.last_modify_time = time;
+        self
+    }
+
+    pub fn unix_permissions(mut self, permissions: u32) -> FileOptions {
+        self.unix_permissions = Some(permissions & 0o7777);
+        self
+    }
+
+    pub fn dos_attributes(mut self, dos_attributes: DosAttributes) -> FileOptions {
+        self.dos_attributes = dos_attributes;
+        self
+    }
+
+    pub fn comment(mut self, comment: &str) -> FileOptions {
+        self.comment = comment.to_string();
+        self
+    }
+
+    /// Adds an extra field record with the given header id
+    pub fn add_extra_field(mut self, id: u16, data: &[u8]) -> FileOptions {
+        extrafield::push(&mut self.extra_data, id, data);
+        self
+    }
+
+    pub fn compression_method(mut self, method: CompressionMethod) -> FileOptions {
+        self.compression_method = method;
+        self
+    }
+
+    pub fn compression_level(mut self, level: Option<i32>) -> FileOptions {
+        self.compression_level = level;
+        self
+    }
+
+    pub fn large_file(mut self, large_file: bool) -> FileOptions {
+        self.large_file = large_file;
+        self
+    }
+
+    /// Start the entry's data at a multiple of alignment bytes (like zipalign), so it can be mmapped in place
+    pub fn with_alignment(mut self, alignment: u16) -> FileOptions {
+        self.alignment = alignment;
+        self
+    }
+
+    /// Catches options the writer can't honor before anything is written
+    fn check(&self, name: &str) -> ZipResult<()> {
+        // The header holds the name's length in 16 bits
+        if name.is_empty() || name.len() > u16::MAX as usize {
+            return Err(ZipError::InvalidArchive(format!("Can't add an entry with a name {} bytes long", name.len())));
+        }
+        if !self.compression_method.is_supported() {
+            return Err(ZipError::InvalidArchive(format!("{} can't be written with {}, only Stored is supported yet", name, self.compression_method)));
+        }
+        if let Some(level) = self.compression_level {
+            return Err(ZipError::InvalidArchive(format!("{} can't be written at compression level {}, Stored has no levels", name, level)));
+        }
+        if extrafield::find(&self.extra_data, extrafield::ZIP64_EXTENDED_INFO).is_some() {
+            return Err(ZipError::InvalidArchive(format!("{} has a ZIP64 extra field, set large_file instead", name)));
+        }
+        // Room is left for the ZIP64 record and alignment padding the writer may add
+        if self.extra_data.len() + 28 + 6 + self.alignment as usize > u16::MAX as usize || self.comment.len() > u16::MAX as usize {
+            return Err(ZipError::InvalidArchive(format!("{} has an extra field or comment too long for the header", name)));
+        }
+        Ok(())
+    }
+}
+
+/// The file currently being written. Data is held until the next start_file or finish,
+/// so the crc and sizes are known when the local header goes out and the output never needs to seek.
+#[derive(Debug)]
+struct PendingFile {
+    name: String,
+    options: FileOptions,
+    data: SpillBuffer,
+    is_directory: bool,
+    /// Full st_mode (file type and permissions) to store, which makes the entry "made on Unix"
+    unix_mode: Option<u32>,
+    started: Instant
+}
+
+/// Writes a zip archive to any Write. Entries are stored uncompressed for now.
+#[derive(Debug)]
+pub struct ZipWriter<W: Write> {
+    inner: BufWriter<Metered<Throttled<W>>>,
+    /// Bytes written to inner so far
+    offset: u64,
+    records: Vec<Cdfhr>,
+    pending: Option<PendingFile>,
+    comment: Vec<u8>,
+    /// Changes to log at the end of the comment when the archive is finished
+    changes: Vec<ChangeRecord>,
+    /// Name of the first file written with each crc32 and size
+    seen_contents: HashMap<(u32, u64), String>,
+    duplicates: Vec<(String, String)>,
+    /// Where the data of the most recently written entry sits in the output
+    last_data_range: Option<Range<u64>>,
+    spill_threshold: Option<u64>,
+    spill_dir: PathBuf
+}
+
+impl<W: Write> ZipWriter<W> {
+    pub fn new(inner: W) -> ZipWriter<W> {
+        ZipWriter::with_options(inner, &WriterOptions::default())
+    }
+
+    pub fn with_options(inner: W, options: &WriterOptions) -> ZipWriter<W> {
+        ZipWriter {
+            inner: BufWriter::with_capacity(options.io_buffer, Metered::new(Throttled::new(inner, options.max_write_rate), options.metrics.clone())),
+            offset: 0,
+            records: Vec::new(),
+            pending: None,
+            comment: Vec::new(),
+            changes: Vec::new(),
+            seen_contents: HashMap::new(),
+            duplicates: Vec::new(),
+            last_data_range: None,
+            spill_threshold: options.spill_threshold,
+            spill_dir: options.spill_dir.clone().unwrap_or_else(std::env::temp_dir)
+        }
+    }
+
+    /// Starts the output with an executable stub (a self-extractor), so the result runs as a program and opens as a zip.
+    /// Every offset in the archive is counted from the start of the file, stub included, like `zip -A` leaves them.
+    pub fn with_sfx_stub(inner: W, stub: &[u8]) -> ZipResult<ZipWriter<W>> {
+        ZipWriter::with_sfx_stub_and_options(inner, stub, &WriterOptions::default())
+    }
+
+    /// with_sfx_stub with the buffer size, spilling, rate limit and metrics from options. The stub is written
+    /// through them like the rest of the output.
+    pub fn with_sfx_stub_and_options(inner: W, stub: &[u8], options: &WriterOptions) -> ZipResult<ZipWriter<W>> {
+        let mut writer = ZipWriter::with_options(inner, options);
+        writer.inner.write_all(stub)?;
+        writer.offset = stub.len() as u64;
+        Ok(writer)
+    }
+
+    /// (added, earlier) name pairs for every file added so far with the same crc32 and size as one already written
+    pub fn duplicates(&self) -> &[(String, String)] {
+        &self.duplicates
+    }
+
+    pub fn set_comment(&mut self, comment: &str) {
+        self.comment = comment.as_bytes().to_vec();
+    }
+
+    /// Sets the comment to raw bytes, such as another archive's comment along with its change log
+    pub fn set_comment_bytes(&mut self, comment: &[u8]) {
+        self.comment = comment.to_vec();
+    }
+
+    /// Logs a change at the end of the archive comment when it's finished, after any log the comment already has,
+    /// so pipelines that audit their artifacts can see who modified the archive and how
+    pub fn record_change(&mut self, change: ChangeRecord) {
+        self.changes.push(change);
+    }
+
+    /// Starts a new entry. Everything written to the ZipWriter until the next start_file/finish becomes its contents.
+    pub fn start_file(&mut self, name: &str, options: FileOptions) -> ZipResult<()> {
+        self.finish_file()?;
+        options.check(name)?;
+        let unix_mode = options.unix_permissions.map(|permissions| attributes::UNIX_REGULAR_FILE | permissions);
+        self.pending = Some(PendingFile { name: name.to_string(), options, data: self.new_buffer(), is_directory: false, unix_mode, started: Instant::now() });
+        Ok(())
+    }
+
+    /// Adds an empty directory entry, so the directory survives extraction even with nothing in it.
+    /// A trailing '/' is added to the name if it's missing.
+    pub fn add_directory(&mut self, name: &str, options: FileOptions) -> ZipResult<()> {
+        self.finish_file()?;
+        let name = if name.ends_with('/') { name.to_string() } else { format!("{}/", name) };
+        options.check(&name)?;
+        let unix_mode = options.unix_permissions.map(|permissions| attributes::UNIX_DIRECTORY | permissions);
+        self.pending = Some(PendingFile { name, options, data: self.new_buffer(), is_directory: true, unix_mode, started: Instant::now() });
+        self.finish_file()
+    }
+
+    /// Adds a symlink entry the way Info-ZIP stores them: a Unix link mode, with the target path as the contents
+    pub fn add_symlink(&mut self, name: &str, target: &str, options: FileOptions) -> ZipResult<()> {
+        self.finish_file()?;
+        options.check(name)?;
+        let mut data = self.new_buffer();
+        data.push(target.as_bytes())?;
+        self.pending = Some(PendingFile {
+            name: name.to_string(),
+            options,
+            data,
+            is_directory: false,
+            unix_mode: Some(attributes::UNIX_SYMLINK | 0o777),
+            started: Instant::now()
+        });
+        self.finish_file()
+    }
+
+    /// Builds a whole archive from (name, contents, options) triples, handing back the writer when done.
+    /// The contents can be any Read, so generated data never needs to touch a temp file.
+    pub fn from_entries<I, S, R>(inner: W, entries: I) -> ZipResult<W> where I: IntoIterator<Item = (S, R, FileOptions)>, S: AsRef<str>, R: Read {
+        let mut writer = ZipWriter::new(inner);
+        writer.write_entries(entries)?;
+        writer.finish()
+    }
+
+    /// Adds a file for each (name, contents, options) triple
+    pub fn write_entries<I, S, R>(&mut self, entries: I) -> ZipResult<()> where I: IntoIterator<Item = (S, R, FileOptions)>, S: AsRef<str>, R: Read {
+        for (name, mut contents, options) in entries {
+            self.start_file(name.as_ref(), options)?;
+            io::copy(&mut contents, self)?;
+        }
+        Ok(())
+    }
+
+    /// Adds everything under root, named relative to it. Directories get their own entries so empty ones survive.
+    /// Anything matched by the walk options' exclude rules (or .gitignore files, if honored) is left out,
+    /// and symlinks are stored, followed or skipped according to the walk options' policy. Links that couldn't be
+    /// followed are returned.
+    pub fn add_dir_all<P: AsRef<Path>>(&mut self, root: P, walk_options: &WalkOptions, options: FileOptions) -> ZipResult<Vec<SkippedLink>> {
+        let walked = walk::walk_dir(root.as_ref(), walk_options)?;
+        for found in walked.entries {
+            if let Some(target) = &found.symlink_target {
+                self.add_symlink(&found.name, &target.to_string_lossy().replace('\\', "/"), options.clone())?;
+            } else if found.is_dir {
+                self.add_directory(&found.name, options.clone())?;
+            } else {
+                self.start_file(&found.name, options.clone())?;
+                let data = fs::read(&found.path)?;
+                self.write_all(&data)?;
+            }
+        }
+        Ok(walked.skipped)
+    }
+
+    /// Adds entries as a walk turns them up, for walks too big to collect first (a walkdir iterator mapped to
+    /// WalkEntry, say). Files are read on a separate thread, up to READ_AHEAD ahead of the writing, so reading and
+    /// writing overlap without the whole tree ending up in memory. A walk error or an unreadable file is collected
+    /// in the returned list and skipped; only failing to write the archive stops the add.
+    pub fn add_walked<I>(&mut self, entries: I, options: FileOptions) -> ZipResult<Vec<AddFailure>>
+        where I: IntoIterator<Item = io::Result<WalkEntry>>, I::IntoIter: Send {
+        let entries = entries.into_iter();
+        let (sender, receiver) = mpsc::sync_channel(READ_AHEAD);
+        thread::scope(|scope| {
+            scope.spawn(move || {
+                for found in entries {
+                    let loaded = match found {
+                        Ok(found) if found.is_dir || found.symlink_target.is_some() => Ok((found, Vec::new())),
+                        Ok(found) => match fs::read(&found.path) {
+                            Ok(data) => Ok((found, data)),
+                            Err(error) => Err(AddFailure { path: Some(found.path), error })
+                        },
+                        Err(error) => Err(AddFailure { path: None, error })
+                    };
+                    // The writer hung up because it failed, nothing left to read for
+                    if sender.send(loaded).is_err() {
+                        break;
+                    }
+                }
+            });
+
+            let mut failures = Vec::new();
+            for loaded in receiver {
+                let (found, data) = match loaded {
+                    Ok(loaded) => loaded,
+                    Err(failure) => {
+                        failures.push(failure);
+                        continue;
+                    }
+                };
+                if let Some(target) = &found.symlink_target {
+                    self.add_symlink(&found.name, &target.to_string_lossy().replace('\\', "/"), options.clone())?;
+                } else if found.is_dir {
+                    self.add_directory(&found.name, options.clone())?;
+                } else {
+                    self.start_file(&found.name, options.clone())?;
+                    self.write_all(&data)?;
+                }
+            }
+            Ok(failures)
+        })
+    }
+
+    /// Copies an entry from another archive without decompressing or re-encoding anything: the data, name bytes,
+    /// flags, times, attributes, extra fields and comment all come across as they were, unless encoding says to
+    /// transcode the name.
+    pub fn raw_copy_file(&mut self, entry: &ZipEntry, encoding: NameEncoding) -> ZipResult<()> {
+        self.raw_copy(entry, None, encoding, false)
+    }
+
+    /// Like raw_copy_file, but without anything saying when or by whom the entry was made: the time and date are
+    /// set to 1980-01-01, the comment is dropped, and so are the extra fields carrying times or a uid and gid
+    /// (NTFS, PKWARE Unix, Info-ZIP extended timestamp and Unix owner). ZipCrypto entries with a data descriptor keep
+    /// the high byte of their time, which their password check is made against.
+    pub fn raw_copy_file_scrubbed(&mut self, entry: &ZipEntry) -> ZipResult<()> {
+        self.raw_copy(entry, None, NameEncoding::Preserve, true)
+    }
+
+    /// Like raw_copy_file, but the entry is written under a new name (in UTF-8, flagged as such when it isn't ASCII).
+    /// Any Info-ZIP Unicode path field is dropped, since readers would take the old name from it.
+    pub fn raw_copy_file_as(&mut self, entry: &ZipEntry, name: &str) -> ZipResult<()> {
+        if name.is_empty() || name.len() > u16::MAX as usize {
+            return Err(ZipError::InvalidArchive(format!("Can't rename {} to a name {} bytes long", entry.name(), name.len())));
+        }
+        self.raw_copy(entry, Some(name), NameEncoding::Preserve, false)
+    }
+
+    /// Copies an entry with its password changed: the ZipCrypto layer is taken off with old_password and put back on
+    /// with new_password, or left off when that's None. The data isn't decompressed, so this works whatever the
+    /// compression method. Unencrypted entries are copied as they are.
+    pub fn raw_copy_file_reencrypted(&mut self, entry: &ZipEntry, old_password: &[u8], new_password: Option<&[u8]>) -> ZipResult<()> {
+        if !entry.is_encrypted() {
+            return self.raw_copy(entry, None, NameEncoding::Preserve, false);
+        }
+        let data = entry.decrypt_raw(old_password)?;
+        // With a data descriptor the local header's sizes aren't used, they're only after the data
+        let local_sizes = entry.flags() & FLAG_DATA_DESCRIPTOR == 0;
+        if entry.record.static_data.compressed_size == ZIP64_OVERFLOW || (local_sizes && entry.local.static_data.compressed_size == ZIP64_OVERFLOW) {
+            return Err(ZipError::InvalidArchive(format!("Can't change the password of {}, its sizes are in a ZIP64 field", entry.name())));
+        }
+
+        let mut reencrypted = entry.clone();
+        let data = match new_password {
+            Some(password) => zipcrypto::encrypt_entry(&data, password, entry.zipcrypto_check_byte()),
+            None => {
+                reencrypted.local.static_data.general_purpose_flag &= !FLAG_ENCRYPTED;
+                reencrypted.record.static_data.general_purpose_flag &= !FLAG_ENCRYPTED;
+                data
+            }
+        };
+        if local_sizes {
+            reencrypted.local.static_data.compressed_size = data.len() as u32;
+        }
+        reencrypted.record.static_data.compressed_size = data.len() as u32;
+        reencrypted.local.compressed_data = data;
+        self.raw_copy(&reencrypted, None, NameEncoding::Preserve, false)
+    }
+
+    fn raw_copy(&mut self, entry: &ZipEntry, new_name: Option<&str>, encoding: NameEncoding, scrub: bool) -> ZipResult<()> {
+        self.finish_file()?;
+        if entry.is_truncated() {
+            return Err(entry.truncated_error());
+        }
+        let started = Instant::now();
+
+        let mut flags = entry.local.static_data.general_purpose_flag;
+        let mut central_flags = entry.record.static_data.general_purpose_flag;
+        let mut name = entry.name_raw().to_vec();
+        let mut local_extra = Cow::Borrowed(&entry.local.extra_field[..]);
+        let mut central_extra_kept = extrafield::without(&entry.record.extra_field_data, extrafield::ZIP64_EXTENDED_INFO);
+        if let Some(new_name) = new_name {
+            name = new_name.as_bytes().to_vec();
+            if !name.is_ascii() {
+                flags |= FLAG_UTF8;
+                central_flags |= FLAG_UTF8;
+            }
+            local_extra = Cow::Owned(extrafield::without(&local_extra, extrafield::INFOZIP_UNICODE_PATH));
+            central_extra_kept = extrafield::without(&central_extra_kept, extrafield::INFOZIP_UNICODE_PATH);
+        } else if encoding == NameEncoding::TranscodeToUtf8 && central_flags & FLAG_UTF8 == 0 && !name.is_ascii() {
+            name = match String::from_utf8(name) {
+                Ok(name) => name.into_bytes(),
+                Err(not_utf8) => cp437::decode(not_utf8.as_bytes()).into_bytes()
+            };
+            flags |= FLAG_UTF8;
+            central_flags |= FLAG_UTF8;
+            // Each CP437 byte can take up to three in UTF-8
+            if name.len() > u16::MAX as usize {
+                return Err(ZipError::InvalidArchive(format!("{} is {} bytes long in UTF-8, too long for the header", entry.name(), name.len())));
+            }
+        }
+        let (mut date, mut time) = (entry.local.static_data.last_modify_date, entry.local.static_data.last_modify_time);
+        if scrub {
+            for id in IDENTIFYING_EXTRA_FIELDS {
+                local_extra = Cow::Owned(extrafield::without(&local_extra, id));
+                central_extra_kept = extrafield::without(&central_extra_kept, id);
+            }
+            let (epoch_date, epoch_time) = DateTime::default().to_dos();
+            let zipcrypto_checks_time = entry.encryption() == EncryptionMethod::ZipCrypto && flags & FLAG_DATA_DESCRIPTOR != 0;
+            date = epoch_date;
+            time = if zipcrypto_checks_time { time & 0xFF00 } else { epoch_time };
+        }
+
+        let local_offset = self.offset;
+        let mut local = entry.local.static_data;
+        local.general_purpose_flag = flags;
+        local.last_modify_date = date;
+        local.last_modify_time = time;
+        local.file_name_length = name.len() as u16;
+        local.extra_field_length = local_extra.len() as u16;
+        let local_extra = &local_extra[..];
+        let data = entry.raw_data();
+
+        self.inner.write_all(local.as_bytes())?;
+        self.inner.write_all(&name)?;
+        self.inner.write_all(local_extra)?;
+        self.inner.write_all(data)?;
+        let data_start = local_offset + (local.as_bytes().len() + name.len() + local_extra.len()) as u64;
+        self.offset = data_start + data.len() as u64;
+        self.last_data_range = Some(data_start..self.offset);
+
+        // The sizes weren't in the local header, so they go after the data like they did originally
+        if flags & FLAG_DATA_DESCRIPTOR != 0 {
+            let mut descriptor = Vec::with_capacity(24);
+            descriptor.extend_from_slice(&DATA_DESCRIPTOR_SIGNATURE.to_le_bytes());
+            descriptor.extend_from_slice(&entry.crc32().to_le_bytes());
+            if extrafield::find(local_extra, extrafield::ZIP64_EXTENDED_INFO).is_some() {
+                descriptor.extend_from_slice(&entry.compressed_size().to_le_bytes());
+                descriptor.extend_from_slice(&entry.uncompressed_size().to_le_bytes());
+            } else {
+                descriptor.extend_from_slice(&(entry.compressed_size() as u32).to_le_bytes());
+                descriptor.extend_from_slice(&(entry.uncompressed_size() as u32).to_le_bytes());
+            }
+            self.inner.write_all(&descriptor)?;
+            self.offset += descriptor.len() as u64;
+        }
+
+        // The ZIP64 record is rebuilt since the entry's offset has changed, the rest of the extra field is kept
+        let mut central = entry.record.static_data;
+        let sizes_overflowed = central.compressed_size == ZIP64_OVERFLOW || central.uncompressed_size == ZIP64_OVERFLOW;
+        let offset_overflows = local_offset >= ZIP64_OVERFLOW as u64;
+        let mut central_extra = central_zip64_record(
+            Some((entry.uncompressed_size(), entry.compressed_size())).filter(|_| sizes_overflowed),
+            Some(local_offset).filter(|_| offset_overflows)
+        );
+        if sizes_overflowed {
+            central.compressed_size = ZIP64_OVERFLOW;
+            central.uncompressed_size = ZIP64_OVERFLOW;
+        }
+        central_extra.extend_from_slice(&central_extra_kept);
+        central.general_purpose_flag = central_flags;
+        central.last_modify_date = date;
+        central.last_modify_time = time;
+        central.file_name_length = name.len() as u16;
+        central.extra_field_length = central_extra.len() as u16;
+        central.relative_offset_localheader = local_offset.min(ZIP64_OVERFLOW as u64) as u32;
+        let comment = if scrub { Vec::new() } else { entry.record.file_comment_data.clone() };
+        central.file_comment_length = comment.len() as u16;
+
+        let mut record = Cdfhr::new();
+        record.static_data = central;
+        record.file_name_data = name;
+        record.extra_field_data = central_extra;
+        record.file_comment_data = comment;
+        self.records.push(record);
+        if let Some(metrics) = self.inner.get_ref().metrics() {
+            metrics.entry_written(&entry.name(), entry.uncompressed_size(), started.elapsed());
+        }
+        Ok(())
+    }
+
+    /// Writes out the current entry now instead of at the next start_file or finish.
+    /// Returns where its data landed in the output, or None if no entry was open.
+    pub fn finish_entry(&mut self) -> ZipResult<Option<Range<u64>>> {
+        if self.pending.is_none() {
+            return Ok(None);
+        }
+        self.finish_file()?;
+        Ok(self.last_data_range.clone())
+    }
+
+    /// Writes the central directory and EOF record, handing back the underlying writer
+    pub fn finish(mut self) -> ZipResult<W> {
+        self.finish_file()?;
+        let started = Instant::now();
+
+        let cdr_start = self.offset;
+        for record in &mut self.records {
+            record.start_offset = self.offset;
+            self.inner.write_all(record.static_data.as_bytes())?;
+            self.inner.write_all(&record.file_name_data)?;
+            self.inner.write_all(&record.extra_field_data)?;
+            self.inner.write_all(&record.file_comment_data)?;
+            self.offset += (record.static_data.as_bytes().len() + record.file_name_data.len()
+                + record.extra_field_data.len() + record.file_comment_data.len()) as u64;
+            record.end_offset = self.offset;
+        }
+
+        let cdr_size = self.offset - cdr_start;
+        let needs_zip64 = self.records.len() >= u16::MAX as usize
+            || cdr_start >= ZIP64_OVERFLOW as u64 || cdr_size >= ZIP64_OVERFLOW as u64;
+        if needs_zip64 {
+            self.write_zip64_end(cdr_start, cdr_size)?;
+        }
+
+        let mut eof = EndOfCentralDirectoryRecord::new();
+        eof.num_cdr_on_disk = self.records.len().min(u16::MAX as usize) as u16;
+        eof.total_cdr = eof.num_cdr_on_disk;
+        eof.size_of_cdr = cdr_size.min(ZIP64_OVERFLOW as u64) as u32;
+        eof.offset_cdr_start = cdr_start.min(ZIP64_OVERFLOW as u64) as u32;
+        let comment = changelog::append(&self.comment, &self.changes);
+        if comment.len() > u16::MAX as usize {
+            return Err(ZipError::InvalidArchive(format!("The archive comment is {} bytes, more than fits in the header", comment.len())));
+        }
+        eof.comment_length = comment.len() as u16;
+        self.inner.write_all(eof.as_bytes())?;
+        self.inner.write_all(&comment)?;
+        self.inner.flush()?;
+
+        if let Some(metrics) = self.inner.get_ref().metrics() {
+            metrics.archive_finished(self.records.len(), started.elapsed());
+        }
+        self.inner.into_inner().map(|inner| inner.into_inner().into_inner()).map_err(|error| ZipError::Io(error.into_error()))
+    }
+
+    /// The ZIP64 end of central directory record and its locator, for when the counts or offsets overflow the EOF record
+    fn write_zip64_end(&mut self, cdr_start: u64, cdr_size: u64) -> ZipResult<()> {
+        let zip64_eocd_start = self.offset;
+        let entries = self.records.len() as u64;
+
+        let mut record = Vec::with_capacity(76);
+        record.extend_from_slice(&ZIP64_EOCD_SIGNATURE.to_le_bytes());
+        record.extend_from_slice(&44u64.to_le_bytes()); // Size of the rest of the record
+        record.extend_from_slice(&VERSION_MADE_BY.to_le_bytes());
+        record.extend_from_slice(&version::VERSION_ZIP64.to_le_bytes());
+        record.extend_from_slice(&0u32.to_le_bytes()); // This disk
+        record.extend_from_slice(&0u32.to_le_bytes()); // Disk the central directory starts on
+        record.extend_from_slice(&entries.to_le_bytes());
+        record.extend_from_slice(&entries.to_le_bytes());
+        record.extend_from_slice(&cdr_size.to_le_bytes());
+        record.extend_from_slice(&cdr_start.to_le_bytes());
+
+        record.extend_from_slice(&ZIP64_LOCATOR_SIGNATURE.to_le_bytes());
+        record.extend_from_slice(&0u32.to_le_bytes()); // Disk the ZIP64 record is on
+        record.extend_from_slice(&zip64_eocd_start.to_le_bytes());
+        record.extend_from_slice(&1u32.to_le_bytes()); // Total disks
+
+        self.inner.write_all(&record)?;
+        self.offset += record.len() as u64;
+        Ok(())
+    }
+
+    fn new_buffer(&self) -> SpillBuffer {
+        SpillBuffer::new(self.spill_threshold, self.spill_dir.clone())
+    }
+
+    fn finish_file(&mut self) -> ZipResult<()> {
+        let mut pending = match self.pending.take() {
+            Some(pending) => pending,
+            None => return Ok(())
+        };
+
+        let size = pending.data.len();
+        let zip64_sizes = pending.options.large_file;
+        if size >= ZIP64_OVERFLOW as u64 && !zip64_sizes {
+            return Err(ZipError::InvalidArchive(format!("{} is 4 GiB or over, set large_file in its FileOptions", pending.name)));
+        }
+        let zip64_offset = self.offset >= ZIP64_OVERFLOW as u64;
+
+        let name = pending.name.as_bytes();
+        let flags = if pending.name.is_ascii() { 0 } else { FLAG_UTF8 };
+        let version_needed = version::version_needed(CompressionMethod::Stored, EncryptionMethod::None, zip64_sizes || zip64_offset);
+        let crc = pending.data.crc32();
+
+        // The local ZIP64 record always holds both sizes
+        let mut local_extra = Vec::new();
+        if zip64_sizes {
+            let sizes = [size.to_le_bytes(), size.to_le_bytes()].concat();
+            extrafield::push(&mut local_extra, extrafield::ZIP64_EXTENDED_INFO, &sizes);
+        }
+        let mut central_extra = central_zip64_record(
+            Some((size, size)).filter(|_| zip64_sizes),
+            Some(self.offset).filter(|_| zip64_offset)
+        );
+        local_extra.extend_from_slice(&pending.options.extra_data);
+        central_extra.extend_from_slice(&pending.options.extra_data);
+
+        let alignment = pending.options.alignment as u64;
+        let unpadded_start = self.offset + LocalFileHeader::new().as_bytes().len() as u64 + name.len() as u64 + local_extra.len() as u64;
+        if alignment > 1 && !unpadded_start.is_multiple_of(alignment) {
+            // The padding record needs at least 6 bytes (header and alignment), so aim for the first boundary past that
+            let padded_start = (unpadded_start + 6).div_ceil(alignment) * alignment;
+            let mut padding = vec![0u8; (padded_start - unpadded_start - 4) as usize];
+            padding[..2].copy_from_slice(&pending.options.alignment.to_le_bytes());
+            extrafield::push(&mut local_extra, extrafield::ALIGNMENT_PADDING, &padding);
+        }
+        let header_size = if zip64_sizes { ZIP64_OVERFLOW } else { size as u32 };
+
+        if !pending.is_directory && !pending.data.is_empty() {
+            let earlier = self.seen_contents.entry((crc, size)).or_insert_with(|| pending.name.clone());
+            if *earlier != pending.name {
+                self.duplicates.push((pending.name.clone(), earlier.clone()));
+            }
+        }
+
+        let mut local = LocalFileHeader::new();
+        local.magic_number = 0x04034b50;
+        local.version_needed = version_needed;
+        local.general_purpose_flag = flags;
+        local.compression_method = CompressionMethod::Stored.to_u16();
+        local.last_modify_time = pending.options.last_modify_time;
+        local.last_modify_date = pending.options.last_modify_date;
+        local.crc32_uncompressed = crc;
+        local.compressed_size = header_size;
+        local.uncompressed_size = header_size;
+        local.file_name_length = name.len() as u16;
+        local.extra_field_length = local_extra.len() as u16;
+
+        let mut central = CentralDirectoryFileHeader::new();
+        central.magic_number = 0x02014b50;
+        central.version_made_by = if pending.unix_mode.is_some() { VERSION_MADE_BY_UNIX } else { VERSION_MADE_BY };
+        central.version_needed = version_needed;
+        central.general_purpose_flag = flags;
+        central.compression_method = local.compression_method;
+        central.last_modify_time = local.last_modify_time;
+        central.last_modify_date = local.last_modify_date;
+        central.crc32_uncompressed = crc;
+        central.compressed_size = local.compressed_size;
+        central.uncompressed_size = local.uncompressed_size;
+        central.file_name_length = local.file_name_length;
+        central.extra_field_length = central_extra.len() as u16;
+        central.file_comment_length = pending.options.comment.len() as u16;
+        let mut dos_bits = pending.options.dos_attributes.to_bits();
+        if pending.is_directory {
+            dos_bits |= attributes::DOS_DIRECTORY;
+        }
+        central.internal_file_attributes = if pending.options.is_text { attributes::INTERNAL_TEXT } else { 0 };
+        central.external_file_attributes = (pending.unix_mode.unwrap_or(0) << 16) | dos_bits as u32;
+        central.relative_offset_localheader = self.offset.min(ZIP64_OVERFLOW as u64) as u32;
+
+        self.inner.write_all(local.as_bytes())?;
+        self.inner.write_all(name)?;
+        self.inner.write_all(&local_extra)?;
+        pending.data.copy_to(&mut self.inner)?;
+        let data_start = self.offset + (local.as_bytes().len() + name.len() + local_extra.len()) as u64;
+        self.offset = data_start + size;
+        self.last_data_range = Some(data_start..self.offset);
+        if let Some(metrics) = self.inner.get_ref().metrics() {
+            metrics.entry_written(&pending.name, size, pending.started.elapsed());
+        }
+
+        let mut record = Cdfhr::new();
+        record.static_data = central;
+        record.file_name_data = name.to_vec();
+        record.extra_field_data = central_extra;
+        record.file_comment_data = pending.options.comment.into_bytes();
+        self.records.push(record);
+
+        Ok(())
+    }
+}
+
+impl ZipWriter<File> {
+    /// Reopens an archive whose writer died before finish, so a long running job can carry on adding to it.
+    /// Every entry that made it out in full is kept; the one being written when it stopped, and anything after it,
+    /// is cut off. finish writes a fresh central directory as usual.
+    /// Only the local headers are left to go on, so recovered entries lose their comments and file attributes.
+    pub fn recover_unfinished<P: AsRef<Path>>(path: P) -> ZipResult<ZipWriter<File>> {
+        let path = path.as_ref();
+        let mut reader = ziparchive::open_reader(path, &ReaderOptions::default())?;
+        let file_size = reader.seek(SeekFrom::End(0))?;
+        reader.seek(SeekFrom::Start(0))?;
+
+        // Truncating something that isn't a zip written from the start (a self-extractor, say) would destroy it
+        let mut signature = [0u8; 4];
+        if file_size >= 4 {
+            reader.read_exact(&mut signature)?;
+            if signature != [0x50, 0x4b, 0x03, 0x04] {
+                return Err(ZipError::InvalidArchive(format!("{} doesn't start with a local header", path.display())));
+            }
+        }
+
+        let (entries, _) = ZipArchive::scan_local_headers(&mut reader);
+        let mut records = Vec::new();
+        let mut end = 0;
+        for entry in entries.iter().filter(|entry| !entry.is_truncated()) {
+            let data_end = entry.data_range()?.end;
+            let entry_end = data_end + ziparchive::data_descriptor_length(&mut reader, &entry.local, data_end);
+            if entry_end > file_size {
+                break;
+            }
+            records.push(recovered_record(entry, end));
+            end = entry_end;
+        }
+
+        let mut file = OpenOptions::new().write(true).open(path)?;
+        file.set_len(end)?;
+        file.seek(SeekFrom::End(0))?;
+        let mut writer = ZipWriter::new(file);
+        writer.offset = end;
+        writer.records = records;
+        Ok(writer)
+    }
+}
+
+/// Rebuilds the central directory record of an entry found by its local header at local_offset
+fn recovered_record(entry: &ZipEntry, local_offset: u64) -> Cdfhr {
+    let local = &entry.local;
+    let mut central = entry.record.static_data;
+    central.version_made_by = VERSION_MADE_BY;
+    let sizes_overflowed = central.compressed_size == ZIP64_OVERFLOW || central.uncompressed_size == ZIP64_OVERFLOW;
+    let offset_overflows = local_offset >= ZIP64_OVERFLOW as u64;
+    let mut central_extra = central_zip64_record(
+        Some((entry.uncompressed_size(), entry.compressed_size())).filter(|_| sizes_overflowed),
+        Some(local_offset).filter(|_| offset_overflows)
+    );
+    let kept = extrafield::without(&local.extra_field, extrafield::ZIP64_EXTENDED_INFO);
+    central_extra.extend_from_slice(&extrafield::without(&kept, extrafield::ALIGNMENT_PADDING));
+    central.extra_field_length = central_extra.len() as u16;
+    central.relative_offset_localheader = local_offset.min(ZIP64_OVERFLOW as u64) as u32;
+    if local.file_name_data.ends_with(b"/") {
+        central.external_file_attributes = attributes::DOS_DIRECTORY as u32;
+    }
+
+    let mut record = Cdfhr::new();
+    record.static_data = central;
+    record.file_name_data = local.file_name_data.clone();
+    record.extra_field_data = central_extra;
+    record
+}
+
+/// Extra fields raw_copy_file_scrubbed leaves out
+const IDENTIFYING_EXTRA_FIELDS: [u16; 5] = [
+    extrafield::NTFS_TIMES,
+    extrafield::PKWARE_UNIX,
+    extrafield::EXTENDED_TIMESTAMP,
+    extrafield::INFOZIP_UNIX_OWNER,
+    extrafield::INFOZIP_UNIX_OWNER_OLD
+];
+
+/// Options that keep an entry's time, attributes and comment when its contents are written out afresh
+fn rewritten_options(entry: &ZipEntry) -> FileOptions {
+    let header = &entry.record.static_data;
+    FileOptions {
+        last_modify_time: header.last_modify_time,
+        last_modify_date: header.last_modify_date,
+        dos_attributes: entry.dos_attributes().unwrap_or_default(),
+        is_text: entry.is_text(),
+        unix_permissions: entry.unix_mode().map(|mode| mode & 0o7777),
+        comment: String::from_utf8_lossy(&entry.record.file_comment_data).into_owned(),
+        ..FileOptions::default()
+    }
+}
+
+impl ZipArchive<'_> {
+    /// Copies every entry into a new archive written to output, under whatever name rename gives it (None keeps
+    /// the old one). The data is copied raw, so nothing is decompressed or re-encrypted, and only the name fields
+    /// and their lengths change. The archive comment, change log included, comes across too.
+    /// Adding a top level directory is `|name| Some(format!("top/{}", name))`, stripping one
+    /// `|name| name.strip_prefix("top/").filter(|rest| !rest.is_empty()).map(str::to_string)` (top/ itself has
+    /// nothing left once stripped, and an empty name can't be written, so it keeps its name).
+    /// Fails without finishing the output if two entries would end up with the same name.
+    pub fn rewrite_renamed<W, F>(&self, output: W, rename: F) -> ZipResult<W> where W: Write, F: FnMut(&str) -> Option<String> {
+        let mut writer = ZipWriter::new(output);
+        writer.set_comment_bytes(self.comment());
+        self.copy_renamed(&mut writer, rename)?;
+        writer.finish()
+    }
+
+    /// rewrite_renamed with the new names looked up by old name. Entries missing from names keep theirs.
+    pub fn rewrite_with_names<W: Write>(&self, output: W, names: &HashMap<String, String>) -> ZipResult<W> {
+        self.rewrite_renamed(output, |name| names.get(name).cloned())
+    }
+
+    /// Rewrites the archive with every entry moved under one top level directory, the way GitHub lays out release
+    /// archives: a flat a.txt, src/lib.rs becomes myproject-1.2.3/, myproject-1.2.3/a.txt, myproject-1.2.3/src/lib.rs.
+    /// The directory gets its own entry at the start. Data is copied raw, as with rewrite_renamed.
+    pub fn prepend_prefix<W: Write>(&self, output: W, prefix: &str) -> ZipResult<W> {
+        let directory = prefix.trim_end_matches('/');
+        if directory.is_empty() || directory.starts_with('/') || directory.split('/').any(|part| part.is_empty() || part == "." || part == "..") {
+            return Err(ZipError::InvalidArchive(format!("{:?} isn't a usable directory prefix", prefix)));
+        }
+        let prefix = format!("{}/", directory);
+
+        let mut writer = ZipWriter::new(output);
+        writer.set_comment_bytes(self.comment());
+        writer.add_directory(&prefix, FileOptions::default().unix_permissions(0o755))?;
+        self.copy_renamed(&mut writer, |name| Some(format!("{}{}", prefix, name)))?;
+        writer.finish()
+    }
+
+    /// Repacks the archive, passing each entry's name and contents to transform on the way. When it returns a new
+    /// name and reader, that's written in the entry's place (keeping its time, attributes and comment); when it
+    /// returns None the entry is copied across raw. Useful for stripping EXIF from images or minifying JSON without
+    /// extracting anything to disk. Entries that can't be read (encrypted, or compressed with a method we don't
+    /// implement) fail the repack rather than go through untransformed.
+    pub fn repack_transformed<W, F>(&self, output: W, transform: F) -> ZipResult<W>
+        where W: Write, F: FnMut(&str, &mut EntryReader) -> io::Result<Option<(String, Box<dyn Read>)>> {
+        let mut writer = ZipWriter::new(output);
+        writer.set_comment_bytes(self.comment());
+        self.copy_transformed(&mut writer, transform)?;
+        writer.finish()
+    }
+
+    /// The copying half of repack_transformed, into a writer set up by the caller, so it can go along with other
+    /// archives being merged into the same output
+    pub fn copy_transformed<W, F>(&self, writer: &mut ZipWriter<W>, mut transform: F) -> ZipResult<()>
+        where W: Write, F: FnMut(&str, &mut EntryReader) -> io::Result<Option<(String, Box<dyn Read>)>> {
+        for entry in self.entries() {
+            let name = entry.name();
+            let mut reader = entry.reader()?;
+            match transform(&name, &mut reader).context(Some(&name), Structure::Data, entry.header_offset())? {
+                Some((new_name, mut data)) => {
+                    writer.start_file(&new_name, rewritten_options(entry))?;
+                    io::copy(&mut data, writer)?;
+                },
+                None => writer.raw_copy_file(entry, NameEncoding::Preserve)?
+            }
+        }
+        Ok(())
+    }
+
+    /// Rewrites the archive without identifying metadata, for publishing it: no archive comment (so no change log),
+    /// and each entry copied raw with raw_copy_file_scrubbed, keeping its name, data and permissions but not its
+    /// times, owner or comment.
+    pub fn scrub<W: Write>(&self, output: W) -> ZipResult<W> {
+        let mut writer = ZipWriter::new(output);
+        for entry in self.entries() {
+            writer.raw_copy_file_scrubbed(entry)?;
+        }
+        writer.finish()
+    }
+
+    /// Rewrites the archive with the password of every encrypted entry changed from old_password to new_password,
+    /// or with encryption removed when new_password is None, one entry at a time and without anything touching the
+    /// disk. Only ZipCrypto can be undone and redone; any entry encrypted otherwise, or with a different password,
+    /// fails the rewrite. Names, times, attributes and the archive comment all stay as they were.
+    pub fn reencrypt<W: Write>(&self, output: W, old_password: &[u8], new_password: Option<&[u8]>) -> ZipResult<W> {
+        let mut writer = ZipWriter::new(output);
+        writer.set_comment_bytes(self.comment());
+        for entry in self.entries() {
+            writer.raw_copy_file_reencrypted(entry, old_password, new_password)?;
+        }
+        writer.finish()
+    }
+
+    /// The copying half of rewrite_renamed, into a writer set up by the caller (to record the change, say)
+    pub fn copy_renamed<W, F>(&self, writer: &mut ZipWriter<W>, mut rename: F) -> ZipResult<()> where W: Write, F: FnMut(&str) -> Option<String> {
+        let mut written: HashMap<String, String> = HashMap::new();
+        for entry in self.entries() {
+            let old_name = entry.name();
+            let new_name = rename(&old_name);
+            let final_name = new_name.clone().unwrap_or_else(|| old_name.to_string());
+            if let Some(earlier) = written.insert(final_name.clone(), old_name.to_string()) {
+                return Err(ZipError::InvalidArchive(format!("Renaming would give {} and {} the same name, {}", earlier, old_name, final_name)));
+            }
+            match new_name {
+                Some(new_name) if new_name != old_name => writer.raw_copy_file_as(entry, &new_name)?,
+                _ => writer.raw_copy_file(entry, NameEncoding::Preserve)?
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for ZipWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self.pending.as_mut() {
+            Some(pending) => {
+                pending.data.push(buf)?;
+                Ok(buf.len())
+            },
+            None => Err(io::Error::other("No file started, call start_file first"))
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Write};
+    use super::*;
+    use crate::ziparchive::ZipArchive;
+
+    /// An archive of stored files, with a comment
+    fn archive_of(files: &[(&str, &str)]) -> Vec<u8> {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        writer.set_comment("the archive comment");
+        for (name, contents) in files {
+            writer.start_file(name, FileOptions::default()).unwrap();
+            writer.write_all(contents.as_bytes()).unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    /// Each entry's name and contents
+    fn contents(archive: &ZipArchive) -> Vec<(String, String)> {
+        archive.entries().iter().map(|entry| (entry.name(), String::from_utf8(entry.read().unwrap()).unwrap())).collect()
+    }
+
+    #[test]
+    fn sfx_stub_with_options_keeps_offsets_from_the_start() {
+        let stub = b"#!/bin/sh\nexit 0\n";
+        let options = WriterOptions { io_buffer: 7, spill_threshold: Some(4), ..WriterOptions::default() };
+        let mut writer = ZipWriter::with_sfx_stub_and_options(Cursor::new(Vec::new()), stub, &options).unwrap();
+        writer.start_file("a.txt", FileOptions::default()).unwrap();
+        writer.write_all(b"spilled past the threshold").unwrap();
+        let written = writer.finish().unwrap().into_inner();
+
+        assert!(written.starts_with(stub));
+        let archive = ZipArchive::parse_bytes(&written).unwrap();
+        assert_eq!(archive.entries()[0].read().unwrap(), b"spilled past the threshold");
+        assert_eq!(archive.entries()[0].record.static_data.relative_offset_localheader as usize, stub.len());
+    }
+
+    #[test]
+    fn compression_level_is_refused() {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        let refused = writer.start_file("a.txt", FileOptions::default().compression_level(Some(9)));
+        assert!(matches!(refused, Err(ZipError::InvalidArchive(_))));
+        assert!(writer.start_file("a.txt", FileOptions::default().compression_level(None)).is_ok());
+    }
+
+    #[test]
+    fn aligned_data_starts_on_the_boundary() {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, alignment) in [("a", 4096), ("longer/name.bin", 4096), ("odd.txt", 4), ("plain.txt", 0)] {
+            writer.start_file(name, FileOptions::default().with_alignment(alignment)).unwrap();
+            writer.write_all(name.as_bytes()).unwrap();
+        }
+        let archive = ZipArchive::parse_bytes(&writer.finish().unwrap().into_inner()).unwrap();
+
+        for (entry, alignment) in archive.entries().iter().zip([4096, 4096, 4, 0]) {
+            let padding = extrafield::find(&entry.local.extra_field, extrafield::ALIGNMENT_PADDING);
+            if alignment == 0 {
+                assert!(padding.is_none());
+            } else {
+                // Data that happens to land on the boundary already gets no padding
+                assert_eq!(entry.data_range().unwrap().start % alignment, 0);
+                if let Some(padding) = padding {
+                    assert_eq!(u16::from_le_bytes([padding[0], padding[1]]) as u64, alignment);
+                }
+            }
+            assert_eq!(entry.read().unwrap(), entry.name().as_bytes());
+        }
+    }
+
+    #[test]
+    fn name_length_is_checked() {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        assert!(matches!(writer.start_file("", FileOptions::default()), Err(ZipError::InvalidArchive(_))));
+        assert!(matches!(writer.start_file(&"a".repeat(65536), FileOptions::default()), Err(ZipError::InvalidArchive(_))));
+        // The trailing '/' counts too
+        assert!(matches!(writer.add_directory(&"d".repeat(65535), FileOptions::default()), Err(ZipError::InvalidArchive(_))));
+        assert!(matches!(writer.add_symlink(&"l".repeat(65536), "target", FileOptions::default()), Err(ZipError::InvalidArchive(_))));
+
+        let longest = "a".repeat(65535);
+        writer.start_file(&longest, FileOptions::default()).unwrap();
+        let archive = ZipArchive::parse_bytes(&writer.finish().unwrap().into_inner()).unwrap();
+        assert_eq!(archive.entries()[0].name(), longest);
+    }
+
+    #[test]
+    fn reencrypt_changes_and_removes_the_password() {
+        let archive = ZipArchive::new("resources/zipcrypto.zip");
+        let contents = archive.entries()[0].read_with_password(b"right").unwrap();
+
+        let changed = archive.reencrypt(Cursor::new(Vec::new()), b"right", Some(b"changed")).unwrap().into_inner();
+        let changed = ZipArchive::parse_bytes(&changed).unwrap();
+        assert_eq!(changed.entries()[0].read_with_password(b"changed").unwrap(), contents);
+        assert!(changed.entries()[0].read_with_password(b"right").is_err());
+        assert_eq!(changed.entries()[0].name(), archive.entries()[0].name());
+
+        let removed = changed.reencrypt(Cursor::new(Vec::new()), b"changed", None).unwrap().into_inner();
+        let removed = ZipArchive::parse_bytes(&removed).unwrap();
+        assert!(!removed.entries()[0].is_encrypted());
+        assert_eq!(removed.entries()[0].read().unwrap(), contents);
+
+        let refused = archive.reencrypt(Cursor::new(Vec::new()), b"wrong", None);
+        assert!(matches!(refused, Err(ZipError::InvalidPassword(_))));
+    }
+
+    #[test]
+    fn rewrite_renames_and_keeps_the_data() {
+        let archive = ZipArchive::parse_bytes(&archive_of(&[("top/a.txt", "a"), ("top/b/c.txt", "c"), ("other.txt", "o")])).unwrap();
+        let stripped = archive.rewrite_renamed(Cursor::new(Vec::new()), |name| name.strip_prefix("top/").map(str::to_string)).unwrap();
+        let stripped = ZipArchive::parse_bytes(stripped.get_ref()).unwrap();
+        assert_eq!(contents(&stripped), [("a.txt", "a"), ("b/c.txt", "c"), ("other.txt", "o")].map(|(name, data)| (name.to_string(), data.to_string())));
+        assert_eq!(stripped.comment(), b"the archive comment");
+        assert_eq!(stripped.entries()[0].crc32(), archive.entries()[0].crc32());
+
+        let names = HashMap::from([("other.txt".to_string(), "renamed.txt".to_string())]);
+        let mapped = archive.rewrite_with_names(Cursor::new(Vec::new()), &names).unwrap();
+        let mapped = ZipArchive::parse_bytes(mapped.get_ref()).unwrap();
+        let mapped_names: Vec<String> = mapped.entries().iter().map(|entry| entry.name()).collect();
+        assert_eq!(mapped_names, ["top/a.txt", "top/b/c.txt", "renamed.txt"]);
+
+        let clash = archive.rewrite_renamed(Cursor::new(Vec::new()), |_| Some("same.txt".to_string()));
+        assert!(matches!(clash, Err(ZipError::InvalidArchive(why)) if why.contains("same name")));
+    }
+
+    #[test]
+    fn prefix_goes_in_front_of_everything() {
+        let archive = ZipArchive::parse_bytes(&archive_of(&[("a.txt", "a"), ("src/lib.rs", "lib")])).unwrap();
+        let prefixed = archive.prepend_prefix(Cursor::new(Vec::new()), "myproject-1.2.3").unwrap();
+        let prefixed = ZipArchive::parse_bytes(prefixed.get_ref()).unwrap();
+
+        let names: Vec<String> = prefixed.entries().iter().map(|entry| entry.name()).collect();
+        assert_eq!(names, ["myproject-1.2.3/", "myproject-1.2.3/a.txt", "myproject-1.2.3/src/lib.rs"]);
+        assert!(prefixed.entries()[0].is_dir());
+        assert_eq!(prefixed.entries()[2].read().unwrap(), b"lib");
+        assert_eq!(prefixed.comment(), b"the archive comment");
+
+        for prefix in ["", "/", "/abs", "../up", "a//b", "a/./b"] {
+            assert!(matches!(archive.prepend_prefix(Cursor::new(Vec::new()), prefix), Err(ZipError::InvalidArchive(_))), "{:?}", prefix);
+        }
+    }
+
+    #[test]
+    fn scrub_drops_identifying_metadata() {
+        let options = FileOptions::default()
+            .last_modified_time(DateTime::from_date_and_time(2024, 3, 1, 12, 30, 0).unwrap())
+            .unix_permissions(0o640)
+            .comment("written by alice")
+            .add_extra_field(extrafield::INFOZIP_UNIX_OWNER, &[1, 4, 0xE8, 3, 0, 0, 4, 0xE8, 3, 0, 0])
+            .add_extra_field(0xCAFE, b"kept");
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        writer.set_comment("built on alice's laptop");
+        writer.start_file("secret.txt", options).unwrap();
+        writer.write_all(b"contents").unwrap();
+        let archive = ZipArchive::parse_bytes(writer.finish().unwrap().get_ref()).unwrap();
+        assert_eq!(archive.entries()[0].unix_owner(), Some((1000, 1000)));
+
+        let scrubbed = archive.scrub(Cursor::new(Vec::new())).unwrap();
+        let scrubbed = ZipArchive::parse_bytes(scrubbed.get_ref()).unwrap();
+        let entry = &scrubbed.entries()[0];
+        assert_eq!(scrubbed.comment(), b"");
+        assert_eq!(entry.name(), "secret.txt");
+        assert_eq!(entry.read().unwrap(), b"contents");
+        assert_eq!(entry.unix_owner(), None);
+        assert_eq!(entry.unix_mode().map(|mode| mode & 0o777), Some(0o640));
+        assert!(entry.record.file_comment_data.is_empty());
+        assert_eq!(entry.last_modified(), Some(DateTime::default()));
+        assert_eq!(extrafield::find(&entry.record.extra_field_data, 0xCAFE), Some(&b"kept"[..]));
+    }
+}